@@ -1,6 +1,6 @@
 use std::fmt::{self, Display, Formatter};
 
-use sqlite3_ext::ffi::SQLITE_MISMATCH;
+use sqlite3_ext::ffi::{SQLITE_CONSTRAINT, SQLITE_CORRUPT, SQLITE_MISMATCH};
 
 #[derive(Debug)]
 pub enum TableError {
@@ -8,12 +8,33 @@ pub enum TableError {
         expected: &'static str,
         found: &'static str,
     },
+    ColumnCountMismatch {
+        expected: usize,
+        found: usize,
+    },
     ColumnDeclaration(String),
     ParseValueType(String),
     SqlError(sqlite3_ext::Error),
     ParseInterval(String),
     PartitionColumn(String),
     WhereClause(String),
+    Corrupt(String),
+    InvalidOption(String),
+    Sealed(String),
+    Unsupported(String),
+    ExpiredPartition {
+        partition_value: i64,
+        expires_at: i64,
+    },
+    NameConflict {
+        table: String,
+        existing: Vec<String>,
+    },
+    MissingPartition(i64),
+    UnlistedValue {
+        table: String,
+        value: String,
+    },
 }
 
 impl Display for TableError {
@@ -22,12 +43,53 @@ impl Display for TableError {
             TableError::ColumnTypeMismatch { expected, found } => {
                 write!(f, "Expected column type {}, but found {}", expected, found)
             }
+            TableError::ColumnCountMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Expected {} column values, but found {}",
+                    expected, found
+                )
+            }
             TableError::ColumnDeclaration(msg) => write!(f, "Column declaration error: {}", msg),
             TableError::ParseValueType(msg) => write!(f, "Parse value type error: {}", msg),
             TableError::SqlError(err) => write!(f, "SQL error: {}", err),
             TableError::ParseInterval(msg) => write!(f, "Parse interval error: {}", msg),
             TableError::PartitionColumn(msg) => write!(f, "Partition column error: {}", msg),
             TableError::WhereClause(msg) => write!(f, "Where clause error: {}", msg),
+            TableError::Corrupt(msg) => write!(f, "Corrupt table: {}", msg),
+            TableError::InvalidOption(msg) => write!(f, "Invalid option: {}", msg),
+            TableError::Sealed(table) => write!(
+                f,
+                "Table {} is sealed, its schema can no longer be changed",
+                table
+            ),
+            TableError::Unsupported(feature) => {
+                write!(f, "{} is not supported on this SQLite build", feature)
+            }
+            TableError::ExpiredPartition {
+                partition_value,
+                expires_at,
+            } => write!(
+                f,
+                "Partition {} expired at {}, the row would be dropped with it",
+                partition_value, expires_at
+            ),
+            TableError::NameConflict { table, existing } => write!(
+                f,
+                "Cannot create {}, names it derives are taken by: {}",
+                table,
+                existing.join(", ")
+            ),
+            TableError::MissingPartition(partition_value) => write!(
+                f,
+                "No partition exists for partition value {}",
+                partition_value
+            ),
+            TableError::UnlistedValue { table, value } => write!(
+                f,
+                "'{}' is not one of the values {} is list partitioned by",
+                value, table
+            ),
         }
     }
 }
@@ -41,19 +103,36 @@ impl From<sqlite3_ext::Error> for TableError {
         TableError::SqlError(e)
     }
 }
-impl Into<sqlite3_ext::Error> for TableError {
-    fn into(self) -> sqlite3_ext::Error {
-        match self {
-            Self::ColumnTypeMismatch { expected, found } => sqlite3_ext::Error::Sqlite(
+impl From<TableError> for sqlite3_ext::Error {
+    fn from(value: TableError) -> Self {
+        match value {
+            TableError::ColumnTypeMismatch { expected, found } => sqlite3_ext::Error::Sqlite(
                 SQLITE_MISMATCH,
                 Some(format!("Expected: {}, Found: {}", expected, found)),
             ),
-            Self::SqlError(err) => err,
-            Self::ColumnDeclaration(err) => sqlite3_ext::Error::Module(err),
-            Self::ParseValueType(err) => sqlite3_ext::Error::Module(err),
-            Self::ParseInterval(err) => sqlite3_ext::Error::Module(err),
-            Self::PartitionColumn(err) => sqlite3_ext::Error::Module(err),
-            Self::WhereClause(err) => sqlite3_ext::Error::Module(err),
+            TableError::ColumnCountMismatch { .. } => {
+                sqlite3_ext::Error::Sqlite(SQLITE_MISMATCH, Some(value.to_string()))
+            }
+            TableError::SqlError(err) => err,
+            TableError::ColumnDeclaration(err) => sqlite3_ext::Error::Module(err),
+            TableError::ParseValueType(err) => sqlite3_ext::Error::Module(err),
+            TableError::ParseInterval(err) => sqlite3_ext::Error::Module(err),
+            TableError::PartitionColumn(err) => sqlite3_ext::Error::Module(err),
+            TableError::WhereClause(err) => sqlite3_ext::Error::Module(err),
+            TableError::Corrupt(_) => {
+                sqlite3_ext::Error::Sqlite(SQLITE_CORRUPT, Some(value.to_string()))
+            }
+            TableError::InvalidOption(err) => sqlite3_ext::Error::Module(err),
+            TableError::Sealed(_) => sqlite3_ext::Error::Module(value.to_string()),
+            TableError::Unsupported(_) => sqlite3_ext::Error::Module(value.to_string()),
+            TableError::ExpiredPartition { .. } => {
+                sqlite3_ext::Error::Sqlite(SQLITE_CONSTRAINT, Some(value.to_string()))
+            }
+            TableError::NameConflict { .. } => sqlite3_ext::Error::Module(value.to_string()),
+            TableError::MissingPartition(_) => sqlite3_ext::Error::Module(value.to_string()),
+            TableError::UnlistedValue { .. } => {
+                sqlite3_ext::Error::Sqlite(SQLITE_CONSTRAINT, Some(value.to_string()))
+            }
         }
     }
 }
@@ -4,6 +4,8 @@ pub mod shadow_tables;
 pub mod types;
 pub mod utils;
 pub mod vtab_interface;
-pub use shadow_tables::{Lookup, LookupTable, RootTable, TemplateTable};
+pub use shadow_tables::{
+    ConfigView, Lookup, LookupTable, PartitionsView, RootConfig, RootTable, TemplateTable,
+};
 pub use types::*;
 pub use vtab_interface::operations;
@@ -0,0 +1,135 @@
+use sqlite3_ext::Connection;
+use sqlite3_ext::Result as ExtResult;
+
+use super::operations::Table;
+use super::PartitionType;
+use crate::utils::validation::quote_identifier;
+use crate::ExpiredInsertPolicy;
+use crate::RootTable;
+use crate::TemplateTable;
+
+/// Represents the read-only view reporting what the module parsed from the create statement.
+///
+/// The view has two columns, `setting` and `value`, with one row for each of the interval in
+/// seconds, the lifetime in seconds (NULL when no lifetime was declared), the name of the
+/// partition column, the `on_expired_insert` policy, the bucket column (NULL when there is
+/// none), whether the table is sealed, the rounding, the index format, whether there is a
+/// default partition, whether column types are enforced, the partitioning strategy, the
+/// further partition columns of a composite key (NULL when there are none), the template
+/// partition tables are named with and the number of parallel scan workers (NULL when scans are
+/// sequential), followed by a `column.<name>` row for each declared column
+/// holding its resolved type. It reads straight
+/// from the root and template tables, so it always reflects the stored configuration, including
+/// changes made after creation such as a new interval.
+#[derive(Debug, Clone)]
+pub struct ConfigView {
+    /// The name of the view.
+    name: String,
+}
+
+impl ConfigView {
+    /// Specifies the postfix for the view's name to distinguish it from the shadow tables.
+    pub const POSTFIX: &'static str = "config";
+
+    /// Computes the name of the view from the virtual table's base name.
+    pub fn format_name(base_name: &str) -> String {
+        format!("{base_name}_{}", Self::POSTFIX)
+    }
+
+    /// Accesses the name of the view.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Creates the view over the given root and template tables.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for executing the creation.
+    /// - `base_name`: Base name of the virtual table, used to derive the view's name.
+    /// - `root_table`: The root table holding the interval, lifetime and partition column.
+    /// - `template_table`: The template table holding the declared columns.
+    ///
+    /// Returns the newly created `ConfigView` instance.
+    pub fn create(
+        db: &Connection,
+        base_name: &str,
+        root_table: &RootTable,
+        template_table: &TemplateTable,
+    ) -> ExtResult<Self> {
+        let view = Self::connect(base_name);
+        db.execute(&view.create_view_query(root_table, template_table), ())?;
+        Ok(view)
+    }
+
+    /// Connects to the view of an existing virtual table. The view is not required to exist,
+    /// tables created by earlier versions of the module do not have one.
+    ///
+    /// Parameters:
+    /// - `base_name`: Base name of the virtual table.
+    pub fn connect(base_name: &str) -> Self {
+        Self {
+            name: Self::format_name(base_name),
+        }
+    }
+
+    /// Drops the view if it exists.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for executing the drop.
+    pub fn drop_view(&self, db: &Connection) -> ExtResult<()> {
+        db.execute(
+            &format!("DROP VIEW IF EXISTS {}", quote_identifier(&self.name)),
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Generates the SQL CREATE VIEW query for the view.
+    ///
+    /// Parameters:
+    /// - `root_table`: The root table holding the interval, lifetime and partition column.
+    /// - `template_table`: The template table holding the declared columns.
+    ///
+    /// Returns the SQL CREATE VIEW query as a `String`.
+    fn create_view_query(&self, root_table: &RootTable, template_table: &TemplateTable) -> String {
+        let root = quote_identifier(root_table.name());
+        let partition_column = <RootTable as PartitionType>::PARTITION_NAME_COLUMN;
+        let interval = <RootTable as PartitionType>::PARTITION_VALUE_COLUMN;
+        let lifetime = <RootTable as PartitionType>::COLUMNS[2].get_name();
+        let on_expired_insert = ExpiredInsertPolicy::OPTION;
+        let bucket_column = <RootTable as PartitionType>::COLUMNS[4].get_name();
+        let sealed = <RootTable as PartitionType>::COLUMNS[5].get_name();
+        let round = <RootTable as PartitionType>::COLUMNS[6].get_name();
+        let index_format = <RootTable as PartitionType>::COLUMNS[7].get_name();
+        let default_partition = <RootTable as PartitionType>::COLUMNS[8].get_name();
+        let strict = <RootTable as PartitionType>::COLUMNS[9].get_name();
+        let strategy = <RootTable as PartitionType>::COLUMNS[10].get_name();
+        let key_columns = <RootTable as PartitionType>::COLUMNS[11].get_name();
+        let partition_name = <RootTable as PartitionType>::COLUMNS[12].get_name();
+        let parallel_scan = <RootTable as PartitionType>::COLUMNS[13].get_name();
+        let list_values = <RootTable as PartitionType>::COLUMNS[14].get_name();
+        let max_statements = <RootTable as PartitionType>::COLUMNS[16].get_name();
+        format!(
+            "CREATE VIEW {} (setting, value) AS \
+             SELECT 'interval', {interval} FROM {root} \
+             UNION ALL SELECT 'lifetime', {lifetime} FROM {root} \
+             UNION ALL SELECT 'partition_column', {partition_column} FROM {root} \
+             UNION ALL SELECT '{on_expired_insert}', {on_expired_insert} FROM {root} \
+             UNION ALL SELECT '{bucket_column}', {bucket_column} FROM {root} \
+             UNION ALL SELECT '{sealed}', {sealed} FROM {root} \
+             UNION ALL SELECT '{round}', {round} FROM {root} \
+             UNION ALL SELECT '{index_format}', {index_format} FROM {root} \
+             UNION ALL SELECT '{default_partition}', {default_partition} FROM {root} \
+             UNION ALL SELECT '{strict}', {strict} FROM {root} \
+             UNION ALL SELECT '{strategy}', {strategy} FROM {root} \
+             UNION ALL SELECT '{key_columns}', {key_columns} FROM {root} \
+             UNION ALL SELECT '{partition_name}', {partition_name} FROM {root} \
+             UNION ALL SELECT '{parallel_scan}', {parallel_scan} FROM {root} \
+             UNION ALL SELECT '{list_values}', {list_values} FROM {root} \
+             UNION ALL SELECT '{max_statements}', {max_statements} FROM {root} \
+             UNION ALL SELECT 'column.' || name, type FROM pragma_table_info('{}')",
+            quote_identifier(&self.name),
+            template_table.name().replace('\'', "''")
+        )
+    }
+}
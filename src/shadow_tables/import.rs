@@ -0,0 +1,195 @@
+use sqlite3_ext::query::QueryResult;
+use sqlite3_ext::vtab::ConflictMode;
+use sqlite3_ext::{FallibleIteratorMut, FromValue, Value, ValueRef};
+
+use super::interface::VirtualTable;
+use crate::utils::validation::quote_identifier;
+
+/// How [`VirtualTable::import_from`] handles source rows whose partition column value cannot be
+/// parsed as a time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    /// The first such row aborts the import and nothing is imported.
+    #[default]
+    Strict,
+    /// Such rows are left out and reported, the remaining rows are imported.
+    Skip,
+}
+
+/// The outcome of [`VirtualTable::import_from`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    /// The number of rows written to the partitions.
+    pub imported: usize,
+    /// The rows left out in [`ImportMode::Skip`], with the columns in declaration order.
+    pub skipped: Vec<Vec<Value>>,
+}
+
+impl<'vtab> VirtualTable<'vtab> {
+    /// Copies every row of another table into the partitions, e.g. to migrate a legacy table.
+    ///
+    /// The source has to have a column for each declared column of the virtual table, matched
+    /// by name. A bucket column is filled in as on any insert and need not exist in the source.
+    /// The import runs inside a savepoint, so it either completes or leaves the table as it was.
+    ///
+    /// # Parameters
+    /// - `source`: The name of the table or view to read from.
+    /// - `mode`: Whether a row with an unparseable partition column value aborts the import or
+    ///   is skipped.
+    ///
+    /// # Returns
+    /// The number of imported rows and the skipped ones. Returns an error naming the value if a
+    /// row cannot be parsed in [`ImportMode::Strict`], or if a row cannot be written.
+    pub fn import_from(&self, source: &str, mode: ImportMode) -> sqlite3_ext::Result<ImportReport> {
+        self.connection.execute("SAVEPOINT import_from", ())?;
+        match self
+            .import_rows(source, mode)
+            .and_then(|report| self.flush_row_counts().map(|_| report))
+        {
+            Ok(report) => {
+                self.connection.execute("RELEASE import_from", ())?;
+                Ok(report)
+            }
+            Err(err) => {
+                self.connection.execute("ROLLBACK TO import_from", ())?;
+                self.connection.execute("RELEASE import_from", ())?;
+                self.discard_row_counts();
+                self.lookup().reload(self.connection)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Copies the rows of `source`. See [`VirtualTable::import_from`].
+    fn import_rows(&self, source: &str, mode: ImportMode) -> sqlite3_ext::Result<ImportReport> {
+        let partition_column_index = self
+            .columns()
+            .0
+            .iter()
+            .position(|column| column.get_name() == self.partition_column_name())
+            .ok_or_else(|| {
+                sqlite3_ext::Error::Module(format!(
+                    "Partition column {} not found",
+                    self.partition_column_name()
+                ))
+            })?;
+        let columns = self
+            .columns()
+            .0
+            .iter()
+            .map(|column| match self.bucket_column() {
+                Some(bucket_column) if bucket_column == column.get_name() => "NULL".to_owned(),
+                _ => quote_identifier(column.get_name()),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {} FROM {}",
+            columns,
+            quote_identifier(source)
+        ))?;
+        stmt.query(())?;
+        let mut report = ImportReport::default();
+        let mut row_number = 0;
+        while let Some(row) = stmt.next()? {
+            row_number += 1;
+            let values: Vec<&ValueRef> = (0..row.len()).map(|index| row[index].as_ref()).collect();
+            let partition_value = match self.partition_value(values[partition_column_index]) {
+                Ok(partition_value) => partition_value,
+                Err(_) if mode == ImportMode::Skip => {
+                    report.skipped.push(owned_row(row)?);
+                    continue;
+                }
+                Err(_) => {
+                    return Err(sqlite3_ext::Error::Module(format!(
+                        "Row {} of {} has the partition column value {:?}, which is not a time",
+                        row_number,
+                        source,
+                        values[partition_column_index].to_owned()?
+                    )))
+                }
+            };
+            self.insert(partition_value, &values, ConflictMode::Abort)?;
+            report.imported += 1;
+        }
+        Ok(report)
+    }
+}
+
+/// Copies the values of a row out of the statement.
+fn owned_row(row: &mut QueryResult) -> sqlite3_ext::Result<Vec<Value>> {
+    (0..row.len()).map(|index| row[index].to_owned()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection as RusqConn;
+    use sqlite3_ext::Connection;
+
+    use super::*;
+    use crate::vtab_interface::init;
+
+    /// Creates an hourly partitioned table and a legacy table holding one unparseable row.
+    fn setup(db: &Connection) {
+        init(db).unwrap();
+        db.execute(
+            "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)",
+            (),
+        )
+        .unwrap();
+        db.execute(
+            "CREATE TABLE legacy (col2 integer, col1 text, note text)",
+            (),
+        )
+        .unwrap();
+        db.execute(
+            "INSERT INTO legacy VALUES (1, '2024-02-01 00:10', 'a'), (2, 'yesterday', 'b'), (3, '2024-02-01 02:00', 'c')",
+            (),
+        )
+        .unwrap();
+    }
+
+    fn count(db: &Connection, sql: &str) -> i64 {
+        db.query_row(sql, (), |row| Ok(row[0].get_i64())).unwrap()
+    }
+
+    #[test]
+    fn test_import_strict() -> sqlite3_ext::Result<()> {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        setup(db);
+        let table = VirtualTable::connect(db, "test")?;
+
+        let err = table.import_from("legacy", ImportMode::Strict).unwrap_err();
+        assert!(err.to_string().contains(
+            "Row 2 of legacy has the partition column value Text(\"yesterday\"), which is not a time"
+        ));
+        assert_eq!(count(db, "SELECT count(*) FROM test"), 0);
+        assert_eq!(count(db, "SELECT count(*) FROM test_lookup"), 0);
+
+        db.execute("DELETE FROM legacy WHERE col2 = 2", ())?;
+        let report = table.import_from("legacy", ImportMode::Strict)?;
+        assert_eq!(report.imported, 2);
+        assert!(report.skipped.is_empty());
+        assert_eq!(count(db, "SELECT sum(col2) FROM test"), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_skip() -> sqlite3_ext::Result<()> {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        setup(db);
+        let table = VirtualTable::connect(db, "test")?;
+
+        let report = table.import_from("legacy", ImportMode::Skip)?;
+        assert_eq!(report.imported, 2);
+        assert_eq!(
+            report.skipped,
+            vec![vec![Value::Text("yesterday".to_owned()), Value::Integer(2)]]
+        );
+        assert_eq!(count(db, "SELECT count(*) FROM test"), 2);
+        assert_eq!(count(db, "SELECT count(*) FROM test_lookup"), 2);
+        Ok(())
+    }
+}
@@ -1,16 +1,45 @@
-use sqlite3_ext::query::ToParam;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Debug};
+use std::ops::{Bound, Index};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sqlite3_ext::query::{Statement, ToParam};
+use sqlite3_ext::vtab::ConflictMode;
 use sqlite3_ext::Connection;
+use sqlite3_ext::FallibleIteratorMut;
+use sqlite3_ext::FromValue;
 use sqlite3_ext::ValueRef;
+use sqlite3_ext::ValueType;
+use sqlparser::ast::Statement as ParsedStatement;
+use sqlparser::{dialect::SQLiteDialect, parser::Parser};
 
-use crate::expiration::LifetimeColumn;
+use crate::error::TableError;
+use crate::operations::{detect_capabilities, Capabilities};
+use crate::utils::registry::{
+    partition_value_parser, rename_partition_value_parser, PartitionValueParser,
+};
+use crate::utils::validation::{check_strict_types, quote_identifier, unquote_identifier};
+use crate::utils::{
+    composite_partition_key, format_interval, format_list_values, hash_partition_value,
+    list_partition_label, list_partition_value, parse_partition_key,
+};
+use crate::BucketRounding;
 use crate::ColumnDeclaration;
 use crate::ColumnDeclarations;
+use crate::ConfigView;
+use crate::ExpiredInsertPolicy;
+use crate::IndexFormat;
 use crate::LookupTable;
+use crate::PartitionsView;
 use crate::RootTable;
+use crate::TableOptions;
 use crate::TemplateTable;
 
 use super::operations::Drop;
 use super::operations::Table;
+use super::PartitionValue;
 
 /// Represents a virtual table with partitioning capabilities in SQLite.
 ///
@@ -23,12 +52,68 @@ pub struct VirtualTable<'vtab> {
     pub connection: &'vtab Connection,
     /// Base name of the virtual table.
     base_name: String,
+    /// File backing the main database of the connection, empty for in-memory and temporary
+    /// databases. Custom partition value parsers are registered by it and the base name.
+    database_file: String,
     /// Associated template table for creating new partitions.
     template_table: TemplateTable,
     /// Root table containing metadata about partitions.
     root_table: RootTable,
     /// Lookup table managing the mapping between partition values and partition names.
     lookup_table: LookupTable<i64>,
+    /// View reporting the parsed configuration of the virtual table.
+    config_view: ConfigView,
+    /// View listing the partitions of the virtual table.
+    partitions_view: PartitionsView,
+    /// Prepared INSERT statements reused by [`VirtualTable::insert`].
+    insert_statements: InsertStatements,
+    /// Rows gained or lost by each partition since the row counts were last written to the
+    /// lookup table, see [`VirtualTable::flush_row_counts`].
+    row_count_changes: RefCell<BTreeMap<String, Option<i64>>>,
+}
+
+/// Cache of the prepared INSERT statements of a virtual table, keyed by their SQL, i.e. by
+/// partition and conflict mode. Bulk inserts prepare one statement per partition instead of one
+/// per row. SQLite re-prepares a cached statement by itself when the schema changes.
+#[derive(Default)]
+struct InsertStatements(RefCell<HashMap<String, Statement>>);
+
+impl InsertStatements {
+    /// The number of statements kept unless the table caps its prepared statements, see
+    /// [`VirtualTable::max_statements`].
+    const DEFAULT_CAPACITY: usize = 64;
+
+    /// Takes the statement for `sql` out of the cache, or prepares it if it is not cached.
+    /// Taking it out rather than borrowing it keeps the cache usable while the statement runs,
+    /// e.g. when a trigger inserts into the same table.
+    fn take(&self, db: &Connection, sql: &str) -> sqlite3_ext::Result<Statement> {
+        match self.0.borrow_mut().remove(sql) {
+            Some(stmt) => Ok(stmt),
+            None => db.prepare(sql),
+        }
+    }
+
+    /// Returns a statement taken with [`InsertStatements::take`] to the cache, emptying the cache
+    /// first if it already holds `capacity` statements.
+    fn put_back(&self, sql: String, stmt: Statement, capacity: usize) {
+        let mut statements = self.0.borrow_mut();
+        if statements.len() >= capacity {
+            statements.clear();
+        }
+        statements.insert(sql, stmt);
+    }
+
+    /// The number of cached statements.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+}
+
+impl Debug for InsertStatements {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.borrow().keys()).finish()
+    }
 }
 
 impl<'vtab> VirtualTable<'vtab> {
@@ -45,7 +130,8 @@ impl<'vtab> VirtualTable<'vtab> {
     /// # Returns
     /// Returns a `VirtualTable` instance if the connection is successful, encapsulating the virtual
     /// table's operational context. On failure, returns an error indicating the issue encountered
-    /// during the connection process.
+    /// during the connection process, including a `TableError::Corrupt` if the root and template
+    /// tables disagree about the partition column.
     pub fn connect(
         db: &'vtab Connection,
         name: &str,
@@ -53,19 +139,85 @@ impl<'vtab> VirtualTable<'vtab> {
         let table = VirtualTable {
             connection: db,
             base_name: name.to_string(),
+            database_file: Self::main_database_file(db)?,
             root_table: RootTable::connect(db, name)?,
             template_table: TemplateTable::connect(db, name)?,
             lookup_table: LookupTable::connect(db, name)?,
+            config_view: ConfigView::connect(name),
+            partitions_view: PartitionsView::connect(name),
+            insert_statements: InsertStatements::default(),
+            row_count_changes: RefCell::default(),
         };
+        table.check_partition_column()?;
         Ok(table)
     }
 
+    /// Looks up the file backing the main database of a connection.
+    ///
+    /// # Returns
+    /// The path of the database file, empty for in-memory and temporary databases.
+    fn main_database_file(db: &Connection) -> sqlite3_ext::Result<String> {
+        db.query_row(
+            "SELECT file FROM pragma_database_list WHERE name = 'main'",
+            (),
+            |row| Ok(row[0].get_str()?.to_owned()),
+        )
+    }
+
+    /// Verifies that the partition column named in the root table is a column of the template
+    /// table with a type supported for partitioning, as it was when the table was created, and
+    /// that the further partition columns of a composite key are columns of it too.
+    ///
+    /// # Returns
+    /// `Ok(())` if the partition column is consistent, a `TableError::Corrupt` otherwise.
+    fn check_partition_column(&self) -> sqlite3_ext::Result<()> {
+        let partition_column = self.partition_column_name();
+        let column = self
+            .columns()
+            .0
+            .iter()
+            .find(|column| column.get_name() == partition_column)
+            .ok_or_else(|| {
+                TableError::Corrupt(format!(
+                    "The partition column '{}' named in {} is not a column of {}",
+                    partition_column,
+                    self.root_table.name(),
+                    self.template_table.name()
+                ))
+            })?;
+        PartitionValue::try_from(column.data_type()).map_err(|_| {
+            TableError::Corrupt(format!(
+                "The partition column '{}' of {} has type {}, which cannot be partitioned on",
+                partition_column,
+                self.template_table.name(),
+                column.get_type()
+            ))
+        })?;
+        if let Some(key_column) = self.key_columns().iter().find(|key_column| {
+            !self
+                .columns()
+                .0
+                .iter()
+                .any(|column| column.get_name() == key_column.as_str())
+        }) {
+            return Err(TableError::Corrupt(format!(
+                "The partition column '{}' named in {} is not a column of {}",
+                key_column,
+                self.root_table.name(),
+                self.template_table.name()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     /// Creates a new instance of a virtual table with specified configurations.
     ///
     /// Initializes and configures a new virtual table in the database, setting up associated structures
-    /// like the lookup table for partition mapping, the root table for metadata, and a template table
-    /// for defining the structure of partitions. This method facilitates setting up a partitioned virtual
-    /// table environment with custom column definitions and partitioning strategy.
+    /// like the lookup table for partition mapping, the root table for metadata, a template table
+    /// for defining the structure of partitions and a `<name>_config` view reporting the parsed
+    /// configuration. This method facilitates setting up a partitioned virtual table environment
+    /// with custom column definitions and partitioning strategy.
     ///
     /// # Parameters
     /// - `db`: A reference to the active database connection.
@@ -73,6 +225,8 @@ impl<'vtab> VirtualTable<'vtab> {
     /// - `column_declarations`: Specifications of columns for the virtual table.
     /// - `partition_column`: The name of the column used to determine partitioning.
     /// - `interval`: The interval used for partitioning data.
+    /// - `options`: The remaining settings, such as the lifetime of each partition. A bucket
+    ///   column has to be among the `column_declarations` already.
     ///
     /// # Returns
     /// On success, returns an instance of `VirtualTable`. If any part of the setup fails, an error is returned.
@@ -82,20 +236,31 @@ impl<'vtab> VirtualTable<'vtab> {
         column_declarations: ColumnDeclarations,
         partition_column: String,
         interval: i64,
-        lifetime_column: Option<i64>,
+        options: TableOptions,
     ) -> sqlite3_ext::Result<Self> {
+        let lookup_table = LookupTable::create(db, name)?;
+        let root_table = RootTable::create(db, name, partition_column, interval, options)?;
+        let template_table = TemplateTable::create(db, name, column_declarations)?;
+        let config_view = ConfigView::create(db, name, &root_table, &template_table)?;
+        let partitions_view = PartitionsView::create(db, name, &lookup_table)?;
         Ok(VirtualTable {
             connection: db,
             base_name: name.to_string(),
-            lookup_table: LookupTable::create(db, name)?,
-            root_table: RootTable::create(db, name, partition_column, interval, lifetime_column)?,
-            template_table: TemplateTable::create(db, name, column_declarations)?,
+            database_file: Self::main_database_file(db)?,
+            lookup_table,
+            root_table,
+            template_table,
+            config_view,
+            partitions_view,
+            insert_statements: InsertStatements::default(),
+            row_count_changes: RefCell::default(),
         })
     }
     /// Destroys the virtual table and all its associated data structures.
     ///
     /// This method deletes all partitions managed by the virtual table, as well as the lookup, root,
-    /// and template tables. It ensures a clean removal of all database artifacts related to the virtual table.
+    /// template and quarantine tables, the default partition and the config and partitions views.
+    /// It ensures a clean removal of all database artifacts related to the virtual table.
     ///
     /// # Returns
     /// On successful execution, returns `Ok(())`. If an error occurs during the deletion of any component,
@@ -106,19 +271,118 @@ impl<'vtab> VirtualTable<'vtab> {
             &std::ops::Bound::Unbounded,
             &std::ops::Bound::Unbounded,
         )? {
-            self.connection
-                .execute(&format!("DROP TABLE {}", partition.1), ())?;
+            self.connection.execute(
+                &format!("DROP TABLE {}", quote_identifier(&partition.1)),
+                (),
+            )?;
         }
+        for table in [self.quarantine_name(), self.default_partition_name()] {
+            self.connection.execute(
+                &format!("DROP TABLE IF EXISTS {}", quote_identifier(&table)),
+                (),
+            )?;
+        }
+        self.config_view.drop_view(self.connection)?;
+        self.partitions_view.drop_view(self.connection)?;
         self.lookup_table.drop_table(self.connection)?;
         self.root_table.drop_table(self.connection)?;
         self.template_table.drop_table(self.connection)?;
         Ok(())
     }
+    /// Renames the virtual table along with all tables and views derived from its name.
+    ///
+    /// The root, lookup, template and quarantine tables and the default partition get the new
+    /// name as prefix, every partition named after the table by its `partition_name` template is
+    /// renamed by the template, and the lookup table records the new partition names.
+    /// The config and partitions views are recreated under the new name. Meant to be called from
+    /// `ALTER TABLE ... RENAME TO`, which rolls all of it back if a step fails. A custom partition
+    /// value parser is registered by table name and has to be registered under the new name.
+    ///
+    /// # Parameters
+    /// - `new_name`: The new name of the virtual table.
+    ///
+    /// # Returns
+    /// `Ok(())` once everything carries the new name. Returns an error if the table is sealed or
+    /// a table of one of the new names already exists.
+    pub fn rename(&self, new_name: &str) -> sqlite3_ext::Result<()> {
+        self.check_unsealed()?;
+        let new_name = unquote_identifier(new_name);
+        let naming = self.root_table.partition_name();
+        let partitions = self
+            .lookup_table
+            .get_partitions_by_range(self.connection, &Bound::Unbounded, &Bound::Unbounded)?
+            .into_iter()
+            .filter_map(|(_, partition)| {
+                let value = naming.value(&self.base_name, &partition)?;
+                let new_partition = naming.name(&new_name, value);
+                Some((partition, new_partition))
+            })
+            .collect::<Vec<(String, String)>>();
+        let mut renames = vec![
+            (
+                self.root_table.name().to_owned(),
+                RootTable::format_name(&new_name),
+            ),
+            (
+                self.lookup_table.name().to_owned(),
+                LookupTable::format_name(&new_name),
+            ),
+            (
+                self.template_table.name().to_owned(),
+                TemplateTable::format_name(&new_name),
+            ),
+        ];
+        for (table, suffix) in [
+            (self.quarantine_name(), Self::QUARANTINE_SUFFIX),
+            (self.default_partition_name(), Self::DEFAULT_SUFFIX),
+        ] {
+            if self.table_exists(&table)? {
+                renames.push((table, format!("{}_{}", new_name, suffix)));
+            }
+        }
+        renames.extend(partitions.iter().cloned());
+
+        self.config_view.drop_view(self.connection)?;
+        self.partitions_view.drop_view(self.connection)?;
+        for (from, to) in &renames {
+            self.connection.execute(
+                &format!(
+                    "ALTER TABLE {} RENAME TO {}",
+                    quote_identifier(from),
+                    quote_identifier(to)
+                ),
+                (),
+            )?;
+        }
+        let lookup_table = LookupTable::connect(self.connection, &new_name)?;
+        for (from, to) in &partitions {
+            self.connection.execute(
+                &format!(
+                    "UPDATE {} SET {} = ? WHERE {} = ?",
+                    quote_identifier(lookup_table.name()),
+                    lookup_table.partition_table_column().get_name(),
+                    lookup_table.partition_table_column().get_name()
+                ),
+                [to.as_str(), from.as_str()],
+            )?;
+        }
+        ConfigView::create(
+            self.connection,
+            &new_name,
+            &RootTable::connect(self.connection, &new_name)?,
+            &TemplateTable::connect(self.connection, &new_name)?,
+        )?;
+        PartitionsView::create(self.connection, &new_name, &lookup_table)?;
+        rename_partition_value_parser(&self.database_file, &self.base_name, &new_name)
+    }
+
     /// Retrieves the name of an existing partition or creates a new partition for the given value.
     ///
     /// This method looks up the partition associated with the provided `partition_value`. If a
     /// partition does not exist, it creates a new partition by copying the template table structure,
     /// updates the lookup table with this new partition's information, and returns the new partition's name.
+    /// Since the in-memory lookup may be stale, a miss is first checked against the lookup table and
+    /// the schema, and a partition created elsewhere, e.g. by another connection, is adopted instead.
     ///
     /// # Parameters
     /// * `partition_value` - The value determining which partition to retrieve or create.
@@ -127,41 +391,995 @@ impl<'vtab> VirtualTable<'vtab> {
     /// The name of the existing or newly created partition as a result. In case of errors during
     /// lookup, creation, or insertion into the lookup table, an appropriate error is returned.
     pub fn get_partition(&self, partition_value: &i64) -> sqlite3_ext::Result<String> {
-        self.lookup_table
-            .get_partition(partition_value)
-            .and_then(|name| match name {
-                None => {
-                    let new_partition_name = self.copy(&partition_value.to_string())?;
-                    let lifetime = self.root_table.get_lifetime();
-                    let expires_at = match lifetime {
-                        Some(lifetime) => Some(lifetime + *partition_value),
-                        None => None,
-                    };
-                    self.lookup_table.insert(
-                        self.connection,
-                        &new_partition_name,
-                        *partition_value,
-                        expires_at,
-                    )?;
-                    Ok(new_partition_name)
+        self.get_keyed_partition(*partition_value, 0)
+    }
+
+    /// Retrieves or creates the partition for a partition value and a partition key, see
+    /// [`VirtualTable::get_partition`]. The partitions of a composite partition key are named
+    /// after both, e.g. `test_1706745600_4817`.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the partition.
+    /// * `partition_key` - The key of the partition among those sharing the partition value, see
+    ///   [`VirtualTable::partition_key`].
+    ///
+    /// # Returns
+    /// The name of the existing or newly created partition.
+    pub fn get_keyed_partition(
+        &self,
+        partition_value: i64,
+        partition_key: i64,
+    ) -> sqlite3_ext::Result<String> {
+        self.get_listed_partition(partition_value, partition_key, None)
+    }
+
+    /// Retrieves or creates the partition a value of the partition column is written to, see
+    /// [`VirtualTable::get_keyed_partition`]. A partition created for a list partitioned table
+    /// records the value in the lookup table, which is what keeps values sharing a hash apart.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the partition.
+    /// * `partition_key` - The partition key of the partition.
+    /// * `value` - The value of the partition column.
+    ///
+    /// # Returns
+    /// The name of the existing or newly created partition.
+    pub fn get_value_partition(
+        &self,
+        partition_value: i64,
+        partition_key: i64,
+        value: &ValueRef,
+    ) -> sqlite3_ext::Result<String> {
+        let list_value = self.list_value(value)?;
+        self.get_listed_partition(partition_value, partition_key, list_value.as_deref())
+    }
+
+    /// Computes what the lookup table records for the partition of a value of a list
+    /// partitioned table, see [`list_partition_label`].
+    ///
+    /// # Parameters
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// The recorded value, or `None` unless the table is list partitioned.
+    pub(crate) fn list_value(&self, value: &ValueRef) -> sqlite3_ext::Result<Option<String>> {
+        match self.strategy() {
+            PartitionValue::List => {
+                list_partition_label(value, self.partition_column_type()).map(Some)
+            }
+            PartitionValue::Interval | PartitionValue::Hash => Ok(None),
+        }
+    }
+
+    /// Fails if a value of the partition column has no partition to go to, because the table is
+    /// list partitioned with declared values and the value is not among them. The insert writes
+    /// a row with such a value to the default partition instead, if there is one.
+    ///
+    /// # Parameters
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// `Ok(())` if the value has a partition, a `TableError::UnlistedValue` otherwise.
+    pub fn check_listed(&self, value: &ValueRef) -> sqlite3_ext::Result<()> {
+        if self.list_values().is_empty() {
+            return Ok(());
+        }
+        self.check_list_value(self.list_value(value)?.as_deref())
+    }
+
+    /// Fails if a list partitioned table declared with values is given another value, see
+    /// [`VirtualTable::check_listed`].
+    fn check_list_value(&self, list_value: Option<&str>) -> sqlite3_ext::Result<()> {
+        match list_value {
+            Some(value)
+                if !self.list_values().is_empty()
+                    && !self.list_values().iter().any(|listed| listed == value) =>
+            {
+                Err(TableError::UnlistedValue {
+                    table: self.base_name.to_owned(),
+                    value: value.to_owned(),
                 }
-                Some(name) => Ok(name.to_owned()),
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Retrieves the values a list partitioned table was declared with, as in
+    /// `list ('EU', 'US')`. Empty if every value gets a partition of its own.
+    pub fn list_values(&self) -> &[String] {
+        self.root_table.list_values()
+    }
+
+    /// Retrieves or creates a partition, recording the value a partition created for a list
+    /// partitioned table belongs to, see [`VirtualTable::get_value_partition`]. A list
+    /// partitioned table declared with values has no partition for any other value, see
+    /// [`VirtualTable::check_listed`].
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the partition.
+    /// * `partition_key` - The partition key of the partition.
+    /// * `list_value` - The value the partition belongs to, see [`VirtualTable::list_value`].
+    ///
+    /// # Returns
+    /// The name of the existing or newly created partition.
+    pub(crate) fn get_listed_partition(
+        &self,
+        partition_value: i64,
+        partition_key: i64,
+        list_value: Option<&str>,
+    ) -> sqlite3_ext::Result<String> {
+        self.check_list_value(list_value)?;
+        self.lookup_table.refresh(self.connection)?;
+        if let Some(name) = self
+            .lookup_table
+            .get_keyed_partition(partition_value, partition_key)?
+        {
+            return Ok(name);
+        }
+        self.lookup_table.sync(self.connection)?;
+        if let Some(name) = self
+            .lookup_table
+            .get_keyed_partition(partition_value, partition_key)?
+        {
+            return Ok(name);
+        }
+        let new_partition_name =
+            self.copy(&self.partition_table_name(partition_value, partition_key))?;
+        let lifetime = self.root_table.get_lifetime();
+        let expires_at = lifetime.map(|lifetime| partition_value.saturating_add(lifetime));
+        let partition = self.lookup_table.insert(
+            self.connection,
+            &new_partition_name,
+            partition_value,
+            partition_key,
+            expires_at,
+            list_value,
+        )?;
+        // A partition created within a split interval is split from the same partition. The
+        // partitions split from one lie within its interval, whatever the rounding.
+        if partition_key == 0 && self.is_time_partitioned() {
+            let interval = self.partition_interval();
+            let split_from = self.round().bucket(partition_value, interval);
+            if let Some(split_interval) = self.lookup_table.split_interval(split_from, interval) {
+                self.lookup_table.record_split(
+                    self.connection,
+                    partition_value,
+                    split_from,
+                    split_interval,
+                )?;
+            }
+        }
+        Ok(partition)
+    }
+
+    /// Drops every partition that lies entirely below a cutoff.
+    ///
+    /// A partition is dropped when its whole interval ends at or before `cutoff`, the partition
+    /// containing the cutoff itself is kept. The partition tables and their lookup rows are
+    /// removed inside a savepoint, so either all of them go or none do.
+    ///
+    /// # Parameters
+    /// - `cutoff`: The cutoff as seconds since the UNIX epoch.
+    ///
+    /// # Returns
+    /// The number of partitions dropped. Fails for a hash partitioned table, whose partitions
+    /// do not cover a time range.
+    pub fn drop_partitions_before(&self, cutoff: i64) -> sqlite3_ext::Result<usize> {
+        self.require_time_partitioning("Dropping partitions before a cutoff")?;
+        // No partition value lies after the last epoch of its partition. A merged partition is
+        // kept until the last interval merged into it is before the cutoff, a partition split
+        // from another one is dropped once its own interval is.
+        let partitions = self
+            .lookup_table
+            .get_partitions_by_range(
+                self.connection,
+                &std::ops::Bound::Unbounded,
+                &std::ops::Bound::Excluded(cutoff),
+            )?
+            .into_iter()
+            .filter(|(partition_value, _)| self.partition_epochs(*partition_value).1 < cutoff)
+            .collect::<Vec<_>>();
+        if partitions.is_empty() {
+            return Ok(0);
+        }
+        self.drop_partition_tables(&partitions)?;
+        Ok(partitions.len())
+    }
+
+    /// Drops the partition of a partition value, the counterpart of the partition
+    /// [`VirtualTable::get_partition`] creates on demand. Its rows are lost, a later insert into
+    /// the interval creates the partition anew.
+    ///
+    /// The partition table and its lookup row are removed inside a savepoint, so either both go
+    /// or neither does. With a composite partition key every partition of the value is dropped.
+    /// A partition merged into another one has no partition of its own anymore.
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value of the partition, e.g. the start of its interval.
+    ///
+    /// # Returns
+    /// `Ok(())` once the partition is gone. A `TableError::MissingPartition` if no partition
+    /// exists for the value, a `TableError::Sealed` if the table is sealed.
+    pub fn drop_partition(&self, partition_value: i64) -> sqlite3_ext::Result<()> {
+        self.root_table.check_unsealed()?;
+        let partitions = self
+            .lookup_table
+            .get_partitions_by_range(
+                self.connection,
+                &std::ops::Bound::Included(partition_value),
+                &std::ops::Bound::Included(partition_value),
+            )?
+            .into_iter()
+            .filter(|(value, _)| *value == partition_value)
+            .collect::<Vec<_>>();
+        if partitions.is_empty() {
+            return Err(TableError::MissingPartition(partition_value).into());
+        }
+        self.drop_partition_tables(&partitions)
+    }
+
+    /// Merges the partitions within a range of partition values into the first of them, e.g. to
+    /// keep old data in fewer, larger tables.
+    ///
+    /// The rows of the later partitions are copied into the first one, by the columns of the
+    /// template, and the later partition tables and their lookup rows are removed. The first
+    /// partition keeps its name and partition value and records the last partition value whose
+    /// rows it holds: it is read by queries reaching into any of the merged intervals, rows
+    /// written for them later go to it, and it expires with the last of them. A partition merged
+    /// earlier whose rows reach into the range counts as its first partition.
+    ///
+    /// The copied rows get new rowids. Rowids reported by a scan are only valid for the statement
+    /// that scanned them, and SQLite does not drop tables while statements run, so no statement
+    /// can refer to the old ones. It all runs inside a savepoint, so a failure leaves the
+    /// partitions as they were.
+    ///
+    /// # Parameters
+    /// - `from`: The partition value the range starts at, e.g. the start of its first interval.
+    /// - `to`: The last partition value of the range.
+    ///
+    /// # Returns
+    /// The number of partitions merged into the first one, `0` if the range holds fewer than two
+    /// partitions. Fails for a sealed table, for a table that is not partitioned by time or has a
+    /// composite partition key, for a lookup table created before partitions could be merged, and
+    /// if a copied row violates a unique index of the first partition.
+    pub fn merge_partitions(&self, from: i64, to: i64) -> sqlite3_ext::Result<usize> {
+        self.root_table.check_unsealed()?;
+        self.require_time_partitioning("Merging partitions")?;
+        if !self.key_columns().is_empty() {
+            return Err(TableError::InvalidOption(format!(
+                "Merging partitions needs a single partition column, {} has a composite \
+                 partition key",
+                self.base_name
+            ))
+            .into());
+        }
+        let partitions = self.lookup_table.get_partitions_by_range(
+            self.connection,
+            &Bound::Included(from),
+            &Bound::Included(to),
+        )?;
+        if let Some((partition_value, _)) = partitions
+            .iter()
+            .find(|(partition_value, _)| self.lookup_table.split_from(*partition_value).is_some())
+        {
+            return Err(TableError::InvalidOption(format!(
+                "The partition for {} was split from another one and cannot be merged",
+                partition_value
+            ))
+            .into());
+        }
+        let Some((first, merged)) = partitions.split_first() else {
+            return Ok(0);
+        };
+        if merged.is_empty() {
+            return Ok(0);
+        }
+        self.connection.execute("SAVEPOINT merge_partitions", ())?;
+        match self.merge_into(first, merged) {
+            Ok(()) => {
+                self.connection.execute("RELEASE merge_partitions", ())?;
+                Ok(merged.len())
+            }
+            Err(err) => {
+                self.connection
+                    .execute("ROLLBACK TO merge_partitions", ())?;
+                self.connection.execute("RELEASE merge_partitions", ())?;
+                self.discard_row_counts();
+                self.lookup_table.reload(self.connection)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Moves the rows of partitions into another one. See [`VirtualTable::merge_partitions`].
+    ///
+    /// # Parameters
+    /// - `first`: The partition value and name of the partition the rows are moved to.
+    /// - `merged`: The partition values and names of the partitions merged into it.
+    fn merge_into(
+        &self,
+        first: &(i64, String),
+        merged: &[(i64, String)],
+    ) -> sqlite3_ext::Result<()> {
+        let (partition_value, partition) = first;
+        let through = merged
+            .iter()
+            .map(|(value, _)| self.lookup_table.covered_through(*value))
+            .fold(
+                self.lookup_table.covered_through(*partition_value),
+                i64::max,
+            );
+        self.lookup_table.record_merge(
+            self.connection,
+            *partition_value,
+            through,
+            self.expires_at(through),
+        )?;
+        let columns = self
+            .columns()
+            .0
+            .iter()
+            .map(|column| quote_identifier(column.get_name()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        for (_, merged_partition) in merged {
+            let moved = self.connection.execute(
+                &format!(
+                    "INSERT INTO {} ({columns}) SELECT {columns} FROM {}",
+                    quote_identifier(partition),
+                    quote_identifier(merged_partition)
+                ),
+                (),
+            )?;
+            self.record_row_count_change(partition, Some(moved));
+            self.connection.execute(
+                &format!("DROP TABLE {}", quote_identifier(merged_partition)),
+                (),
+            )?;
+        }
+        let merged_values = merged.iter().map(|(value, _)| *value).collect::<Vec<i64>>();
+        self.lookup_table.remove(self.connection, &merged_values)?;
+        self.flush_row_counts()
+    }
+
+    /// Splits a partition into partitions of a finer interval, e.g. a daily partition that grew
+    /// too large into hourly ones.
+    ///
+    /// Every row is moved to the partition its partition column falls into with the finer
+    /// interval, created as needed, and the original partition is dropped. The new partitions
+    /// record the partition value they were split from: they are read for queries on it, and
+    /// rows written for it later go to the partition of the finer interval they fall into. The
+    /// bucket column keeps the partition value of the table's interval. A partition without rows
+    /// is simply dropped.
+    ///
+    /// As with [`VirtualTable::merge_partitions`] the moved rows get new rowids, and it all runs
+    /// inside a savepoint, so a failure leaves the partition as it was.
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value of the partition to split.
+    /// - `finer_interval`: The interval of the new partitions in seconds. It has to divide the
+    ///   interval of the table evenly.
+    ///
+    /// # Returns
+    /// The number of partitions the rows were split into. Fails for a sealed table, if no
+    /// partition exists for the value, for a table that is not partitioned by time or has a
+    /// composite partition key, for an interval that does not divide the table's, for a partition
+    /// that was merged or split before, and for a lookup table created before partitions could be
+    /// split.
+    pub fn split_partition(
+        &self,
+        partition_value: i64,
+        finer_interval: i64,
+    ) -> sqlite3_ext::Result<usize> {
+        self.root_table.check_unsealed()?;
+        self.require_time_partitioning("Splitting partitions")?;
+        if !self.key_columns().is_empty() {
+            return Err(TableError::InvalidOption(format!(
+                "Splitting partitions needs a single partition column, {} has a composite \
+                 partition key",
+                self.base_name
+            ))
+            .into());
+        }
+        let interval = self.partition_interval();
+        let round = self.round();
+        // With round=nearest the finer partitions may straddle the boundaries of the interval.
+        if finer_interval <= 0
+            || finer_interval >= interval
+            || interval % finer_interval != 0
+            || (round.offset(interval) - round.offset(finer_interval)) % finer_interval != 0
+        {
+            return Err(TableError::ParseInterval(format!(
+                "Interval {} does not evenly divide the partition interval {}",
+                finer_interval, interval
+            ))
+            .into());
+        }
+        let Some((_, partition)) = self
+            .lookup_table
+            .get_partitions_by_range(
+                self.connection,
+                &Bound::Included(partition_value),
+                &Bound::Included(partition_value),
+            )?
+            .into_iter()
+            .find(|(value, _)| *value == partition_value)
+        else {
+            return Err(TableError::MissingPartition(partition_value).into());
+        };
+        if self.lookup_table.covered_through(partition_value) != partition_value
+            || self.lookup_table.split_from(partition_value).is_some()
+        {
+            return Err(TableError::InvalidOption(format!(
+                "The partition for {} was merged or split before and cannot be split",
+                partition_value
+            ))
+            .into());
+        }
+        self.connection.execute("SAVEPOINT split_partition", ())?;
+        match self.split_into(partition_value, &partition, finer_interval) {
+            Ok(split) => {
+                self.connection.execute("RELEASE split_partition", ())?;
+                Ok(split)
+            }
+            Err(err) => {
+                self.connection.execute("ROLLBACK TO split_partition", ())?;
+                self.connection.execute("RELEASE split_partition", ())?;
+                self.discard_row_counts();
+                self.lookup_table.reload(self.connection)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Moves the rows of a partition into partitions of a finer interval. See
+    /// [`VirtualTable::split_partition`].
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value of the partition to split.
+    /// - `partition`: The name of the partition to split.
+    /// - `finer_interval`: The interval of the new partitions.
+    ///
+    /// # Returns
+    /// The number of partitions the rows were moved to.
+    fn split_into(
+        &self,
+        partition_value: i64,
+        partition: &str,
+        finer_interval: i64,
+    ) -> sqlite3_ext::Result<usize> {
+        // The counts noted so far go to the lookup rows, before the partition's row is removed.
+        self.flush_row_counts()?;
+        // The partition at the start of the interval is named like the original one.
+        let staged_name = format!("{}_split", partition);
+        self.connection.execute(
+            &format!(
+                "ALTER TABLE {} RENAME TO {}",
+                quote_identifier(partition),
+                quote_identifier(&staged_name)
+            ),
+            (),
+        )?;
+        self.lookup_table
+            .remove(self.connection, &[partition_value])?;
+
+        let partition_column_index = self.partition_column_index()?;
+        let mut split = BTreeSet::new();
+        let mut stmt = self
+            .connection
+            .prepare(&format!("SELECT * FROM {}", quote_identifier(&staged_name)))?;
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            let values: Vec<&ValueRef> = (0..row.len()).map(|index| row[index].as_ref()).collect();
+            let epoch = self.parse_partition_epoch(values[partition_column_index])?;
+            let split_value = self.round().bucket(epoch, finer_interval);
+            let split_partition = self.get_partition(&split_value)?;
+            if split.insert(split_value) && self.lookup_table.split_from(split_value).is_none() {
+                self.lookup_table.record_split(
+                    self.connection,
+                    split_value,
+                    partition_value,
+                    finer_interval,
+                )?;
+            }
+            self.write_row(
+                &split_partition,
+                Some(partition_value),
+                &values,
+                ConflictMode::Abort,
+            )?;
+        }
+        drop(stmt);
+        self.connection.execute(
+            &format!("DROP TABLE {}", quote_identifier(&staged_name)),
+            (),
+        )?;
+        self.flush_row_counts()?;
+        Ok(split.len())
+    }
+
+    /// Drops partition tables and removes them from the lookup table inside a savepoint. When a
+    /// step fails, everything is rolled back and the partitions map is reloaded.
+    ///
+    /// # Parameters
+    /// - `partitions`: The partition values and names of the partitions to drop.
+    fn drop_partition_tables(&self, partitions: &[(i64, String)]) -> sqlite3_ext::Result<()> {
+        self.connection.execute("SAVEPOINT drop_partitions", ())?;
+        let result = partitions
+            .iter()
+            .try_for_each(|(_, partition)| {
+                self.connection
+                    .execute(&format!("DROP TABLE {}", quote_identifier(partition)), ())
+                    .map(|_| ())
             })
+            .and_then(|_| {
+                let partition_values = partitions
+                    .iter()
+                    .map(|(partition_value, _)| *partition_value)
+                    .collect::<Vec<i64>>();
+                self.lookup_table.remove(self.connection, &partition_values)
+            });
+        match result {
+            Ok(()) => {
+                self.connection.execute("RELEASE drop_partitions", ())?;
+                Ok(())
+            }
+            Err(err) => {
+                self.connection.execute("ROLLBACK TO drop_partitions", ())?;
+                self.connection.execute("RELEASE drop_partitions", ())?;
+                self.lookup_table.reload(self.connection)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Computes what dropping the partitions that have expired by `now` would free, without
+    /// dropping anything, e.g. to gauge a retention run before committing to it.
+    ///
+    /// # Parameters
+    /// - `now`: The point in time expiry is evaluated at, as seconds since the UNIX epoch.
+    ///
+    /// # Returns
+    /// The number of expired partitions and the approximate number of bytes their tables and
+    /// indexes occupy, as reported by SQLite's `dbstat` table. A table without a lifetime never
+    /// expires and yields `(0, 0)`. A `TableError::Unsupported` if SQLite was built without
+    /// `dbstat`.
+    pub fn reclaimable_by_expiration(&self, now: i64) -> sqlite3_ext::Result<(usize, i64)> {
+        let Some(last_partition_value) = self
+            .lifetime()
+            .and_then(|lifetime| now.checked_sub(lifetime))
+        else {
+            return Ok((0, 0));
+        };
+        Capabilities::require(detect_capabilities(self.connection)?.dbstat, "dbstat")?;
+        let partitions = self.lookup_table.get_partitions_by_range(
+            self.connection,
+            &std::ops::Bound::Unbounded,
+            &std::ops::Bound::Included(last_partition_value),
+        )?;
+        let mut bytes = 0;
+        for (_, partition) in &partitions {
+            bytes += self.partition_size(partition)?;
+        }
+        Ok((partitions.len(), bytes))
+    }
+
+    /// Computes the approximate number of bytes a partition table and its indexes occupy.
+    ///
+    /// # Parameters
+    /// * `partition` - The name of the partition table.
+    ///
+    /// # Returns
+    /// The total size of the pages of the table and its indexes.
+    fn partition_size(&self, partition: &str) -> sqlite3_ext::Result<i64> {
+        self.connection.query_row(
+            "SELECT coalesce(sum(pgsize), 0) FROM dbstat \
+             WHERE name IN (SELECT name FROM sqlite_schema WHERE tbl_name = ?)",
+            [partition],
+            |row| Ok(row.index(0).get_i64()),
+        )
+    }
+
+    /// Re-buckets all data of the table to a new partition interval.
+    ///
+    /// Every existing partition is moved aside, the lookup table is emptied and the new interval is
+    /// stored in the root table. The rows of the old partitions are then re-inserted, which creates
+    /// partitions for the new interval as needed, merging or splitting the old ones. Finally the old
+    /// partitions are dropped. The whole operation runs inside a savepoint, so a failure leaves the
+    /// table as it was.
+    ///
+    /// # Parameters
+    /// - `new_interval`: The new partition interval in seconds.
+    ///
+    /// # Returns
+    /// `Ok(())` once all rows live in partitions of the new interval. Returns an error if the
+    /// table is sealed or hash partitioned, if the interval is not positive or if moving the data
+    /// fails.
+    pub fn reinterval(&self, new_interval: i64) -> sqlite3_ext::Result<()> {
+        self.root_table.check_unsealed()?;
+        self.require_time_partitioning("Changing the interval")?;
+        if new_interval <= 0 {
+            return Err(TableError::ParseInterval(format!(
+                "Interval must be a positive number of seconds, got {}",
+                new_interval
+            ))
+            .into());
+        }
+        if new_interval == self.partition_interval() {
+            return Ok(());
+        }
+        self.connection.execute("SAVEPOINT reinterval", ())?;
+        match self.rebucket(new_interval) {
+            Ok(()) => {
+                self.connection.execute("RELEASE reinterval", ())?;
+                Ok(())
+            }
+            Err(err) => {
+                self.connection.execute("ROLLBACK TO reinterval", ())?;
+                self.connection.execute("RELEASE reinterval", ())?;
+                self.discard_row_counts();
+                self.root_table.reload_interval(self.connection)?;
+                self.lookup_table.reload(self.connection)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Moves every row into a partition of `new_interval`. See [`VirtualTable::reinterval`].
+    fn rebucket(&self, new_interval: i64) -> sqlite3_ext::Result<()> {
+        let partition_column_index = self.partition_column_index()?;
+        let old_partitions = self.lookup_table.get_partitions_by_range(
+            self.connection,
+            &std::ops::Bound::Unbounded,
+            &std::ops::Bound::Unbounded,
+        )?;
+        let mut staged_partitions = Vec::with_capacity(old_partitions.len());
+        for (_, partition) in old_partitions {
+            let staged_name = format!("{}_reinterval", partition);
+            self.connection.execute(
+                &format!(
+                    "ALTER TABLE {} RENAME TO {}",
+                    quote_identifier(&partition),
+                    quote_identifier(&staged_name)
+                ),
+                (),
+            )?;
+            staged_partitions.push(staged_name);
+        }
+        // The counts noted so far belong to the old partitions, which are counted afresh.
+        self.discard_row_counts();
+        self.lookup_table.clear(self.connection)?;
+        self.root_table
+            .set_interval(self.connection, new_interval)?;
+
+        for staged_name in staged_partitions {
+            let mut stmt = self
+                .connection
+                .prepare(&format!("SELECT * FROM {}", quote_identifier(&staged_name)))?;
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                let values: Vec<&ValueRef> =
+                    (0..row.len()).map(|index| row[index].as_ref()).collect();
+                let epoch = self.parse_partition_epoch(values[partition_column_index])?;
+                let partition_value = self.round().bucket(epoch, new_interval);
+                self.insert(partition_value, &values, ConflictMode::Abort)?;
+            }
+            self.connection.execute(
+                &format!("DROP TABLE {}", quote_identifier(&staged_name)),
+                (),
+            )?;
+        }
+        self.flush_row_counts()
+    }
+
+    /// Adds a column to the template and to every partition of the table.
+    ///
+    /// A column the table already had when this `VirtualTable` was connected is rejected. Tables
+    /// that have gained a column of that name since are left alone, so an addition that only
+    /// reached some of the tables can be completed by running it again. It runs inside a
+    /// savepoint. Altering the tables changes
+    /// the schema, so SQLite reconnects the virtual table and it shows the new column. A
+    /// `VirtualTable` connected before keeps its list of columns.
+    ///
+    /// # Parameters
+    /// - `declaration`: The column to add, e.g. `col4 integer`. It cannot be a partition column,
+    ///   a lifetime or a primary key.
+    ///
+    /// # Returns
+    /// The names of the partitions that were altered, including the quarantine table and the
+    /// default partition if they exist. Returns an error if the table is sealed, if the
+    /// declaration is invalid, if the column already exists or if a table cannot be altered.
+    pub fn add_column(&self, declaration: &str) -> sqlite3_ext::Result<Vec<String>> {
+        self.root_table.check_unsealed()?;
+        let column = ColumnDeclaration::try_from(declaration)?;
+        if column.is_partition_column() || column.is_lifetime_column() || column.is_primary_key() {
+            return Err(TableError::ColumnDeclaration(format!(
+                "Expected a plain column such as 'name type', found '{}'",
+                declaration
+            ))
+            .into());
+        }
+        if self
+            .columns()
+            .0
+            .iter()
+            .any(|existing| existing.get_name().eq_ignore_ascii_case(column.get_name()))
+        {
+            return Err(TableError::ColumnDeclaration(format!(
+                "Column {} already exists",
+                column.get_name()
+            ))
+            .into());
+        }
+        let mut columns = self.columns().clone();
+        columns.0.push(column.clone());
+        columns.require_rowid_name()?;
+        self.connection.execute("SAVEPOINT add_column", ())?;
+        match self.add_missing_column(&column) {
+            Ok(altered) => {
+                self.connection.execute("RELEASE add_column", ())?;
+                Ok(altered)
+            }
+            Err(err) => {
+                self.connection.execute("ROLLBACK TO add_column", ())?;
+                self.connection.execute("RELEASE add_column", ())?;
+                Err(err)
+            }
+        }
     }
 
-    /// Copies the template table structure to create a new partition table with a specified suffix.
+    /// Creates an index on the template and on every existing partition of the table.
+    ///
+    /// SQLite does not allow indexes on a virtual table, so an index created on the template only
+    /// reaches partitions created afterwards. This creates it on the partitions that already exist
+    /// as well, including the quarantine table and the default partition if they exist. It runs
+    /// inside a savepoint, so the index is created everywhere or nowhere.
+    ///
+    /// # Parameters
+    /// - `sql`: A named CREATE INDEX statement on the table or on its template, e.g.
+    ///   `CREATE INDEX test_col2 ON test(col2)`.
+    ///
+    /// # Returns
+    /// The names of the partitions that were indexed. Returns an error if the table is sealed,
+    /// if the statement is not a CREATE INDEX on the table or if an index cannot be created.
+    pub fn create_index_on_all_partitions(&self, sql: &str) -> sqlite3_ext::Result<Vec<String>> {
+        self.root_table.check_unsealed()?;
+        let dialect = SQLiteDialect {};
+        let mut statements = Parser::new(&dialect)
+            .try_with_sql(sql)
+            .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?
+            .parse_statements()
+            .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?;
+        let statement = match (statements.pop(), statements.is_empty()) {
+            (Some(statement @ ParsedStatement::CreateIndex { .. }), true) => statement,
+            _ => {
+                return Err(sqlite3_ext::Error::Module(format!(
+                    "Expected a single CREATE INDEX statement, found '{}'",
+                    sql
+                )))
+            }
+        };
+        if let ParsedStatement::CreateIndex { table_name, .. } = &statement {
+            let target = unquote_identifier(&table_name.to_string());
+            if !target.eq_ignore_ascii_case(&self.base_name)
+                && !target.eq_ignore_ascii_case(self.template_table.name())
+            {
+                return Err(sqlite3_ext::Error::Module(format!(
+                    "The index is on {}, expected {} or {}",
+                    target,
+                    self.base_name,
+                    self.template_table.name()
+                )));
+            }
+        }
+        let mut partitions = self
+            .lookup_table
+            .get_partitions_by_range(self.connection, &Bound::Unbounded, &Bound::Unbounded)?
+            .into_iter()
+            .map(|(_, partition)| partition)
+            .collect::<Vec<String>>();
+        partitions.extend(self.side_tables()?);
+        self.connection.execute("SAVEPOINT create_index", ())?;
+        match self
+            .template_table
+            .create_index(self.connection, &statement, &partitions)
+        {
+            Ok(()) => {
+                self.connection.execute("RELEASE create_index", ())?;
+                Ok(partitions)
+            }
+            Err(err) => {
+                self.connection.execute("ROLLBACK TO create_index", ())?;
+                self.connection.execute("RELEASE create_index", ())?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Adds `column` to every table lacking it. See [`VirtualTable::add_column`].
+    fn add_missing_column(&self, column: &ColumnDeclaration) -> sqlite3_ext::Result<Vec<String>> {
+        let mut partitions = self
+            .lookup_table
+            .get_partitions_by_range(self.connection, &Bound::Unbounded, &Bound::Unbounded)?
+            .into_iter()
+            .map(|(_, partition)| partition)
+            .collect::<Vec<String>>();
+        partitions.extend(self.side_tables()?);
+        self.add_column_if_missing(self.template_table.name(), column)?;
+        let mut altered = Vec::new();
+        for partition in partitions {
+            if self.add_column_if_missing(&partition, column)? {
+                altered.push(partition);
+            }
+        }
+        Ok(altered)
+    }
+
+    /// Adds `column` to a single table unless it already has a column of that name.
+    ///
+    /// # Returns
+    /// Whether the table was altered.
+    fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &ColumnDeclaration,
+    ) -> sqlite3_ext::Result<bool> {
+        let has_column = self.connection.query_row(
+            "SELECT count(*) FROM pragma_table_info(?) WHERE name = ? COLLATE NOCASE",
+            [table, column.get_name()],
+            |row| Ok(row.index(0).get_i64() > 0),
+        )?;
+        if has_column {
+            return Ok(false);
+        }
+        self.connection.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN {}",
+                quote_identifier(table),
+                column
+            ),
+            (),
+        )?;
+        Ok(true)
+    }
+
+    /// Serializes all rows of a partition as a JSON array of objects keyed by column name.
+    ///
+    /// Each value is written through [`ValueDef`](crate::ValueDef), so the result keeps the
+    /// SQLite storage class of every value, e.g. `{"col1":{"Text":"2024-02-01 10:00"}}`. Blobs are
+    /// encoded as base64 strings. Intended for debugging and small exports, the whole partition is
+    /// held in memory.
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value (start of the interval) of the partition to export.
+    ///
+    /// # Returns
+    /// The JSON document as a string. Returns an error if no partition exists for the value.
+    #[cfg(feature = "json")]
+    pub fn export_partition_json(&self, partition_value: i64) -> sqlite3_ext::Result<String> {
+        let partition = self
+            .lookup_table
+            .get_partition(&partition_value)?
+            .ok_or(TableError::MissingPartition(partition_value))?;
+        let column_names = self
+            .columns()
+            .0
+            .iter()
+            .map(|column| column.get_name().to_string())
+            .collect::<Vec<String>>();
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {} FROM {}",
+            column_names
+                .iter()
+                .map(|name| quote_identifier(name))
+                .collect::<Vec<String>>()
+                .join(", "),
+            quote_identifier(&partition)
+        ))?;
+        stmt.query(())?;
+        let mut rows = Vec::new();
+        while let Some(row) = stmt.next()? {
+            let mut object = serde_json::Map::with_capacity(column_names.len());
+            for (index, name) in column_names.iter().enumerate() {
+                let value = crate::ValueDef::from(row[index].to_owned()?);
+                let value = serde_json::to_value(&value)
+                    .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?;
+                object.insert(name.to_owned(), value);
+            }
+            rows.push(serde_json::Value::Object(object));
+        }
+        serde_json::to_string(&rows).map_err(|err| sqlite3_ext::Error::Module(err.to_string()))
+    }
+
+    /// Copies the template table structure to create a new partition table of the given name.
+    /// The indexes of the template table are copied along, e.g. a unique index created on
+    /// `<name>_template` applies to every partition created afterwards.
+    ///
+    /// If a table of that name already exists it is adopted as is and no DDL is run.
     ///
     /// # Parameters
-    /// * `suffix` - The suffix to append to the base name for the new partition table.
+    /// * `new_table_name` - The name of the new partition table, see
+    ///   [`VirtualTable::partition_table_name`].
     ///
     /// # Returns
-    /// The name of the newly created partition table.
-    fn copy(&self, suffix: &str) -> sqlite3_ext::Result<String> {
-        let new_table_name = self.format_new_table_name(suffix);
-        self.template_table.copy(&new_table_name, self.connection)?;
+    /// The name of the newly created or adopted partition table.
+    fn copy(&self, new_table_name: &str) -> sqlite3_ext::Result<String> {
+        let new_table_name = new_table_name.to_owned();
+        if !self.table_exists(&new_table_name)? {
+            self.template_table.copy(&new_table_name, self.connection)?;
+            self.template_table
+                .copy_indices_query(self.connection, &new_table_name)?;
+            if let Some(bucket_column) = self.bucket_column() {
+                self.connection.execute(
+                    &format!(
+                        "CREATE INDEX {} ON {} ({})",
+                        quote_identifier(&format!("{new_table_name}_{bucket_column}")),
+                        quote_identifier(&new_table_name),
+                        quote_identifier(bucket_column)
+                    ),
+                    (),
+                )?;
+            }
+        }
         Ok(new_table_name)
     }
 
+    /// Formats the suffix of the partition table holding the given partition value. Partitions
+    /// before 1970 have a negative partition value, the sign is written as `m` to keep the name a
+    /// valid identifier, e.g. `test_m3600`.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the partition.
+    fn partition_suffix(partition_value: i64) -> String {
+        if partition_value < 0 {
+            format!("m{}", partition_value.unsigned_abs())
+        } else {
+            partition_value.to_string()
+        }
+    }
+
+    /// Names the partition table of a partition following the template the table was created
+    /// with, `{table}_{value}` unless `partition_name` was declared. Every partition table name
+    /// is made here, so partitions created, looked up and renamed agree on it.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the partition.
+    /// * `partition_key` - The key of the partition among those sharing the partition value, 0
+    ///   unless the partition key is composite. A nonzero key is appended to the value, e.g.
+    ///   `test_1706745600_4817`.
+    ///
+    /// # Returns
+    /// The name of the partition table, which may not exist yet.
+    pub fn partition_table_name(&self, partition_value: i64, partition_key: i64) -> String {
+        let value = match partition_key {
+            0 => Self::partition_suffix(partition_value),
+            _ => format!(
+                "{}_{}",
+                Self::partition_suffix(partition_value),
+                Self::partition_suffix(partition_key)
+            ),
+        };
+        self.root_table
+            .partition_name()
+            .name(&self.base_name, &value)
+    }
+
+    /// Checks the schema for a table of the given name.
+    ///
+    /// # Parameters
+    /// * `table_name` - The name of the table.
+    ///
+    /// # Returns
+    /// `true` if the table exists.
+    fn table_exists(&self, table_name: &str) -> sqlite3_ext::Result<bool> {
+        self.connection.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE type = 'table' AND name = ?",
+            [table_name],
+            |row| Ok(row.index(0).get_i64() > 0),
+        )
+    }
+
     /// Generates a new table name by appending a suffix to the virtual table's base name.
     ///
     /// # Parameters
@@ -173,6 +1391,18 @@ impl<'vtab> VirtualTable<'vtab> {
         format!("{}_{}", self.base_name, suffix)
     }
 
+    /// Retrieves the stored CREATE TABLE statement of the template table.
+    ///
+    /// Unlike [`VirtualTable::create_table_query`], which is rebuilt from the parsed columns, this
+    /// is the statement exactly as kept in `sqlite_schema`, including any constraints, so the
+    /// partition structure can be replicated elsewhere.
+    ///
+    /// # Returns
+    /// The `CREATE TABLE <name>_template (...)` statement.
+    pub fn template_sql(&self) -> sqlite3_ext::Result<String> {
+        self.template_table.sql(self.connection)
+    }
+
     /// Retrieves the SQL query to create a table based on the template table's schema.
     ///
     /// # Returns
@@ -183,6 +1413,15 @@ impl<'vtab> VirtualTable<'vtab> {
         //     ColumnDeclaration::new("_partition".to_string(), sqlite3_ext::ValueType::Text);
         // hidden_column.set_hidden();
         interface_schema.name = self.base_name.clone();
+        // Only the partitions enforce the primary key, the virtual table has none of its own.
+        // Without a default partition a row has to have a time, so the partition column is
+        // declared `NOT NULL`. SQLite then hands `min()` of it over as an ascending order, as it
+        // does `max()` as a descending one, see `PartitionMetaTable::best_index`.
+        let not_null = self.is_time_partitioned() && !self.has_default_partition();
+        for column in interface_schema.columns.0.iter_mut() {
+            column.set_primary_key(false);
+            column.set_not_null(not_null && column.get_name() == self.partition_column_name());
+        }
         // interface_schema.columns.0.push(hidden_column);
         interface_schema.table_query()
     }
@@ -195,6 +1434,24 @@ impl<'vtab> VirtualTable<'vtab> {
         self.template_table.columns()
     }
 
+    /// Retrieves the name the rowid of the partitions is read and addressed by, see
+    /// [`ColumnDeclarations::rowid_name`].
+    ///
+    /// # Returns
+    /// `rowid`, or `_rowid_` or `oid` if a column is named `rowid`.
+    pub fn rowid_name(&self) -> &'static str {
+        self.columns().rowid_name().unwrap_or("rowid")
+    }
+
+    /// Returns whether the partition column is declared as the primary key of the partitions.
+    /// The partitions then carry an index on it, which reads of a partition are ordered by, so
+    /// rows come back in time order.
+    pub fn partition_column_is_primary_key(&self) -> bool {
+        self.columns().0.iter().any(|column| {
+            column.is_primary_key() && column.get_name() == self.partition_column_name()
+        })
+    }
+
     /// Retrieves the name of the partition column from the root table.
     ///
     /// # Returns
@@ -203,16 +1460,444 @@ impl<'vtab> VirtualTable<'vtab> {
         self.root_table.partition_column()
     }
 
+    /// Retrieves the name of the column holding the partition value of each row, if the table
+    /// was created with `add_bucket_column=<name>`. The column is filled in on insert and indexed
+    /// in every partition.
+    pub fn bucket_column(&self) -> Option<&str> {
+        self.root_table.bucket_column()
+    }
+
     /// Retrieves the partition interval set in the root table.
     ///
     /// # Returns
-    /// The partition interval in seconds.
+    /// The partition interval in seconds, or the number of partitions of a hash partitioned
+    /// table.
     pub fn partition_interval(&self) -> i64 {
         self.root_table.get_interval()
     }
 
-    pub fn lifetime(&self) -> Option<i64> {
-        self.root_table.get_lifetime()
+    /// Retrieves how rows are assigned to partitions.
+    pub fn strategy(&self) -> PartitionValue {
+        self.root_table.strategy()
+    }
+
+    /// Retrieves the number of partitions rows are hashed to, if the table was created with
+    /// `hash <n>` rather than an interval.
+    pub fn hash_partitions(&self) -> Option<i64> {
+        match self.strategy() {
+            PartitionValue::Hash => Some(self.partition_interval()),
+            PartitionValue::Interval | PartitionValue::List => None,
+        }
+    }
+
+    /// Returns whether the partitions cover ranges of time, rather than hash buckets or single
+    /// values of the partition column. Only then are the partition values ordered like the
+    /// values of the partition column.
+    pub fn is_time_partitioned(&self) -> bool {
+        self.strategy() == PartitionValue::Interval
+    }
+
+    /// Fails unless the table is partitioned by time, for operations that work on time ranges.
+    ///
+    /// # Parameters
+    /// * `operation` - What needs time partitioning, for the error message.
+    pub(crate) fn require_time_partitioning(&self, operation: &str) -> sqlite3_ext::Result<()> {
+        match self.strategy() {
+            PartitionValue::Interval => Ok(()),
+            strategy => Err(TableError::InvalidOption(format!(
+                "{} needs a table partitioned by time, {} is {} partitioned",
+                operation,
+                self.base_name,
+                strategy.as_str()
+            ))
+            .into()),
+        }
+    }
+
+    /// Retrieves the partition interval in a human-readable form for display, e.g. "1 hour" or
+    /// "25 hours". The interval is stored in seconds, so this is the largest unit that divides it
+    /// exactly rather than the text of the create statement.
+    ///
+    /// # Returns
+    /// The interval as a count and a unit, which parses back to the same interval, or e.g.
+    /// `hash 8`, `list` or `list ('EU', 'US')` for a hash or list partitioned table.
+    pub fn interval_label(&self) -> String {
+        match self.strategy() {
+            PartitionValue::Interval => format_interval(self.partition_interval()),
+            PartitionValue::Hash => {
+                format!(
+                    "{} {}",
+                    PartitionValue::HASH_KEYWORD,
+                    self.partition_interval()
+                )
+            }
+            PartitionValue::List if self.list_values().is_empty() => {
+                PartitionValue::LIST_KEYWORD.to_owned()
+            }
+            PartitionValue::List => format!(
+                "{} ({})",
+                PartitionValue::LIST_KEYWORD,
+                format_list_values(self.list_values())
+            ),
+        }
+    }
+
+    /// Converts a value of the partition column to a UNIX epoch timestamp, using the parser
+    /// registered for this table or the built-in datetime parsing if there is none. A partition
+    /// column declared `integer` or `float` is read as a plain number instead, see
+    /// [`parse_partition_key`].
+    ///
+    /// # Parameters
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// The UNIX epoch timestamp or number represented by the value.
+    pub fn parse_partition_epoch(&self, value: &ValueRef) -> sqlite3_ext::Result<i64> {
+        match partition_value_parser(&self.database_file, &self.base_name)? {
+            Some(parser) => parser(value),
+            None => parse_partition_key(value, self.partition_column_type()),
+        }
+    }
+
+    /// Returns the parsing [`VirtualTable::parse_partition_epoch`] does as a function of its own,
+    /// which other threads can call without the table.
+    ///
+    /// # Returns
+    /// The parser registered for this table, or the built-in parsing for the declared type of
+    /// the partition column.
+    pub fn partition_epoch_parser(&self) -> sqlite3_ext::Result<PartitionValueParser> {
+        match partition_value_parser(&self.database_file, &self.base_name)? {
+            Some(parser) => Ok(parser),
+            None => {
+                let declared_type = *self.partition_column_type();
+                Ok(Arc::new(move |value| {
+                    parse_partition_key(value, &declared_type)
+                }))
+            }
+        }
+    }
+
+    /// Tells whether SQL orders the values of the partition column in time order. Only a column
+    /// declared `integer` or `float` is sure to, it stores every value as a number. A text column
+    /// may mix datetime text with epoch integers, which SQL orders by storage class first, or
+    /// hold datetimes of several formats.
+    pub fn partition_column_orders_by_time(&self) -> bool {
+        matches!(
+            self.partition_column_type(),
+            ValueType::Integer | ValueType::Float
+        )
+    }
+
+    /// Retrieves the data type the partition column was declared with.
+    fn partition_column_type(&self) -> &ValueType {
+        self.columns()
+            .0
+            .iter()
+            .find(|column| column.get_name() == self.partition_column_name())
+            .map_or(&ValueType::Text, |column| column.data_type())
+    }
+
+    /// Computes the partition value, i.e. the interval boundary a value of the partition column
+    /// is bucketed to. This is the start of the interval the value falls into, unless the table
+    /// was created with another rounding. Hash partitioned tables hash the value instead, see
+    /// [`hash_partition_value`], and list partitioned tables look up the partition recorded for
+    /// the value, see [`VirtualTable::list_partition_value`].
+    ///
+    /// # Parameters
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// The partition value, aligned to the partition interval, or the hash of the value.
+    pub fn partition_value(&self, value: &ValueRef) -> sqlite3_ext::Result<i64> {
+        if !self.is_time_partitioned() {
+            let declared_type = self.partition_column_type();
+            return match self.hash_partitions() {
+                Some(partitions) => hash_partition_value(value, declared_type, partitions),
+                None => self.list_partition_value(value),
+            };
+        }
+        let interval = self.partition_interval();
+        let round = self.round();
+        self.parse_partition_epoch(value)
+            .map(|epoch| round.bucket(epoch, interval))
+    }
+
+    /// Computes the partition value of a value of a list partitioned table: the partition value
+    /// of its partitions if the lookup table records any for the value, otherwise the partition
+    /// value they would be created with, the hash of the value or, if a partition of another
+    /// value has the hash, the next free partition value, see [`list_partition_value`].
+    ///
+    /// # Parameters
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// The partition value, or an error if the value cannot be hashed, e.g. NULL.
+    pub fn list_partition_value(&self, value: &ValueRef) -> sqlite3_ext::Result<i64> {
+        let declared_type = self.partition_column_type();
+        let hash = list_partition_value(value, declared_type)?;
+        if !self.lookup_table.has_list_values() {
+            return Ok(hash);
+        }
+        let list_value = list_partition_label(value, declared_type)?;
+        self.lookup_table.refresh(self.connection)?;
+        if let Some(partition_value) = self.lookup_table.listed_partition_value(&list_value) {
+            return Ok(partition_value);
+        }
+        self.lookup_table.sync(self.connection)?;
+        Ok(self
+            .lookup_table
+            .listed_partition_value(&list_value)
+            .unwrap_or_else(|| self.lookup_table.free_partition_value(hash)))
+    }
+
+    /// Computes the partition value of the partition a value of the partition column is written
+    /// to, which differs from [`VirtualTable::partition_value`] once the partition of the value
+    /// was split, see [`VirtualTable::split_partition`].
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the value.
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// The partition value of the finer partition the value falls into, or `partition_value`
+    /// if its partition was not split.
+    pub fn split_partition_value(
+        &self,
+        partition_value: i64,
+        value: &ValueRef,
+    ) -> sqlite3_ext::Result<i64> {
+        match self
+            .lookup_table
+            .split_interval(partition_value, self.partition_interval())
+        {
+            Some(split_interval) => self
+                .parse_partition_epoch(value)
+                .map(|epoch| self.round().bucket(epoch, split_interval)),
+            None => Ok(partition_value),
+        }
+    }
+
+    /// Retrieves the first and the last UNIX epoch a partition holds rows for. A partition
+    /// covers one interval, several if others were merged into it, and a fraction of one if it
+    /// was split from another.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The partition value of the partition.
+    pub fn partition_epochs(&self, partition_value: i64) -> (i64, i64) {
+        let round = self.round();
+        let (interval, through) = match self.lookup_table.split_from(partition_value) {
+            Some((_, split_interval)) => (split_interval, partition_value),
+            None => (
+                self.partition_interval(),
+                self.lookup_table.covered_through(partition_value),
+            ),
+        };
+        (
+            round.first_epoch(partition_value, interval),
+            round
+                .first_epoch(through, interval)
+                .saturating_add(interval - 1),
+        )
+    }
+
+    /// Retrieves the position of the partition column among the declared columns.
+    fn partition_column_index(&self) -> sqlite3_ext::Result<usize> {
+        self.columns()
+            .0
+            .iter()
+            .position(|column| column.get_name() == self.partition_column_name())
+            .ok_or_else(|| {
+                TableError::PartitionColumn(format!(
+                    "Partition column {} not found",
+                    self.partition_column_name()
+                ))
+                .into()
+            })
+    }
+
+    /// Retrieves the partition columns after the first, empty unless the partition key is
+    /// composite.
+    pub fn key_columns(&self) -> &[String] {
+        self.root_table.key_columns()
+    }
+
+    /// Retrieves the number of worker threads reading partitions ahead of a scan, or `None` if
+    /// scans read the partitions one after the other, see `parallel_scan` in the create
+    /// statement.
+    pub fn parallel_scan(&self) -> Option<usize> {
+        self.root_table.parallel_scan()
+    }
+
+    /// Retrieves the most partition statements the table's connection keeps prepared at once,
+    /// see `max_statements` in the create statement. It bounds the cached INSERT statements,
+    /// 64 of which are kept by default, and the `parallel_scan` workers, each reading one
+    /// partition at a time. Scans read by the cursor itself keep one partition statement open.
+    pub fn max_statements(&self) -> usize {
+        self.root_table
+            .max_statements()
+            .unwrap_or(InsertStatements::DEFAULT_CAPACITY)
+    }
+
+    /// Retrieves the number of worker threads scans of the table start, `parallel_scan` bounded
+    /// by [`VirtualTable::max_statements`], or `None` if scans read the partitions one after
+    /// the other.
+    pub fn scan_workers(&self) -> Option<usize> {
+        self.parallel_scan()
+            .map(|workers| workers.min(self.max_statements()))
+    }
+
+    /// Computes the partition key of a row, which tells apart the partitions of a composite
+    /// partition key sharing a partition value. Every combination of values of the key columns
+    /// gets partitions of its own, see [`composite_partition_key`].
+    ///
+    /// # Parameters
+    /// * `columns` - The values of a row, in declaration order.
+    ///
+    /// # Returns
+    /// The partition key, `0` if the partition key is not composite. Returns an error if a
+    /// value of a key column cannot be hashed, e.g. NULL, or a `TableError::ColumnCountMismatch`
+    /// if the number of values differs from the number of declared columns.
+    pub fn partition_key(&self, columns: &[&ValueRef]) -> sqlite3_ext::Result<i64> {
+        if self.key_columns().is_empty() {
+            return Ok(0);
+        }
+        self.check_column_count(columns)?;
+        let declarations = &self.columns().0;
+        let values = self
+            .key_columns()
+            .iter()
+            .map(|key_column| {
+                declarations
+                    .iter()
+                    .position(|column| column.get_name() == key_column.as_str())
+                    .map(|index| (columns[index], declarations[index].data_type()))
+                    .ok_or_else(|| {
+                        TableError::PartitionColumn(format!(
+                            "Partition column {} not found",
+                            key_column
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, TableError>>()?;
+        composite_partition_key(&values)
+    }
+
+    /// Finds the partition a stored row belongs to by the values of its partition columns, e.g.
+    /// after an update changed one of them. The partition is created if it does not exist yet.
+    ///
+    /// # Parameters
+    /// * `partition` - The name of the partition table holding the row.
+    /// * `rowid` - The rowid of the row within the partition table.
+    ///
+    /// # Returns
+    /// The partition value of the row and the name of its partition.
+    pub fn partition_of_row(
+        &self,
+        partition: &str,
+        rowid: i64,
+    ) -> sqlite3_ext::Result<(i64, String)> {
+        let column_names = self
+            .columns()
+            .0
+            .iter()
+            .map(|column| quote_identifier(column.get_name()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {} FROM {} WHERE {} = ?",
+            column_names,
+            quote_identifier(partition),
+            self.rowid_name()
+        ))?;
+        stmt.query([rowid])?;
+        let row = stmt.next()?.ok_or_else(|| {
+            sqlite3_ext::Error::Module(format!("Row {} not found in {}", rowid, partition))
+        })?;
+        let values: Vec<&ValueRef> = (0..row.len()).map(|index| row[index].as_ref()).collect();
+        let partition_column_index = self.partition_column_index()?;
+        let partition_value = self.partition_value(values[partition_column_index])?;
+        let partition_key = self.partition_key(&values)?;
+        Ok((
+            partition_value,
+            self.get_value_partition(
+                partition_value,
+                partition_key,
+                values[partition_column_index],
+            )?,
+        ))
+    }
+
+    /// Reports which partition a value of the partition column belongs to, without creating
+    /// the partition or registering it in the lookup table.
+    ///
+    /// A partition already known for the bucket is reported by its name, otherwise the name the
+    /// partition would be created with. Once the partition of the bucket was split, the finer
+    /// partition the value falls into is reported. Expiry is not taken into account, a row for
+    /// an expired partition is still rejected or quarantined on insert.
+    ///
+    /// # Parameters
+    /// * `value` - A value of the partition column.
+    ///
+    /// # Returns
+    /// The partition value and the name of the partition, which may not exist yet.
+    pub fn partition_for_value(&self, value: &ValueRef) -> sqlite3_ext::Result<(i64, String)> {
+        let partition_value = self.partition_value(value)?;
+        let partition_value = self.split_partition_value(partition_value, value)?;
+        let name = match self.lookup_table.get_partition(&partition_value)? {
+            Some(name) => name,
+            None => self.partition_table_name(partition_value, 0),
+        };
+        Ok((partition_value, name))
+    }
+
+    /// Retrieves which interval boundary values of the partition column are bucketed to.
+    pub fn round(&self) -> BucketRounding {
+        self.root_table.round()
+    }
+
+    /// Retrieves how query plans are serialized into the index string.
+    pub fn index_format(&self) -> IndexFormat {
+        self.root_table.index_format()
+    }
+
+    pub fn lifetime(&self) -> Option<i64> {
+        self.root_table.get_lifetime()
+    }
+
+    /// Marks the schema of the table as immutable. Afterwards schema changes such as
+    /// [`VirtualTable::reinterval`] are refused, while rows can still be read and written.
+    /// Sealing cannot be undone.
+    ///
+    /// # Returns
+    /// `Ok(())` once the flag is stored in the root table.
+    pub fn seal(&self) -> sqlite3_ext::Result<()> {
+        self.root_table.seal(self.connection)
+    }
+
+    /// Returns whether the schema of the table may no longer be changed, see
+    /// [`VirtualTable::seal`].
+    pub fn is_sealed(&self) -> bool {
+        self.root_table.is_sealed()
+    }
+
+    /// Refuses schema changes once the table is sealed, see [`VirtualTable::seal`].
+    ///
+    /// The flag is read from the root table rather than from this instance, as the table may have
+    /// been sealed through another instance after this one was connected, e.g. while SQLite keeps
+    /// the virtual table connected.
+    ///
+    /// # Returns
+    /// `Ok(())` if the table is not sealed, a `TableError::Sealed` otherwise.
+    pub fn check_unsealed(&self) -> sqlite3_ext::Result<()> {
+        RootTable::connect(self.connection, &self.base_name)?.check_unsealed()
+    }
+
+    /// Provides the file backing the main database of the table's connection.
+    ///
+    /// # Returns
+    /// The path of the database file, empty for in-memory and temporary databases.
+    pub(crate) fn database_file(&self) -> &str {
+        &self.database_file
     }
 
     /// Provides a reference to the lookup table associated with the virtual table.
@@ -223,36 +1908,514 @@ impl<'vtab> VirtualTable<'vtab> {
         &self.lookup_table
     }
 
+    /// Loads the partitions within a range of partition values into the in-memory lookup map
+    /// ahead of time, so that the first query over the range does not have to sync the map.
+    ///
+    /// # Parameters
+    /// * `from` - The lower bound of the partition values to load.
+    /// * `to` - The upper bound of the partition values to load.
+    ///
+    /// # Returns
+    /// The number of partitions in the range, all of which are in the map afterwards.
+    pub fn prewarm(&self, from: Bound<i64>, to: Bound<i64>) -> sqlite3_ext::Result<usize> {
+        self.lookup_table.refresh(self.connection)?;
+        self.lookup_table.sync_range(self.connection, &from, &to)
+    }
+
+    /// Retrieves the first and last partition of the table, e.g. to show the time range covered
+    /// by the data. The lookup table is queried directly, so the result does not depend on the
+    /// in-memory lookup map being synced.
+    ///
+    /// # Returns
+    /// The partition values (bucket starts) of the earliest and latest partition, or `None` if the
+    /// table has no partitions.
+    pub fn partition_bounds(&self) -> sqlite3_ext::Result<Option<(i64, i64)>> {
+        self.lookup_table.bounds(self.connection)
+    }
+
+    /// Retrieves a copy of the in-memory lookup map of the table, without syncing it first.
+    ///
+    /// # Returns
+    /// The partition values and partition keys of the cached partitions mapped to their partition
+    /// table names.
+    pub fn partition_map_snapshot(&self) -> sqlite3_ext::Result<BTreeMap<(i64, i64), String>> {
+        self.lookup_table.snapshot()
+    }
+
+    /// Lists every partition with its number of rows, without scanning the partitions.
+    ///
+    /// The counts are kept in the lookup table and include the changes not yet written to it
+    /// by this `VirtualTable`. A partition whose count is not known, because the table was created
+    /// before row counts were kept or rows were inserted with `INSERT OR REPLACE`, is counted
+    /// once and its count is kept from then on, if the lookup table has room for it.
+    ///
+    /// # Returns
+    /// The partition value, the name and the number of rows of each partition, ordered by
+    /// partition value.
+    pub fn partition_stats(&self) -> sqlite3_ext::Result<Vec<(i64, String, i64)>> {
+        let mut changes = self.row_count_changes.borrow_mut();
+        let mut stats = Vec::new();
+        for (partition_value, partition, rows) in
+            self.lookup_table.partition_row_counts(self.connection)?
+        {
+            let rows = match (rows, changes.get(&partition)) {
+                (Some(rows), None) => rows,
+                (Some(rows), Some(Some(change))) => rows + change,
+                _ => {
+                    let rows = self.connection.query_row(
+                        &format!("SELECT count(*) FROM {}", quote_identifier(&partition)),
+                        (),
+                        |row| Ok(row[0].get_i64()),
+                    )?;
+                    self.lookup_table
+                        .set_row_count(self.connection, &partition, rows)?;
+                    changes.remove(&partition);
+                    rows
+                }
+            };
+            stats.push((partition_value, partition, rows));
+        }
+        Ok(stats)
+    }
+
+    /// Notes that a partition gained or lost rows, to be written to the lookup table by
+    /// [`VirtualTable::flush_row_counts`].
+    ///
+    /// # Parameters
+    /// * `partition` - The name of the partition table.
+    /// * `change` - The number of rows gained, negative if rows were lost, or `None` if it is
+    ///   not known.
+    pub(crate) fn record_row_count_change(&self, partition: &str, change: Option<i64>) {
+        if !self.lookup_table.has_row_counts() {
+            return;
+        }
+        let mut changes = self.row_count_changes.borrow_mut();
+        let total = changes.entry(partition.to_owned()).or_insert(Some(0));
+        *total = total.zip(change).map(|(total, change)| total + change);
+    }
+
+    /// Tells whether row count changes were noted since the last flush, i.e. whether
+    /// [`VirtualTable::flush_row_counts`] writes anything.
+    pub(crate) fn has_row_count_changes(&self) -> bool {
+        !self.row_count_changes.borrow().is_empty()
+    }
+
+    /// Writes the row count changes noted since the last flush to the lookup table. Called
+    /// whenever the pending writes of the virtual table are flushed, so the counts are
+    /// committed or rolled back together with the rows.
+    pub(crate) fn flush_row_counts(&self) -> sqlite3_ext::Result<()> {
+        let changes = std::mem::take(&mut *self.row_count_changes.borrow_mut());
+        self.lookup_table.add_row_counts(self.connection, &changes)
+    }
+
+    /// Forgets the row count changes noted since the last flush, when the rows they count are
+    /// rolled back.
+    pub(crate) fn discard_row_counts(&self) {
+        self.row_count_changes.borrow_mut().clear();
+    }
+
+    /// Counts the rows of every bucket of the partition interval within a time range, including
+    /// buckets without a partition, e.g. to chart a continuous time axis.
+    ///
+    /// # Parameters
+    /// * `from` - The start of the range as a UNIX epoch. The bucket containing it is the first
+    ///   one returned.
+    /// * `to` - The end of the range as a UNIX epoch, exclusive.
+    ///
+    /// # Returns
+    /// The start of each bucket in ascending order paired with its number of rows, `0` for
+    /// buckets that have no partition. Empty if `to` is not after `from`. Fails for a hash
+    /// partitioned table.
+    pub fn counts_by_bucket(&self, from: i64, to: i64) -> sqlite3_ext::Result<Vec<(i64, i64)>> {
+        self.require_time_partitioning("Counting rows by time bucket")?;
+        let interval = self.partition_interval();
+        let first = self.round().bucket(from, interval);
+        let partitions = self.lookup_table.get_partitions_by_range(
+            self.connection,
+            &Bound::Included(first),
+            &Bound::Excluded(to),
+        )?;
+        let mut bucket_counts = BTreeMap::new();
+        for (partition_value, partition) in partitions {
+            if self.lookup_table.covered_through(partition_value) == partition_value {
+                // A partition split from another one counts towards the bucket of that one.
+                let bucket = self
+                    .lookup_table
+                    .split_from(partition_value)
+                    .map_or(partition_value, |(split_from, _)| split_from);
+                let count = self.connection.query_row(
+                    &format!("SELECT count(*) FROM {}", quote_identifier(&partition)),
+                    (),
+                    |row| Ok(row.index(0).get_i64()),
+                )?;
+                *bucket_counts.entry(bucket).or_insert(0) += count;
+                continue;
+            }
+            // The rows of a merged partition are bucketed one by one.
+            let mut stmt = self.connection.prepare(&format!(
+                "SELECT {} FROM {}",
+                quote_identifier(self.partition_column_name()),
+                quote_identifier(&partition)
+            ))?;
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                let epoch = self.parse_partition_epoch(row[0].as_ref())?;
+                *bucket_counts
+                    .entry(self.round().bucket(epoch, interval))
+                    .or_insert(0) += 1;
+            }
+        }
+        let mut counts = Vec::new();
+        let mut bucket = first;
+        while bucket < to {
+            counts.push((
+                bucket,
+                bucket_counts.get(&bucket).copied().unwrap_or_default(),
+            ));
+            bucket += interval;
+        }
+        Ok(counts)
+    }
+
+    /// The suffix of the table holding rows that arrived for partitions that had already expired.
+    pub const QUARANTINE_SUFFIX: &'static str = "quarantine";
+
+    /// Retrieves the name of the quarantine table, which holds rows that arrived for partitions
+    /// that had already expired, see [`ExpiredInsertPolicy::Quarantine`]. The table is created
+    /// with the first such row and is not part of the partitioned table.
+    pub fn quarantine_name(&self) -> String {
+        self.format_new_table_name(Self::QUARANTINE_SUFFIX)
+    }
+
+    /// The suffix of the table holding rows without a usable partition column value.
+    pub const DEFAULT_SUFFIX: &'static str = "default";
+
+    /// Lists the names of the tables and views a virtual table named `base_name` creates besides
+    /// its partitions: the root, lookup and template tables, the config and partitions views,
+    /// and the quarantine table and default partition, which are created once needed.
+    ///
+    /// # Parameters
+    /// * `base_name` - The name of the virtual table.
+    ///
+    /// # Returns
+    /// The derived names, prefixed with `base_name`.
+    pub fn derived_names(base_name: &str) -> Vec<String> {
+        vec![
+            RootTable::format_name(base_name),
+            LookupTable::format_name(base_name),
+            TemplateTable::format_name(base_name),
+            ConfigView::format_name(base_name),
+            PartitionsView::format_name(base_name),
+            format!("{}_{}", base_name, Self::QUARANTINE_SUFFIX),
+            format!("{}_{}", base_name, Self::DEFAULT_SUFFIX),
+        ]
+    }
+
+    /// Retrieves the name of the default partition, which holds rows whose partition value
+    /// cannot be computed, e.g. because the partition column is NULL, if the table was created
+    /// with `default_partition`. Like the quarantine table it is created with the first such row,
+    /// but unlike it the default partition is read by scans of the table.
+    pub fn default_partition_name(&self) -> String {
+        self.format_new_table_name(Self::DEFAULT_SUFFIX)
+    }
+
+    /// Retrieves the name of the default partition once the first row written to it created it,
+    /// see [`VirtualTable::default_partition_name`].
+    ///
+    /// # Returns
+    /// The name of the default partition, or `None` if the table was created without one or no
+    /// row was written to it yet.
+    pub fn existing_default_partition(&self) -> sqlite3_ext::Result<Option<String>> {
+        if !self.has_default_partition() {
+            return Ok(None);
+        }
+        let name = self.default_partition_name();
+        Ok(self.table_exists(&name)?.then_some(name))
+    }
+
+    /// Retrieves whether rows without a usable partition column value go to the default
+    /// partition rather than failing the insert.
+    pub fn has_default_partition(&self) -> bool {
+        self.root_table.default_partition()
+    }
+
+    /// Checks written values against the declared types of their columns if the table was
+    /// created `strict`, see [`check_strict_types`]. The partition column is checked on every
+    /// write anyway and the bucket column is filled in by the module, so neither is checked here.
+    ///
+    /// # Parameters
+    /// * `values` - The values, in the order the columns were declared.
+    ///
+    /// # Returns
+    /// `Ok(())` if the table is lax or every value matches, a column type mismatch otherwise.
+    pub fn check_value_types(&self, values: &[&ValueRef]) -> sqlite3_ext::Result<()> {
+        if !self.root_table.is_strict() {
+            return Ok(());
+        }
+        let exempt = [Some(self.partition_column_name()), self.bucket_column()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<&str>>();
+        check_strict_types(values, &self.columns().0, &exempt)
+    }
+
+    /// Lists the tables that are not partitions but share the columns of the template, i.e. the
+    /// quarantine table and the default partition, as far as they exist.
+    fn side_tables(&self) -> sqlite3_ext::Result<Vec<String>> {
+        let mut tables = Vec::new();
+        for table in [self.quarantine_name(), self.default_partition_name()] {
+            if self.table_exists(&table)? {
+                tables.push(table);
+            }
+        }
+        Ok(tables)
+    }
+
+    /// Brings the recorded expiration of every partition in line with the current lifetime.
+    ///
+    /// Partitions record when they expire as they are created, so after the lifetime of the
+    /// table changed the existing partitions keep expiring under the old one until this is
+    /// called. Without a lifetime the recorded expirations are cleared.
+    ///
+    /// # Returns
+    /// The number of partitions whose expiration changed.
+    pub fn recompute_expirations(&self) -> sqlite3_ext::Result<usize> {
+        self.lookup_table
+            .set_expirations(self.connection, self.lifetime())
+    }
+
+    /// Computes when a partition expires, if it already has.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The value of the partition.
+    ///
+    /// # Returns
+    /// The UNIX epoch the partition expired at, or `None` if the table has no lifetime or the
+    /// partition has not expired yet.
+    pub fn expired_at(&self, partition_value: i64) -> Option<i64> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() as i64)
+            .unwrap_or_default();
+        self.expires_at(partition_value)
+            .filter(|expires_at| *expires_at <= now)
+    }
+
+    /// Computes when a partition expires.
+    ///
+    /// # Parameters
+    /// * `partition_value` - The value of the partition.
+    ///
+    /// # Returns
+    /// The UNIX epoch the partition expires at, or `None` if the table has no lifetime.
+    pub fn expires_at(&self, partition_value: i64) -> Option<i64> {
+        self.lifetime()
+            .map(|lifetime| partition_value.saturating_add(lifetime))
+    }
+
     /// Inserts a new row into the appropriate partition based on the specified partition value.
     ///
+    /// A row for a partition that has already expired is handled according to the table's
+    /// [`ExpiredInsertPolicy`]: it is either rejected or written to the quarantine table. The
+    /// bucket column, if any, is set to the partition value whatever value was given for it.
+    ///
+    /// With `ConflictMode::Ignore` or `ConflictMode::Replace` the row is written with
+    /// `INSERT OR IGNORE` or `INSERT OR REPLACE`, so a row violating a unique index of its
+    /// partition is skipped or replaces the existing row. Every other mode is a plain `INSERT`,
+    /// whose constraint error SQLite resolves for the statement.
+    ///
+    /// With a composite partition key the row goes to the partition of its partition key, see
+    /// [`VirtualTable::partition_key`].
+    ///
     /// # Parameters
     /// * `partition_value` - The value determining which partition the new row belongs to.
     /// * `columns` - An array of references to `ValueRef`, representing the values to be inserted.
+    /// * `on_conflict` - The ON CONFLICT mode of the inserting statement.
+    ///
+    /// # Returns
+    /// The ROWID of the inserted row. Returns a `TableError::ColumnCountMismatch` if the number of
+    /// values differs from the number of declared columns, and a `TableError::ExpiredPartition`
+    /// if the partition has expired and such rows are rejected.
+    pub fn insert(
+        &self,
+        partition_value: i64,
+        columns: &[&ValueRef],
+        on_conflict: ConflictMode,
+    ) -> sqlite3_ext::Result<i64> {
+        let partition_key = self.partition_key(columns)?;
+        self.insert_with_key(partition_value, partition_key, columns, on_conflict)
+    }
+
+    /// Inserts a new row into the partition of a partition value and a partition key computed
+    /// beforehand, see [`VirtualTable::insert`].
+    ///
+    /// # Parameters
+    /// * `partition_value` - The value determining which partition the new row belongs to.
+    /// * `partition_key` - The partition key of the row, see [`VirtualTable::partition_key`].
+    /// * `columns` - An array of references to `ValueRef`, representing the values to be inserted.
+    /// * `on_conflict` - The ON CONFLICT mode of the inserting statement.
+    ///
+    /// # Returns
+    /// The ROWID of the inserted row, or the errors of [`VirtualTable::insert`].
+    pub fn insert_with_key(
+        &self,
+        partition_value: i64,
+        partition_key: i64,
+        columns: &[&ValueRef],
+        on_conflict: ConflictMode,
+    ) -> sqlite3_ext::Result<i64> {
+        self.check_column_count(columns)?;
+        // Rows of a split partition go to the partitions of the finer interval, the bucket
+        // column keeps the partition value of the table's interval.
+        let partition_column_value = columns[self.partition_column_index()?];
+        let target_value = match partition_key {
+            0 => self.split_partition_value(partition_value, partition_column_value)?,
+            _ => partition_value,
+        };
+        let partition = match self.expired_at(target_value) {
+            Some(expires_at) => match self.root_table.on_expired_insert() {
+                ExpiredInsertPolicy::Reject => {
+                    return Err(TableError::ExpiredPartition {
+                        partition_value: target_value,
+                        expires_at,
+                    }
+                    .into())
+                }
+                ExpiredInsertPolicy::Quarantine => self.copy(&self.quarantine_name())?,
+            },
+            None => {
+                self.get_value_partition(target_value, partition_key, partition_column_value)?
+            }
+        };
+        self.write_row(&partition, Some(partition_value), columns, on_conflict)
+    }
+
+    /// Inserts a row into the default partition, creating it if needed. The bucket column, if
+    /// any, is set to NULL.
+    ///
+    /// # Parameters
+    /// * `columns` - An array of references to `ValueRef`, representing the values to be inserted.
+    /// * `on_conflict` - The ON CONFLICT mode of the inserting statement.
+    ///
+    /// # Returns
+    /// The ROWID of the inserted row. Returns a `TableError::ColumnCountMismatch` if the number of
+    /// values differs from the number of declared columns.
+    pub fn insert_default(
+        &self,
+        columns: &[&ValueRef],
+        on_conflict: ConflictMode,
+    ) -> sqlite3_ext::Result<i64> {
+        self.check_column_count(columns)?;
+        let partition = self.copy(&self.default_partition_name())?;
+        self.write_row(&partition, None, columns, on_conflict)
+    }
+
+    /// Fails with a `TableError::ColumnCountMismatch` unless there is one value per declared
+    /// column.
+    fn check_column_count(&self, columns: &[&ValueRef]) -> sqlite3_ext::Result<()> {
+        let expected = self.columns().0.len();
+        if columns.len() != expected {
+            return Err(TableError::ColumnCountMismatch {
+                expected,
+                found: columns.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Writes a row into a table holding the columns of the template. See
+    /// [`VirtualTable::insert`].
+    ///
+    /// # Parameters
+    /// * `partition` - The name of the table.
+    /// * `bucket` - The value of the bucket column, if the table has one.
+    /// * `columns` - The values of the declared columns.
+    /// * `on_conflict` - The ON CONFLICT mode of the inserting statement.
     ///
     /// # Returns
     /// The ROWID of the inserted row.
-    pub fn insert(&self, partition_value: i64, columns: &[&ValueRef]) -> sqlite3_ext::Result<i64> {
-        let partition = self.get_partition(&partition_value)?;
-        let placeholders = std::iter::repeat("?")
-            .take(columns.len())
+    fn write_row(
+        &self,
+        partition: &str,
+        bucket: Option<i64>,
+        columns: &[&ValueRef],
+        on_conflict: ConflictMode,
+    ) -> sqlite3_ext::Result<i64> {
+        let placeholders = std::iter::repeat_n("?", columns.len())
             .collect::<Vec<_>>()
             .join(",");
-        let sql = format!("INSERT INTO {} VALUES({})", partition, placeholders);
-        let mut stmt = self.connection.prepare(&sql)?;
-        for (index, column) in columns.iter().enumerate() {
-            column.bind_param(&mut stmt, (index + 1) as i32)?
-        }
-        stmt.insert(())
+        // Naming the columns keeps the insert working for partitions that have gained columns
+        // since this `VirtualTable` was connected, see `add_column`.
+        let column_names = self
+            .columns()
+            .0
+            .iter()
+            .map(|column| quote_identifier(column.get_name()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let insert = match on_conflict {
+            ConflictMode::Ignore => "INSERT OR IGNORE",
+            ConflictMode::Replace => "INSERT OR REPLACE",
+            ConflictMode::Rollback | ConflictMode::Fail | ConflictMode::Abort => "INSERT",
+        };
+        let sql = format!(
+            "{} INTO {} ({}) VALUES({})",
+            insert,
+            quote_identifier(partition),
+            column_names,
+            placeholders
+        );
+        let mut stmt = self.insert_statements.take(self.connection, &sql)?;
+        let bucket_column_index = self.bucket_column().and_then(|bucket_column| {
+            self.columns()
+                .0
+                .iter()
+                .position(|column| column.get_name() == bucket_column)
+        });
+        // Binding inside `insert` lets it reset the statement from its previous use first.
+        let rowid = stmt.insert(|stmt: &mut Statement| {
+            for (index, column) in columns.iter().enumerate() {
+                if Some(index) == bucket_column_index {
+                    bucket.bind_param(stmt, (index + 1) as i32)?
+                } else {
+                    column.bind_param(stmt, (index + 1) as i32)?
+                }
+            }
+            Ok(())
+        });
+        self.insert_statements
+            .put_back(sql, stmt, self.max_statements());
+        let rowid = rowid?;
+        let inserted = match on_conflict {
+            // Only the inserted row is counted, not the rows it replaced.
+            ConflictMode::Replace => None,
+            ConflictMode::Ignore => {
+                let mut stmt = self
+                    .insert_statements
+                    .take(self.connection, "SELECT changes()")?;
+                let changes = stmt.query_row((), |row| Ok(row[0].get_i64()));
+                self.insert_statements.put_back(
+                    "SELECT changes()".to_owned(),
+                    stmt,
+                    self.max_statements(),
+                );
+                Some(changes?)
+            }
+            ConflictMode::Rollback | ConflictMode::Fail | ConflictMode::Abort => Some(1),
+        };
+        self.record_row_count_change(partition, inserted);
+        Ok(rowid)
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::ops::{Deref, Index, IndexMut};
-
     use crate::{utils::parse_interval, PartitionColumn};
 
+    use std::time::Instant;
+
     use super::*;
     use rusqlite::Connection as RusqConn;
     use sqlite3_ext::Connection;
@@ -276,11 +2439,10 @@ mod tests {
             columns,
             partition_column_name.to_string(),
             interval,
-            None,
+            TableOptions::default(),
         );
         assert!(table.is_ok());
-        let table = table.unwrap();
-        table
+        table.unwrap()
     }
     #[test]
     fn test_create_virtual_table() {
@@ -290,13 +2452,550 @@ mod tests {
         };
         let conn = Connection::from_rusqlite(&conn);
 
-        let virtual_table = create_virtual_table(&conn);
-        let lookup_schema = virtual_table.lookup().schema();
-        let root_schema = virtual_table.root_table.schema();
-        let template_schema = virtual_table.template_table.schema();
+        let virtual_table = create_virtual_table(conn);
         assert_eq!(
             virtual_table.create_table_query().to_lowercase(),
-            "create table test (first_column text, second_column integer, third_column text)"
+            "create table \"test\" (\"first_column\" text not null, \"second_column\" integer, \"third_column\" text)"
         )
     }
+
+    #[test]
+    fn test_template_sql() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let stored_sql = conn.query_row(
+            "SELECT sql FROM sqlite_schema WHERE name = 'test_template'",
+            (),
+            |row| Ok(row[0].get_str()?.to_owned()),
+        )?;
+        assert_eq!(virtual_table.template_sql()?, stored_sql);
+        assert_eq!(
+            virtual_table.template_sql()?,
+            "CREATE TABLE \"test_template\" (\"first_column\" TEXT, \"second_column\" INTEGER, \"third_column\" TEXT)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_interval_label() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        assert_eq!(virtual_table.interval_label(), "1 hour");
+        virtual_table.reinterval(90000)?;
+        assert_eq!(virtual_table.interval_label(), "25 hours");
+        Ok(())
+    }
+
+    #[test]
+    fn test_prewarm() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let partition_values = [1706745600, 1706749200, 1706752800, 1706756400];
+        for partition_value in &partition_values {
+            virtual_table.get_partition(partition_value)?;
+        }
+        // Start from an empty map, as if nothing had been loaded yet.
+        virtual_table.lookup().partitions.write().unwrap().clear();
+
+        let loaded = virtual_table.prewarm(
+            Bound::Included(partition_values[1]),
+            Bound::Excluded(partition_values[3]),
+        )?;
+        assert_eq!(loaded, 2);
+        let partitions = virtual_table.partition_map_snapshot()?;
+        assert_eq!(
+            partitions
+                .into_iter()
+                .collect::<Vec<((i64, i64), String)>>(),
+            vec![
+                ((1706749200, 0), "test_1706749200".to_owned()),
+                ((1706752800, 0), "test_1706752800".to_owned())
+            ]
+        );
+
+        let loaded = virtual_table.prewarm(Bound::Unbounded, Bound::Unbounded)?;
+        assert_eq!(loaded, 4);
+        assert_eq!(virtual_table.partition_map_snapshot()?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_rejects_inconsistent_partition_column() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        create_virtual_table(conn);
+        assert!(VirtualTable::connect(conn, "test").is_ok());
+
+        conn.execute(
+            "UPDATE test_root SET partition_column = 'missing_column'",
+            (),
+        )?;
+        let err = VirtualTable::connect(conn, "test").unwrap_err();
+        assert!(err.to_string().contains(
+            "The partition column 'missing_column' named in test_root is not a column of test_template"
+        ));
+
+        conn.execute(
+            "ALTER TABLE test_template ADD COLUMN fourth_column BLOB",
+            (),
+        )?;
+        conn.execute(
+            "UPDATE test_root SET partition_column = 'fourth_column'",
+            (),
+        )?;
+        let err = VirtualTable::connect(conn, "test").unwrap_err();
+        assert!(err.to_string().contains(
+            "The partition column 'fourth_column' of test_template has type BLOB, which cannot be partitioned on"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_rejects_invalid_column_name() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        create_virtual_table(conn);
+        // A template edited by hand, with a quoted name that generated SQL cannot carry.
+        conn.execute("DROP TABLE test_template", ())?;
+        conn.execute(
+            "CREATE TABLE test_template (first_column TEXT, \"second column\" INTEGER)",
+            (),
+        )?;
+        let err = VirtualTable::connect(conn, "test").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Invalid column name \"\\\"second column\\\"\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_bounds() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        assert_eq!(virtual_table.partition_bounds()?, None);
+
+        for partition_value in [1706752800, 1706745600, 1706767200, 1706756400] {
+            virtual_table.get_partition(&partition_value)?;
+        }
+        // Bounds are read from the lookup table, not from the in-memory map.
+        virtual_table.lookup().partitions.write().unwrap().clear();
+        assert_eq!(
+            virtual_table.partition_bounds()?,
+            Some((1706745600, 1706767200))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_map_snapshot() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        assert!(virtual_table.partition_map_snapshot()?.is_empty());
+
+        for partition_value in [1706752800, 1706745600, 1706752800] {
+            virtual_table.get_partition(&partition_value)?;
+        }
+        let snapshot = virtual_table.partition_map_snapshot()?;
+        assert_eq!(
+            snapshot.into_iter().collect::<Vec<((i64, i64), String)>>(),
+            vec![
+                ((1706745600, 0), "test_1706745600".to_owned()),
+                ((1706752800, 0), "test_1706752800".to_owned())
+            ]
+        );
+
+        // The snapshot is a copy, later changes to the map do not show up in it.
+        let snapshot = virtual_table.partition_map_snapshot()?;
+        virtual_table.lookup().partitions.write().unwrap().clear();
+        assert_eq!(snapshot.len(), 2);
+        assert!(virtual_table.partition_map_snapshot()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reclaimable_by_expiration() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let (name, columns, _, interval) = mock_template();
+        let virtual_table = VirtualTable::create(
+            conn,
+            &name,
+            columns,
+            "first_column".to_owned(),
+            interval,
+            TableOptions {
+                lifetime: Some(86400),
+                ..TableOptions::default()
+            },
+        )?;
+        let hour = 3600;
+        let first = 1706745600;
+        for partition_value in [first, first + hour, first + 5 * hour] {
+            let partition = virtual_table.get_partition(&partition_value)?;
+            for row in 0..50 {
+                conn.execute(
+                    &format!(
+                        "INSERT INTO {} VALUES ({}, {}, '{}')",
+                        partition,
+                        partition_value,
+                        row,
+                        "x".repeat(100)
+                    ),
+                    (),
+                )?;
+            }
+        }
+        assert_eq!(virtual_table.reclaimable_by_expiration(first)?, (0, 0));
+
+        // One day after the second partition started, the first two have expired.
+        let (partitions, bytes) = virtual_table.reclaimable_by_expiration(first + hour + 86400)?;
+        assert_eq!(partitions, 2);
+        assert!(bytes >= 2 * 50 * 100, "{}", bytes);
+        let (partitions, all_bytes) = virtual_table.reclaimable_by_expiration(i64::MAX)?;
+        assert_eq!(partitions, 3);
+        assert!(all_bytes > bytes);
+
+        // Nothing was dropped.
+        assert_eq!(virtual_table.partition_map_snapshot()?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_counts_by_bucket() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let mut stmt = conn.prepare(
+            "SELECT '2024-02-01 00:10', 1, 'a' UNION ALL SELECT '2024-02-01 00:20', 2, 'b' \
+             UNION ALL SELECT '2024-02-01 03:00', 3, 'c'",
+        )?;
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            let values = [row[0].as_ref(), row[1].as_ref(), row[2].as_ref()];
+            let partition_value = virtual_table.partition_value(values[0])?;
+            virtual_table.insert(partition_value, &values, ConflictMode::Abort)?;
+        }
+
+        // The range starts inside the first bucket and leaves out the bucket starting at `to`.
+        let counts = virtual_table.counts_by_bucket(1706745600 + 600, 1706745600 + 4 * 3600)?;
+        assert_eq!(
+            counts,
+            vec![
+                (1706745600, 2),
+                (1706749200, 0),
+                (1706752800, 0),
+                (1706756400, 1)
+            ]
+        );
+        assert_eq!(
+            virtual_table.counts_by_bucket(1706760000, 1706767200)?,
+            vec![(1706760000, 0), (1706763600, 0)]
+        );
+        assert!(virtual_table
+            .counts_by_bucket(1706745600, 1706745600)?
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_for_value() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let mut stmt = conn.prepare("SELECT '2024-02-01 10:15', 42, 'a'")?;
+        stmt.query(())?;
+        let row = stmt.next()?.unwrap();
+        let values = [row[0].as_ref(), row[1].as_ref(), row[2].as_ref()];
+
+        let (partition_value, name) = virtual_table.partition_for_value(values[0])?;
+        assert_eq!(
+            (partition_value, name.as_str()),
+            (1706781600, "test_1706781600")
+        );
+        // Nothing is created by asking.
+        assert!(virtual_table
+            .lookup()
+            .get_partition(&partition_value)?
+            .is_none());
+        assert!(!virtual_table.table_exists(&name)?);
+
+        virtual_table.insert(partition_value, &values, ConflictMode::Abort)?;
+        assert_eq!(
+            virtual_table.lookup().get_partition(&partition_value)?,
+            Some(name.clone())
+        );
+        let row_count = conn.query_row(
+            &format!("SELECT count(*) FROM {}", quote_identifier(&name)),
+            (),
+            |row| Ok(row[0].get_i64()),
+        )?;
+        assert_eq!(row_count, 1);
+        assert_eq!(
+            virtual_table.partition_for_value(values[0])?,
+            (partition_value, name)
+        );
+        Ok(())
+    }
+
+    /// Inserts `rows` rows spread over five hourly partitions, starting at 2024-02-01 00:00.
+    /// With `cached` unset the statement cache is emptied before every row, as if each insert
+    /// prepared its own statement.
+    fn insert_rows(
+        virtual_table: &VirtualTable,
+        rows: i64,
+        cached: bool,
+    ) -> sqlite3_ext::Result<()> {
+        let conn = virtual_table.connection;
+        conn.execute("BEGIN", ())?;
+        for row in 0..rows {
+            let mut stmt =
+                conn.prepare("SELECT datetime(1706745600 + ? * 18000 / ?, 'unixepoch'), ?, 'abc'")?;
+            stmt.query([row, rows, row])?;
+            let row = stmt.next()?.unwrap();
+            let values = [row[0].as_ref(), row[1].as_ref(), row[2].as_ref()];
+            if !cached {
+                virtual_table.insert_statements.0.borrow_mut().clear();
+            }
+            let partition_value = virtual_table.partition_value(values[0])?;
+            virtual_table.insert(partition_value, &values, ConflictMode::Abort)?;
+        }
+        conn.execute("COMMIT", ())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_reuses_statements() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        insert_rows(&virtual_table, 2000, true)?;
+        // One statement per partition, shared by all rows of the partition.
+        assert_eq!(virtual_table.insert_statements.len(), 5);
+        assert_eq!(
+            virtual_table.scan(Bound::Unbounded, Bound::Unbounded, |row| Ok(
+                row[1].get_i64()
+            ))?,
+            (0..2000).collect::<Vec<i64>>()
+        );
+
+        // A cached statement keeps working after the partition is dropped and created again.
+        virtual_table.drop_partitions_before(1706749200)?;
+        insert_rows(&virtual_table, 5, true)?;
+        assert_eq!(
+            conn.query_row("SELECT count(*) FROM test_1706745600", (), |row| Ok(
+                row[0].get_i64()
+            ))?,
+            1
+        );
+        Ok(())
+    }
+
+    /// Shows inserts through the statement cache taking less time than preparing a statement per
+    /// row. Run with `cargo test --release bench_insert_statements -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_insert_statements() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let started = Instant::now();
+        insert_rows(&virtual_table, 20000, false)?;
+        let uncached_time = started.elapsed();
+        let started = Instant::now();
+        insert_rows(&virtual_table, 20000, true)?;
+        let cached_time = started.elapsed();
+        assert!(
+            cached_time < uncached_time,
+            "20000 rows took {:?} cached, {:?} prepared per row",
+            cached_time,
+            uncached_time
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_index_on_all_partitions() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let indexes = || -> sqlite3_ext::Result<Vec<(String, String)>> {
+            let mut stmt = conn.prepare(
+                "SELECT tbl_name, name FROM sqlite_schema \
+                 WHERE type = 'index' AND (name LIKE 'test_second%' OR name LIKE 'test_third%') \
+                 ORDER BY tbl_name",
+            )?;
+            let mut indexes = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                indexes.push((row[0].get_str()?.to_owned(), row[1].get_str()?.to_owned()));
+            }
+            Ok(indexes)
+        };
+        insert_rows(&virtual_table, 2, true)?;
+        assert!(indexes()?.is_empty());
+
+        let indexed = virtual_table
+            .create_index_on_all_partitions("CREATE INDEX test_second ON test(second_column)")?;
+        assert_eq!(indexed, vec!["test_1706745600", "test_1706752800"]);
+        // Partitions created afterwards are copied from the template as usual.
+        let mut stmt = conn.prepare("SELECT '2024-02-02 00:00', 1, 'a'")?;
+        stmt.query(())?;
+        let row = stmt.next()?.unwrap();
+        let values = [row[0].as_ref(), row[1].as_ref(), row[2].as_ref()];
+        virtual_table.insert(
+            virtual_table.partition_value(values[0])?,
+            &values,
+            ConflictMode::Abort,
+        )?;
+        assert_eq!(
+            indexes()?,
+            vec![
+                (
+                    "test_1706745600".to_owned(),
+                    "test_second_test_1706745600".to_owned()
+                ),
+                (
+                    "test_1706752800".to_owned(),
+                    "test_second_test_1706752800".to_owned()
+                ),
+                (
+                    "test_1706832000".to_owned(),
+                    "test_second_test_1706832000".to_owned()
+                ),
+                ("test_template".to_owned(), "test_second".to_owned()),
+            ]
+        );
+
+        assert!(virtual_table
+            .create_index_on_all_partitions("CREATE INDEX other_first ON other(first_column)")
+            .is_err());
+        assert!(virtual_table
+            .create_index_on_all_partitions("DROP INDEX test_second")
+            .is_err());
+        // Two rows share the third column, so the unique index fails and none is created.
+        insert_rows(&virtual_table, 2, true)?;
+        assert!(virtual_table
+            .create_index_on_all_partitions(
+                "CREATE UNIQUE INDEX test_third ON test_template(third_column)"
+            )
+            .is_err());
+        assert_eq!(indexes()?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recompute_expirations() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        insert_rows(&virtual_table, 2, true)?;
+        let expirations = || -> sqlite3_ext::Result<Vec<Option<i64>>> {
+            let mut stmt =
+                conn.prepare("SELECT expires_at FROM test_lookup ORDER BY partition_value")?;
+            let mut expirations = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                expirations.push((!row[0].is_null()).then(|| row[0].get_i64()));
+            }
+            Ok(expirations)
+        };
+        assert_eq!(expirations()?, vec![None, None]);
+        assert_eq!(virtual_table.recompute_expirations()?, 0);
+
+        conn.execute("UPDATE test_root SET lifetime = 86400", ())?;
+        let virtual_table = VirtualTable::connect(conn, "test")?;
+        assert_eq!(virtual_table.recompute_expirations()?, 2);
+        assert_eq!(
+            expirations()?,
+            vec![Some(1706745600 + 86400), Some(1706752800 + 86400)]
+        );
+        // Nothing changes when the expirations are up to date.
+        assert_eq!(virtual_table.recompute_expirations()?, 0);
+
+        conn.execute("UPDATE test_root SET lifetime = NULL", ())?;
+        let virtual_table = VirtualTable::connect(conn, "test")?;
+        assert_eq!(virtual_table.recompute_expirations()?, 2);
+        assert_eq!(expirations()?, vec![None, None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_column_count_mismatch() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let mut stmt = conn.prepare("SELECT '2024-02-01 10:15', 42")?;
+        stmt.query(())?;
+        let row = stmt.next()?.unwrap();
+        let values = [row[0].as_ref(), row[1].as_ref()];
+        let err = virtual_table
+            .insert(1706781600, &values, ConflictMode::Abort)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Expected 3 column values, but found 2"));
+        // No partition is created for a rejected row.
+        assert!(virtual_table.lookup().get_partition(&1706781600)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_adopt_partition_created_elsewhere() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let stale_table = VirtualTable::connect(conn, "test")?;
+        let mut stmt = conn.prepare("SELECT '2024-02-01 10:15', 42, 'a'")?;
+        stmt.query(())?;
+        let row = stmt.next()?.unwrap();
+        let values = [row[0].as_ref(), row[1].as_ref(), row[2].as_ref()];
+
+        // The partition is created through one instance, the other one has not seen it yet.
+        virtual_table.insert(1706781600, &values, ConflictMode::Abort)?;
+        assert!(stale_table.lookup().get_partition(&1706781600)?.is_none());
+        stale_table.insert(1706781600, &values, ConflictMode::Abort)?;
+
+        // A table created outside of the module is adopted into the lookup table.
+        conn.execute(
+            "CREATE TABLE test_1706785200 AS SELECT * FROM test_template",
+            (),
+        )?;
+        assert_eq!(stale_table.get_partition(&1706785200)?, "test_1706785200");
+
+        let count = |sql: &str| conn.query_row(sql, (), |row| Ok(row.index(0).get_i64()));
+        assert_eq!(count("SELECT count(*) FROM test_1706781600")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_export_partition_json() -> sqlite3_ext::Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let virtual_table = create_virtual_table(conn);
+        let mut stmt = conn.prepare("SELECT '2024-02-01 10:15', 42, X'01FF'")?;
+        stmt.query(())?;
+        let row = stmt.next()?.unwrap();
+        let values = [row[0].as_ref(), row[1].as_ref(), row[2].as_ref()];
+        virtual_table.insert(1706781600, &values, ConflictMode::Abort)?;
+
+        let json = virtual_table.export_partition_json(1706781600)?;
+        let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            exported,
+            serde_json::json!([{
+                "first_column": {"Text": "2024-02-01 10:15"},
+                "second_column": {"Integer": 42},
+                "third_column": {"Blob": "Af8="}
+            }])
+        );
+        assert!(virtual_table.export_partition_json(0).is_err());
+        Ok(())
+    }
 }
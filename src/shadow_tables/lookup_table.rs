@@ -1,15 +1,32 @@
+use sqlite3_ext::ffi::{SQLITE_CONSTRAINT, SQLITE_SCHEMA};
 use sqlite3_ext::query::{Statement, ToParam};
-use sqlite3_ext::{Connection, Value, ValueRef, ValueType};
+use sqlite3_ext::{params, Connection, Value, ValueRef, ValueType};
 use sqlite3_ext::{FallibleIteratorMut, FromValue, Result as ExtResult};
 use std::collections::BTreeMap;
-use std::ops::Bound;
-use std::sync::RwLock;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use crate::utils::parse_to_unix_epoch;
+use crate::utils::validation::quote_identifier;
+use crate::BucketRounding;
 use crate::ColumnDeclaration;
 
 use super::operations::{Connect, Create, Drop, SchemaDeclaration, Table};
-use super::{PartitionType, PartitionValue};
+use super::{PartitionType, PartitionValue, RootTable};
+
+/// Identifies a partition in the partitions map by its partition value and partition key.
+type PartitionMapKey = (i64, i64);
+
+/// A change to the partitions map, kept while a transaction is tracked so that rolling it back,
+/// or rolling back to a savepoint, can undo the change, see [`LookupTable::undo_changes`].
+#[derive(Debug)]
+enum MapChange {
+    /// A partition was added to the map.
+    Added(PartitionMapKey),
+    /// A partition was taken out of the map, with the name it was mapped to.
+    Removed(PartitionMapKey, String),
+}
 
 /// This trait defines the necessary methods for creating the lookup table, generating SQL queries for
 /// creation and insertion, connecting to existing tables, and managing and accessing partition information
@@ -23,7 +40,6 @@ pub trait Lookup<T> {
     ///
     /// Returns:
     /// - A result containing the name of the partition table and a boolean indicating if it needs to be created.
-
     fn get_partition(&self, db: &Connection, value: T) -> ExtResult<(String, bool)>;
 
     /// Synchronizes the internal partitions map with the current state of the database.
@@ -84,6 +100,30 @@ impl PartitionType for LookupTable<i64> {
             std::borrow::Cow::Borrowed(Self::PARTITION_EXPIRATION_COLUMN),
             Self::PARTITION_EXPIRATION_COLUMN_TYPE,
         ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::ROW_COUNT_COLUMN),
+            Self::ROW_COUNT_COLUMN_TYPE,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::PARTITION_KEY_COLUMN),
+            Self::PARTITION_KEY_COLUMN_TYPE,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::MERGED_THROUGH_COLUMN),
+            Self::MERGED_THROUGH_COLUMN_TYPE,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::SPLIT_FROM_COLUMN),
+            Self::SPLIT_FROM_COLUMN_TYPE,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::SPLIT_INTERVAL_COLUMN),
+            Self::SPLIT_INTERVAL_COLUMN_TYPE,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::LIST_VALUE_COLUMN),
+            Self::LIST_VALUE_COLUMN_TYPE,
+        ),
     ];
 }
 impl Table for LookupTable<i64> {
@@ -95,11 +135,21 @@ impl Table for LookupTable<i64> {
 impl Create for LookupTable<i64> {
     fn table_query(schema: &SchemaDeclaration) -> Result<String, String> {
         Ok(format!(
-            "CREATE TABLE {} ({} UNIQUE, {} UNIQUE, {});",
-            schema.name(),
+            "CREATE TABLE {} ({} UNIQUE, {}, {}, {} DEFAULT 0, {} DEFAULT 0, {}, {}, {}, {}, UNIQUE ({}, {}), UNIQUE ({}, {}));",
+            quote_identifier(schema.name()),
             <Self as PartitionType>::COLUMNS[0],
             <Self as PartitionType>::COLUMNS[1],
-            <Self as PartitionType>::COLUMNS[2]
+            <Self as PartitionType>::COLUMNS[2],
+            <Self as PartitionType>::COLUMNS[3],
+            <Self as PartitionType>::COLUMNS[4],
+            <Self as PartitionType>::COLUMNS[5],
+            <Self as PartitionType>::COLUMNS[6],
+            <Self as PartitionType>::COLUMNS[7],
+            <Self as PartitionType>::COLUMNS[8],
+            quote_identifier(<Self as PartitionType>::COLUMNS[1].get_name()),
+            quote_identifier(<Self as PartitionType>::COLUMNS[4].get_name()),
+            quote_identifier(<Self as PartitionType>::COLUMNS[8].get_name()),
+            quote_identifier(<Self as PartitionType>::COLUMNS[4].get_name())
         ))
     }
 }
@@ -108,13 +158,118 @@ impl Connect for LookupTable<i64> {}
 #[derive(Debug)]
 pub struct LookupTable<T> {
     pub(super) schema: SchemaDeclaration,
-    pub partitions: RwLock<BTreeMap<T, String>>,
+    /// The partition tables by partition value and partition key. The key tells apart the
+    /// partitions of a composite partition key sharing a partition value, and is `0` otherwise.
+    pub partitions: RwLock<BTreeMap<(T, T), String>>,
+    /// The name of the root table keeping the generation of the partitions, see
+    /// [`LookupTable::refresh`].
+    root_name: String,
+    /// The generation of the partitions the partitions map was last reloaded at or kept up with
+    /// since, or `None` if the next refresh has to reload it.
+    generation_seen: Mutex<Option<i64>>,
+    /// Whether the root table is known to have the generation column.
+    generation_column: AtomicBool,
+    /// The largest partition value and rowid [`LookupTable::sync`] has read, or `None` if the
+    /// next sync has to read the whole lookup table.
+    synced_through: Mutex<Option<(i64, i64)>>,
+    /// Whether a range without cached partitions is synced even though the cache is known to be
+    /// complete for it, see [`LookupTable::set_sync_empty_ranges`].
+    sync_empty_ranges: AtomicBool,
+    /// Whether the lookup table keeps the number of rows of each partition. Lookup tables
+    /// created before row counts were kept lack the column.
+    row_counts: bool,
+    /// Whether the lookup table has the partition key column. Lookup tables created before
+    /// composite partition keys existed lack it, all their partitions have the key `0`.
+    partition_keys: bool,
+    /// Whether the lookup table has the column recording merged partitions. Lookup tables
+    /// created before partitions could be merged lack it, their partitions cannot be merged.
+    merges: bool,
+    /// The last partition value covered by each partition that other partitions were merged
+    /// into, by its partition value, see [`LookupTable::covered_through`].
+    merged: RwLock<BTreeMap<i64, i64>>,
+    /// Whether the lookup table has the columns recording split partitions. Lookup tables
+    /// created before partitions could be split lack them, their partitions cannot be split.
+    splits: bool,
+    /// The partition value a partition was split from and the interval it covers, by its
+    /// partition value, see [`LookupTable::split_from`].
+    split: RwLock<BTreeMap<i64, (i64, i64)>>,
+    /// Whether the lookup table has the column recording the value of each list partition.
+    /// Lookup tables created before the values were recorded lack it, their list partitions are
+    /// keyed on the hash of the value alone.
+    lists: bool,
+    /// The partition value of the partitions of each value of a list partitioned table, by the
+    /// value, see [`LookupTable::listed_partition_value`].
+    listed: RwLock<BTreeMap<String, i64>>,
+    /// The changes made to the partitions map since the transaction began, oldest first, or
+    /// `None` while no transaction is tracked.
+    changes: Mutex<Option<Vec<MapChange>>>,
 }
 impl LookupTable<i64> {
     const PARTITION_EXPIRATION_COLUMN: &'static str = "expires_at";
     const PARTITION_EXPIRATION_COLUMN_TYPE: ValueType = ValueType::Integer;
-    fn parse_partition_value(value: &ValueRef, interval: i64) -> sqlite3_ext::Result<i64> {
-        parse_to_unix_epoch(value).map(|epoch| epoch - epoch % interval)
+    const ROW_COUNT_COLUMN: &'static str = "row_count";
+    const ROW_COUNT_COLUMN_TYPE: ValueType = ValueType::Integer;
+    const PARTITION_KEY_COLUMN: &'static str = "partition_key";
+    const PARTITION_KEY_COLUMN_TYPE: ValueType = ValueType::Integer;
+    /// Holds the last partition value whose rows a partition holds after later partitions were
+    /// merged into it, NULL for a partition holding only its own interval.
+    const MERGED_THROUGH_COLUMN: &'static str = "merged_through";
+    const MERGED_THROUGH_COLUMN_TYPE: ValueType = ValueType::Integer;
+    /// Holds the partition value of the partition a partition was split from, NULL for a
+    /// partition covering a whole interval.
+    const SPLIT_FROM_COLUMN: &'static str = "split_from";
+    const SPLIT_FROM_COLUMN_TYPE: ValueType = ValueType::Integer;
+    /// Holds the interval a partition split from another one covers, NULL for a partition
+    /// covering a whole interval.
+    const SPLIT_INTERVAL_COLUMN: &'static str = "split_interval";
+    const SPLIT_INTERVAL_COLUMN_TYPE: ValueType = ValueType::Integer;
+    /// Holds the value of the partition column the partition of a list partitioned table
+    /// belongs to, NULL for the partitions of other tables.
+    const LIST_VALUE_COLUMN: &'static str = "list_value";
+    const LIST_VALUE_COLUMN_TYPE: ValueType = ValueType::Text;
+
+    /// Locks the partitions map for reading, see [`LookupTable::write_partitions`] for how a
+    /// poisoned lock is recovered.
+    fn read_partitions(&self) -> RwLockReadGuard<'_, BTreeMap<(i64, i64), String>> {
+        if self.partitions.is_poisoned() {
+            drop(self.write_partitions());
+        }
+        self.partitions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the partitions map for writing.
+    ///
+    /// A panic while the lock was held poisons it, and may have left the map half updated. The
+    /// map is only a cache of the lookup table, so rather than failing every later access it is
+    /// emptied, to be synced again in full by the next lookup, and the poison is cleared.
+    fn write_partitions(&self) -> RwLockWriteGuard<'_, BTreeMap<(i64, i64), String>> {
+        self.partitions.write().unwrap_or_else(|err| {
+            self.partitions.clear_poison();
+            let mut partitions = err.into_inner();
+            partitions.clear();
+            self.forget_synced();
+            partitions
+        })
+    }
+
+    /// Makes the next [`LookupTable::sync`] read the whole lookup table again, e.g. once the
+    /// partitions map was emptied, or when rows it read were rolled back and their rowids may be
+    /// reused.
+    pub(crate) fn forget_synced(&self) {
+        *self
+            .synced_through
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    pub fn parse_partition_value(
+        value: &ValueRef,
+        interval: i64,
+        round: BucketRounding,
+    ) -> sqlite3_ext::Result<i64> {
+        parse_to_unix_epoch(value).map(|epoch| round.bucket(epoch, interval))
     }
 
     pub fn partition_table_column(&self) -> &'static ColumnDeclaration {
@@ -126,6 +281,103 @@ impl LookupTable<i64> {
     pub fn expiration_column(&self) -> &'static ColumnDeclaration {
         &<Self as PartitionType>::COLUMNS[2]
     }
+    pub fn row_count_column(&self) -> &'static ColumnDeclaration {
+        &<Self as PartitionType>::COLUMNS[3]
+    }
+    pub fn partition_key_column(&self) -> &'static ColumnDeclaration {
+        &<Self as PartitionType>::COLUMNS[4]
+    }
+    pub fn merged_through_column(&self) -> &'static ColumnDeclaration {
+        &<Self as PartitionType>::COLUMNS[5]
+    }
+    pub fn split_from_column(&self) -> &'static ColumnDeclaration {
+        &<Self as PartitionType>::COLUMNS[6]
+    }
+    pub fn split_interval_column(&self) -> &'static ColumnDeclaration {
+        &<Self as PartitionType>::COLUMNS[7]
+    }
+    pub fn list_value_column(&self) -> &'static ColumnDeclaration {
+        &<Self as PartitionType>::COLUMNS[8]
+    }
+
+    /// Selects the partition key in queries, `0` for lookup tables lacking the column.
+    fn partition_key_expression(&self) -> &'static str {
+        match self.partition_keys {
+            true => self.partition_key_column().get_name(),
+            false => "0",
+        }
+    }
+
+    /// Selects the last partition value merged into a partition in queries, NULL for lookup
+    /// tables lacking the column.
+    fn merged_through_expression(&self) -> &'static str {
+        match self.merges {
+            true => self.merged_through_column().get_name(),
+            false => "NULL",
+        }
+    }
+
+    /// Selects the partition value a partition was split from and the interval it covers in
+    /// queries, NULL for lookup tables lacking the columns.
+    fn split_expressions(&self) -> (&'static str, &'static str) {
+        match self.splits {
+            true => (
+                self.split_from_column().get_name(),
+                self.split_interval_column().get_name(),
+            ),
+            false => ("NULL", "NULL"),
+        }
+    }
+
+    /// Selects the value a list partition belongs to in queries, NULL for lookup tables lacking
+    /// the column.
+    fn list_value_expression(&self) -> &'static str {
+        match self.lists {
+            true => self.list_value_column().get_name(),
+            false => "NULL",
+        }
+    }
+
+    /// Locks the merged partitions for reading. They are rebuilt by the next full sync, so a
+    /// poisoned lock is simply taken over.
+    fn read_merged(&self) -> RwLockReadGuard<'_, BTreeMap<i64, i64>> {
+        self.merged.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the merged partitions for writing, see [`LookupTable::read_merged`].
+    fn write_merged(&self) -> RwLockWriteGuard<'_, BTreeMap<i64, i64>> {
+        self.merged.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the split partitions for reading, see [`LookupTable::read_merged`].
+    fn read_split(&self) -> RwLockReadGuard<'_, BTreeMap<i64, (i64, i64)>> {
+        self.split.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the split partitions for writing, see [`LookupTable::read_merged`].
+    fn write_split(&self) -> RwLockWriteGuard<'_, BTreeMap<i64, (i64, i64)>> {
+        self.split.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the values of the list partitions for reading, see [`LookupTable::read_merged`].
+    fn read_listed(&self) -> RwLockReadGuard<'_, BTreeMap<String, i64>> {
+        self.listed.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the values of the list partitions for writing, see [`LookupTable::read_merged`].
+    fn write_listed(&self) -> RwLockWriteGuard<'_, BTreeMap<String, i64>> {
+        self.listed.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Retrieves whether the lookup table records the value each list partition belongs to.
+    pub fn has_list_values(&self) -> bool {
+        self.lists
+    }
+
+    /// Retrieves whether the lookup table keeps the number of rows of each partition.
+    pub fn has_row_counts(&self) -> bool {
+        self.row_counts
+    }
 
     /// Creates a new instance of `LookupTable` with a specified base name. This involves initializing
     /// the lookup table's partitions map and setting up the table schema according to the specified
@@ -156,24 +408,23 @@ impl LookupTable<i64> {
         Ok(LookupTable {
             partitions: RwLock::default(),
             schema,
+            root_name: RootTable::format_name(base_name),
+            generation_seen: Mutex::new(Some(0)),
+            generation_column: AtomicBool::new(false),
+            synced_through: Mutex::new(None),
+            sync_empty_ranges: AtomicBool::new(true),
+            row_counts: true,
+            partition_keys: true,
+            merges: true,
+            merged: RwLock::default(),
+            splits: true,
+            split: RwLock::default(),
+            lists: true,
+            listed: RwLock::default(),
+            changes: Mutex::default(),
         })
     }
 
-    /// Executes the SQL query to create the lookup table in the specified database connection.
-    ///
-    /// # Parameters
-    /// - `db`: A reference to the database connection.
-    ///
-    /// # Returns
-    /// - `Result<bool>`: `Ok(true)` if the table is successfully created.
-
-    /// Generates the SQL query to create the lookup table in the database.
-    ///
-    /// This query includes creating a table with columns for the partition table name and partition value.
-    ///
-    /// # Returns
-    /// - `String`: The SQL query string.
-
     /// Generates the SQL query for inserting a new partition into the lookup table.
     ///
     /// # Returns
@@ -182,10 +433,22 @@ impl LookupTable<i64> {
         let partition_table_name = self.partition_table_column().get_name().to_owned();
         let partition_value_name = self.partition_value_column().get_name().to_owned();
         let expiration_column_name = self.expiration_column().get_name().to_owned();
+        // A lookup table without the key column only ever holds partitions of key 0.
+        let (partition_key_name, partition_key_placeholder) = match self.partition_keys {
+            true => (
+                format!(", {}", self.partition_key_column().get_name()),
+                ", ?",
+            ),
+            false => (String::new(), ""),
+        };
+        let (list_value_name, list_value_placeholder) = match self.lists {
+            true => (format!(", {}", self.list_value_column().get_name()), ", ?"),
+            false => (String::new(), ""),
+        };
 
         let sql = format!(
-            "INSERT INTO {} ({partition_table_name}, {partition_value_name}, {expiration_column_name}) VALUES (?, ?, ?)",
-            self.name()
+            "INSERT INTO {} ({partition_table_name}, {partition_value_name}, {expiration_column_name}{partition_key_name}{list_value_name}) VALUES (?, ?, ?{partition_key_placeholder}{list_value_placeholder})",
+            quote_identifier(self.name())
         );
         sql
     }
@@ -203,18 +466,152 @@ impl LookupTable<i64> {
     /// # Errors
     /// This method may return an error if there's a problem reading the partitions from the database or if there are issues with database connectivity.
     pub fn get_partition(&self, partition_value: &i64) -> sqlite3_ext::Result<Option<String>> {
-        let borrowed_partitions = self.partitions.read().map_err(|err| {
-            sqlite3_ext::Error::Sqlite(1, Some(format!("Error reading partitions: {}", err)))
-        })?;
+        self.get_keyed_partition(*partition_value, 0)
+    }
+
+    /// Retrieves a partition of a composite partition key from the in-memory partitions map,
+    /// see [`LookupTable::get_partition`].
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value of the partition.
+    /// - `partition_key`: The key of the partition among those sharing the partition value.
+    ///
+    /// # Returns
+    /// - `Result<Option<String>>`: The name of the partition table, or `None` if it is not known.
+    pub fn get_keyed_partition(
+        &self,
+        partition_value: i64,
+        partition_key: i64,
+    ) -> sqlite3_ext::Result<Option<String>> {
+        let borrowed_partitions = self.read_partitions();
 
-        Ok(borrowed_partitions
-            .get(partition_value)
+        if let Some(name) = borrowed_partitions.get(&(partition_value, partition_key)) {
+            return Ok(Some(name.to_owned()));
+        }
+        if partition_key != 0 {
+            return Ok(None);
+        }
+        Ok(self
+            .merged_partition_covering(partition_value)
+            .and_then(|merged_value| borrowed_partitions.get(&(merged_value, 0)))
             .map(|name| name.to_owned()))
     }
 
+    /// Finds the merged partition whose rows include those of a partition value.
+    ///
+    /// # Parameters
+    /// - `partition_value`: A partition value without a partition of its own.
+    ///
+    /// # Returns
+    /// - The partition value of the merged partition, or `None` if no merge covers the value.
+    fn merged_partition_covering(&self, partition_value: i64) -> Option<i64> {
+        self.read_merged()
+            .range(..partition_value)
+            .rev()
+            .find(|(_, through)| **through >= partition_value)
+            .map(|(merged_value, _)| *merged_value)
+    }
+
+    /// Retrieves the last partition value whose rows a partition holds: its own value, unless
+    /// later partitions were merged into it with [`VirtualTable::merge_partitions`].
+    ///
+    /// [`VirtualTable::merge_partitions`]: crate::shadow_tables::interface::VirtualTable::merge_partitions
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value of the partition.
+    pub fn covered_through(&self, partition_value: i64) -> i64 {
+        self.read_merged()
+            .get(&partition_value)
+            .copied()
+            .unwrap_or(partition_value)
+    }
+
+    /// Retrieves where a partition created by [`VirtualTable::split_partition`] comes from.
+    ///
+    /// [`VirtualTable::split_partition`]: crate::shadow_tables::interface::VirtualTable::split_partition
+    ///
+    /// # Parameters
+    /// - `partition_value`: The partition value of the partition.
+    ///
+    /// # Returns
+    /// - The partition value of the partition it was split from and the interval it covers, or
+    ///   `None` if the partition covers a whole interval.
+    pub fn split_from(&self, partition_value: i64) -> Option<(i64, i64)> {
+        self.read_split().get(&partition_value).copied()
+    }
+
+    /// Retrieves the interval the partition of a partition value was split by, which the rows
+    /// for it are written to partitions of instead.
+    ///
+    /// # Parameters
+    /// - `partition_value`: A partition value of the table's interval.
+    /// - `interval`: The table's interval. The partitions split from a partition lie within one
+    ///   interval of its partition value, whatever the rounding.
+    ///
+    /// # Returns
+    /// - The finer interval, or `None` if the partition was not split.
+    pub fn split_interval(&self, partition_value: i64, interval: i64) -> Option<i64> {
+        let from = partition_value.saturating_sub(interval);
+        let to = partition_value.saturating_add(interval);
+        self.read_split()
+            .range(from..=to)
+            .find(|(_, (split_from, _))| *split_from == partition_value)
+            .map(|(_, (_, split_interval))| *split_interval)
+    }
+
+    /// Retrieves the partition value of the partitions of a value of a list partitioned table,
+    /// from the in-memory map.
+    ///
+    /// # Parameters
+    /// - `list_value`: The value, see [`list_partition_label`](crate::utils::list_partition_label).
+    ///
+    /// # Returns
+    /// - The partition value, or `None` if no partition is known for the value.
+    pub fn listed_partition_value(&self, list_value: &str) -> Option<i64> {
+        self.read_listed().get(list_value).copied()
+    }
+
+    /// Finds the partition value a value of a list partitioned table without partitions gets:
+    /// the hash of the value, or the first value after it that no partition has, so that values
+    /// sharing a hash get partitions of their own.
+    ///
+    /// # Parameters
+    /// - `hash`: The hash of the value, see [`list_partition_value`](crate::utils::list_partition_value).
+    ///
+    /// # Returns
+    /// - The first partition value from the hash on without a partition in the in-memory map.
+    pub fn free_partition_value(&self, hash: i64) -> i64 {
+        let partitions = self.read_partitions();
+        let mut partition_value = hash;
+        while partitions
+            .range((partition_value, i64::MIN)..=(partition_value, i64::MAX))
+            .next()
+            .is_some()
+        {
+            partition_value = partition_value.wrapping_add(1);
+        }
+        partition_value
+    }
+
+    /// Copies the in-memory partitions map as it is at the time of the call, e.g. to inspect it
+    /// while debugging. The map is not synced with the database first.
+    ///
+    /// # Returns
+    /// - `Result<BTreeMap<(i64, i64), String>>`: The partition values and partition keys mapped
+    ///   to their partition table names.
+    pub fn snapshot(&self) -> ExtResult<BTreeMap<(i64, i64), String>> {
+        let borrowed_partitions = self.read_partitions();
+        Ok(borrowed_partitions.clone())
+    }
+
     /// Synchronizes the in-memory partitions map with the current state of the lookup table in the database.
     ///
-    /// This method updates the partitions map to reflect the actual partitions present in the database. It is particularly useful to ensure that the in-memory representation of partitions is consistent with the database, especially after modifications such as adding or dropping partitions.
+    /// This method adds the partitions recorded in the lookup table since the last sync to the map,
+    /// e.g. by other connections. Partitions are added mostly in increasing order, so only rows
+    /// with a partition value above the largest one seen so far are read, along with rows
+    /// inserted since, which covers partitions created for late arriving rows. The query stays the
+    /// same size however many partitions the table has. Partitions removed from the lookup table
+    /// are not removed from the map, see [`LookupTable::reload`] for a full resync.
     ///
     /// # Parameters
     /// - `db`: A reference to the database connection. This connection is used to query the current state of the lookup table.
@@ -223,64 +620,253 @@ impl LookupTable<i64> {
     /// - `Result<()>`: Indicates success or failure of the synchronization process. Returns `Ok(())` on successful synchronization. On failure, returns an error detailing the issue encountered.
     ///
     /// # Errors
-    /// Errors may occur due to issues acquiring write locks on the partitions map, preparing the SQL statement, executing the SQL query, or reading the query results. These errors are wrapped and returned as `sqlite3_ext::Result` for handling.
+    /// Errors may occur due to issues preparing the SQL statement, executing the SQL query, or reading the query results. These errors are wrapped and returned as `sqlite3_ext::Result` for handling.
     pub fn sync(&self, db: &Connection) -> ExtResult<()> {
         // Acquire a write lock on partitions upfront, simplifying error handling.
-        let mut borrowed_partitions = self.partitions.write().map_err(|err| {
-            sqlite3_ext::Error::Sqlite(
-                1,
-                Some(format!("Error acquiring write lock on partitions: {}", err)),
-            )
-        })?;
-
-        // Collect existing partition values to use in the query.
-        let partition_values: Vec<i64> = borrowed_partitions.keys().copied().collect();
-        let placeholders = std::iter::repeat("?")
-            .take(partition_values.len())
-            .collect::<Vec<_>>()
-            .join(",");
-        let sql = if !placeholders.is_empty() {
-            format!(
-                "SELECT {}, {} FROM {} WHERE {} NOT IN ({});",
-                self.partition_value_column().get_name(),
-                self.partition_table_column().get_name(),
-                self.name(),
-                self.partition_value_column().get_name(),
-                placeholders
-            )
-        } else {
-            format!(
-                "SELECT {}, {} FROM {};",
-                self.partition_value_column().get_name(),
-                self.partition_table_column().get_name(),
-                self.name(),
-            )
-        };
-        // Prepare SQL query using placeholders for the collected partition values.
+        let mut borrowed_partitions = self.write_partitions();
+        let mut synced_through = self
+            .synced_through
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
 
+        let (sql, params) = self.sync_query(*synced_through);
         let mut statement = db.prepare(&sql).map_err(|err| {
             sqlite3_ext::Error::Sqlite(1, Some(format!("Error preparing SQL statement: {}", err)))
         })?;
-
-        // Execute the query with the collected partition values as parameters.
-        let results = statement.query(partition_values).map_err(|err| {
+        let results = statement.query(params).map_err(|err| {
             sqlite3_ext::Error::Sqlite(1, Some(format!("Error executing SQL query: {}", err)))
         })?;
 
+        let mut merged = self.write_merged();
+        let mut split = self.write_split();
+        let mut listed = self.write_listed();
+        if synced_through.is_none() {
+            merged.clear();
+            split.clear();
+            listed.clear();
+        }
+        let mut through = *synced_through;
         while let Ok(Some(row)) = results.next() {
             let partition_value = row[0].get_i64();
+            let rowid = row[2].get_i64();
+            let partition_key = row[3].get_i64();
+            if row[4].value_type() != ValueType::Null {
+                merged.insert(partition_value, row[4].get_i64());
+            }
+            if row[5].value_type() != ValueType::Null {
+                split.insert(partition_value, (row[5].get_i64(), row[6].get_i64()));
+            }
+            if row[7].value_type() != ValueType::Null {
+                listed.insert(row[7].get_str()?.to_owned(), partition_value);
+            }
             let partition_table_name = row[1].get_str()?;
-            borrowed_partitions.insert(partition_value, partition_table_name.to_string());
+            self.map_partition(
+                &mut borrowed_partitions,
+                (partition_value, partition_key),
+                partition_table_name.to_string(),
+            );
+            through = Some(match through {
+                Some((value, last_rowid)) => (value.max(partition_value), last_rowid.max(rowid)),
+                None => (partition_value, rowid),
+            });
         }
+        *synced_through = through;
 
+        drop(listed);
+        drop(split);
+        drop(merged);
         drop(borrowed_partitions);
 
         Ok(())
     }
 
+    /// Builds the query [`LookupTable::sync`] reads new partitions with.
+    ///
+    /// # Parameters
+    /// - `synced_through`: The largest partition value and rowid read by earlier syncs, or
+    ///   `None` to read every partition.
+    ///
+    /// # Returns
+    /// - The query, selecting the partition value, the partition table, the rowid, the
+    ///   partition key, the last merged partition value, where a split partition comes from and
+    ///   the value a list partition belongs to of each row, and its parameters. Neither grows
+    ///   with the number of partitions.
+    fn sync_query(&self, synced_through: Option<(i64, i64)>) -> (String, Vec<i64>) {
+        let value_column = self.partition_value_column().get_name();
+        let (split_from, split_interval) = self.split_expressions();
+        let sql = format!(
+            "SELECT {}, {}, rowid, {}, {}, {split_from}, {split_interval}, {} FROM {}",
+            value_column,
+            self.partition_table_column().get_name(),
+            self.partition_key_expression(),
+            self.merged_through_expression(),
+            self.list_value_expression(),
+            quote_identifier(self.name()),
+        );
+        match synced_through {
+            Some((partition_value, rowid)) => (
+                format!("{sql} WHERE {value_column} > ? OR rowid > ?"),
+                vec![partition_value, rowid],
+            ),
+            None => (sql, Vec::new()),
+        }
+    }
+
+    /// Loads the partitions within a range of partition values from the lookup table into the
+    /// in-memory partitions map, leaving the rest of the map as it is.
+    ///
+    /// Unlike [`LookupTable::sync`], only the range is read, so it stays cheap for large lookup
+    /// tables.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `from`: The lower bound of the partition value range.
+    /// - `to`: The upper bound of the partition value range.
+    ///
+    /// # Returns
+    /// - `Result<usize>`: The number of partitions in the range, all of which are in the map
+    ///   afterwards.
+    pub fn sync_range(
+        &self,
+        db: &Connection,
+        from: &Bound<i64>,
+        to: &Bound<i64>,
+    ) -> ExtResult<usize> {
+        let value_column = self.partition_value_column().get_name();
+        let mut conditions = Vec::new();
+        let mut bounds = Vec::new();
+        for (bound, inclusive, exclusive) in [(from, ">=", ">"), (to, "<=", "<")] {
+            match bound {
+                Bound::Included(value) => {
+                    conditions.push(format!("{} {} ?", value_column, inclusive));
+                    bounds.push(*value);
+                }
+                Bound::Excluded(value) => {
+                    conditions.push(format!("{} {} ?", value_column, exclusive));
+                    bounds.push(*value);
+                }
+                Bound::Unbounded => {}
+            }
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+        let (split_from, split_interval) = self.split_expressions();
+        let sql = format!(
+            "SELECT {}, {}, {}, {}, {split_from}, {split_interval}, {} FROM {}{};",
+            value_column,
+            self.partition_table_column().get_name(),
+            self.partition_key_expression(),
+            self.merged_through_expression(),
+            self.list_value_expression(),
+            quote_identifier(self.name()),
+            where_clause
+        );
+
+        let mut statement = db.prepare(&sql)?;
+        statement.query(bounds)?;
+        let mut loaded = Vec::new();
+        let mut merged = Vec::new();
+        let mut split = Vec::new();
+        let mut listed = Vec::new();
+        while let Some(row) = statement.next()? {
+            if row[3].value_type() != ValueType::Null {
+                merged.push((row[0].get_i64(), row[3].get_i64()));
+            }
+            if row[4].value_type() != ValueType::Null {
+                split.push((row[0].get_i64(), (row[4].get_i64(), row[5].get_i64())));
+            }
+            if row[6].value_type() != ValueType::Null {
+                listed.push((row[6].get_str()?.to_owned(), row[0].get_i64()));
+            }
+            loaded.push((
+                (row[0].get_i64(), row[2].get_i64()),
+                row[1].get_str()?.to_owned(),
+            ));
+        }
+        let count = loaded.len();
+        self.write_merged().extend(merged);
+        self.write_split().extend(split);
+        self.write_listed().extend(listed);
+        let mut partitions = self.write_partitions();
+        for (key, partition) in loaded {
+            self.map_partition(&mut partitions, key, partition);
+        }
+        Ok(count)
+    }
+
+    /// Reads the smallest and largest partition value straight from the lookup table, without
+    /// touching the partitions map.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    ///
+    /// # Returns
+    /// - `Result<Option<(i64, i64)>>`: The first and last partition value, or `None` if there
+    ///   are no partitions.
+    pub fn bounds(&self, db: &Connection) -> ExtResult<Option<(i64, i64)>> {
+        let value_column = self.partition_value_column().get_name();
+        db.query_row(
+            &format!(
+                "SELECT min({value_column}), max({value_column}) FROM {}",
+                quote_identifier(self.name())
+            ),
+            (),
+            |row| match row[0].value_type() {
+                ValueType::Null => Ok(None),
+                _ => Ok(Some((row[0].get_i64(), row[1].get_i64()))),
+            },
+        )
+    }
+
+    /// Sets whether [`LookupTable::get_partitions_by_range`] syncs a range that has no cached
+    /// partitions even when the cache is known to be complete for it. On by default; turn it off
+    /// to answer such ranges from the cache. The cache counts as complete while the generation
+    /// in the root table is the one it was kept up with, see [`LookupTable::refresh`], so
+    /// partitions recorded by anything not keeping the generation, such as an older version of
+    /// the extension, are missed until the next change.
+    ///
+    /// # Parameters
+    /// - `enabled`: `true` to always sync before reading a range.
+    pub fn set_sync_empty_ranges(&self, enabled: bool) {
+        self.sync_empty_ranges.store(enabled, Ordering::Release);
+    }
+
+    /// Decides whether the partitions map can answer that a range has no partitions without
+    /// syncing: syncing empty ranges is turned off, the map is current as told by
+    /// [`LookupTable::refresh`] and the range lies between the first and last cached partitions.
+    fn is_known_empty(
+        &self,
+        partitions: &BTreeMap<(i64, i64), String>,
+        from: &Bound<i64>,
+        to: &Bound<i64>,
+    ) -> bool {
+        if self.sync_empty_ranges.load(Ordering::Acquire) {
+            return false;
+        }
+        let (Some((first, _)), Some((last, _))) =
+            (partitions.keys().next(), partitions.keys().next_back())
+        else {
+            return false;
+        };
+        let after_first = match from {
+            Bound::Included(value) | Bound::Excluded(value) => value >= first,
+            Bound::Unbounded => false,
+        };
+        let before_last = match to {
+            Bound::Included(value) | Bound::Excluded(value) => value <= last,
+            Bound::Unbounded => false,
+        };
+        after_first && before_last && partitions.range(key_range(from, to)).next().is_none()
+    }
+
     /// Retrieves a list of partitions within a specified range of partition values.
     ///
-    /// This method filters the partitions by the specified range, defined by `from` and `to` bounds, and returns their names along with their corresponding values. It ensures that the lookup table's partition map is synchronized with the database state before fetching the partition information.
+    /// This method filters the partitions by the specified range, defined by `from` and `to` bounds, and returns their names along with their corresponding values. It ensures that the lookup table's partition map is synchronized with the database state before fetching the partition information, unless the map already knows the range to be empty.
+    ///
+    /// With a composite partition key several partitions share a partition value, all of them are listed, ordered by partition key.
     ///
     /// # Parameters
     /// - `db`: A reference to the database connection. Used for syncing the lookup table and querying partition data.
@@ -298,23 +884,109 @@ impl LookupTable<i64> {
         from: &Bound<i64>,
         to: &Bound<i64>,
     ) -> ExtResult<Vec<(i64, String)>> {
-        self.sync(db)?;
-        let borrowed_partitions = self.partitions.read().map_err(|err| {
-            sqlite3_ext::Error::Sqlite(
-                1,
-                Some(format!(
-                    "Error acquiring read permissions to partitions: {}",
-                    err
-                )),
-            )
-        })?;
-        let range = borrowed_partitions.range((*from, *to));
-        let pair = range
-            .map(|(key, value)| (*key, value.to_string()))
-            .collect::<Vec<(i64, String)>>();
+        let current = self.refresh(db)?;
+        let known_empty = current && {
+            let borrowed_partitions = self.read_partitions();
+            self.is_known_empty(&borrowed_partitions, from, to)
+        };
+        if !known_empty {
+            self.sync(db)?;
+        }
+        let borrowed_partitions = self.read_partitions();
+        let mut pair = self.merged_into_range(&borrowed_partitions, from, to);
+        if !known_empty {
+            let range = borrowed_partitions.range(key_range(from, to));
+            pair.extend(
+                range.map(|((partition_value, _), value)| (*partition_value, value.to_string())),
+            );
+        }
+        let split = self.split_into_range(&borrowed_partitions, from, to);
+        if !split.is_empty() {
+            pair.extend(split);
+            pair.sort_by_key(|(partition_value, _)| *partition_value);
+        }
         Ok(pair)
     }
 
+    /// Lists the partitions split from a partition value within a range whose own partition
+    /// values lie outside of it, which [`LookupTable::get_partitions_by_range`] lists along
+    /// with the partitions in the range.
+    ///
+    /// # Parameters
+    /// - `partitions`: The locked partitions map.
+    /// - `from`: The lower bound of the partition value range.
+    /// - `to`: The upper bound of the partition value range.
+    ///
+    /// # Returns
+    /// - The partition values and names of those partitions, in increasing order.
+    fn split_into_range(
+        &self,
+        partitions: &BTreeMap<PartitionMapKey, String>,
+        from: &Bound<i64>,
+        to: &Bound<i64>,
+    ) -> Vec<(i64, String)> {
+        let range = (*from, *to);
+        self.read_split()
+            .iter()
+            .filter(|(value, (split_from, _))| {
+                range.contains(split_from) && !range.contains(*value)
+            })
+            .filter_map(|(value, _)| {
+                partitions
+                    .get(&(*value, 0))
+                    .map(|partition| (*value, partition.to_owned()))
+            })
+            .collect()
+    }
+
+    /// Lists the merged partitions starting before a range whose rows reach into it, which
+    /// [`LookupTable::get_partitions_by_range`] lists ahead of the partitions in the range.
+    ///
+    /// # Parameters
+    /// - `partitions`: The locked partitions map.
+    /// - `from`: The lower bound of the partition value range.
+    /// - `to`: The upper bound of the partition value range.
+    ///
+    /// # Returns
+    /// - The partition values and names of those partitions, in increasing order.
+    fn merged_into_range(
+        &self,
+        partitions: &BTreeMap<PartitionMapKey, String>,
+        from: &Bound<i64>,
+        to: &Bound<i64>,
+    ) -> Vec<(i64, String)> {
+        let reaches_into = |through: i64| {
+            let after_from = match from {
+                Bound::Included(value) => through >= *value,
+                Bound::Excluded(value) => through > *value,
+                Bound::Unbounded => false,
+            };
+            let range_empty = match (from, to) {
+                (Bound::Included(from), Bound::Included(to)) => from > to,
+                (
+                    Bound::Included(from) | Bound::Excluded(from),
+                    Bound::Included(to) | Bound::Excluded(to),
+                ) => from >= to,
+                _ => false,
+            };
+            after_from && !range_empty
+        };
+        let starts_before = |value: i64| match from {
+            Bound::Included(from) => value < *from,
+            Bound::Excluded(from) => value <= *from,
+            Bound::Unbounded => false,
+        };
+        self.read_merged()
+            .iter()
+            .filter(|(value, through)| starts_before(**value) && reaches_into(**through))
+            .filter_map(|(value, _)| {
+                partitions
+                    .get(&(*value, 0))
+                    .map(|partition| (*value, partition.to_owned()))
+            })
+            .collect()
+    }
+
     /// Connects to an existing lookup table in the database, initializing the `LookupTable` instance
     /// based on the retrieved schema and partitions.
     ///
@@ -344,11 +1016,37 @@ impl LookupTable<i64> {
     pub fn connect(db: &Connection, base_name: &str) -> ExtResult<Self> {
         let table_name = &Self::format_name(base_name);
         let schema = <Self as Connect>::schema(db, table_name)?;
+        let has_column = |name: &str| {
+            schema
+                .columns()
+                .0
+                .iter()
+                .any(|column| column.get_name() == name)
+        };
+        let row_counts = has_column(Self::ROW_COUNT_COLUMN);
+        let partition_keys = has_column(Self::PARTITION_KEY_COLUMN);
+        let merges = has_column(Self::MERGED_THROUGH_COLUMN);
+        let splits = has_column(Self::SPLIT_FROM_COLUMN) && has_column(Self::SPLIT_INTERVAL_COLUMN);
+        let lists = has_column(Self::LIST_VALUE_COLUMN);
         let table = Self {
             partitions: RwLock::new(std::collections::BTreeMap::new()),
             schema,
+            root_name: RootTable::format_name(base_name),
+            generation_seen: Mutex::new(None),
+            generation_column: AtomicBool::new(false),
+            synced_through: Mutex::new(None),
+            sync_empty_ranges: AtomicBool::new(true),
+            row_counts,
+            partition_keys,
+            merges,
+            merged: RwLock::default(),
+            splits,
+            split: RwLock::default(),
+            lists,
+            listed: RwLock::default(),
+            changes: Mutex::default(),
         };
-        table.sync(db)?;
+        table.reload(db)?;
         Ok(table)
     }
 
@@ -359,51 +1057,714 @@ impl LookupTable<i64> {
     /// partitions map to reflect this addition. This method is crucial for maintaining the integrity
     /// and accuracy of the partitioning system.
     ///
+    /// Another connection may have recorded a partition for the same value since the map was last
+    /// synced. The insert then fails on the unique constraint, and the partition recorded by the
+    /// other connection is adopted into the map instead, so both connections agree on it. A
+    /// partition recorded for another value of a list partitioned table is not adopted, the
+    /// insert fails instead.
+    ///
     /// # Parameters
     /// - `db`: A reference to the database connection. Used to execute the insert operation in the lookup table.
     /// - `partition_name`: The name of the new partition to insert. This name should be unique within the lookup table.
     /// - `partition_value`: The value associated with the new partition. This value is used to determine the partition's position and relationship with other partitions.
+    /// - `partition_key`: The key of the partition among those sharing its partition value, `0` unless the partition key is composite.
+    /// - `expires_at`: When the partition expires, if the table has a lifetime.
+    /// - `list_value`: The value of the partition column the partition belongs to, for a list partitioned table, see [`list_partition_label`](crate::utils::list_partition_label). Not recorded if the lookup table lacks the column.
     ///
     /// # Returns
-    /// - `Result<&str>`: On successful insertion, returns the name of the newly inserted partition table. On failure, returns an error detailing the issue encountered during the insertion process.
+    /// - `Result<String>`: On successful insertion, returns the name of the newly inserted partition table, or the name of the partition another connection recorded for the value first. On failure, returns an error detailing the issue encountered during the insertion process.
     ///
     /// # Errors
-    /// This method may return an error if there are issues executing the insert query, such as database connectivity problems, violations of unique constraints, or if the provided partition name or value is invalid. Errors are wrapped and returned as `ExtResult<&str>` for handling.
-    pub(crate) fn insert<'a>(
-        &'a self,
+    /// This method may return an error if there are issues executing the insert query, such as database connectivity problems, violations of unique constraints, or if the provided partition name or value is invalid. Errors are wrapped and returned as `ExtResult<String>` for handling.
+    pub(crate) fn insert(
+        &self,
         db: &Connection,
-        partition_name: &'a str,
+        partition_name: &str,
         partition_value: i64,
+        partition_key: i64,
         expires_at: Option<i64>,
-    ) -> ExtResult<&str> {
-        Connection::prepare(db, &self.insert_query())?.execute(|stmt: &mut Statement| {
-            partition_name.bind_param(stmt, 1)?;
-            partition_value.bind_param(stmt, 2)?;
-            expires_at.bind_param(stmt, 3)?;
+        list_value: Option<&str>,
+    ) -> ExtResult<String> {
+        let list_value = list_value.filter(|_| self.lists);
+        let inserted =
+            Connection::prepare(db, &self.insert_query())?.execute(|stmt: &mut Statement| {
+                partition_name.bind_param(stmt, 1)?;
+                partition_value.bind_param(stmt, 2)?;
+                expires_at.bind_param(stmt, 3)?;
+                let mut index = 4;
+                if self.partition_keys {
+                    partition_key.bind_param(stmt, index)?;
+                    index += 1;
+                }
+                if self.lists {
+                    list_value.bind_param(stmt, index)?;
+                }
 
-            Ok(())
-        })?;
+                Ok(())
+            });
+        let partition_name = match inserted {
+            Ok(_) => {
+                self.bump_generation(db)?;
+                partition_name.to_owned()
+            }
+            Err(sqlite3_ext::Error::Sqlite(code, message)) if code & 0xff == SQLITE_CONSTRAINT => {
+                match self.recorded_partition(db, partition_value, partition_key)? {
+                    Some((name, recorded))
+                        if list_value.is_none() || recorded.as_deref() == list_value =>
+                    {
+                        name
+                    }
+                    _ => return Err(sqlite3_ext::Error::Sqlite(code, message)),
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(list_value) = list_value {
+            self.write_listed()
+                .insert(list_value.to_owned(), partition_value);
+        }
 
-        let mut borrowed_partitions = self.partitions.write().map_err(|err| {
-            sqlite3_ext::Error::Sqlite(
-                1,
-                Some(format!(
-                    "Error acquiring write permissions to partitions: {}",
-                    err
-                )),
-            )
-        })?;
+        let mut borrowed_partitions = self.write_partitions();
 
-        borrowed_partitions.insert(partition_value, partition_name.to_string());
+        self.map_partition(
+            &mut borrowed_partitions,
+            (partition_value, partition_key),
+            partition_name.clone(),
+        );
 
         Ok(partition_name)
     }
+
+    /// Maps a partition in the partitions map, noting the change if a transaction is tracked.
+    ///
+    /// # Parameters
+    /// - `partitions`: The locked partitions map.
+    /// - `key`: The partition value and partition key of the partition.
+    /// - `partition_name`: The name of the partition table.
+    fn map_partition(
+        &self,
+        partitions: &mut BTreeMap<PartitionMapKey, String>,
+        key: PartitionMapKey,
+        partition_name: String,
+    ) {
+        if partitions.get(&key) == Some(&partition_name) {
+            return;
+        }
+        let replaced = partitions.insert(key, partition_name);
+        if let Some(changes) = self
+            .changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            if let Some(replaced) = replaced {
+                changes.push(MapChange::Removed(key, replaced));
+            }
+            changes.push(MapChange::Added(key));
+        }
+    }
+
+    /// Takes partitions out of the partitions map, noting the changes if a transaction is
+    /// tracked.
+    ///
+    /// # Parameters
+    /// - `partitions`: The locked partitions map.
+    /// - `keep`: Decides by its partition value and partition key whether a partition stays.
+    fn unmap_partitions(
+        &self,
+        partitions: &mut BTreeMap<PartitionMapKey, String>,
+        mut keep: impl FnMut(&PartitionMapKey) -> bool,
+    ) {
+        let mut changes = self.changes.lock().unwrap_or_else(PoisonError::into_inner);
+        partitions.retain(|key, partition_name| {
+            let kept = keep(key);
+            if let (false, Some(changes)) = (kept, changes.as_mut()) {
+                changes.push(MapChange::Removed(*key, partition_name.clone()));
+            }
+            kept
+        });
+    }
+
+    /// Starts noting the changes to the partitions map, when a transaction begins, so that they
+    /// can be undone if it is rolled back. Changes noted earlier are dropped.
+    pub(crate) fn track_changes(&self) {
+        *self.changes.lock().unwrap_or_else(PoisonError::into_inner) = Some(Vec::new());
+    }
+
+    /// Stops noting the changes to the partitions map, once the transaction ends.
+    pub(crate) fn untrack_changes(&self) {
+        *self.changes.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Returns the number of changes noted since the transaction began, which marks the state
+    /// a savepoint rolls back to, see [`LookupTable::undo_changes`].
+    pub(crate) fn change_count(&self) -> usize {
+        self.changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map_or(0, Vec::len)
+    }
+
+    /// Undoes the changes to the partitions map made after a mark, newest first, once the
+    /// statements that made them were rolled back. The partition tables and lookup rows are
+    /// restored by SQLite together with the rest of the transaction, the map is left as it was
+    /// at the mark.
+    ///
+    /// The rollback also restores the generation in the root table, so other instances of the
+    /// lookup table that synced the rolled back partitions reload their maps, and so does this
+    /// one, whose map may hold partitions it synced meanwhile.
+    ///
+    /// # Parameters
+    /// - `mark`: The number of changes noted before the savepoint rolled back to, as returned by
+    ///   [`LookupTable::change_count`], or `0` for the whole transaction.
+    pub(crate) fn undo_changes(&self, mark: usize) {
+        let undone = match self
+            .changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+        {
+            Some(changes) => changes.split_off(mark.min(changes.len())),
+            None => return,
+        };
+        if undone.is_empty() {
+            return;
+        }
+        let mut partitions = self.write_partitions();
+        for change in undone.into_iter().rev() {
+            match change {
+                MapChange::Added(key) => {
+                    partitions.remove(&key);
+                }
+                MapChange::Removed(key, partition_name) => {
+                    partitions.insert(key, partition_name);
+                }
+            }
+        }
+        drop(partitions);
+        self.forget_synced();
+        self.forget_generation();
+    }
+
+    /// Reads the name of the partition recorded for a value from the lookup table, bypassing
+    /// the partitions map.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `partition_value`: The value of the partition.
+    /// - `partition_key`: The key of the partition among those sharing the partition value.
+    ///
+    /// # Returns
+    /// - `Result<Option<(String, Option<String>)>>`: The name of the partition and the value it
+    ///   belongs to if the table is list partitioned, or `None` if no partition is recorded.
+    fn recorded_partition(
+        &self,
+        db: &Connection,
+        partition_value: i64,
+        partition_key: i64,
+    ) -> ExtResult<Option<(String, Option<String>)>> {
+        let mut statement = db.prepare(&format!(
+            "SELECT {}, {} FROM {} WHERE {} = ? AND {} = ?",
+            self.partition_table_column().get_name(),
+            self.list_value_expression(),
+            quote_identifier(self.name()),
+            self.partition_value_column().get_name(),
+            self.partition_key_expression()
+        ))?;
+        statement.query([partition_value, partition_key])?;
+        match statement.next()? {
+            Some(row) => {
+                let list_value = match row[1].value_type() {
+                    ValueType::Null => None,
+                    _ => Some(row[1].get_str()?.to_owned()),
+                };
+                Ok(Some((row[0].get_str()?.to_owned(), list_value)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Removes every partition from the lookup table and clears the in-memory partitions map.
+    ///
+    /// The partition tables themselves are left in place, dropping or moving them is the
+    /// responsibility of the caller.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    ///
+    /// # Returns
+    /// - `Result<()>`: `Ok(())` once both the lookup table and the map are empty.
+    pub(crate) fn clear(&self, db: &Connection) -> ExtResult<()> {
+        db.execute(
+            &format!("DELETE FROM {}", quote_identifier(self.name())),
+            (),
+        )?;
+        self.unmap_partitions(&mut self.write_partitions(), |_| false);
+        self.write_merged().clear();
+        self.write_split().clear();
+        self.write_listed().clear();
+        self.bump_generation(db)
+    }
+
+    /// Sets the expiration of every recorded partition to `lifetime` after its partition value,
+    /// or clears it if there is no lifetime. Partitions already expiring then are left alone.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `lifetime`: The lifetime of the partitions in seconds, if they expire.
+    ///
+    /// # Returns
+    /// - `Result<usize>`: The number of partitions whose expiration changed.
+    pub(crate) fn set_expirations(
+        &self,
+        db: &Connection,
+        lifetime: Option<i64>,
+    ) -> ExtResult<usize> {
+        // SQLite turns an overflowing sum into a REAL, the expiration is clamped like
+        // `i64::saturating_add` would instead.
+        // A merged partition expires with the last interval merged into it.
+        let value = format!(
+            "coalesce({}, {})",
+            self.merged_through_expression(),
+            self.partition_value_column().get_name()
+        );
+        let expires_at = format!(
+            "CASE WHEN {value} > {max} - ?1 THEN {max} ELSE {value} + ?1 END",
+            max = i64::MAX
+        );
+        let changed = db.execute(
+            &format!(
+                "UPDATE {} SET {expiration} = {expires_at} WHERE {expiration} IS NOT {expires_at}",
+                quote_identifier(self.name()),
+                expiration = self.expiration_column().get_name(),
+            ),
+            [lifetime],
+        )?;
+        Ok(changed as usize)
+    }
+
+    /// Removes the given partitions from the lookup table and the in-memory partitions map.
+    ///
+    /// As with [`LookupTable::clear`], the partition tables themselves are left in place. Every
+    /// partition key of the given values is removed.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `partition_values`: The partition values of the partitions to remove.
+    ///
+    /// # Returns
+    /// - `Result<()>`: `Ok(())` once the partitions are no longer listed.
+    pub(crate) fn remove(&self, db: &Connection, partition_values: &[i64]) -> ExtResult<()> {
+        if partition_values.is_empty() {
+            return Ok(());
+        }
+        let placeholders = std::iter::repeat_n("?", partition_values.len())
+            .collect::<Vec<_>>()
+            .join(",");
+        db.execute(
+            &format!(
+                "DELETE FROM {} WHERE {} IN ({})",
+                quote_identifier(self.name()),
+                self.partition_value_column().get_name(),
+                placeholders
+            ),
+            partition_values.to_vec(),
+        )?;
+        self.unmap_partitions(&mut self.write_partitions(), |(partition_value, _)| {
+            !partition_values.contains(partition_value)
+        });
+        self.write_merged()
+            .retain(|partition_value, _| !partition_values.contains(partition_value));
+        self.write_split()
+            .retain(|partition_value, _| !partition_values.contains(partition_value));
+        self.write_listed()
+            .retain(|_, partition_value| !partition_values.contains(partition_value));
+        self.bump_generation(db)
+    }
+
+    /// Records that the partitions following a partition up to a partition value were merged
+    /// into it. It is then listed for ranges reaching into the merged intervals, and rows for
+    /// their partition values are written to it. The merged partitions are expected to be
+    /// removed with [`LookupTable::remove`].
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `partition_value`: The partition value of the partition the others were merged into.
+    /// - `through`: The last partition value whose rows the partition holds now.
+    /// - `expires_at`: When the partition expires now, if the table has a lifetime.
+    ///
+    /// # Returns
+    /// - `Result<()>`: An error if the lookup table was created before partitions could be
+    ///   merged.
+    pub(crate) fn record_merge(
+        &self,
+        db: &Connection,
+        partition_value: i64,
+        through: i64,
+        expires_at: Option<i64>,
+    ) -> ExtResult<()> {
+        if !self.merges {
+            return Err(sqlite3_ext::Error::Module(format!(
+                "{} was created before partitions could be merged",
+                self.name()
+            )));
+        }
+        db.execute(
+            &format!(
+                "UPDATE {} SET {} = ?, {} = ? WHERE {} = ? AND {} = 0",
+                quote_identifier(self.name()),
+                self.merged_through_column().get_name(),
+                self.expiration_column().get_name(),
+                self.partition_value_column().get_name(),
+                self.partition_key_expression()
+            ),
+            params![through, expires_at, partition_value],
+        )?;
+        self.write_merged().insert(partition_value, through);
+        self.bump_generation(db)
+    }
+
+    /// Records that a partition was split from another one and covers a finer interval. It is
+    /// then listed for ranges including the partition it was split from, and rows for that
+    /// partition value are written to the partitions of the finer interval.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `partition_value`: The partition value of the partition split off.
+    /// - `split_from`: The partition value of the partition it was split from.
+    /// - `split_interval`: The interval the partition covers.
+    ///
+    /// # Returns
+    /// - `Result<()>`: An error if the lookup table was created before partitions could be
+    ///   split.
+    pub(crate) fn record_split(
+        &self,
+        db: &Connection,
+        partition_value: i64,
+        split_from: i64,
+        split_interval: i64,
+    ) -> ExtResult<()> {
+        if !self.splits {
+            return Err(sqlite3_ext::Error::Module(format!(
+                "{} was created before partitions could be split",
+                self.name()
+            )));
+        }
+        db.execute(
+            &format!(
+                "UPDATE {} SET {} = ?, {} = ? WHERE {} = ? AND {} = 0",
+                quote_identifier(self.name()),
+                self.split_from_column().get_name(),
+                self.split_interval_column().get_name(),
+                self.partition_value_column().get_name(),
+                self.partition_key_expression()
+            ),
+            params![split_from, split_interval, partition_value],
+        )?;
+        self.write_split()
+            .insert(partition_value, (split_from, split_interval));
+        self.bump_generation(db)
+    }
+
+    /// Adds to the row counts of partitions.
+    ///
+    /// Nothing is recorded if the lookup table does not keep row counts.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `changes`: The partition tables mapped to the number of rows they gained, negative if
+    ///   they lost rows, or to `None` if the number is not known, which leaves the count unknown
+    ///   until [`LookupTable::set_row_count`] is called for it.
+    pub(crate) fn add_row_counts(
+        &self,
+        db: &Connection,
+        changes: &BTreeMap<String, Option<i64>>,
+    ) -> ExtResult<()> {
+        if !self.row_counts || changes.is_empty() {
+            return Ok(());
+        }
+        let row_count = self.row_count_column().get_name();
+        let mut statement = db.prepare(&format!(
+            "UPDATE {} SET {row_count} = {row_count} + ? WHERE {} = ?",
+            quote_identifier(self.name()),
+            self.partition_table_column().get_name()
+        ))?;
+        for (partition, change) in changes {
+            statement.execute(params![*change, partition.as_str()])?;
+        }
+        Ok(())
+    }
+
+    /// Records the number of rows of a partition, e.g. after counting them.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    /// - `partition`: The name of the partition table.
+    /// - `rows`: The number of rows it holds.
+    pub(crate) fn set_row_count(
+        &self,
+        db: &Connection,
+        partition: &str,
+        rows: i64,
+    ) -> ExtResult<()> {
+        if !self.row_counts {
+            return Ok(());
+        }
+        db.execute(
+            &format!(
+                "UPDATE {} SET {} = ? WHERE {} = ?",
+                quote_identifier(self.name()),
+                self.row_count_column().get_name(),
+                self.partition_table_column().get_name()
+            ),
+            params![rows, partition],
+        )?;
+        Ok(())
+    }
+
+    /// Lists every recorded partition with its row count, ordered by partition value.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    ///
+    /// # Returns
+    /// - `Result<Vec<(i64, String, Option<i64>)>>`: The partition values, the partition table
+    ///   names and the recorded row counts, `None` where the count is not known or the lookup
+    ///   table does not keep row counts.
+    pub(crate) fn partition_row_counts(
+        &self,
+        db: &Connection,
+    ) -> ExtResult<Vec<(i64, String, Option<i64>)>> {
+        let row_count = if self.row_counts {
+            self.row_count_column().get_name()
+        } else {
+            "NULL"
+        };
+        let mut statement = db.prepare(&format!(
+            "SELECT {}, {}, {} FROM {} ORDER BY {}",
+            self.partition_value_column().get_name(),
+            self.partition_table_column().get_name(),
+            row_count,
+            quote_identifier(self.name()),
+            self.partition_value_column().get_name()
+        ))?;
+        statement.query(())?;
+        let mut partitions = Vec::new();
+        while let Some(row) = statement.next()? {
+            let rows = match row[2].value_type() {
+                ValueType::Null => None,
+                _ => Some(row[2].get_i64()),
+            };
+            partitions.push((row[0].get_i64(), row[1].get_str()?.to_owned(), rows));
+        }
+        Ok(partitions)
+    }
+
+    /// Reloads the in-memory partitions map if the partitions changed since it was last loaded.
+    ///
+    /// Every change to the recorded partitions replaces the generation kept in the root table,
+    /// in the same transaction, so a changed generation tells of partitions recorded, merged,
+    /// split or removed by any connection of any process, or of this instance's own changes
+    /// being rolled back. A lookup table keeps up with the generation across its own changes,
+    /// see [`LookupTable::bump_generation`]. [`LookupTable::sync`] only ever adds partitions,
+    /// and only finds those recorded past the rowids it read, so this is what keeps the map
+    /// from listing partitions that no longer exist.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    ///
+    /// # Returns
+    /// - `Result<bool>`: Whether the map is known to hold every recorded partition. Root tables
+    ///   created before the generation was kept lack it until the partitions first change,
+    ///   their maps are never known to be complete.
+    pub(crate) fn refresh(&self, db: &Connection) -> ExtResult<bool> {
+        let Some(generation) = self.generation(db)? else {
+            return Ok(false);
+        };
+        let seen = *self
+            .generation_seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if seen != Some(generation) {
+            self.reload(db)?;
+        }
+        Ok(true)
+    }
+
+    /// Reads the generation of the partitions from the root table, treating NULL, the value of
+    /// a table whose partitions never changed, as `0`.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    ///
+    /// # Returns
+    /// - `Result<Option<i64>>`: The generation, or `None` if the root table lacks the column
+    ///   or does not exist yet, or cannot be read while the schema is reloaded, as when planning
+    ///   the first statement after a rollback.
+    fn generation(&self, db: &Connection) -> ExtResult<Option<i64>> {
+        if self.generation_column(db)? != Some(true) {
+            return Ok(None);
+        }
+        let generation = db.query_row(
+            &format!(
+                "SELECT coalesce({}, 0) FROM {}",
+                RootTable::GENERATION_COLUMN,
+                quote_identifier(&self.root_name)
+            ),
+            (),
+            |row| Ok(row[0].get_i64()),
+        );
+        match generation {
+            Ok(generation) => Ok(Some(generation)),
+            Err(sqlite3_ext::Error::Sqlite(code, _)) if code & 0xff == SQLITE_SCHEMA => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Looks up whether the root table has the generation column, remembering once it does.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    ///
+    /// # Returns
+    /// - `Result<Option<bool>>`: Whether the column exists, or `None` if the root table does
+    ///   not.
+    fn generation_column(&self, db: &Connection) -> ExtResult<Option<bool>> {
+        if self.generation_column.load(Ordering::Acquire) {
+            return Ok(Some(true));
+        }
+        let exists = db.query_row(
+            "SELECT count(*), coalesce(sum(name = ?), 0) FROM pragma_table_info(?)",
+            params![RootTable::GENERATION_COLUMN, self.root_name.as_str()],
+            |row| {
+                Ok(match row[0].get_i64() {
+                    0 => None,
+                    _ => Some(row[1].get_i64() > 0),
+                })
+            },
+        )?;
+        if exists == Some(true) {
+            self.generation_column.store(true, Ordering::Release);
+        }
+        Ok(exists)
+    }
+
+    /// Replaces the generation in the root table after a change to the recorded partitions,
+    /// adding the column to root tables created before it was kept.
+    ///
+    /// The map already holds the change, so if the generation was still the one the map was
+    /// kept up with, the map stays current under the new one. Otherwise another connection
+    /// changed the partitions in between, and the next [`LookupTable::refresh`] reloads.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    fn bump_generation(&self, db: &Connection) -> ExtResult<()> {
+        match self.generation_column(db)? {
+            None => return Ok(()),
+            Some(true) => {}
+            Some(false) => {
+                db.execute(
+                    &format!(
+                        "ALTER TABLE {} ADD COLUMN {} INTEGER",
+                        quote_identifier(&self.root_name),
+                        RootTable::GENERATION_COLUMN
+                    ),
+                    (),
+                )?;
+            }
+        }
+        let generation = db.query_row("SELECT random()", (), |row| Ok(row[0].get_i64()))?;
+        let update = format!(
+            "UPDATE {} SET {} = ?",
+            quote_identifier(&self.root_name),
+            RootTable::GENERATION_COLUMN
+        );
+        let mut seen = self
+            .generation_seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(previous) = *seen {
+            let kept_up = db.execute(
+                &format!(
+                    "{update} WHERE coalesce({}, 0) = ?",
+                    RootTable::GENERATION_COLUMN
+                ),
+                params![generation, previous],
+            )? > 0;
+            if kept_up {
+                *seen = Some(generation);
+                return Ok(());
+            }
+            *seen = None;
+        }
+        db.execute(&update, params![generation])?;
+        Ok(())
+    }
+
+    /// Makes the next [`LookupTable::refresh`] reload the partitions map, e.g. once changes it
+    /// holds were rolled back.
+    fn forget_generation(&self) {
+        *self
+            .generation_seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Discards the in-memory partitions map and rebuilds it from the whole lookup table.
+    ///
+    /// Unlike [`LookupTable::sync`], entries that no longer exist in the database are removed,
+    /// and partitions recorded below the largest partition value and rowid seen, which only
+    /// happens after deletes, are found.
+    ///
+    /// # Parameters
+    /// - `db`: A reference to the database connection.
+    pub(crate) fn reload(&self, db: &Connection) -> ExtResult<()> {
+        // Read before the lookup table, so partitions changed meanwhile trigger the next reload.
+        let generation = self.generation(db)?;
+        let mut partitions = self.write_partitions();
+        self.unmap_partitions(&mut partitions, |_| false);
+        self.forget_synced();
+        drop(partitions);
+        self.sync(db)?;
+        *self
+            .generation_seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = generation;
+        Ok(())
+    }
+}
+
+/// Widens a range of partition values to the range of the partitions map covering every
+/// partition key of those values.
+///
+/// Parameters:
+/// - `from`: The lower bound of the partition values.
+/// - `to`: The upper bound of the partition values.
+///
+/// Returns:
+/// - The bounds of the partitions map entries within the range.
+fn key_range(
+    from: &Bound<i64>,
+    to: &Bound<i64>,
+) -> (Bound<PartitionMapKey>, Bound<PartitionMapKey>) {
+    let from = match from {
+        Bound::Included(value) => Bound::Included((*value, i64::MIN)),
+        Bound::Excluded(value) => Bound::Excluded((*value, i64::MAX)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let to = match to {
+        Bound::Included(value) => Bound::Included((*value, i64::MAX)),
+        Bound::Excluded(value) => Bound::Excluded((*value, i64::MIN)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (from, to)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::shadow_tables::interface::VirtualTable;
-    use crate::ColumnDeclarations;
+    use crate::{ColumnDeclarations, TableOptions};
 
     use super::*;
 
@@ -413,8 +1774,7 @@ mod tests {
 
     // Function to set up an in-memory database connection
     fn setup_db(rusq_conn: &RusqConn) -> &Connection {
-        let conn = Connection::from_rusqlite(rusq_conn);
-        conn
+        Connection::from_rusqlite(rusq_conn)
     }
     fn init_rusq_conn() -> RusqConn {
         RusqConn::open_in_memory().unwrap()
@@ -424,9 +1784,15 @@ mod tests {
         let declarations =
             ColumnDeclarations::from_iter(&["col1 timestamp partition_column", "col2 text"]);
 
-        let virtual_table =
-            VirtualTable::create(db, "test", declarations, "col1".to_string(), 3600, None).unwrap();
-        virtual_table
+        VirtualTable::create(
+            db,
+            "test",
+            declarations,
+            "col1".to_string(),
+            3600,
+            TableOptions::default(),
+        )
+        .unwrap()
     }
     #[test]
     fn test_create_table_query() {
@@ -437,7 +1803,7 @@ mod tests {
         let query = LookupTable::table_query(lookup.schema()).unwrap();
         assert_eq!(
             query,
-            "CREATE TABLE test_lookup (partition_table TEXT UNIQUE, partition_value INTEGER UNIQUE, expires_at INTEGER);"
+            "CREATE TABLE \"test_lookup\" (\"partition_table\" TEXT UNIQUE, \"partition_value\" INTEGER, \"expires_at\" INTEGER, \"row_count\" INTEGER DEFAULT 0, \"partition_key\" INTEGER DEFAULT 0, \"merged_through\" INTEGER, \"split_from\" INTEGER, \"split_interval\" INTEGER, \"list_value\" TEXT, UNIQUE (\"partition_value\", \"partition_key\"), UNIQUE (\"list_value\", \"partition_key\"));"
         );
     }
     #[test]
@@ -465,13 +1831,90 @@ mod tests {
             virtual_table.connection,
             partition_name,
             partition_value,
+            0,
             lifetime,
+            None,
         )?;
         assert_eq!(partition, partition_name);
 
         Ok(())
     }
     #[test]
+    fn test_poisoned_partitions_lock() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        let virtual_table = setup_lookup_table(db);
+        let lookup_table = virtual_table.lookup();
+        lookup_table.insert(db, "test_1710000000", 1710000000, 0, None, None)?;
+
+        // A panic while the map is held poisons the lock.
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut partitions = lookup_table.partitions.write().unwrap();
+            partitions.insert((1710003600, 0), "half written".to_owned());
+            panic!("panic while holding the partitions map");
+        }));
+        assert!(panicked.is_err());
+        assert!(lookup_table.partitions.is_poisoned());
+
+        // The map is recovered from the lookup table, dropping what the panic left behind.
+        assert_eq!(lookup_table.get_partition(&1710003600)?, None);
+        assert!(!lookup_table.partitions.is_poisoned());
+        assert_eq!(
+            lookup_table.get_partitions_by_range(db, &Bound::Unbounded, &Bound::Unbounded)?,
+            vec![(1710000000, "test_1710000000".to_owned())]
+        );
+        lookup_table.insert(db, "test_1710007200", 1710007200, 0, None, None)?;
+        assert_eq!(
+            lookup_table.get_partition(&1710007200)?.as_deref(),
+            Some("test_1710007200")
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_undo_changes() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        let virtual_table = setup_lookup_table(db);
+        let lookup_table = virtual_table.lookup();
+        // Changes are only noted while a transaction is tracked.
+        lookup_table.insert(db, "test_1709996400", 1709996400, 0, None, None)?;
+        assert_eq!(lookup_table.change_count(), 0);
+        let committed = lookup_table.snapshot()?;
+
+        db.execute("BEGIN", ())?;
+        lookup_table.track_changes();
+        lookup_table.insert(db, "test_1710000000", 1710000000, 0, None, None)?;
+        let mark = lookup_table.change_count();
+        let before_savepoint = lookup_table.snapshot()?;
+        db.execute("SAVEPOINT inner", ())?;
+        lookup_table.insert(db, "test_1710003600", 1710003600, 0, None, None)?;
+        lookup_table.insert(db, "test_1710003600_7", 1710003600, 7, None, None)?;
+        lookup_table.remove(db, &[1709996400])?;
+        assert_eq!(lookup_table.snapshot()?.len(), 3);
+
+        // Rolling back to the savepoint restores the map as it was when it was taken.
+        db.execute("ROLLBACK TO inner", ())?;
+        lookup_table.undo_changes(mark);
+        assert_eq!(lookup_table.snapshot()?, before_savepoint);
+        assert_eq!(lookup_table.get_keyed_partition(1710003600, 7)?, None);
+        assert_eq!(
+            lookup_table.get_partition(&1709996400)?.as_deref(),
+            Some("test_1709996400")
+        );
+
+        // Rolling back the transaction undoes the rest.
+        db.execute("ROLLBACK", ())?;
+        lookup_table.undo_changes(0);
+        lookup_table.untrack_changes();
+        assert_eq!(lookup_table.snapshot()?, committed);
+        assert_eq!(lookup_table.change_count(), 0);
+        assert_eq!(
+            lookup_table.get_partitions_by_range(db, &Bound::Unbounded, &Bound::Unbounded)?,
+            vec![(1709996400, "test_1709996400".to_owned())]
+        );
+        Ok(())
+    }
+    #[test]
     fn test_sync() -> sqlite3_ext::Result<()> {
         let rusq_conn = init_rusq_conn();
         let db = setup_db(&rusq_conn);
@@ -485,7 +1928,9 @@ mod tests {
                 virtual_table.connection,
                 &format!("test_{}", partition_value),
                 partition_value,
+                0,
                 lifetime,
+                None,
             )?;
             let partition_name = lookup_table.get_partition(&partition_value)?;
             assert!(partition_name.is_some());
@@ -510,6 +1955,68 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn test_incremental_sync() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        let virtual_table = setup_lookup_table(db);
+        let writer = virtual_table.lookup();
+        for partition_value in (1..=1000).map(|hour| hour * 3600) {
+            writer.insert(
+                db,
+                &format!("test_{}", partition_value),
+                partition_value,
+                0,
+                None,
+                None,
+            )?;
+        }
+
+        let reader = LookupTable::connect(db, "test")?;
+        let (full_query, params) = reader.sync_query(None);
+        assert!(params.is_empty());
+        reader.sync(db)?;
+        assert_eq!(reader.read_partitions().len(), 1000);
+        assert_eq!(
+            *reader.synced_through.lock().unwrap(),
+            Some((3_600_000, 1000))
+        );
+
+        // Later syncs read only what was added since, with a query of a fixed size.
+        let (query, params) = reader.sync_query(*reader.synced_through.lock().unwrap());
+        assert_eq!(params, vec![3_600_000, 1000]);
+        assert_eq!(
+            query,
+            format!("{full_query} WHERE partition_value > ? OR rowid > ?")
+        );
+        let rows = db.query_row(&format!("SELECT count(*) FROM ({query})"), params, |row| {
+            Ok(row[0].get_i64())
+        })?;
+        assert_eq!(rows, 0);
+
+        // A newer partition, and an older one created for a late row, are both picked up.
+        writer.insert(db, "test_3603600", 3_603_600, 0, None, None)?;
+        writer.insert(db, "test_0", 0, 0, None, None)?;
+        reader.sync(db)?;
+        assert_eq!(
+            reader.get_partition(&3_603_600)?.as_deref(),
+            Some("test_3603600")
+        );
+        assert_eq!(reader.get_partition(&0)?.as_deref(), Some("test_0"));
+        assert_eq!(
+            *reader.synced_through.lock().unwrap(),
+            Some((3_603_600, 1002))
+        );
+
+        // Deleted partitions need a full resync to leave the map.
+        db.execute("DELETE FROM test_lookup WHERE partition_value < 36000", ())?;
+        reader.sync(db)?;
+        assert_eq!(reader.read_partitions().len(), 1002);
+        reader.reload(db)?;
+        assert_eq!(reader.read_partitions().len(), 992);
+        assert_eq!(reader.get_partition(&0)?, None);
+        Ok(())
+    }
+    #[test]
     fn test_get_by_range() -> sqlite3_ext::Result<()> {
         let rusq_conn = init_rusq_conn();
         let db = setup_db(&rusq_conn);
@@ -523,7 +2030,9 @@ mod tests {
                 virtual_table.connection,
                 &format!("test_{}", partition_value),
                 partition_value,
+                0,
                 lifetime,
+                None,
             )?;
             let partition_name = lookup_table.get_partition(&partition_value)?;
             assert!(partition_name.is_some());
@@ -538,4 +2047,138 @@ mod tests {
         assert!(partitions.len() == 2);
         Ok(())
     }
+    #[test]
+    fn test_known_empty_range_skips_sync() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        let virtual_table = setup_lookup_table(db);
+        let lookup_table = virtual_table.lookup();
+        let lifetime = virtual_table.lifetime();
+        for partition_value in [1710000000, 1710014400] {
+            lookup_table.insert(
+                db,
+                &format!("test_{}", partition_value),
+                partition_value,
+                0,
+                lifetime,
+                None,
+            )?;
+        }
+        lookup_table.sync(db)?;
+        // A partition recorded without replacing the generation, which the map cannot notice.
+        db.execute(
+            "INSERT INTO test_lookup VALUES ('test_1710007200', 1710007200, NULL, 0, 0, NULL, NULL, NULL, NULL)",
+            (),
+        )?;
+        let gap = (Bound::Included(1710003600), Bound::Excluded(1710010800));
+        // When configured to, the range is answered from the map without syncing.
+        lookup_table.set_sync_empty_ranges(false);
+        assert!(lookup_table
+            .get_partitions_by_range(db, &gap.0, &gap.1)?
+            .is_empty());
+
+        // By default the range is synced.
+        lookup_table.set_sync_empty_ranges(true);
+        assert_eq!(
+            lookup_table.get_partitions_by_range(db, &gap.0, &gap.1)?,
+            vec![(1710007200, "test_1710007200".to_string())]
+        );
+
+        // A partition recorded by another instance replaces the generation, so the map is
+        // reloaded rather than trusted.
+        lookup_table.set_sync_empty_ranges(false);
+        let other = LookupTable::connect(db, "test")?;
+        other.insert(db, "test_1710010800", 1710010800, 0, lifetime, None)?;
+        assert_eq!(
+            lookup_table.get_partitions_by_range(
+                db,
+                &Bound::Excluded(1710007200),
+                &Bound::Excluded(1710014400)
+            )?,
+            vec![(1710010800, "test_1710010800".to_string())]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_refresh_after_changes_by_other_connection() -> sqlite3_ext::Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "partitioner_lookup_refresh_{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let first_conn = RusqConn::open(&path).unwrap();
+        let second_conn = RusqConn::open(&path).unwrap();
+        let first_db = setup_db(&first_conn);
+        let second_db = setup_db(&second_conn);
+        setup_lookup_table(first_db);
+        let first = LookupTable::connect(first_db, "test")?;
+        first.insert(first_db, "test_1710000000", 1710000000, 0, None, None)?;
+        first.insert(first_db, "test_1710003600", 1710003600, 0, None, None)?;
+        let second = LookupTable::connect(second_db, "test")?;
+        assert_eq!(second.read_partitions().len(), 2);
+
+        // The other connection drops the last partition and records an earlier one, reusing
+        // its rowid, which an incremental sync would not find.
+        first.remove(first_db, &[1710003600])?;
+        first.insert(first_db, "test_1709996400", 1709996400, 0, None, None)?;
+        assert_eq!(
+            second.get_partitions_by_range(second_db, &Bound::Unbounded, &Bound::Unbounded)?,
+            vec![
+                (1709996400, "test_1709996400".to_string()),
+                (1710000000, "test_1710000000".to_string())
+            ]
+        );
+
+        // The generation is left alone while nothing changes, and kept up with across the
+        // connection's own changes.
+        assert!(second.refresh(second_db)?);
+        let generation = second.generation(second_db)?;
+        assert!(second.refresh(second_db)?);
+        assert_eq!(second.generation(second_db)?, generation);
+        second.insert(second_db, "test_1710007200", 1710007200, 0, None, None)?;
+        assert_ne!(second.generation(second_db)?, generation);
+        assert_eq!(
+            *second.generation_seen.lock().unwrap(),
+            second.generation(second_db)?
+        );
+        drop((first, second));
+        drop((first_conn, second_conn));
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+    #[test]
+    fn test_insert_race_adopts_recorded_partition() -> sqlite3_ext::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("partitioner_lookup_race_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let first_conn = RusqConn::open(&path).unwrap();
+        let second_conn = RusqConn::open(&path).unwrap();
+        let first_db = setup_db(&first_conn);
+        let second_db = setup_db(&second_conn);
+        setup_lookup_table(first_db);
+        let first = LookupTable::connect(first_db, "test")?;
+        let second = LookupTable::connect(second_db, "test")?;
+
+        // Both connections miss the partition, the first one records it first.
+        assert_eq!(
+            first.insert(first_db, "test_1710000000", 1710000000, 0, None, None)?,
+            "test_1710000000"
+        );
+        assert!(second.get_partition(&1710000000)?.is_none());
+        let name = second.insert(second_db, "test_1710000000_late", 1710000000, 0, None, None)?;
+        assert_eq!(name, "test_1710000000");
+        assert_eq!(
+            second.get_partition(&1710000000)?,
+            Some("test_1710000000".to_string())
+        );
+
+        // Other constraint violations are still reported.
+        assert!(second
+            .insert(second_db, "test_1710000000", 1710003600, 0, None, None)
+            .is_err());
+        drop((first, second));
+        drop((first_conn, second_conn));
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
 }
@@ -1,11 +1,19 @@
+pub mod config_view;
+mod import;
 pub mod interface;
 pub mod lookup_table;
 pub mod operations;
 mod partition_interface;
+pub mod partitions_view;
 pub mod root_table;
+mod scan;
 pub mod template_table;
+pub use config_view::*;
+pub use import::{ImportMode, ImportReport};
 pub use lookup_table::*;
-pub use partition_interface::partition::Partition;
+pub use partition_interface::partition::{Partition, PartitionFilter};
+pub use partition_interface::prefetch::{PartitionPrefetch, PrefetchedRow, PrefetchedValue};
+pub use partitions_view::*;
 
 pub use root_table::*;
 use sqlite3_ext::ValueType;
@@ -13,40 +21,167 @@ pub use template_table::*;
 
 use crate::{error::TableError, ColumnDeclaration, ColumnDeclarations};
 
+/// How rows are assigned to partitions, declared by the first argument of the create statement
+/// and stored in the root table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum PartitionValue {
+    /// Rows are bucketed by time, the partition value is the interval boundary of the partition
+    /// column value, e.g. `partitioner(1 hour, ...)`.
+    #[default]
     Interval,
+    /// Rows are bucketed by a hash of the partition column value, the partition value is the
+    /// hash modulo the number of partitions, e.g. `partitioner(hash 8, ...)`.
+    Hash,
+    /// Every distinct value of the partition column gets a partition of its own, the partition
+    /// value is a hash of the value, e.g. `partitioner(list, ...)`. Declared with values, as in
+    /// `partitioner(list ('EU', 'US'), ...)`, only those values get partitions.
+    List,
 }
 
 impl PartitionValue {
+    /// The name of the root table column the strategy is stored in.
+    pub const OPTION: &'static str = "strategy";
+
+    /// The keyword introducing hash partitioning in the create statement, as in `hash 8`.
+    pub const HASH_KEYWORD: &'static str = "hash";
+
+    /// The keyword declaring list partitioning in the create statement.
+    pub const LIST_KEYWORD: &'static str = "list";
+
     const fn to_valuetype(partitionvalue: Self) -> ValueType {
         match partitionvalue {
-            Self::Interval => ValueType::Integer,
+            Self::Interval | Self::Hash | Self::List => ValueType::Integer,
+        }
+    }
+
+    /// Returns the name of the strategy, as stored in the root table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartitionValue::Interval => "interval",
+            PartitionValue::Hash => Self::HASH_KEYWORD,
+            PartitionValue::List => Self::LIST_KEYWORD,
         }
     }
+
+    /// Parses the first argument of the create statement if it declares hash or list
+    /// partitioning. The values list partitioning may be declared with are read by
+    /// [`PartitionValue::list_values_argument`].
+    ///
+    /// Parameters:
+    /// - `arg`: The first argument of the create statement, e.g. `hash 8`, `list`,
+    ///   `list ('EU', 'US')` or `1 hour`.
+    ///
+    /// Returns:
+    /// - `None` if the argument declares neither, e.g. because it is an interval.
+    /// - `Some(Ok(..))` with the strategy and the value stored in place of the interval, the
+    ///   number of partitions for hash partitioning and `0` for list partitioning.
+    /// - `Some(Err(..))` if the number of hash partitions is not a positive integer.
+    pub fn from_argument(arg: &str) -> Option<Result<(Self, i64), TableError>> {
+        if let Some(values) = Self::list_arguments(arg) {
+            return Some(match values.is_empty() || Self::list_values_argument(arg).is_some() {
+                true => Ok((PartitionValue::List, 0)),
+                false => Err(TableError::ParseInterval(format!(
+                    "Expected the values of list partitioning in parentheses such as {} ('EU', 'US'), found '{}'",
+                    Self::LIST_KEYWORD,
+                    arg.trim()
+                ))),
+            });
+        }
+        let mut tokens = arg.split_whitespace();
+        let keyword = tokens.next()?;
+        if !keyword.eq_ignore_ascii_case(Self::HASH_KEYWORD) {
+            return None;
+        }
+        let partitions = match (tokens.next(), tokens.next()) {
+            (Some(count), None) => count.parse::<i64>().ok().filter(|count| *count > 0),
+            _ => None,
+        };
+        Some(
+            partitions
+                .map(|partitions| (PartitionValue::Hash, partitions))
+                .ok_or_else(|| {
+                    TableError::ParseInterval(format!(
+                        "Expected a positive number of partitions such as '{} 8', found '{}'",
+                        Self::HASH_KEYWORD,
+                        arg.trim()
+                    ))
+                }),
+        )
+    }
+
+    /// Retrieves the values list partitioning is declared with, as in `list ('EU', 'US')`.
+    ///
+    /// Parameters:
+    /// - `arg`: The first argument of the create statement.
+    ///
+    /// Returns:
+    /// - The text between the parentheses, to be read by
+    ///   [`parse_list_values`](crate::utils::parse_list_values), or `None` if the argument does
+    ///   not declare list partitioning with values.
+    pub fn list_values_argument(arg: &str) -> Option<&str> {
+        Self::list_arguments(arg)?
+            .strip_prefix('(')?
+            .strip_suffix(')')
+    }
+
+    /// Splits the `list` keyword off the first argument of the create statement.
+    ///
+    /// Returns:
+    /// - What follows the keyword, trimmed, or `None` if the argument does not start with the
+    ///   keyword followed by a space, a parenthesis or nothing.
+    fn list_arguments(arg: &str) -> Option<&str> {
+        let arg = arg.trim();
+        let keyword = arg.get(..Self::LIST_KEYWORD.len())?;
+        let rest = &arg[Self::LIST_KEYWORD.len()..];
+        let separated =
+            rest.is_empty() || rest.starts_with(char::is_whitespace) || rest.starts_with('(');
+        (keyword.eq_ignore_ascii_case(Self::LIST_KEYWORD) && separated).then(|| rest.trim())
+    }
 }
 impl From<PartitionValue> for ValueType {
     fn from(value: PartitionValue) -> ValueType {
-        match value {
-            PartitionValue::Interval => ValueType::Integer,
-        }
+        PartitionValue::to_valuetype(value)
     }
 }
 impl<'a> From<&'a PartitionValue> for &'a ValueType {
     fn from(value: &'a PartitionValue) -> &'a ValueType {
         match value {
-            PartitionValue::Interval => &ValueType::Integer,
+            PartitionValue::Interval | PartitionValue::Hash | PartitionValue::List => {
+                &ValueType::Integer
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for PartitionValue {
+    type Error = TableError;
+
+    /// Parses the name of a strategy as stored in the root table, ignoring case.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "interval" => Ok(PartitionValue::Interval),
+            "hash" => Ok(PartitionValue::Hash),
+            "list" => Ok(PartitionValue::List),
+            _ => Err(TableError::Corrupt(format!(
+                "Unknown partitioning strategy '{}', expected interval, hash or list",
+                value
+            ))),
         }
     }
 }
 
 impl<'a> TryFrom<&'a ValueType> for PartitionValue {
     type Error = TableError;
+    /// Checks the declared type of a partition column. Timestamps are stored as text and parsed,
+    /// integers and floats are plain numbers cut into ranges of the interval, e.g. seconds since
+    /// the UNIX epoch. Hash and list partitioning accept text and integers, hashing the text or
+    /// the integer.
     fn try_from(value: &'a ValueType) -> Result<Self, Self::Error> {
         match value {
-            ValueType::Text => Ok(PartitionValue::Interval),
+            ValueType::Text | ValueType::Integer | ValueType::Float => Ok(PartitionValue::Interval),
             _ => Err(TableError::PartitionColumn(format!(
-                "Supported types for partition column: {:#?}",
-                "timestamp"
+                "Supported types for partition column: {:?}, {:?} or {:?}",
+                "timestamp", "integer", "float"
             ))),
         }
     }
@@ -6,6 +6,7 @@ use sqlite3_ext::Connection;
 use sqlite3_ext::Error as ExtError;
 use sqlite3_ext::FromValue;
 use sqlite3_ext::Result as ExtResult;
+use sqlparser::ast::ColumnOption;
 use sqlparser::ast::Ident;
 use sqlparser::ast::ObjectName;
 use sqlparser::ast::Statement as ParsedStatement;
@@ -13,6 +14,7 @@ use sqlparser::dialect::SQLiteDialect;
 use sqlparser::parser::Parser;
 
 use crate::error::TableError;
+use crate::utils::validation::{quote_identifier, unquote_identifier, validate_identifier};
 use crate::ColumnDeclaration;
 use crate::ColumnDeclarations;
 
@@ -66,12 +68,15 @@ pub trait Copy: Table {
                 nulls_distinct,
                 predicate,
             } => ParsedStatement::CreateIndex {
-                name: Some(ObjectName(vec![Ident::new(format!(
-                    "{}_{}",
-                    name.unwrap(),
-                    new_table
-                ))])),
-                table_name: ObjectName(vec![Ident::new(new_table)]),
+                name: Some(ObjectName(vec![Ident::with_quote(
+                    '"',
+                    format!(
+                        "{}_{}",
+                        unquote_identifier(&name.unwrap().to_string()),
+                        new_table
+                    ),
+                )])),
+                table_name: ObjectName(vec![Ident::with_quote('"', new_table)]),
                 using,
                 columns,
                 unique,
@@ -85,6 +90,26 @@ pub trait Copy: Table {
             _ => unreachable!(),
         }
     }
+
+    /// Generates an SQL statement creating a new table with the structure of a parsed CREATE
+    /// TABLE statement. The column definitions are kept as they are, including constraints and
+    /// generated column expressions, only the table name changes. The statement does nothing if
+    /// the new table already exists.
+    fn adjust_table_creation_statement(statement: &ParsedStatement, new_table: &str) -> String {
+        let mut statement = statement.to_owned();
+        match &mut statement {
+            ParsedStatement::CreateTable {
+                name,
+                if_not_exists,
+                ..
+            } => {
+                *name = ObjectName(vec![Ident::with_quote('"', new_table)]);
+                *if_not_exists = true;
+            }
+            _ => unreachable!(),
+        }
+        statement.to_string()
+    }
 }
 
 /// Defines behaviors for creating a new table in the database, including schema
@@ -137,11 +162,8 @@ pub trait Connect: Table {
     {
         let dialect = SQLiteDialect {};
         let parser = Parser::new(&dialect);
-        let schema_sql = format!(
-            "SELECT sql FROM sqlite_schema WHERE NAME = '{}'",
-            name // Self::Table::format_name(name)
-        );
-        let mut schema = db.query_row(&schema_sql, (), |result| {
+        let schema_sql = "SELECT sql FROM sqlite_schema WHERE NAME = ?";
+        let mut schema = db.query_row(schema_sql, [name], |result| {
             let sql = &sqlite3_ext::query::QueryResult::index_mut(result, 0).get_str()?;
             parser
                 .try_with_sql(sql)
@@ -159,9 +181,19 @@ pub trait Connect: Table {
         let column_declarations: Result<Vec<ColumnDeclaration>, TableError> =
             columns.iter().try_fold(Vec::default(), |mut acc, column| {
                 let column_name = column.name.to_string();
+                validate_identifier(&column_name)?;
                 let data_type = column.data_type.to_string();
-                let column_declaration =
+                let mut column_declaration =
                     ColumnDeclaration::try_from(format!("{column_name} {data_type}").as_str())?;
+                column_declaration.set_primary_key(column.options.iter().any(|option| {
+                    matches!(
+                        option.option,
+                        ColumnOption::Unique {
+                            is_primary: true,
+                            ..
+                        }
+                    )
+                }));
 
                 acc.push(column_declaration);
                 Ok(acc)
@@ -170,7 +202,7 @@ pub trait Connect: Table {
             Ok(value) => Ok(value),
             Err(err) => Err(sqlite3_ext::Error::Module(err.to_string())),
         }?;
-        let name = name.to_string();
+        let name = unquote_identifier(&name.to_string());
         Ok(SchemaDeclaration::new(
             name,
             ColumnDeclarations(column_declarations),
@@ -194,7 +226,7 @@ pub trait Drop: Table {
     /// Constructs the SQL query string for dropping the table, specifying the
     /// command for execution in the database environment.
     fn drop_table_query(&self) -> String {
-        format!("DROP TABLE {}", &self.name())
+        format!("DROP TABLE {}", quote_identifier(self.name()))
     }
 }
 
@@ -245,7 +277,7 @@ impl SchemaDeclaration {
     ///
     /// Returns the SQL CREATE TABLE query as a `String`.
     pub fn table_query(&self) -> String {
-        let table_name = self.name();
+        let table_name = quote_identifier(self.name());
         let columns: String = self.columns().to_string();
         format!("CREATE TABLE {table_name} ({columns})")
     }
@@ -1 +1,2 @@
 pub mod partition;
+pub mod prefetch;
@@ -1,7 +1,9 @@
 use sqlite3_ext::query::{QueryResult, Statement, ToParam};
-use sqlite3_ext::{Connection, FallibleIteratorMut};
+use sqlite3_ext::vtab::ConstraintOp;
+use sqlite3_ext::{Connection, FallibleIteratorMut, FromValue, Value};
 
 use crate::constraints::Conditions;
+use crate::utils::validation::quote_identifier;
 use crate::ConstraintOpDef;
 
 /// Represents a database partition, encapsulating the SQL statement for querying
@@ -56,6 +58,183 @@ impl From<(String, Statement)> for Partition {
     }
 }
 
+/// The WHERE clause applied to every partition of a scan, together with owned copies of the
+/// values bound to it.
+///
+/// Owning the values allows the cursor to prepare the partitions one at a time, when it reaches
+/// them, instead of preparing every partition in range up front.
+#[derive(Debug, Default)]
+pub struct PartitionFilter {
+    /// The select list after the rowid, or `None` to select every column.
+    projection: Option<String>,
+    /// The WHERE clause, including the `WHERE` keyword, or an empty string.
+    where_clause: String,
+    /// The values bound to the placeholders of the WHERE clause, in order.
+    values: Vec<Value>,
+    /// The column the rows of a partition are ordered by, or `None` to read them in rowid order.
+    order_by: Option<String>,
+    /// Whether the rows are ordered descending by `order_by`.
+    descending: bool,
+    /// The name the rowid is selected by, `rowid` unless a column hides it, see
+    /// [`ColumnDeclarations::rowid_name`](crate::ColumnDeclarations::rowid_name).
+    rowid_name: Option<&'static str>,
+}
+
+impl PartitionFilter {
+    /// Restricts the query to the columns the statement actually reads.
+    ///
+    /// Unused columns are selected as `NULL`, which keeps every column at the same position in
+    /// the result while letting SQLite answer the query from a covering index of the partition.
+    ///
+    /// Parameters:
+    /// - `columns`: The declared columns in order, each paired with whether it is used.
+    ///
+    /// Returns the filter with the projection applied.
+    pub fn with_projection<'c>(
+        mut self,
+        columns: impl IntoIterator<Item = (&'c str, bool)>,
+    ) -> Self {
+        let projection = columns
+            .into_iter()
+            .map(|(name, used)| match used {
+                true => quote_identifier(name),
+                false => "NULL".to_owned(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.projection = Some(projection);
+        self
+    }
+
+    /// Orders the rows of each partition by a column, e.g. the partition column when it is the
+    /// primary key and thus indexed.
+    ///
+    /// Parameters:
+    /// - `column`: The name of the column to order by.
+    ///
+    /// Returns the filter with the ordering applied.
+    pub fn with_order_by(mut self, column: &str) -> Self {
+        self.order_by = Some(column.to_owned());
+        self
+    }
+
+    /// Reverses the order set by [`PartitionFilter::with_order_by`], reading the rows of each
+    /// partition in descending order.
+    ///
+    /// Returns the filter with the order reversed.
+    pub fn with_descending_order(mut self) -> Self {
+        self.descending = true;
+        self
+    }
+
+    /// Selects the rowid by another name than `rowid`, for partitions with a column named
+    /// `rowid`.
+    ///
+    /// Parameters:
+    /// - `rowid_name`: `rowid`, `_rowid_` or `oid`.
+    ///
+    /// Returns the filter selecting the rowid by that name.
+    pub fn with_rowid_name(mut self, rowid_name: &'static str) -> Self {
+        self.rowid_name = Some(rowid_name);
+        self
+    }
+
+    /// Builds the query for a single partition.
+    ///
+    /// Parameters:
+    /// - `partition_name`: The name of the partition to query.
+    ///
+    /// Returns the SQL SELECT query, with the rowid as the first column.
+    pub fn query(&self, partition_name: &str) -> String {
+        let query = format!(
+            "SELECT {}, {} FROM {} {}",
+            self.rowid_name.unwrap_or("rowid"),
+            self.projection.as_deref().unwrap_or("*"),
+            quote_identifier(partition_name),
+            self.where_clause
+        );
+        match &self.order_by {
+            Some(column) => format!(
+                "{} ORDER BY {}{}",
+                query,
+                quote_identifier(column),
+                if self.descending { " DESC" } else { "" }
+            ),
+            None => query,
+        }
+    }
+
+    /// Retrieves the values bound to the placeholders of the WHERE clause, in order.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Prepares the query for a single partition and binds the filter values.
+    ///
+    /// Parameters:
+    /// - `db`: The connection the partition lives on.
+    /// - `partition_name`: The name of the partition to query.
+    ///
+    /// Returns a `Partition` ready to be iterated, or an error if the SQL statement preparation
+    /// or parameter binding fails.
+    pub fn prepare(&self, db: &Connection, partition_name: &str) -> sqlite3_ext::Result<Partition> {
+        let mut stmt = db.prepare(&self.query(partition_name))?;
+        for (index, value) in self.values.iter().enumerate() {
+            value.clone().bind_param(&mut stmt, (index + 1) as i32)?;
+        }
+        Ok(Partition::from((partition_name.to_string(), stmt)))
+    }
+}
+
+impl<'a> TryFrom<Option<&Conditions<'a>>> for PartitionFilter {
+    type Error = sqlite3_ext::Error;
+
+    /// Converts the conditions into a WHERE clause. Unary operators such as `IS NULL` do not
+    /// take a placeholder, so their value is not bound.
+    fn try_from(conditions: Option<&Conditions<'a>>) -> Result<Self, Self::Error> {
+        let Some(conditions) = conditions else {
+            return Ok(Self::default());
+        };
+        let mut values = Vec::new();
+        let condition_str = conditions
+            .as_slice()
+            .iter()
+            .map(|condition| {
+                let operator = ConstraintOpDef::from(*condition.operator);
+                match condition.operator {
+                    ConstraintOp::IsNull | ConstraintOp::IsNotNull => Ok(format!(
+                        "{} {}",
+                        quote_identifier(condition.column),
+                        operator
+                    )),
+                    _ => {
+                        values.push(condition.value.to_owned()?);
+                        Ok(format!(
+                            "{} {} ?",
+                            quote_identifier(condition.column),
+                            operator
+                        ))
+                    }
+                }
+            })
+            .collect::<sqlite3_ext::Result<Vec<String>>>()?
+            .join(" AND ");
+        let where_clause = if condition_str.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", condition_str)
+        };
+        Ok(Self {
+            projection: None,
+            where_clause,
+            values,
+            order_by: None,
+            descending: false,
+            rowid_name: None,
+        })
+    }
+}
+
 type PartitionName<'query> = &'query str;
 type PartitionConditions<'query> = Option<&'query Conditions<'query>>;
 type PartitionArgs<'vtab, 'query> = (
@@ -76,41 +255,6 @@ impl<'vtab, 'query> TryFrom<PartitionArgs<'vtab, 'query>> for Partition {
     /// or parameter binding fails.
     fn try_from(value: PartitionArgs) -> Result<Self, Self::Error> {
         let (db, partition_name, conditions) = value;
-        let where_clause = if let Some(conditions) = conditions {
-            let condition_str = conditions
-                .as_slice()
-                .iter()
-                .map(|condition| {
-                    format!(
-                        "{} {} {}",
-                        condition.column,
-                        ConstraintOpDef::from(*condition.operator),
-                        "?"
-                    )
-                })
-                .collect::<Vec<String>>()
-                .join(" AND ");
-
-            format!("WHERE {}", condition_str)
-        } else {
-            String::new()
-        };
-
-        let sql = format!(
-            "SELECT rowid as row_id, * FROM {} {}",
-            partition_name, where_clause
-        );
-        let mut stmt = db.prepare(&sql)?;
-        conditions.map(|conditions| {
-            conditions
-                .as_slice()
-                .iter()
-                .enumerate()
-                .try_for_each(|(index, condition)| {
-                    condition.value.bind_param(&mut stmt, (index + 1) as i32)
-                })
-        });
-
-        Ok(Partition::from((partition_name.to_string(), stmt)))
+        PartitionFilter::try_from(conditions)?.prepare(db, partition_name)
     }
 }
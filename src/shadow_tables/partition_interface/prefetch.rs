@@ -0,0 +1,428 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use sqlite3_ext::query::ToParam;
+use sqlite3_ext::vtab::ColumnContext;
+use sqlite3_ext::{
+    Blob, Database, FallibleIteratorMut, FromValue, OpenFlags, Value, ValueRef, ValueType,
+};
+
+use super::partition::PartitionFilter;
+use crate::utils::registry::PartitionValueParser;
+
+/// The number of rows a worker passes on at once.
+const BATCH_ROWS: usize = 256;
+
+/// The number of batches a worker reads ahead of the cursor in each partition before it waits.
+const PREFETCH_BATCHES: usize = 4;
+
+/// How often the workers start their read transactions again when another connection commits
+/// while they start them, before the scan is left to the cursor.
+const SNAPSHOT_ATTEMPTS: usize = 3;
+
+/// What a worker sends for a partition: batches of its rows, then `None` once the partition is
+/// exhausted.
+type PrefetchMessage = sqlite3_ext::Result<Option<Vec<PrefetchedRow>>>;
+
+/// An owned copy of a value read by a worker. Unlike [`Value`] it can be sent between threads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrefetchedValue {
+    /// An integer.
+    Integer(i64),
+    /// A floating point number.
+    Float(f64),
+    /// A text.
+    Text(String),
+    /// A BLOB.
+    Blob(Vec<u8>),
+    /// NULL.
+    Null,
+}
+
+impl PrefetchedValue {
+    /// Copies a value read from a partition.
+    fn read(value: &ValueRef) -> sqlite3_ext::Result<Self> {
+        Ok(match value.value_type() {
+            ValueType::Integer => Self::Integer(value.get_i64()),
+            ValueType::Float => Self::Float(value.get_f64()),
+            ValueType::Text => Self::Text(value.try_get_str()?.to_owned()),
+            ValueType::Blob => Self::Blob(value.try_get_blob()?.to_vec()),
+            ValueType::Null => Self::Null,
+        })
+    }
+
+    /// Copies a value bound to the partition queries, so that the workers can bind it.
+    fn from_value(value: &Value) -> Self {
+        match value {
+            Value::Integer(value) => Self::Integer(*value),
+            Value::Float(value) => Self::Float(*value),
+            Value::Text(value) => Self::Text(value.clone()),
+            Value::Blob(value) => Self::Blob(value.as_slice().to_vec()),
+            Value::Null => Self::Null,
+        }
+    }
+
+    /// Converts the copy back into a value, on the thread that binds it.
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Integer(value) => Value::Integer(*value),
+            Self::Float(value) => Value::Float(*value),
+            Self::Text(value) => Value::Text(value.clone()),
+            Self::Blob(value) => Value::Blob(Blob::from(value.as_slice())),
+            Self::Null => Value::Null,
+        }
+    }
+
+    /// Sets the value as the result of a column of the virtual table.
+    pub fn set_result(&self, context: &ColumnContext) -> sqlite3_ext::Result<()> {
+        match self {
+            Self::Integer(value) => context.set_result(*value),
+            Self::Float(value) => context.set_result(*value),
+            Self::Text(value) => context.set_result(value.clone()),
+            Self::Blob(value) => context.set_result(value.as_slice()),
+            Self::Null => context.set_result(()),
+        }
+    }
+}
+
+/// A row of a partition read by a worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefetchedRow {
+    /// The values of the row as the partition query selects them, the rowid first.
+    values: Vec<PrefetchedValue>,
+    /// The epoch of the partition column, if the scan asked for it and the value parses.
+    epoch: Option<i64>,
+}
+
+impl PrefetchedRow {
+    /// Retrieves the rowid of the row within its partition.
+    pub fn rowid(&self) -> i64 {
+        match self.values.first() {
+            Some(PrefetchedValue::Integer(rowid)) => *rowid,
+            _ => 0,
+        }
+    }
+
+    /// Retrieves a declared column of the row, by its index among the declared columns.
+    pub fn column(&self, index: usize) -> Option<&PrefetchedValue> {
+        self.values.get(index + 1)
+    }
+
+    /// Retrieves the epoch of the partition column, see [`PartitionPrefetch::start`].
+    pub fn epoch(&self) -> Option<i64> {
+        self.epoch
+    }
+}
+
+/// How the workers read the partitions, shared between them.
+struct PrefetchJob {
+    /// The database file the workers open.
+    filename: String,
+    /// The partitions no worker has taken yet, in scan order, each with its query and the sender
+    /// its rows are passed on through.
+    queue: Mutex<VecDeque<(String, SyncSender<PrefetchMessage>)>>,
+    /// The values bound to every partition query.
+    values: Vec<PrefetchedValue>,
+    /// The index of the partition column among the selected values and its parser, if the
+    /// workers parse its epoch.
+    epoch_parser: Option<(usize, PartitionValueParser)>,
+    /// Set once the scan is abandoned, so that the workers stop early.
+    cancelled: AtomicBool,
+}
+
+/// Reads the partitions of a scan ahead of the cursor on worker threads, each with a read-only
+/// connection of its own.
+///
+/// The workers take the partitions in scan order, so the partition the cursor reads next is
+/// always being read or already done. Each partition passes batches of its rows through a bounded
+/// channel, which keeps the rows in the order of the partition query and limits how far the
+/// workers run ahead. The cursor drains the partitions one after the other, so the rows come out
+/// in the same order as a sequential scan produces them.
+///
+/// The workers only see data committed to the database file, and only run on databases in WAL
+/// mode, where their read transactions do not keep writers from committing. Each worker reads
+/// all of its partitions in one read transaction, started before any partition is handed out.
+/// `PRAGMA data_version` is read before and after the workers start them, and they start again
+/// if another connection committed in between, so they all read the same snapshot: a commit made
+/// during the scan is seen by none of them.
+pub struct PartitionPrefetch {
+    /// The partitions the cursor has not reached yet, in scan order.
+    pending: VecDeque<(String, Receiver<PrefetchMessage>)>,
+    /// The partition the cursor is reading.
+    current: Option<(String, Receiver<PrefetchMessage>)>,
+    /// The rows of the current batch after the one the cursor is positioned on.
+    batch: std::vec::IntoIter<PrefetchedRow>,
+    /// The row the cursor is positioned on.
+    row: Option<PrefetchedRow>,
+    /// The job the workers share, to cancel it.
+    job: Arc<PrefetchJob>,
+    /// The worker threads, joined once the prefetch is dropped.
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for PartitionPrefetch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartitionPrefetch")
+            .field("pending", &self.pending.len())
+            .field("current", &self.current_name())
+            .field("workers", &self.workers.len())
+            .finish()
+    }
+}
+
+impl PartitionPrefetch {
+    /// Starts the workers reading a list of partitions.
+    ///
+    /// # Parameters
+    /// - `filename`: The database file holding the partitions.
+    /// - `partitions`: The names of the partitions, in the order the cursor reads them.
+    /// - `filter`: The query applied to every partition.
+    /// - `epoch_parser`: The index of the partition column among the declared columns and the
+    ///   parser of its values, if the cursor checks rows against the time of their partition
+    ///   column. The workers parse it, see [`PrefetchedRow::epoch`].
+    /// - `workers`: The number of worker threads, at most one per partition is started.
+    ///
+    /// # Returns
+    /// The prefetch, not yet positioned on a partition, `None` if the database is not in WAL
+    /// mode or the workers could not start on one snapshot, or an error if a worker thread
+    /// cannot be started.
+    pub fn start(
+        filename: String,
+        partitions: Vec<String>,
+        filter: &PartitionFilter,
+        epoch_parser: Option<(usize, PartitionValueParser)>,
+        workers: usize,
+    ) -> sqlite3_ext::Result<Option<Self>> {
+        let Ok(witness) = Database::open_with_flags(&filename, OpenFlags::READONLY) else {
+            return Ok(None);
+        };
+        let wal = witness.query_row("PRAGMA journal_mode", (), |row| {
+            Ok(row[0].get_str()?.eq_ignore_ascii_case("wal"))
+        })?;
+        if !wal {
+            return Ok(None);
+        }
+        let mut queue = VecDeque::new();
+        let mut pending = VecDeque::new();
+        for partition in partitions {
+            let (sender, receiver) = sync_channel(PREFETCH_BATCHES);
+            queue.push_back((filter.query(&partition), sender));
+            pending.push_back((partition, receiver));
+        }
+        let workers = workers.min(pending.len());
+        let job = Arc::new(PrefetchJob {
+            filename,
+            queue: Mutex::new(queue),
+            values: filter
+                .values()
+                .iter()
+                .map(PrefetchedValue::from_value)
+                .collect(),
+            epoch_parser: epoch_parser.map(|(index, parser)| (index + 1, parser)),
+            cancelled: AtomicBool::new(false),
+        });
+        let mut prefetch = Self {
+            pending,
+            current: None,
+            batch: Vec::new().into_iter(),
+            row: None,
+            job,
+            workers: Vec::with_capacity(workers),
+        };
+        // Dropped before the prefetch, which waits for the workers, so that they stop waiting
+        // for a decision first.
+        let mut decisions = Vec::with_capacity(workers);
+        let data_version =
+            || witness.query_row("PRAGMA data_version", (), |row| Ok(row[0].get_i64()));
+        let mut version = data_version()?;
+        let (ready_sender, ready) = channel();
+        for worker in 0..workers {
+            let job = Arc::clone(&prefetch.job);
+            let ready_sender = ready_sender.clone();
+            let (decision, decided) = channel();
+            decisions.push(decision);
+            let handle = thread::Builder::new()
+                .name(format!("partition-prefetch-{worker}"))
+                .spawn(move || run_worker(&job, ready_sender, decided))
+                .map_err(|err| {
+                    sqlite3_ext::Error::Module(format!("Could not start a scan worker: {err}"))
+                })?;
+            prefetch.workers.push(handle);
+        }
+        drop(ready_sender);
+        for _ in 0..SNAPSHOT_ATTEMPTS {
+            // The channel closes early if a worker stops before it is ready.
+            if ready.iter().take(workers).count() < workers {
+                return Ok(None);
+            }
+            let started_version = std::mem::replace(&mut version, data_version()?);
+            let same_snapshot = started_version == version;
+            for decision in &decisions {
+                let _ = decision.send(same_snapshot);
+            }
+            if same_snapshot {
+                return Ok(Some(prefetch));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Moves on to the next partition, in the order the partitions were given.
+    ///
+    /// # Returns
+    /// The name of the partition, or `None` once every partition was reached.
+    pub fn next_partition(&mut self) -> Option<&str> {
+        self.batch = Vec::new().into_iter();
+        self.row = None;
+        self.current = self.pending.pop_front();
+        self.current_name()
+    }
+
+    /// Moves on to the next row of the current partition, waiting for its worker if needed.
+    ///
+    /// # Returns
+    /// The row, `None` once the partition is exhausted, or the error the worker ran into.
+    pub fn next_row(&mut self) -> sqlite3_ext::Result<Option<&PrefetchedRow>> {
+        self.row = self.batch.next();
+        if self.row.is_none() {
+            let Some((partition, receiver)) = &self.current else {
+                return Ok(None);
+            };
+            let batch = match receiver.recv() {
+                Ok(message) => message?,
+                Err(_) => {
+                    return Err(sqlite3_ext::Error::Module(format!(
+                        "The scan worker reading {partition} stopped unexpectedly"
+                    )))
+                }
+            };
+            match batch {
+                Some(batch) => {
+                    self.batch = batch.into_iter();
+                    self.row = self.batch.next();
+                }
+                None => self.current = None,
+            }
+        }
+        Ok(self.row.as_ref())
+    }
+
+    /// Retrieves the row the cursor is positioned on.
+    pub fn current_row(&self) -> Option<&PrefetchedRow> {
+        self.row.as_ref()
+    }
+
+    /// Retrieves the name of the partition the cursor is reading.
+    pub fn current_name(&self) -> Option<&str> {
+        self.current
+            .as_ref()
+            .map(|(partition, _)| partition.as_str())
+    }
+}
+
+impl Drop for PartitionPrefetch {
+    /// Stops the workers and waits for them, so that no read-only connection outlives the scan.
+    /// Dropping the receivers wakes workers waiting for the cursor.
+    fn drop(&mut self) {
+        self.job.cancelled.store(true, Ordering::Relaxed);
+        self.current = None;
+        self.pending.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Starts the read transaction a worker reads all of its partitions in.
+fn begin_snapshot(db: &Database) -> sqlite3_ext::Result<()> {
+    db.execute("BEGIN", ())?;
+    // The transaction only takes its snapshot with the first read.
+    db.query_row("SELECT count(*) FROM sqlite_schema", (), |_| Ok(()))
+}
+
+/// Takes partitions off the queue of a job until it is empty or cancelled.
+///
+/// The worker first opens its read-only connection and starts its read transaction, tells
+/// `ready` so and waits for `decided`: `true` keeps the transaction, `false` starts it again
+/// since another connection committed meanwhile. A closed channel abandons the scan.
+fn run_worker(job: &PrefetchJob, ready: Sender<()>, decided: Receiver<bool>) {
+    let opened = Database::open_with_flags(&job.filename, OpenFlags::READONLY);
+    let db = loop {
+        let started = opened
+            .as_ref()
+            .map_err(Clone::clone)
+            .and_then(|db| begin_snapshot(db).map(|_| db));
+        let _ = ready.send(());
+        match decided.recv() {
+            Ok(true) => break started,
+            Ok(false) => {
+                if let Ok(db) = &opened {
+                    let _ = db.execute("ROLLBACK", ());
+                }
+            }
+            Err(_) => return,
+        }
+    };
+    drop(ready);
+    while !job.cancelled.load(Ordering::Relaxed) {
+        let Some((query, sender)) = job
+            .queue
+            .lock()
+            .ok()
+            .and_then(|mut queue| queue.pop_front())
+        else {
+            return;
+        };
+        let result = match &db {
+            Ok(db) => read_partition(job, db, &query, &sender),
+            Err(err) => Err(err.clone()),
+        };
+        if let Err(err) = result {
+            let _ = sender.send(Err(err));
+        }
+    }
+}
+
+/// Runs the query of a partition, sending its rows in batches and then the end of the
+/// partition.
+///
+/// # Returns
+/// `Ok(())` once the partition is exhausted or the cursor stopped listening, or the error
+/// reading the partition failed with.
+fn read_partition(
+    job: &PrefetchJob,
+    db: &Database,
+    query: &str,
+    sender: &SyncSender<PrefetchMessage>,
+) -> sqlite3_ext::Result<()> {
+    let mut stmt = db.prepare(query)?;
+    for (index, value) in job.values.iter().enumerate() {
+        value.to_value().bind_param(&mut stmt, (index + 1) as i32)?;
+    }
+    stmt.query(())?;
+    let mut batch = Vec::with_capacity(BATCH_ROWS);
+    while let Some(row) = stmt.next()? {
+        let values = (0..row.len())
+            .map(|index| PrefetchedValue::read(row[index].as_ref()))
+            .collect::<sqlite3_ext::Result<Vec<_>>>()?;
+        let epoch = job
+            .epoch_parser
+            .as_ref()
+            .and_then(|(index, parser)| parser(row[*index].as_ref()).ok());
+        batch.push(PrefetchedRow { values, epoch });
+        if batch.len() == BATCH_ROWS {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_ROWS));
+            if job.cancelled.load(Ordering::Relaxed) || sender.send(Ok(Some(full))).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if !batch.is_empty() && sender.send(Ok(Some(batch))).is_err() {
+        return Ok(());
+    }
+    let _ = sender.send(Ok(None));
+    Ok(())
+}
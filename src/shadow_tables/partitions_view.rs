@@ -0,0 +1,98 @@
+use sqlite3_ext::Connection;
+use sqlite3_ext::Result as ExtResult;
+
+use super::operations::Table;
+use super::PartitionType;
+use crate::utils::validation::quote_identifier;
+use crate::LookupTable;
+
+/// Represents the read-only view listing the partitions of a virtual table.
+///
+/// The view has the columns `partition_value`, `partition_table`, `expires_at` (NULL when the
+/// table has no lifetime) and `row_count`, with one row per partition. It reads straight from
+/// the lookup table, so it can be filtered and joined like any other table, e.g. to find the
+/// partitions that have expired. The row count is computed with the `partitioner_row_count`
+/// SQL function when the view is queried.
+#[derive(Debug, Clone)]
+pub struct PartitionsView {
+    /// The name of the view.
+    name: String,
+}
+
+impl PartitionsView {
+    /// Specifies the postfix for the view's name to distinguish it from the shadow tables.
+    pub const POSTFIX: &'static str = "partitions";
+
+    /// Names the SQL function counting the rows of a partition.
+    pub const ROW_COUNT_FUNCTION: &'static str = "partitioner_row_count";
+
+    /// Computes the name of the view from the virtual table's base name.
+    pub fn format_name(base_name: &str) -> String {
+        format!("{base_name}_{}", Self::POSTFIX)
+    }
+
+    /// Accesses the name of the view.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Creates the view over the given lookup table.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for executing the creation.
+    /// - `base_name`: Base name of the virtual table, used to derive the view's name.
+    /// - `lookup_table`: The lookup table listing the partitions.
+    ///
+    /// Returns the newly created `PartitionsView` instance.
+    pub fn create(
+        db: &Connection,
+        base_name: &str,
+        lookup_table: &LookupTable<i64>,
+    ) -> ExtResult<Self> {
+        let view = Self::connect(base_name);
+        db.execute(&view.create_view_query(lookup_table), ())?;
+        Ok(view)
+    }
+
+    /// Connects to the view of an existing virtual table. The view is not required to exist,
+    /// tables created by earlier versions of the module do not have one.
+    ///
+    /// Parameters:
+    /// - `base_name`: Base name of the virtual table.
+    pub fn connect(base_name: &str) -> Self {
+        Self {
+            name: Self::format_name(base_name),
+        }
+    }
+
+    /// Drops the view if it exists.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for executing the drop.
+    pub fn drop_view(&self, db: &Connection) -> ExtResult<()> {
+        db.execute(
+            &format!("DROP VIEW IF EXISTS {}", quote_identifier(&self.name)),
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Generates the SQL CREATE VIEW query for the view.
+    ///
+    /// Parameters:
+    /// - `lookup_table`: The lookup table listing the partitions.
+    ///
+    /// Returns the SQL CREATE VIEW query as a `String`.
+    fn create_view_query(&self, lookup_table: &LookupTable<i64>) -> String {
+        let partition_table = <LookupTable<i64> as PartitionType>::PARTITION_NAME_COLUMN;
+        let partition_value = <LookupTable<i64> as PartitionType>::PARTITION_VALUE_COLUMN;
+        let expires_at = lookup_table.expiration_column().get_name();
+        format!(
+            "CREATE VIEW {} (partition_value, partition_table, expires_at, row_count) AS \
+             SELECT {partition_value}, {partition_table}, {expires_at}, {}({partition_table}) FROM {}",
+            quote_identifier(&self.name),
+            Self::ROW_COUNT_FUNCTION,
+            quote_identifier(lookup_table.name())
+        )
+    }
+}
@@ -1,14 +1,22 @@
+use std::cell::Cell;
 use std::ops::IndexMut;
 
 use sqlite3_ext::params;
+use sqlite3_ext::query::QueryResult;
 use sqlite3_ext::Connection;
 use sqlite3_ext::FromValue;
 use sqlite3_ext::Result as ExtResult;
 use sqlite3_ext::ValueType;
 
+use crate::error::TableError;
+use crate::utils::validation::{quote_identifier, unquote_identifier};
+use crate::utils::{format_list_values, parse_list_values};
+use crate::BucketRounding;
 use crate::ColumnDeclaration;
-use crate::ColumnDeclarations;
-use crate::PartitionColumn;
+use crate::ExpiredInsertPolicy;
+use crate::IndexFormat;
+use crate::PartitionNaming;
+use crate::TableOptions;
 
 use super::operations::Connect;
 use super::operations::Create;
@@ -19,6 +27,20 @@ use super::PartitionValue;
 use super::operations::Table;
 use super::PartitionType;
 
+/// The configuration held by the row of a root table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RootConfig {
+    /// The name of the column used for partitioning the data.
+    pub partition_column: String,
+    /// The interval at which new partitions are created, in seconds.
+    pub interval: i64,
+    /// The lifetime, expired insert policy, bucket column, rounding and index format given at
+    /// creation.
+    pub options: TableOptions,
+    /// Whether the schema of the table may no longer be changed.
+    pub sealed: bool,
+}
+
 /// Represents the root table in a database partitioning scheme, which manages partition metadata.
 ///
 /// This table tracks the partition column and the corresponding interval for dynamic partitioning
@@ -29,9 +51,12 @@ pub struct RootTable {
     /// The name of the column used for partitioning the data.
     partition_column: String,
     /// The interval at which new partitions are created.
-    interval: i64,
-    /// The Lifetime of each partition expressed as seconds
-    lifetime: Option<i64>,
+    interval: Cell<i64>,
+    /// The lifetime, expired insert policy, bucket column, rounding and index format given at
+    /// creation.
+    options: TableOptions,
+    /// Whether the schema of the table may no longer be changed, see [`RootTable::seal`].
+    sealed: Cell<bool>,
     /// The schema declaration for the root table, detailing its structure.
     schema: SchemaDeclaration,
 }
@@ -52,7 +77,8 @@ impl PartitionType for RootTable {
     /// The column name storing partition identifier, E.G the column which is used for partitioning the table will be stored in this column.
     const PARTITION_NAME_COLUMN: &'static str = "partition_column";
     /// The column name storing partition values, the specified interval will be stored here as a
-    /// integer value in seconds. E.G 3600 if the interval was set to 1 hour.
+    /// integer value in seconds. E.G 3600 if the interval was set to 1 hour. A hash partitioned
+    /// table stores its number of partitions here instead.
     const PARTITION_VALUE_COLUMN: &'static str = "partition_value";
 
     /// The data type of the partition value column, indicating the nature of partitioning (e.g., time intervals).
@@ -66,12 +92,80 @@ impl PartitionType for RootTable {
             std::borrow::Cow::Borrowed(Self::PARTITION_LIFETIME_COLUMN),
             Self::PARTITION_LIFETIME_COLUMN_TYPE,
         ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(ExpiredInsertPolicy::OPTION),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::BUCKET_COLUMN),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::SEALED_COLUMN),
+            ValueType::Integer,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(BucketRounding::OPTION),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(IndexFormat::OPTION),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(TableOptions::DEFAULT_PARTITION_OPTION),
+            ValueType::Integer,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(TableOptions::STRICT_OPTION),
+            ValueType::Integer,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(PartitionValue::OPTION),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::KEY_COLUMNS_COLUMN),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(PartitionNaming::OPTION),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(TableOptions::PARALLEL_SCAN_OPTION),
+            ValueType::Integer,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::LIST_VALUES_COLUMN),
+            ValueType::Text,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(Self::GENERATION_COLUMN),
+            ValueType::Integer,
+        ),
+        ColumnDeclaration::new(
+            std::borrow::Cow::Borrowed(TableOptions::MAX_STATEMENTS_OPTION),
+            ValueType::Integer,
+        ),
     ];
 }
 
 impl RootTable {
     const PARTITION_LIFETIME_COLUMN: &'static str = "lifetime";
     const PARTITION_LIFETIME_COLUMN_TYPE: ValueType = ValueType::Integer;
+    const BUCKET_COLUMN: &'static str = "bucket_column";
+    const SEALED_COLUMN: &'static str = "sealed";
+    /// Holds the partition columns after the first, separated by spaces, which column names
+    /// cannot contain. NULL unless the partition key is composite.
+    const KEY_COLUMNS_COLUMN: &'static str = "key_columns";
+    /// Holds the values a list partitioned table was declared with, quoted and separated by
+    /// commas. NULL unless the table was declared with values.
+    const LIST_VALUES_COLUMN: &'static str = "list_values";
+    /// Holds a random value replaced whenever partitions are recorded or removed, by any
+    /// connection, see [`LookupTable::refresh`](super::LookupTable::refresh). NULL until the
+    /// partitions first change. Root tables created before it existed get it on demand.
+    pub(crate) const GENERATION_COLUMN: &'static str = "generation";
     /// Accesses the partition column name.
     pub fn partition_column(&self) -> &str {
         &self.partition_column
@@ -84,6 +178,7 @@ impl RootTable {
     /// - `base_name`: Base name for the table, used to derive the full table name.
     /// - `partition_column`: Name of the column to be used for partitioning.
     /// - `interval`: Interval value for creating new partitions.
+    /// - `options`: The remaining settings given at creation, such as the lifetime.
     ///
     /// Returns a newly created `RootTable` instance.
     pub fn create(
@@ -91,15 +186,16 @@ impl RootTable {
         base_name: &str,
         partition_column: String,
         interval: i64,
-        lifetime: Option<i64>,
+        options: TableOptions,
     ) -> ExtResult<Self> {
         let table_name = Self::format_name(base_name);
         let columns = <Self as PartitionType>::columns();
         let schema = <Self as Create>::schema(db, table_name, columns)?;
         let table = Self {
             partition_column,
-            interval,
-            lifetime,
+            interval: Cell::new(interval),
+            options,
+            sealed: Cell::new(false),
             schema,
         };
         table.insert(db)?;
@@ -110,6 +206,10 @@ impl RootTable {
     /// Connects to an existing `RootTable` based on the base name, retrieving its schema
     /// and configuration from the database.
     ///
+    /// The root table is verified to hold exactly one row first, see [`RootTable::check_integrity`].
+    /// Root tables created before a setting existed lack its column, such settings keep their
+    /// defaults.
+    ///
     /// Parameters:
     /// - `db`: Database connection for querying the table.
     /// - `base_name`: Base name of the table to connect to.
@@ -118,6 +218,7 @@ impl RootTable {
     pub fn connect(db: &Connection, base_name: &str) -> ExtResult<Self> {
         let table_name = &Self::format_name(base_name);
         let schema = <Self as Connect>::schema(db, &table_name.to_string())?;
+        Self::check_integrity(db, base_name)?;
         let columns: String = schema
             .columns()
             .0
@@ -125,33 +226,106 @@ impl RootTable {
             .map(|column| column.get_name().to_string())
             .collect::<Vec<String>>()
             .join(", ");
-        let query = format!("SELECT {columns} FROM {table_name}");
-        let mut partition_column: String = String::default();
-        let mut interval: i64 = 0i64;
-        let mut lifetime: Option<i64> = None;
-        db.query_row(&query, (), |row| {
-            let column_count = row.len();
-            for index in 0..column_count {
-                let column = row.index_mut(index);
-                let name = column.name()?;
-                if name.eq(<Self as PartitionType>::COLUMNS[0].get_name()) {
-                    partition_column = column.get_str()?.to_owned();
-                } else if name.eq(<Self as PartitionType>::COLUMNS[1].get_name()) {
-                    interval = column.get_i64();
-                } else if name.eq(<Self as PartitionType>::COLUMNS[2].get_name()) {
-                    lifetime = Some(column.get_i64());
-                }
-            }
-            Ok(())
-        })?;
+        let query = format!("SELECT {columns} FROM {}", quote_identifier(table_name));
+        let config = db.query_row(&query, (), Self::from_row)?;
         Ok(Self {
             schema,
-            partition_column,
-            interval,
-            lifetime,
+            partition_column: config.partition_column,
+            interval: Cell::new(config.interval),
+            options: config.options,
+            sealed: Cell::new(config.sealed),
         })
     }
 
+    /// Maps a row of the root table to its configuration.
+    ///
+    /// Columns are matched by name, so the row may hold them in any order. Missing columns and
+    /// NULL values keep their defaults, which is how root tables created before a setting existed
+    /// are read.
+    ///
+    /// Parameters:
+    /// - `row`: A row selecting columns of the root table.
+    ///
+    /// Returns the configuration, or an error if a value cannot be read, e.g. an unknown expired
+    /// insert policy.
+    pub fn from_row(row: &mut QueryResult) -> ExtResult<RootConfig> {
+        let mut config = RootConfig::default();
+        for index in 0..row.len() {
+            let column = row.index_mut(index);
+            if column.value_type() == ValueType::Null {
+                continue;
+            }
+            let name = column.name()?;
+            if name.eq(<Self as PartitionType>::COLUMNS[0].get_name()) {
+                // Tables created before names were stored unquoted may hold a quoted name.
+                config.partition_column = unquote_identifier(column.get_str()?);
+            } else if name.eq(<Self as PartitionType>::COLUMNS[1].get_name()) {
+                config.interval = column.get_i64();
+            } else if name.eq(<Self as PartitionType>::COLUMNS[2].get_name()) {
+                config.options.lifetime = Some(column.get_i64());
+            } else if name.eq(<Self as PartitionType>::COLUMNS[3].get_name()) {
+                config.options.on_expired_insert =
+                    ExpiredInsertPolicy::try_from(column.get_str()?)?;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[4].get_name()) {
+                config.options.bucket_column = Some(column.get_str()?.to_owned());
+            } else if name.eq(<Self as PartitionType>::COLUMNS[5].get_name()) {
+                config.sealed = column.get_i64() != 0;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[6].get_name()) {
+                config.options.round = BucketRounding::try_from(column.get_str()?)?;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[7].get_name()) {
+                config.options.index_format = IndexFormat::try_from(column.get_str()?)?;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[8].get_name()) {
+                config.options.default_partition = column.get_i64() != 0;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[9].get_name()) {
+                config.options.strict = column.get_i64() != 0;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[10].get_name()) {
+                config.options.strategy = PartitionValue::try_from(column.get_str()?)?;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[11].get_name()) {
+                config.options.key_columns = column
+                    .get_str()?
+                    .split_whitespace()
+                    .map(str::to_owned)
+                    .collect();
+            } else if name.eq(<Self as PartitionType>::COLUMNS[12].get_name()) {
+                config.options.partition_name = PartitionNaming::try_from(column.get_str()?)?;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[13].get_name()) {
+                config.options.parallel_scan = usize::try_from(column.get_i64()).ok();
+            } else if name.eq(<Self as PartitionType>::COLUMNS[14].get_name()) {
+                config.options.list_values =
+                    parse_list_values(column.get_str()?, &ValueType::Text)?;
+            } else if name.eq(<Self as PartitionType>::COLUMNS[16].get_name()) {
+                config.options.max_statements = usize::try_from(column.get_i64()).ok();
+            }
+        }
+        Ok(config)
+    }
+
+    /// Verifies that the root table holds exactly one row.
+    ///
+    /// The row is written once at creation. With no row the configuration is lost, with more than
+    /// one it is ambiguous which row holds the configuration, so both are reported as corruption.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for querying the table.
+    /// - `base_name`: Base name of the table to check.
+    ///
+    /// Returns `Ok(())` if the table holds exactly one row, a `TableError::Corrupt` otherwise.
+    pub fn check_integrity(db: &Connection, base_name: &str) -> ExtResult<()> {
+        let table_name = Self::format_name(base_name);
+        let rows = db.query_row(
+            &format!("SELECT count(*) FROM {}", quote_identifier(&table_name)),
+            (),
+            |row| Ok(row.index_mut(0).get_i64()),
+        )?;
+        if rows != 1 {
+            return Err(TableError::Corrupt(format!(
+                "{table_name} must hold exactly one row, found {rows}"
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     /// Inserts partition metadata into the root table, recording a new partition's details.
     /// This should only be executed once, at creation. Only one row should be present in the root
     /// table
@@ -163,16 +337,55 @@ impl RootTable {
         let partition_name_column = Self::COLUMNS[0].get_name().to_owned();
         let partition_value_column = Self::COLUMNS[1].get_name().to_owned();
         let partition_lifetime_column = Self::COLUMNS[2].get_name().to_owned();
+        let on_expired_insert_column = Self::COLUMNS[3].get_name().to_owned();
+        let bucket_column = Self::COLUMNS[4].get_name().to_owned();
+        let sealed_column = Self::COLUMNS[5].get_name().to_owned();
+        let round_column = Self::COLUMNS[6].get_name().to_owned();
+        let index_format_column = Self::COLUMNS[7].get_name().to_owned();
+        let default_partition_column = Self::COLUMNS[8].get_name().to_owned();
+        let strict_column = Self::COLUMNS[9].get_name().to_owned();
+        let strategy_column = Self::COLUMNS[10].get_name().to_owned();
+        let key_columns_column = Self::COLUMNS[11].get_name().to_owned();
+        let partition_name_template_column = Self::COLUMNS[12].get_name().to_owned();
+        let parallel_scan_column = Self::COLUMNS[13].get_name().to_owned();
+        let list_values_column = Self::COLUMNS[14].get_name().to_owned();
+        let max_statements_column = Self::COLUMNS[16].get_name().to_owned();
+        let key_columns = match self.options.key_columns.is_empty() {
+            true => None,
+            false => Some(self.options.key_columns.join(" ")),
+        };
+        let list_values = match self.options.list_values.is_empty() {
+            true => None,
+            false => Some(format_list_values(&self.options.list_values)),
+        };
 
         let sql = format!(
-            "INSERT INTO {} ({partition_name_column}, {partition_value_column}, {partition_lifetime_column}) VALUES (?, ?, ?);",
-            self.name()
+            "INSERT INTO {} ({partition_name_column}, {partition_value_column}, {partition_lifetime_column}, {on_expired_insert_column}, {bucket_column}, {sealed_column}, {round_column}, {index_format_column}, {default_partition_column}, {strict_column}, {strategy_column}, {key_columns_column}, {partition_name_template_column}, {parallel_scan_column}, {list_values_column}, {max_statements_column}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            quote_identifier(self.name())
         );
-        println!("lifetime {:#?}", self.lifetime);
+        //TODO: Fix proper expiration handling
         db.insert(
             &sql,
-            params![self.partition_column, self.get_interval(), self.lifetime], //TODO: Fix proper expiration
-                                                                                //handling
+            params![
+                self.partition_column,
+                self.get_interval(),
+                self.options.lifetime,
+                self.options.on_expired_insert.as_str(),
+                self.options.bucket_column.as_deref(),
+                self.is_sealed(),
+                self.options.round.as_str(),
+                self.options.index_format.as_str(),
+                self.options.default_partition,
+                self.options.strict,
+                self.options.strategy.as_str(),
+                key_columns.as_deref(),
+                self.options.partition_name.as_str(),
+                self.options.parallel_scan.map(|workers| workers as i64),
+                list_values.as_deref(),
+                self.options
+                    .max_statements
+                    .map(|statements| statements as i64)
+            ],
         )?;
         Ok(true)
     }
@@ -181,10 +394,151 @@ impl RootTable {
     ///
     /// Returns the interval value as an `i64`.
     pub fn get_interval(&self) -> i64 {
-        self.interval
+        self.interval.get()
+    }
+
+    /// Persists a new partition interval in the root table and updates the cached value.
+    ///
+    /// Existing partitions are not touched, re-bucketing the data is up to the caller.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for the update.
+    /// - `interval`: The new interval in seconds.
+    pub fn set_interval(&self, db: &Connection, interval: i64) -> ExtResult<()> {
+        self.check_unsealed()?;
+        let sql = format!(
+            "UPDATE {} SET {} = ?",
+            quote_identifier(self.name()),
+            Self::COLUMNS[1].get_name()
+        );
+        db.execute(&sql, params![interval])?;
+        self.interval.set(interval);
+        Ok(())
+    }
+
+    /// Re-reads the interval from the root table, discarding the cached value.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for the query.
+    pub fn reload_interval(&self, db: &Connection) -> ExtResult<()> {
+        let sql = format!(
+            "SELECT {} FROM {}",
+            Self::COLUMNS[1].get_name(),
+            quote_identifier(self.name())
+        );
+        let interval = db.query_row(&sql, (), |row| Ok(row.index_mut(0).get_i64()))?;
+        self.interval.set(interval);
+        Ok(())
     }
     pub fn get_lifetime(&self) -> Option<i64> {
-        self.lifetime
+        self.options.lifetime
+    }
+    /// Retrieves what happens to rows inserted into partitions that have already expired.
+    pub fn on_expired_insert(&self) -> ExpiredInsertPolicy {
+        self.options.on_expired_insert
+    }
+    /// Retrieves the name of the column holding the partition value of each row, if any.
+    pub fn bucket_column(&self) -> Option<&str> {
+        self.options.bucket_column.as_deref()
+    }
+    /// Retrieves which interval boundary values of the partition column are bucketed to.
+    pub fn round(&self) -> BucketRounding {
+        self.options.round
+    }
+    /// Retrieves how query plans are serialized into the index string.
+    pub fn index_format(&self) -> IndexFormat {
+        self.options.index_format
+    }
+    /// Retrieves whether rows without a usable partition column value go to the default
+    /// partition.
+    pub fn default_partition(&self) -> bool {
+        self.options.default_partition
+    }
+    /// Retrieves whether every written value has to match the declared type of its column.
+    pub fn is_strict(&self) -> bool {
+        self.options.strict
+    }
+    /// Retrieves how rows are assigned to partitions.
+    pub fn strategy(&self) -> PartitionValue {
+        self.options.strategy
+    }
+    /// Retrieves the partition columns after the first, empty unless the partition key is
+    /// composite.
+    pub fn key_columns(&self) -> &[String] {
+        &self.options.key_columns
+    }
+    /// Retrieves how the tables holding the partitions are named.
+    pub fn partition_name(&self) -> &PartitionNaming {
+        &self.options.partition_name
+    }
+    /// Retrieves the number of worker threads reading partitions ahead of a scan, if scans are
+    /// parallel.
+    pub fn parallel_scan(&self) -> Option<usize> {
+        self.options.parallel_scan
+    }
+    /// Retrieves the most partition statements a connection keeps prepared for the table at
+    /// once, if the table was declared with a cap.
+    pub fn max_statements(&self) -> Option<usize> {
+        self.options.max_statements
+    }
+    /// Retrieves the values a list partitioned table was declared with, empty if every value
+    /// gets a partition.
+    pub fn list_values(&self) -> &[String] {
+        &self.options.list_values
+    }
+    /// Retrieves whether the schema of the table may no longer be changed.
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.get()
+    }
+
+    /// Marks the schema of the table as immutable. There is no way back, a sealed table can only
+    /// be dropped as a whole.
+    ///
+    /// Root tables created before sealing existed lack the column, it is added on demand.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for the update.
+    pub fn seal(&self, db: &Connection) -> ExtResult<()> {
+        let sealed_column = Self::COLUMNS[5].get_name();
+        let has_column = db.query_row(
+            "SELECT count(*) FROM pragma_table_info(?) WHERE name = ?",
+            params![self.name(), sealed_column],
+            |row| Ok(row.index_mut(0).get_i64() > 0),
+        )?;
+        if !has_column {
+            db.execute(
+                &format!(
+                    "ALTER TABLE {} ADD COLUMN {} INTEGER",
+                    quote_identifier(self.name()),
+                    sealed_column
+                ),
+                (),
+            )?;
+        }
+        db.execute(
+            &format!(
+                "UPDATE {} SET {} = 1",
+                quote_identifier(self.name()),
+                sealed_column
+            ),
+            (),
+        )?;
+        self.sealed.set(true);
+        Ok(())
+    }
+
+    /// Refuses schema changes once the table is sealed.
+    ///
+    /// Returns `Ok(())` if the table is not sealed, a `TableError::Sealed` otherwise.
+    pub fn check_unsealed(&self) -> ExtResult<()> {
+        if self.is_sealed() {
+            let table = self.name();
+            let base_name = table
+                .strip_suffix(&format!("_{}", Self::POSTFIX))
+                .unwrap_or(table);
+            return Err(TableError::Sealed(base_name.to_owned()).into());
+        }
+        Ok(())
     }
 }
 
@@ -205,7 +559,7 @@ mod tests {
             "test",
             "col".to_string(),
             3600,
-            None,
+            TableOptions::default(),
         );
 
         assert_eq!(root_table.as_ref().unwrap().schema().name(), "test_root");
@@ -225,9 +579,15 @@ mod tests {
             Err(err) => panic!("{}", err.to_string()),
         };
         let connection = Connection::from_rusqlite(&rusq_conn);
-        let root_table =
-            RootTable::create(connection, "test", "col".to_string(), 3600, None).unwrap();
-        root_table.insert(connection).unwrap();
+        let root_table = RootTable::create(
+            connection,
+            "test",
+            "col".to_string(),
+            3600,
+            TableOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(root_table.get_interval(), 3600);
 
         let connected_table = RootTable::connect(connection, "test");
         assert!(connected_table.is_ok());
@@ -241,14 +601,115 @@ mod tests {
             Err(err) => panic!("{}", err.to_string()),
         };
         let connection = Connection::from_rusqlite(&rusq_conn);
-        let root_table =
-            RootTable::create(connection, "test", "col".to_string(), 3600, Some(3600)).unwrap();
-        root_table.insert(connection).unwrap();
+        let root_table = RootTable::create(
+            connection,
+            "test",
+            "col".to_string(),
+            3600,
+            TableOptions {
+                lifetime: Some(3600),
+                ..TableOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(root_table.get_lifetime(), Some(3600));
 
         let connected_table = RootTable::connect(connection, "test");
         assert!(connected_table.is_ok());
 
         // println!("{:#?}", r);
     }
+    #[test]
+    fn test_connect_rejects_corrupt_root_table() {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let connection = Connection::from_rusqlite(&rusq_conn);
+        let root_table = RootTable::create(
+            connection,
+            "test",
+            "col".to_string(),
+            3600,
+            TableOptions::default(),
+        )
+        .unwrap();
+        root_table.insert(connection).unwrap();
+
+        let err = RootTable::connect(connection, "test").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("test_root must hold exactly one row, found 2"));
+
+        connection.execute("DELETE FROM test_root", ()).unwrap();
+        assert!(RootTable::connect(connection, "test").is_err());
+    }
+    #[test]
+    fn test_from_row() -> ExtResult<()> {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let connection = Connection::from_rusqlite(&rusq_conn);
+        let config = connection.query_row(
+            "SELECT 86400 AS partition_value, 'col' AS partition_column, NULL AS lifetime, \
+             'quarantine' AS on_expired_insert, 'bucket' AS bucket_column, 'nearest' AS round, \
+             'binary' AS index_format, 1 AS default_partition, 1 AS strict, 'hash' AS strategy, \
+             'tenant region' AS key_columns, 'p_{table}_{value}' AS partition_name, \
+             4 AS parallel_scan, '''EU'', ''it''''s''' AS list_values, 16 AS max_statements",
+            (),
+            RootTable::from_row,
+        )?;
+        assert_eq!(
+            config,
+            RootConfig {
+                partition_column: "col".to_string(),
+                interval: 86400,
+                options: TableOptions {
+                    lifetime: None,
+                    on_expired_insert: ExpiredInsertPolicy::Quarantine,
+                    bucket_column: Some("bucket".to_string()),
+                    round: BucketRounding::Nearest,
+                    index_format: IndexFormat::Binary,
+                    default_partition: true,
+                    strict: true,
+                    strategy: PartitionValue::Hash,
+                    key_columns: vec!["tenant".to_owned(), "region".to_owned()],
+                    partition_name: PartitionNaming::try_from("p_{table}_{value}")?,
+                    parallel_scan: Some(4),
+                    list_values: vec!["EU".to_owned(), "it's".to_owned()],
+                    max_statements: Some(16),
+                },
+                sealed: false,
+            }
+        );
+
+        // A row from before the newer settings existed keeps their defaults.
+        let config = connection.query_row(
+            "SELECT 'col' AS partition_column, 3600 AS partition_value, 600 AS lifetime",
+            (),
+            RootTable::from_row,
+        )?;
+        assert_eq!(config.options.lifetime, Some(600));
+        assert_eq!(
+            config.options.on_expired_insert,
+            ExpiredInsertPolicy::Reject
+        );
+        assert_eq!(config.options.bucket_column, None);
+        assert_eq!(config.options.round, BucketRounding::Floor);
+        assert_eq!(config.options.index_format, IndexFormat::Json);
+        assert!(!config.options.default_partition);
+        assert!(!config.options.strict);
+        assert_eq!(config.options.strategy, PartitionValue::Interval);
+        assert!(config.options.key_columns.is_empty());
+        assert_eq!(config.options.partition_name, PartitionNaming::default());
+        assert_eq!(config.options.parallel_scan, None);
+        assert!(config.options.list_values.is_empty());
+        assert!(!config.sealed);
+
+        let err = connection
+            .query_row(
+                "SELECT 'col' AS partition_column, 'later' AS on_expired_insert",
+                (),
+                RootTable::from_row,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("later"));
+        Ok(())
+    }
     // Additional tests for `create_table`, `connect`, and `insert` could be added here
 }
@@ -0,0 +1,628 @@
+use std::ops::Bound;
+use std::thread;
+
+use sqlite3_ext::query::QueryResult;
+use sqlite3_ext::{ffi, Connection, Database, FallibleIteratorMut, OpenFlags};
+
+use super::interface::VirtualTable;
+use crate::utils::validation::quote_identifier;
+
+impl<'vtab> VirtualTable<'vtab> {
+    /// Scans every row of the partitions in a range of partition values, one partition after the
+    /// other, on the table's own connection.
+    ///
+    /// # Parameters
+    /// - `from`: The lower bound of the partition values to scan.
+    /// - `to`: The upper bound of the partition values to scan.
+    /// - `f`: Called for every row, with the columns in declaration order.
+    ///
+    /// # Returns
+    /// The values returned by `f`, ordered by partition and by rowid within a partition.
+    pub fn scan<T, F>(&self, from: Bound<i64>, to: Bound<i64>, f: F) -> sqlite3_ext::Result<Vec<T>>
+    where
+        F: Fn(&QueryResult) -> sqlite3_ext::Result<T>,
+    {
+        let mut values = Vec::new();
+        for (_, partition) in self
+            .lookup()
+            .get_partitions_by_range(self.connection, &from, &to)?
+        {
+            scan_partition(
+                self.connection,
+                &partition,
+                self.rowid_name(),
+                &f,
+                &mut values,
+            )?;
+        }
+        Ok(values)
+    }
+
+    /// Scans every row of the partitions in a range of partition values in chronological order of
+    /// the partition column, regardless of the order the rows were inserted in.
+    ///
+    /// The rows of each partition are ordered by the epoch their partition column value denotes,
+    /// so text datetimes and integer epochs are placed by time rather than by storage class. Rows
+    /// with the same time keep their rowid order. Partitions cover disjoint, ascending ranges of
+    /// time, so merging them comes down to reading them one after the other.
+    ///
+    /// # Parameters
+    /// - `from`: The lower bound of the partition values to scan.
+    /// - `to`: The upper bound of the partition values to scan.
+    /// - `f`: Called for every row, with the columns in declaration order.
+    ///
+    /// # Returns
+    /// The values returned by `f`, ordered by the time of their rows. Returns an error if the
+    /// partition column of a row cannot be parsed as a time, or if the table is hash
+    /// partitioned.
+    pub fn scan_ordered<T, F>(
+        &self,
+        from: Bound<i64>,
+        to: Bound<i64>,
+        f: F,
+    ) -> sqlite3_ext::Result<Vec<T>>
+    where
+        F: Fn(&QueryResult) -> sqlite3_ext::Result<T>,
+    {
+        self.require_time_partitioning("Scanning in time order")?;
+        let partition_column_index = self
+            .columns()
+            .0
+            .iter()
+            .position(|column| column.get_name() == self.partition_column_name())
+            .ok_or_else(|| {
+                sqlite3_ext::Error::Module(format!(
+                    "Partition column {} not found",
+                    self.partition_column_name()
+                ))
+            })?;
+        let mut values = Vec::new();
+        let partitions = self
+            .lookup()
+            .get_partitions_by_range(self.connection, &from, &to)?;
+        // The partitions of a composite partition key sharing a partition value cover the same
+        // range of time, their rows are sorted together.
+        for group in partitions.chunk_by(|(first, _), (second, _)| first == second) {
+            let mut timed_values = Vec::new();
+            for (_, partition) in group {
+                scan_partition(
+                    self.connection,
+                    partition,
+                    self.rowid_name(),
+                    &|row: &QueryResult| {
+                        let epoch =
+                            self.parse_partition_epoch(row[partition_column_index].as_ref())?;
+                        Ok((epoch, f(row)?))
+                    },
+                    &mut timed_values,
+                )?;
+            }
+            timed_values.sort_by_key(|(epoch, _)| *epoch);
+            values.extend(timed_values.into_iter().map(|(_, value)| value));
+        }
+        Ok(values)
+    }
+
+    /// Scans every row of the partitions in a range of partition values, spreading the partitions
+    /// over worker threads.
+    ///
+    /// Each worker opens its own read-only connection to the database file and scans whole
+    /// partitions, so this only works for file databases and only sees committed data. Rows written
+    /// by an open transaction on the table's own connection are not visible to the workers. The
+    /// results are merged in the same order as [`VirtualTable::scan`] returns them.
+    ///
+    /// # Parameters
+    /// - `from`: The lower bound of the partition values to scan.
+    /// - `to`: The upper bound of the partition values to scan.
+    /// - `f`: Called for every row, with the columns in declaration order. It runs on the worker
+    ///   threads, concurrently.
+    ///
+    /// # Returns
+    /// The values returned by `f`, ordered by partition and by rowid within a partition. Returns an
+    /// error if the database is not backed by a file or if any worker fails.
+    pub fn scan_parallel<T, F>(
+        &self,
+        from: Bound<i64>,
+        to: Bound<i64>,
+        f: F,
+    ) -> sqlite3_ext::Result<Vec<T>>
+    where
+        T: Send,
+        F: Fn(&QueryResult) -> sqlite3_ext::Result<T> + Sync,
+    {
+        let partitions = self
+            .lookup()
+            .get_partitions_by_range(self.connection, &from, &to)?
+            .into_iter()
+            .map(|(_, partition)| partition)
+            .collect::<Vec<String>>();
+        if partitions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filename = self.database_filename()?;
+        let rowid_name = self.rowid_name();
+        let workers = thread::available_parallelism()
+            .map(|workers| workers.get())
+            .unwrap_or(1)
+            .min(partitions.len());
+
+        // Worker `n` takes every `workers`-th partition, starting at partition `n`.
+        let mut scanned: Vec<Vec<Vec<T>>> = thread::scope(|scope| {
+            let handles = (0..workers)
+                .map(|worker| {
+                    let (filename, partitions, f) = (&filename, &partitions, &f);
+                    scope.spawn(move || -> sqlite3_ext::Result<Vec<Vec<T>>> {
+                        let db = Database::open_with_flags(filename, OpenFlags::READONLY)?;
+                        partitions
+                            .iter()
+                            .skip(worker)
+                            .step_by(workers)
+                            .map(|partition| {
+                                let mut values = Vec::new();
+                                scan_partition(&db, partition, rowid_name, f, &mut values)?;
+                                Ok(values)
+                            })
+                            .collect()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().map_err(|_| {
+                        sqlite3_ext::Error::Module("Partition scan worker panicked".to_owned())
+                    })?
+                })
+                .collect::<sqlite3_ext::Result<Vec<_>>>()
+        })?;
+
+        let mut merged = Vec::new();
+        let mut per_worker = scanned
+            .iter_mut()
+            .map(|values| values.drain(..))
+            .collect::<Vec<_>>();
+        for index in 0..partitions.len() {
+            if let Some(values) = per_worker[index % workers].next() {
+                merged.extend(values);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Looks up the file backing the main database of the table's connection.
+    ///
+    /// # Returns
+    /// The path of the database file, or an error for in-memory and temporary databases.
+    pub(crate) fn database_filename(&self) -> sqlite3_ext::Result<String> {
+        let filename = self.database_file().to_owned();
+        if filename.is_empty() {
+            return Err(sqlite3_ext::Error::Module(
+                "Parallel scans need a database file, in-memory databases cannot be shared"
+                    .to_owned(),
+            ));
+        }
+        Ok(filename)
+    }
+
+    /// Tells whether the table's connection has a transaction open, e.g. after `BEGIN`. Its
+    /// changes are not visible to other connections before it commits.
+    pub(crate) fn in_transaction(&self) -> bool {
+        // SAFETY: The handle belongs to the table's connection, which outlives the call.
+        unsafe { ffi::sqlite3_get_autocommit(self.connection.as_mut_ptr()) == 0 }
+    }
+}
+
+/// Runs `f` for every row of a partition in rowid order, appending the results to `values`.
+/// The rowid is addressed as `rowid_name`, since a column may hide the name `rowid`.
+fn scan_partition<T, F>(
+    db: &Connection,
+    partition: &str,
+    rowid_name: &str,
+    f: &F,
+    values: &mut Vec<T>,
+) -> sqlite3_ext::Result<()>
+where
+    F: Fn(&QueryResult) -> sqlite3_ext::Result<T>,
+{
+    let mut stmt = db.prepare(&format!(
+        "SELECT * FROM {} ORDER BY {}",
+        quote_identifier(partition),
+        rowid_name
+    ))?;
+    stmt.query(())?;
+    while let Some(row) = stmt.next()? {
+        values.push(f(row)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Bound, Index};
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    use rusqlite::Connection as RusqConn;
+    use sqlite3_ext::query::QueryResult;
+    use sqlite3_ext::{Connection, FallibleIteratorMut, FromValue};
+
+    use crate::shadow_tables::interface::VirtualTable;
+    use crate::vtab_interface::init;
+
+    /// Removes the database file when the test is done with it.
+    struct TempDatabase(PathBuf);
+    impl TempDatabase {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "partitioner_{}_{}.db",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+            for suffix in ["-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Switches the database to WAL mode, which the workers of `parallel_scan` need.
+    fn use_wal(db: &Connection) {
+        db.query_row("PRAGMA journal_mode = WAL", (), |_| Ok(()))
+            .unwrap();
+    }
+
+    /// Creates an hourly partitioned table with `rows_per_partition` rows in each of `partitions`
+    /// partitions, starting at 2024-02-01 00:00.
+    fn populate(db: &Connection, partitions: i64, rows_per_partition: i64) {
+        init(db).unwrap();
+        populate_table(db, "test", "", partitions, rows_per_partition);
+    }
+
+    /// Creates a table like [`populate`] does under another name, with further create options.
+    fn populate_table(
+        db: &Connection,
+        name: &str,
+        options: &str,
+        partitions: i64,
+        rows_per_partition: i64,
+    ) {
+        db.execute(
+            &format!(
+                "CREATE VIRTUAL TABLE {} USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, col3 text{})",
+                name, options
+            ),
+            (),
+        )
+        .unwrap();
+        db.execute("BEGIN", ()).unwrap();
+        for row in 0..partitions * rows_per_partition {
+            let epoch = 1706745600 + row * 3600 / rows_per_partition;
+            db.insert(
+                &format!(
+                    "INSERT INTO {} values ({}, {}, 'row {}')",
+                    name, epoch, row, row
+                ),
+                (),
+            )
+            .unwrap();
+        }
+        db.execute("COMMIT", ()).unwrap();
+    }
+
+    /// Runs a query and collects its rows, each column quoted as SQL.
+    fn query_rows(db: &Connection, sql: &str) -> sqlite3_ext::Result<Vec<Vec<String>>> {
+        let mut stmt = db.prepare(sql)?;
+        stmt.query(())?;
+        let mut rows = Vec::new();
+        while let Some(row) = stmt.next()? {
+            rows.push(
+                (0..row.len())
+                    .map(|index| Ok(row[index].get_str()?.to_owned()))
+                    .collect::<sqlite3_ext::Result<Vec<String>>>()?,
+            );
+        }
+        Ok(rows)
+    }
+
+    fn read_row(row: &QueryResult) -> sqlite3_ext::Result<(i64, String)> {
+        Ok((
+            row.index(1).get_i64(),
+            row.index(2).as_ref().try_get_str()?.to_owned(),
+        ))
+    }
+
+    #[test]
+    fn test_scan_parallel_matches_scan() -> sqlite3_ext::Result<()> {
+        let file = TempDatabase::new("scan_parallel");
+        let rusq_conn = RusqConn::open(&file.0).unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        populate(db, 12, 25);
+        let table = VirtualTable::connect(db, "test")?;
+
+        let sequential = table.scan(Bound::Unbounded, Bound::Unbounded, read_row)?;
+        let parallel = table.scan_parallel(Bound::Unbounded, Bound::Unbounded, read_row)?;
+        assert_eq!(sequential.len(), 300);
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel[299], (299, "row 299".to_owned()));
+
+        // Partitions 02:00 up to, but not including, 05:00.
+        let from = Bound::Included(1706745600 + 2 * 3600);
+        let to = Bound::Excluded(1706745600 + 5 * 3600);
+        let parallel = table.scan_parallel(from, to, read_row)?;
+        assert_eq!(parallel, table.scan(from, to, read_row)?);
+        assert_eq!(
+            parallel.iter().map(|(id, _)| *id).collect::<Vec<i64>>(),
+            (50..125).collect::<Vec<i64>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_scan_option_matches_sequential_scan() -> sqlite3_ext::Result<()> {
+        let file = TempDatabase::new("parallel_scan_option");
+        let rusq_conn = RusqConn::open(&file.0).unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        use_wal(db);
+        populate(db, 12, 25);
+        populate_table(db, "par", ", parallel_scan=3", 12, 25);
+        db.query_row(
+            "SELECT value FROM par_config WHERE setting = 'parallel_scan'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 3);
+                Ok(())
+            },
+        )?;
+
+        for query in [
+            "SELECT quote(col1), quote(col2), quote(col3) FROM {}",
+            // Partly covered partitions, whose rows are checked against the times.
+            "SELECT quote(col2) FROM {} WHERE col1 >= 1706753700 AND col1 < '2024-02-01 07:20'",
+            "SELECT quote(col2) FROM {} WHERE col1 IN (1706745600, 1706760000, '2024-02-01 09:00')",
+            "SELECT quote(col2), quote(col3) FROM {} WHERE col2 % 3 = 0 AND col3 > 'row 2'",
+            "SELECT quote(col2) FROM {} ORDER BY col1 DESC",
+            "SELECT quote(col2) FROM {} WHERE col1 >= 1706760000 ORDER BY col1",
+        ] {
+            let sequential = query_rows(db, &query.replace("{}", "test"))?;
+            assert!(!sequential.is_empty(), "{}", query);
+            assert_eq!(
+                query_rows(db, &query.replace("{}", "par"))?,
+                sequential,
+                "{}",
+                query
+            );
+        }
+        assert_eq!(
+            query_rows(
+                db,
+                "SELECT quote(col2) FROM par WHERE col1 IN (1706745600, 1706760000)"
+            )?,
+            vec![vec!["0".to_owned()], vec!["100".to_owned()]]
+        );
+
+        // Counting the statements the main connection keeps open tells the workers read the
+        // partitions rather than the connection itself.
+        let open_reads = || {
+            let handle = unsafe { rusq_conn.handle() };
+            let mut count = 0;
+            let mut stmt =
+                unsafe { rusqlite::ffi::sqlite3_next_stmt(handle, std::ptr::null_mut()) };
+            while !stmt.is_null() {
+                if unsafe { rusqlite::ffi::sqlite3_stmt_readonly(stmt) } != 0 {
+                    count += 1;
+                }
+                stmt = unsafe { rusqlite::ffi::sqlite3_next_stmt(handle, stmt) };
+            }
+            count
+        };
+        for (table, open) in [("test", 2), ("par", 1)] {
+            let mut stmt = db.prepare(&format!("SELECT col2 FROM {}", table))?;
+            stmt.query(())?;
+            assert!(stmt.next()?.is_some());
+            assert_eq!(open_reads(), open, "{}", table);
+        }
+
+        // Inside a transaction the workers would miss the uncommitted rows, the table's
+        // connection reads the partitions itself.
+        db.execute("BEGIN", ())?;
+        db.insert("INSERT INTO par values (1706745600, 1000, 'row 1000')", ())?;
+        db.query_row("SELECT count(col2) FROM par", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 301);
+            Ok(())
+        })?;
+        db.execute("ROLLBACK", ())?;
+
+        // An abandoned scan stops its workers.
+        db.query_row("SELECT col2 FROM par WHERE col2 > 10", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 11);
+            Ok(())
+        })?;
+
+        // Another connection writing does not keep the workers from starting, and a row it
+        // commits during the scan is seen by none of them.
+        let writer = RusqConn::open(&file.0).unwrap();
+        writer.execute_batch("BEGIN IMMEDIATE").unwrap();
+        {
+            let mut stmt = db.prepare("SELECT col2 FROM par")?;
+            stmt.query(())?;
+            assert!(stmt.next()?.is_some());
+            assert_eq!(open_reads(), 1);
+            writer
+                .execute_batch(
+                    "INSERT INTO par_1706785200 VALUES (1706785200, 1000, 'row 1000'); COMMIT",
+                )
+                .unwrap();
+            let mut rows = 1;
+            while stmt.next()?.is_some() {
+                rows += 1;
+            }
+            assert_eq!(rows, 300);
+        }
+        drop(writer);
+        assert_eq!(query_rows(db, "SELECT count(col2) FROM par")?, [["301"]]);
+
+        // Rows deleted earlier in the same statement are not committed yet either.
+        db.execute("CREATE TABLE log (n)", ())?;
+        db.execute("CREATE TABLE counts (n)", ())?;
+        db.execute(
+            "CREATE TRIGGER count_par AFTER INSERT ON log BEGIN \
+             DELETE FROM par WHERE col2 < 100; \
+             INSERT INTO counts SELECT count(col2) FROM par; \
+             END",
+            (),
+        )?;
+        db.insert("INSERT INTO log VALUES (1)", ())?;
+        db.query_row("SELECT n FROM counts", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 201);
+            Ok(())
+        })?;
+
+        // In rollback journal mode the read transactions of the workers would keep writers from
+        // committing, the table's connection reads the partitions itself.
+        db.query_row("PRAGMA journal_mode = DELETE", (), |_| Ok(()))?;
+        {
+            let mut stmt = db.prepare("SELECT col2 FROM par")?;
+            stmt.query(())?;
+            assert!(stmt.next()?.is_some());
+            assert_eq!(open_reads(), 2);
+        }
+        db.execute("DROP TABLE par", ())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_ordered() -> sqlite3_ext::Result<()> {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        init(db)?;
+        db.execute(
+            "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, col3 text)",
+            (),
+        )?;
+        // Out of order within each bucket, mixing text datetimes and integer epochs.
+        for (col1, col2) in [
+            ("'2024-02-01 01:50'", 5),
+            ("'2024-02-01 00:45'", 2),
+            ("1706745600", 0),
+            ("'2024-02-01 01:10'", 4),
+            ("'2024-02-01 00:30'", 1),
+            ("1706749200", 3),
+        ] {
+            db.insert(
+                &format!(
+                    "INSERT INTO test values ({}, {}, 'row {}')",
+                    col1, col2, col2
+                ),
+                (),
+            )?;
+        }
+        let table = VirtualTable::connect(db, "test")?;
+
+        let ordered = table.scan_ordered(Bound::Unbounded, Bound::Unbounded, read_row)?;
+        assert_eq!(
+            ordered.iter().map(|(id, _)| *id).collect::<Vec<i64>>(),
+            (0..6).collect::<Vec<i64>>()
+        );
+        let ordered =
+            table.scan_ordered(Bound::Included(1706749200), Bound::Unbounded, read_row)?;
+        assert_eq!(
+            ordered,
+            vec![
+                (3, "row 3".to_owned()),
+                (4, "row 4".to_owned()),
+                (5, "row 5".to_owned())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_parallel_needs_database_file() {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        populate(db, 2, 2);
+        let table = VirtualTable::connect(db, "test").unwrap();
+        assert!(table
+            .scan_parallel(Bound::Unbounded, Bound::Unbounded, read_row)
+            .is_err());
+    }
+
+    /// Shows a parallel scan of a larger table taking less time than a sequential one. Run with
+    /// `cargo test --release bench_scan_parallel -- --ignored`, on more than one CPU.
+    #[test]
+    #[ignore]
+    fn bench_scan_parallel() -> sqlite3_ext::Result<()> {
+        let file = TempDatabase::new("bench_scan_parallel");
+        let rusq_conn = RusqConn::open(&file.0).unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        let workers = std::thread::available_parallelism()
+            .map(|workers| workers.get())
+            .unwrap_or(1);
+        assert!(workers > 1, "the workers need more than one CPU");
+        populate(db, 64, 5000);
+        let table = VirtualTable::connect(db, "test")?;
+
+        let started = Instant::now();
+        let sequential = table.scan(Bound::Unbounded, Bound::Unbounded, read_row)?;
+        let sequential_time = started.elapsed();
+        let started = Instant::now();
+        let parallel = table.scan_parallel(Bound::Unbounded, Bound::Unbounded, read_row)?;
+        let parallel_time = started.elapsed();
+
+        assert_eq!(parallel, sequential);
+        // A tenth faster at least, so that noise does not pass for a gain.
+        assert!(
+            parallel_time.mul_f64(1.1) < sequential_time,
+            "{} rows took {:?} in parallel, {:?} sequentially",
+            sequential.len(),
+            parallel_time,
+            sequential_time
+        );
+        Ok(())
+    }
+
+    /// Shows queries over many partitions of a table created with `parallel_scan` taking less
+    /// time than the same queries of a table whose partitions the cursor reads itself. Run with
+    /// `cargo test --release bench_parallel_scan_option -- --ignored`, on more than one CPU.
+    #[test]
+    #[ignore]
+    fn bench_parallel_scan_option() -> sqlite3_ext::Result<()> {
+        let file = TempDatabase::new("bench_parallel_scan_option");
+        let rusq_conn = RusqConn::open(&file.0).unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        let workers = std::thread::available_parallelism()
+            .map(|workers| workers.get())
+            .unwrap_or(1);
+        assert!(workers > 1, "the workers need more than one CPU");
+        use_wal(db);
+        populate(db, 64, 5000);
+        populate_table(db, "par", &format!(", parallel_scan={}", workers), 64, 5000);
+
+        let query = "SELECT count(col2), sum(length(col3)) FROM {} WHERE col3 LIKE '%7%'";
+        let started = Instant::now();
+        let sequential = query_rows(db, &query.replace("{}", "test"))?;
+        let sequential_time = started.elapsed();
+        let started = Instant::now();
+        let parallel = query_rows(db, &query.replace("{}", "par"))?;
+        let parallel_time = started.elapsed();
+
+        assert_eq!(parallel, sequential);
+        // A tenth faster at least, so that noise does not pass for a gain.
+        assert!(
+            parallel_time.mul_f64(1.1) < sequential_time,
+            "{} workers took {:?}, reading sequentially {:?}",
+            workers,
+            parallel_time,
+            sequential_time
+        );
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use std::ops::IndexMut;
 use super::operations::{Connect, Create, Drop, SchemaDeclaration, Table};
 use crate::{shadow_tables::operations::Copy, ColumnDeclarations};
 use sqlite3_ext::{Connection, FallibleIterator, FallibleIteratorMut, FromValue, Result};
+use sqlparser::ast::{Ident, ObjectName, Statement as ParsedStatement};
 use sqlparser::{dialect::SQLiteDialect, parser::Parser};
 
 /// Represents a template table in a database schema, used as a blueprint for creating
@@ -65,18 +66,52 @@ impl TemplateTable {
         Ok(Self { schema })
     }
 
+    /// Retrieves the statement the template table was created with, exactly as stored in
+    /// `sqlite_schema`.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for querying the schema.
+    ///
+    /// Returns the SQL CREATE TABLE statement of the template table.
+    pub fn sql(&self, db: &Connection) -> Result<String> {
+        db.query_row(
+            "SELECT sql FROM sqlite_schema WHERE type = 'table' AND name = ?",
+            [self.name()],
+            |row| Ok(row.index_mut(0).get_str()?.to_owned()),
+        )
+    }
+
     /// Generates an SQL query for copying the template table's structure to a new table.
     ///
+    /// The statement the template was created with is parsed and re-targeted at the new table,
+    /// so the partition gets exactly the template's column definitions. Unlike copying through
+    /// `CREATE TABLE ... AS SELECT`, the declared types, and with them the column affinities, are
+    /// kept as written rather than inferred, generated columns stay generated and constraints are
+    /// kept. Columns added to the template later are part of its statement as well, which
+    /// [`SchemaDeclaration::table_query`] would not know about.
+    ///
     /// Parameters:
+    /// - `db`: Database connection for reading the template's statement.
     /// - `new_table_name`: The name of the new table to create from the template.
     ///
     /// Returns the SQL CREATE TABLE query string.
-    fn copy_query(&self, new_table_name: &str) -> String {
-        format!(
-            "CREATE TABLE IF NOT EXISTS {} AS SELECT * FROM {}",
+    fn copy_query(&self, db: &Connection, new_table_name: &str) -> Result<String> {
+        let dialect = SQLiteDialect {};
+        let statement = Parser::new(&dialect)
+            .try_with_sql(&self.sql(db)?)
+            .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?
+            .parse_statement()
+            .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?;
+        if !matches!(statement, ParsedStatement::CreateTable { .. }) {
+            return Err(sqlite3_ext::Error::Module(format!(
+                "Expected a CREATE TABLE statement for {}",
+                self.name()
+            )));
+        }
+        Ok(<Self as Copy>::adjust_table_creation_statement(
+            &statement,
             new_table_name,
-            self.name()
-        )
+        ))
     }
 
     /// Copies the template table to create a new partition with the same structure but a different name.
@@ -94,7 +129,7 @@ impl TemplateTable {
         new_table_name: &'a str,
         db: &Connection,
     ) -> sqlite3_ext::Result<&'a str> {
-        let sql = self.copy_query(new_table_name);
+        let sql = self.copy_query(db, new_table_name)?;
         Connection::execute(db, &sql, ())?;
         Ok(new_table_name)
     }
@@ -109,15 +144,11 @@ impl TemplateTable {
     /// - `new_table`: The name of the new table to which indices will be copied.
     ///
     /// Returns a vector of SQL queries used to copy the indices.
-
     pub fn copy_indices_query(&self, db: &Connection, new_table: &str) -> Result<Vec<String>> {
         let dialect = SQLiteDialect {};
         let parser = Parser::new(&dialect);
-        let schema_sql = format!(
-            "SELECT sql FROM sqlite_schema WHERE tbl_name = '{}' AND type = 'index'",
-            self.name()
-        );
-        let mut create_index_rows = db.query(&schema_sql, ())?;
+        let schema_sql = "SELECT sql FROM sqlite_schema WHERE tbl_name = ? AND type = 'index' AND sql IS NOT NULL";
+        let mut create_index_rows = db.query(schema_sql, [self.name()])?;
         let queries = create_index_rows
             .map(|row| Ok(row.index_mut(0).get_str()?.to_owned()))
             .collect::<Vec<_>>()?
@@ -140,6 +171,44 @@ impl TemplateTable {
             })?;
         Ok(index_queries)
     }
+
+    /// Creates an index on the template table and on a list of existing partitions.
+    ///
+    /// The statement is re-targeted at the template under its own name, so partitions created
+    /// later get the index through [`TemplateTable::copy_indices_query`]. The partitions get the
+    /// index under the name they would have gotten it under when they were created.
+    ///
+    /// Parameters:
+    /// - `db`: Database connection for executing the statements.
+    /// - `statement`: A parsed, named CREATE INDEX statement. The table it names is ignored.
+    /// - `partitions`: The names of the partitions to create the index on.
+    ///
+    /// Returns `Ok(())` once the index exists on all tables.
+    pub fn create_index(
+        &self,
+        db: &Connection,
+        statement: &ParsedStatement,
+        partitions: &[String],
+    ) -> Result<()> {
+        let ParsedStatement::CreateIndex { name: Some(_), .. } = statement else {
+            return Err(sqlite3_ext::Error::Module(format!(
+                "Expected a named CREATE INDEX statement, found '{}'",
+                statement
+            )));
+        };
+        let mut template_statement = statement.clone();
+        if let ParsedStatement::CreateIndex { table_name, .. } = &mut template_statement {
+            *table_name = ObjectName(vec![Ident::with_quote('"', self.name())]);
+        }
+        db.execute(&template_statement.to_string(), ())?;
+        for partition in partitions {
+            db.execute(
+                &<Self as Copy>::adjust_index_creation_statement(statement, partition),
+                (),
+            )?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -171,6 +240,73 @@ mod tests {
         assert!(table.is_ok());
     }
     #[test]
+    fn test_copy_keeps_generated_columns() -> Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let (name, columns) = mock_template();
+        let table = TemplateTable::create(conn, &name, columns)?;
+        conn.execute(
+            "ALTER TABLE test_template ADD COLUMN label TEXT GENERATED ALWAYS AS (upper(third_column)) VIRTUAL",
+            (),
+        )?;
+
+        table.copy("test_100", conn)?;
+        // Copying an existing partition again leaves it alone.
+        table.copy("test_100", conn)?;
+        conn.execute(
+            "INSERT INTO test_100 (first_column, second_column, third_column) VALUES (1, 2, 'abc')",
+            (),
+        )?;
+        conn.query_row("SELECT label FROM test_100", (), |row| {
+            assert_eq!(row.index_mut(0).get_str()?, "ABC");
+            Ok(())
+        })?;
+        let err = conn
+            .execute("INSERT INTO test_100 (label) VALUES ('xyz')", ())
+            .unwrap_err();
+        assert!(err.to_string().contains("generated column"));
+        Ok(())
+    }
+    #[test]
+    fn test_copy_keeps_declared_types() -> Result<()> {
+        let conn = RusqConn::open_in_memory().unwrap();
+        let conn = Connection::from_rusqlite(&conn);
+        let columns = ColumnDeclarations::from_iter(&[
+            "first_column timestamp",
+            "second_column real",
+            "third_column text",
+            "fourth_column blob",
+        ]);
+        let table = TemplateTable::create(conn, "test", columns)?;
+
+        // The template is empty, so no type could be inferred from its rows.
+        table.copy("test_100", conn)?;
+        let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info('test_100')")?;
+        stmt.query(())?;
+        let mut types = Vec::new();
+        while let Some(row) = stmt.next()? {
+            types.push((
+                row.index_mut(0).get_str()?.to_owned(),
+                row.index_mut(1).get_str()?.to_owned(),
+            ));
+        }
+        let declared = table
+            .columns()
+            .0
+            .iter()
+            .map(|column| (column.get_name().to_owned(), column.get_type().to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(types.len(), declared.len());
+        for ((name, column_type), (declared_name, declared_type)) in types.iter().zip(&declared) {
+            assert_eq!(name, declared_name);
+            assert!(
+                column_type.eq_ignore_ascii_case(declared_type),
+                "{name}: {column_type} != {declared_type}"
+            );
+        }
+        Ok(())
+    }
+    #[test]
     fn test_create_index() {
         let conn = match RusqConn::open_in_memory() {
             Ok(conn) => conn,
@@ -197,11 +333,11 @@ mod tests {
 
         assert_eq!(
             indexes[0],
-            "CREATE INDEX template_test_testindex_test_100 ON test_100(first_column)"
+            "CREATE INDEX \"template_test_testindex_test_100\" ON \"test_100\"(first_column)"
         );
         assert_eq!(
             indexes[1],
-            "CREATE INDEX template_test_testindex2_test_100 ON test_100(third_column)"
+            "CREATE INDEX \"template_test_testindex2_test_100\" ON \"test_100\"(third_column)"
         );
     }
 }
@@ -1,7 +1,6 @@
 use std::{
     borrow::Cow,
     fmt::{self, Display},
-    vec,
 };
 
 use sqlite3_ext::ValueType;
@@ -9,11 +8,13 @@ use sqlite3_ext::ValueType;
 use crate::{
     error::TableError,
     parse_value_type,
-    utils::{parse_interval, value_type_to_string},
+    utils::{
+        parse_interval,
+        validation::{quote_identifier, unquote_identifier, validate_identifier},
+        value_type_to_string,
+    },
 };
 
-use super::ColumnDeclarations;
-
 /// Describes a single column within a table schema, including its name, data type,
 /// and whether it serves as a partition column.
 #[derive(Clone, Debug)]
@@ -23,6 +24,8 @@ pub struct ColumnDeclaration {
     is_partition_column: bool,
     is_hidden: bool,
     is_lifetime_column: bool,
+    is_primary_key: bool,
+    is_not_null: bool,
     default_value: Option<i64>, //TODO:should it really be here? If yes, make it accept any valid datatype
 }
 
@@ -38,11 +41,13 @@ impl ColumnDeclaration {
             is_partition_column: false,
             is_hidden: false,
             is_lifetime_column: false,
+            is_primary_key: false,
+            is_not_null: false,
             default_value: None,
         }
     }
 
-    /// Returns the column's name.
+    /// Returns the column's name without quotes, as SQLite reports it.
     pub fn get_name(&self) -> &str {
         &self.name
     }
@@ -71,6 +76,22 @@ impl ColumnDeclaration {
         self.default_value
     }
 
+    /// Indicates whether the column is declared as the primary key, as in
+    /// `col1 timestamp partition_column primary key`.
+    pub fn is_primary_key(&self) -> bool {
+        self.is_primary_key
+    }
+
+    /// Sets whether the column is rendered as the primary key of the table.
+    pub fn set_primary_key(&mut self, is_primary_key: bool) {
+        self.is_primary_key = is_primary_key;
+    }
+
+    /// Sets whether the column is rendered as `NOT NULL`.
+    pub fn set_not_null(&mut self, is_not_null: bool) {
+        self.is_not_null = is_not_null;
+    }
+
     /// Indicates that this column will be hidden.
     /// https://www.sqlite.org/vtab.html#hiddencol
     pub fn set_hidden(&mut self) {
@@ -82,9 +103,18 @@ impl<'a> TryFrom<&'a str> for ColumnDeclaration {
     type Error = TableError;
 
     /// Attempts to create a `ColumnDeclaration` from a string slice, parsing the
-    /// column name, data type, and partition column flag.
+    /// column name, data type, and partition column flag. A trailing `primary key` marks the
+    /// column as the primary key. The name has to pass [`validate_identifier`], so it can be
+    /// used in generated SQL. Quotes around the name are dropped, e.g. `"group"` is stored as
+    /// `group`.
     fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        let tokens: Vec<&str> = value.split_whitespace().collect();
+        let mut tokens: Vec<&str> = value.split_whitespace().collect();
+        let is_primary_key = tokens.len() > 2
+            && tokens[tokens.len() - 2].eq_ignore_ascii_case("primary")
+            && tokens[tokens.len() - 1].eq_ignore_ascii_case("key");
+        if is_primary_key {
+            tokens.truncate(tokens.len() - 2);
+        }
         let mut is_partition_column = false;
         let mut is_lifetime_column = false;
         let mut value_type: Option<ValueType> = None;
@@ -94,7 +124,6 @@ impl<'a> TryFrom<&'a str> for ColumnDeclaration {
                 if tokens[2].to_lowercase().eq("partition_column") {
                     is_partition_column = true;
                 } else if tokens[0].to_lowercase().eq("lifetime") {
-                    println!("{:#?}", "found lifetime");
                     is_lifetime_column = true;
                     value_type = Some(ValueType::Integer);
                     default_value = Some(parse_interval(&format!("{} {}", tokens[1], tokens[2]))?);
@@ -106,16 +135,19 @@ impl<'a> TryFrom<&'a str> for ColumnDeclaration {
                 )));
             }
         }
+        validate_identifier(tokens[0])?;
         let value_type: ValueType = match value_type {
             Some(v) => v,
             None => parse_value_type(&tokens[1].trim().to_uppercase())?,
         };
         Ok(Self {
-            name: Cow::Owned(tokens[0].trim().to_string()),
+            name: Cow::Owned(unquote_identifier(tokens[0].trim())),
             data_type: value_type,
             is_partition_column,
             is_hidden: false,
             is_lifetime_column,
+            is_primary_key,
+            is_not_null: false,
             default_value,
         })
     }
@@ -134,17 +166,27 @@ impl<'a> TryFrom<&'a str> for ColumnDeclaration {
 // }
 
 impl Display for ColumnDeclaration {
-    /// Formats the `ColumnDeclaration` for display, including its name and data type.
+    /// Formats the `ColumnDeclaration` for display, including its quoted name and data type.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let hidden = match self.is_hidden {
             true => " hidden",
             false => "",
         };
+        let primary_key = match self.is_primary_key {
+            true => " PRIMARY KEY",
+            false => "",
+        };
+        let not_null = match self.is_not_null {
+            true => " NOT NULL",
+            false => "",
+        };
         f.write_fmt(format_args!(
-            "{} {}{}",
-            self.get_name(),
+            "{} {}{}{}{}",
+            quote_identifier(self.get_name()),
             self.get_type(),
-            hidden
+            hidden,
+            primary_key,
+            not_null
         ))
     }
 }
@@ -2,6 +2,7 @@ use std::fmt::Display;
 use std::vec;
 
 use super::ColumnDeclaration;
+use crate::error::TableError;
 
 /// A collection of `ColumnDeclaration` instances, representing the schema of a table.
 #[derive(Clone, Debug)]
@@ -12,17 +13,43 @@ impl<'a> FromIterator<&'a &'a str> for ColumnDeclarations {
     fn from_iter<T: IntoIterator<Item = &'a &'a str>>(iter: T) -> Self {
         let columns: Vec<ColumnDeclaration> = iter
             .into_iter()
-            .filter_map(
-                |&column_arg| match ColumnDeclaration::try_from(column_arg) {
-                    Ok(column) => Some(column),
-                    Err(_) => None,
-                },
-            )
+            .filter_map(|&column_arg| ColumnDeclaration::try_from(column_arg).ok())
             .collect();
         Self(columns)
     }
 }
 
+impl ColumnDeclarations {
+    /// The names SQLite accepts for the rowid of a table, as long as no column takes them.
+    pub const ROWID_NAMES: [&'static str; 3] = ["rowid", "_rowid_", "oid"];
+
+    /// Returns the name to refer to the rowid of a table with these columns by. A column named
+    /// `rowid` hides the rowid under that name, so the first of [`Self::ROWID_NAMES`] that is not
+    /// the name of a column is used.
+    ///
+    /// Returns `None` if every name is taken by a column, the rowid can not be read then.
+    pub fn rowid_name(&self) -> Option<&'static str> {
+        Self::ROWID_NAMES.into_iter().find(|name| {
+            !self
+                .0
+                .iter()
+                .any(|column| column.get_name().eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Like [`Self::rowid_name`], but a table whose columns take every name is an error.
+    ///
+    /// Returns a `TableError::ColumnDeclaration` if no name is left for the rowid.
+    pub fn require_rowid_name(&self) -> Result<&'static str, TableError> {
+        self.rowid_name().ok_or_else(|| {
+            TableError::ColumnDeclaration(format!(
+                "Columns named {} hide the rowid, at least one of the names has to stay free",
+                Self::ROWID_NAMES.join(", ")
+            ))
+        })
+    }
+}
+
 impl From<ColumnDeclarations> for String {
     /// Converts `ColumnDeclarations` into a comma-separated string of column definitions.
     fn from(value: ColumnDeclarations) -> Self {
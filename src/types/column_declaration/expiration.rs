@@ -1,8 +1,83 @@
+use crate::error::TableError;
+
 use super::ColumnDeclaration;
 
 /// Represents the declaration of a partition column within a table schema, optionally
 /// encapsulating a `ColumnDeclaration` to define the partitioning behavior.
 pub type LifetimeColumn = ColumnDeclaration;
+
+/// Decides what happens to a row whose partition has already outlived the table's lifetime,
+/// such as late arriving data older than the lifetime. Written to such a partition, the row
+/// would be lost as soon as expired partitions are dropped.
+///
+/// Declared in the create statement as `on_expired_insert reject` or
+/// `on_expired_insert quarantine`, and stored in the root table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpiredInsertPolicy {
+    /// The insert fails.
+    #[default]
+    Reject,
+    /// The row is written to the `<name>_quarantine` table, which is not part of the partitioned
+    /// table and never dropped by retention.
+    Quarantine,
+}
+
+impl ExpiredInsertPolicy {
+    /// The keyword introducing the policy in the create statement.
+    pub const OPTION: &'static str = "on_expired_insert";
+
+    /// Returns the name of the policy, as written in the create statement.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpiredInsertPolicy::Reject => "reject",
+            ExpiredInsertPolicy::Quarantine => "quarantine",
+        }
+    }
+
+    /// Parses a create statement argument declaring the policy.
+    ///
+    /// Parameters:
+    /// - `arg`: An argument of the create statement, e.g. `on_expired_insert quarantine`.
+    ///
+    /// Returns:
+    /// - `None` if the argument does not declare the policy, e.g. because it is a column.
+    /// - `Some(Err(..))` if it does but names an unknown policy.
+    pub fn from_argument(arg: &str) -> Option<Result<Self, TableError>> {
+        let tokens = arg.split_whitespace().collect::<Vec<&str>>();
+        if !tokens
+            .first()
+            .is_some_and(|token| token.eq_ignore_ascii_case(Self::OPTION))
+        {
+            return None;
+        }
+        Some(match tokens.as_slice() {
+            [_, policy] => Self::try_from(*policy),
+            _ => Err(TableError::InvalidOption(format!(
+                "Expected '{} reject' or '{} quarantine', found '{}'",
+                Self::OPTION,
+                Self::OPTION,
+                arg
+            ))),
+        })
+    }
+}
+
+impl TryFrom<&str> for ExpiredInsertPolicy {
+    type Error = TableError;
+
+    /// Parses the name of a policy, ignoring case.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "reject" => Ok(ExpiredInsertPolicy::Reject),
+            "quarantine" => Ok(ExpiredInsertPolicy::Quarantine),
+            _ => Err(TableError::InvalidOption(format!(
+                "Unknown {} policy '{}', expected reject or quarantine",
+                Self::OPTION,
+                value
+            ))),
+        }
+    }
+}
 // pub struct LifetimeColumn(pub i64);
 // impl FromIterator<ColumnDeclaration> for LifetimeColumn {
 //     /// Creates a `PartitionColumn` from an iterator of `ColumnDeclaration` items, selecting
@@ -4,6 +4,7 @@ pub mod expiration;
 pub mod partition;
 pub use column::ColumnDeclaration;
 pub use columns::ColumnDeclarations;
+pub use expiration::ExpiredInsertPolicy;
 pub use partition::PartitionColumn;
 
 impl<'a> From<&'a ColumnDeclarations> for &'a [ColumnDeclaration] {
@@ -7,7 +7,7 @@ use sqlite3_ext::{vtab::ConstraintOp, ValueRef};
 /// - `column`: The column name to which the condition applies.
 /// - `operator`: The comparison operator used in the condition, such as "=", ">", or "<=".
 /// - `value`: A reference to the value used in the comparison, supporting various data types.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Condition<'a> {
     pub column: &'a str,
     pub operator: &'a ConstraintOp,
@@ -4,10 +4,12 @@ use crate::error::TableError;
 
 pub use self::{
     conditions::{Condition, Conditions},
+    query_plan::QueryPlan,
     where_clauses::{WhereClause, WhereClauses},
 };
 
 mod conditions;
+mod query_plan;
 mod where_clauses;
 
 /// Converts a reference to a `WhereClause` and a mutable reference to a `ValueRef`
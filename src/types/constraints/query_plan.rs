@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::WhereClauses;
+use crate::error::TableError;
+use crate::IndexFormat;
+
+/// The plan `best_index` hands over to `filter` through the index string: the where clauses to
+/// apply and the columns SQLite will read from the cursor.
+///
+/// The serialized plan shows up in `EXPLAIN QUERY PLAN` output, so it starts with a readable
+/// description of which partitions are scanned, e.g. `{"scan":"partitions where col1 > ?",...`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QueryPlan {
+    /// Human readable description of the partitions the scan visits.
+    #[serde(default)]
+    pub scan: String,
+    /// The where clauses, keyed by the table they apply to.
+    pub where_clauses: WhereClauses,
+    /// Bitmask of the columns used by the statement, as reported in `colUsed`. Bit N is set if
+    /// column N is used, the highest bit stands for column 63 and every column after it.
+    pub columns_used: u64,
+    /// The argv indexes of the `IN` constraints on the partition column whose values SQLite
+    /// hands over all at once, as a list, rather than one `filter` call per value.
+    #[serde(default)]
+    pub value_lists: Vec<i32>,
+    /// Whether SQLite relies on the rows coming back ordered by the partition column. Partitions
+    /// are scanned oldest first anyway, so only the rows within each partition are sorted.
+    #[serde(default)]
+    pub ordered: bool,
+    /// Whether the order SQLite relies on is descending. Partitions are then scanned newest
+    /// first and the rows within each partition are sorted in descending order.
+    #[serde(default)]
+    pub descending: bool,
+    /// Whether the statement reads no column and has no constraint, e.g. `SELECT count(*)`.
+    /// The rows are then produced from the row counts kept in the lookup table, without reading
+    /// the partitions.
+    #[serde(default)]
+    pub counted: bool,
+}
+
+impl QueryPlan {
+    /// Constructs a new `QueryPlan`.
+    pub fn new(scan: String, where_clauses: WhereClauses, columns_used: u64) -> Self {
+        Self {
+            scan,
+            where_clauses,
+            columns_used,
+            value_lists: Vec::new(),
+            ordered: false,
+            descending: false,
+            counted: false,
+        }
+    }
+
+    /// Sets whether the rows have to come back ordered by the partition column.
+    pub fn with_ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Sets whether the order the rows have to come back in is descending.
+    pub fn with_descending(mut self, descending: bool) -> Self {
+        self.descending = descending;
+        self
+    }
+
+    /// Sets whether the rows are produced from the row counts of the partitions.
+    pub fn with_counted(mut self, counted: bool) -> Self {
+        self.counted = counted;
+        self
+    }
+
+    /// Sets the argv indexes of the constraints passed as lists of values.
+    pub fn with_value_lists(mut self, value_lists: Vec<i32>) -> Self {
+        self.value_lists = value_lists;
+        self
+    }
+
+    /// Indicates whether the statement reads the column at `index`.
+    pub fn is_column_used(&self, index: usize) -> bool {
+        self.columns_used & (1 << index.min(63)) != 0
+    }
+
+    /// Serializes the plan into an index string in the given format.
+    ///
+    /// Without the `binary_index` feature the plan is always written as JSON.
+    ///
+    /// Returns:
+    /// - The index string, or a `TableError::WhereClause` if the plan cannot be serialized.
+    pub fn to_index_str(&self, format: IndexFormat) -> Result<String, TableError> {
+        match format {
+            #[cfg(feature = "binary_index")]
+            IndexFormat::Binary => {
+                use base64::Engine;
+                let bytes = bincode::serialize(self)
+                    .map_err(|err| TableError::WhereClause(err.to_string()))?;
+                Ok(format!(
+                    "{}{}",
+                    IndexFormat::BINARY_PREFIX,
+                    base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes)
+                ))
+            }
+            _ => {
+                serde_json::to_string(self).map_err(|err| TableError::WhereClause(err.to_string()))
+            }
+        }
+    }
+
+    /// Reads a plan written by [`QueryPlan::to_index_str`], telling the format by
+    /// [`IndexFormat::BINARY_PREFIX`].
+    ///
+    /// Returns:
+    /// - The plan, or a `TableError::WhereClause` if the string is not a plan, or a binary plan
+    ///   and the module was built without the `binary_index` feature.
+    pub fn from_index_str(idx_str: &str) -> Result<Self, TableError> {
+        let invalid = |err: &dyn std::fmt::Display| {
+            TableError::WhereClause(format!("Invalid index string '{idx_str}': {err}"))
+        };
+        match idx_str.strip_prefix(IndexFormat::BINARY_PREFIX) {
+            #[cfg(feature = "binary_index")]
+            Some(encoded) => {
+                use base64::Engine;
+                let bytes = base64::engine::general_purpose::STANDARD_NO_PAD
+                    .decode(encoded)
+                    .map_err(|err| invalid(&err))?;
+                bincode::deserialize(&bytes).map_err(|err| invalid(&err))
+            }
+            #[cfg(not(feature = "binary_index"))]
+            Some(_) => Err(invalid(&"binary plans need the binary_index feature")),
+            None => serde_json::from_str(idx_str).map_err(|err| invalid(&err)),
+        }
+    }
+}
+
+impl Default for QueryPlan {
+    /// A plan without where clauses that reads every column.
+    fn default() -> Self {
+        Self::new(String::new(), WhereClauses(HashMap::default()), u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraints::WhereClause;
+    use sqlite3_ext::vtab::ConstraintOp;
+    #[cfg(feature = "binary_index")]
+    use std::time::Instant;
+
+    /// A plan constraining `constraints` columns of the partitions and the lookup table.
+    fn plan(constraints: i32) -> QueryPlan {
+        let partition_clauses = (0..constraints)
+            .map(|index| WhereClause::new(format!("col{index}"), ConstraintOp::GE, index))
+            .collect();
+        let lookup_clauses = vec![WhereClause::new(
+            "partition_value".to_string(),
+            ConstraintOp::LT,
+            constraints,
+        )];
+        let where_clauses: WhereClauses = [
+            ("partition_table".to_string(), partition_clauses),
+            ("lookup_table".to_string(), lookup_clauses),
+        ]
+        .into_iter()
+        .collect();
+        QueryPlan::new(
+            "partitions where col0 >= ?".to_string(),
+            where_clauses,
+            0b1011,
+        )
+        .with_value_lists(vec![1])
+        .with_ordered(true)
+        .with_descending(true)
+    }
+
+    /// Flattens the where clauses of a plan in a stable order for comparison.
+    fn clauses(plan: &QueryPlan) -> Vec<(String, String, String, i32)> {
+        let mut clauses: Vec<_> = plan
+            .where_clauses
+            .iter()
+            .flat_map(|(table, clauses)| {
+                clauses.iter().map(move |clause| {
+                    (
+                        table.clone(),
+                        clause.get_name().to_owned(),
+                        format!("{:?}", clause.get_operator()),
+                        clause.get_constraint_index(),
+                    )
+                })
+            })
+            .collect();
+        clauses.sort();
+        clauses
+    }
+
+    #[test]
+    fn test_index_str_round_trip() {
+        let original = plan(5);
+        for format in [IndexFormat::Json, IndexFormat::Binary] {
+            let idx_str = original.to_index_str(format).unwrap();
+            assert!(!idx_str.contains('\0'));
+            assert_eq!(
+                idx_str.starts_with(IndexFormat::BINARY_PREFIX),
+                format == IndexFormat::Binary && cfg!(feature = "binary_index"),
+                "{}",
+                format.as_str()
+            );
+            let decoded = QueryPlan::from_index_str(&idx_str).unwrap();
+            assert_eq!(decoded.scan, original.scan);
+            assert_eq!(decoded.columns_used, original.columns_used);
+            assert_eq!(decoded.value_lists, original.value_lists);
+            assert_eq!(decoded.ordered, original.ordered);
+            assert_eq!(decoded.descending, original.descending);
+            assert_eq!(decoded.counted, original.counted);
+            assert_eq!(clauses(&decoded), clauses(&original));
+        }
+        // Unreadable strings are reported rather than scanning everything.
+        for idx_str in [
+            "",
+            "{\"scan\":",
+            "(scan:\"all partitions\")",
+            "bin:not base64!",
+        ] {
+            assert!(QueryPlan::from_index_str(idx_str).is_err(), "{}", idx_str);
+        }
+    }
+
+    #[test]
+    fn test_index_str_json_form() {
+        let lookup_clauses = vec![WhereClause::new(
+            "partition_value".to_string(),
+            ConstraintOp::IsNull,
+            0,
+        )];
+        let where_clauses: WhereClauses = [("lookup_table".to_string(), lookup_clauses)]
+            .into_iter()
+            .collect();
+        let original = QueryPlan::new("all partitions".to_string(), where_clauses, 0b11);
+        let idx_str = original.to_index_str(IndexFormat::Json).unwrap();
+        assert_eq!(
+            idx_str,
+            r#"{"scan":"all partitions","where_clauses":{"lookup_table":[{"column_name":"partition_value","operator":"IsNull","constraint_index":0}]},"columns_used":3,"value_lists":[],"ordered":false,"descending":false,"counted":false}"#
+        );
+        let decoded = QueryPlan::from_index_str(&idx_str).unwrap();
+        assert_eq!(clauses(&decoded), clauses(&original));
+        assert_eq!(decoded.to_index_str(IndexFormat::Json).unwrap(), idx_str);
+        // Fields added to the plan later default when reading older strings.
+        let decoded =
+            QueryPlan::from_index_str(r#"{"where_clauses":{},"columns_used":1}"#).unwrap();
+        assert!(decoded.value_lists.is_empty() && !decoded.ordered && !decoded.counted);
+    }
+
+    /// Shows a plan with many constraints written shorter and encoded and decoded faster in the
+    /// binary format than as JSON. Run with
+    /// `cargo test --release --features binary_index bench_index_str -- --ignored`.
+    #[cfg(feature = "binary_index")]
+    #[test]
+    #[ignore]
+    fn bench_index_str() {
+        let plan = plan(64);
+        let [(json_time, json_length), (binary_time, binary_length)] =
+            [IndexFormat::Json, IndexFormat::Binary].map(|format| {
+                let started = Instant::now();
+                let mut length = 0;
+                for _ in 0..10000 {
+                    let idx_str = plan.to_index_str(format).unwrap();
+                    length = idx_str.len();
+                    assert_eq!(
+                        QueryPlan::from_index_str(&idx_str).unwrap().columns_used,
+                        0b1011
+                    );
+                }
+                (started.elapsed(), length)
+            });
+        assert!(
+            binary_length < json_length,
+            "{binary_length} bytes, as JSON {json_length}"
+        );
+        assert!(
+            binary_time < json_time,
+            "10000 round trips took {binary_time:?}, as JSON {json_time:?}"
+        );
+    }
+}
@@ -83,8 +83,20 @@ impl FromIterator<(String, Vec<WhereClause>)> for WhereClauses {
 }
 impl Display for WhereClause {
     /// Formats a `WhereClause` for display, showing the column name, operator, and a placeholder
-    /// for the value, which is represented by a "?" in prepared SQL statements.
+    /// for the value, which is represented by a "?" in prepared SQL statements. Unary operators
+    /// such as `IS NULL` take no value.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if matches!(
+            self.operator,
+            ConstraintOp::IsNull | ConstraintOp::IsNotNull
+        ) {
+            return write!(
+                f,
+                "{} {}",
+                self.column_name,
+                ConstraintOpDef::from(self.operator)
+            );
+        }
         write!(
             f,
             "{} {} ?",
@@ -0,0 +1,104 @@
+use crate::error::TableError;
+
+/// How the query plan is serialized into the index string `best_index` hands to `filter`.
+///
+/// Declared in the create statement as `index_format=json` or `index_format=binary`, and stored in
+/// the root table. JSON keeps the plan readable in `EXPLAIN QUERY PLAN` output, the binary format
+/// is smaller and faster to parse for queries with many constraints but needs the module to be
+/// built with the `binary_index` feature. Tables created when the readable format was RON are
+/// stored as `ron`, which is read as JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexFormat {
+    /// The plan is written as JSON, e.g. `{"scan":"all partitions","where_clauses":{},...}`.
+    #[default]
+    Json,
+    /// The plan is encoded with bincode and base64, behind [`IndexFormat::BINARY_PREFIX`].
+    Binary,
+}
+
+impl IndexFormat {
+    /// The keyword introducing the format in the create statement, as in `index_format=binary`.
+    pub const OPTION: &'static str = "index_format";
+
+    /// Marks an index string holding a binary plan. A JSON plan always starts with `{`, so the
+    /// format of an index string can be told without knowing the table it was made for.
+    pub const BINARY_PREFIX: &'static str = "bin:";
+
+    /// Returns the name of the format, as written in the create statement.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexFormat::Json => "json",
+            IndexFormat::Binary => "binary",
+        }
+    }
+
+    /// Parses a create statement argument declaring the format.
+    ///
+    /// Parameters:
+    /// - `arg`: An argument of the create statement, e.g. `index_format=binary`.
+    ///
+    /// Returns:
+    /// - `None` if the argument does not declare the format, e.g. because it is a column.
+    /// - `Some(Err(..))` if it does but names an unknown format, or the binary format without
+    ///   the `binary_index` feature.
+    pub fn from_argument(arg: &str) -> Option<Result<Self, TableError>> {
+        let (option, format) = arg.split_once('=')?;
+        if !option.trim().eq_ignore_ascii_case(Self::OPTION) {
+            return None;
+        }
+        Some(Self::try_from(format.trim()).and_then(|format| {
+            if format == IndexFormat::Binary && !cfg!(feature = "binary_index") {
+                return Err(TableError::InvalidOption(format!(
+                    "{}=binary needs the module to be built with the binary_index feature",
+                    Self::OPTION
+                )));
+            }
+            Ok(format)
+        }))
+    }
+}
+
+impl TryFrom<&str> for IndexFormat {
+    type Error = TableError;
+
+    /// Parses the name of a format, ignoring case. `ron`, the readable format of earlier
+    /// versions, is taken as JSON.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "json" | "ron" => Ok(IndexFormat::Json),
+            "binary" => Ok(IndexFormat::Binary),
+            _ => Err(TableError::InvalidOption(format!(
+                "Unknown {} '{}', expected json or binary",
+                Self::OPTION,
+                value
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_argument() {
+        assert_eq!(
+            IndexFormat::from_argument(" INDEX_FORMAT = Json ")
+                .unwrap()
+                .unwrap(),
+            IndexFormat::Json
+        );
+        assert_eq!(IndexFormat::try_from("ron").unwrap(), IndexFormat::Json);
+        assert_eq!(
+            IndexFormat::from_argument("index_format=binary")
+                .unwrap()
+                .is_ok(),
+            cfg!(feature = "binary_index")
+        );
+        assert!(IndexFormat::from_argument("index_format=xml")
+            .unwrap()
+            .is_err());
+        assert!(IndexFormat::from_argument("round=ceil").is_none());
+        assert!(IndexFormat::from_argument("index_format text").is_none());
+    }
+}
@@ -8,7 +8,15 @@ use sqlite3_ext::{vtab::ConstraintOp, Value};
 
 pub mod column_declaration;
 pub mod constraints;
+pub mod index_format;
+pub mod options;
+pub mod partition_naming;
+pub mod rounding;
 pub use column_declaration::*;
+pub use index_format::IndexFormat;
+pub use options::TableOptions;
+pub use partition_naming::PartitionNaming;
+pub use rounding::BucketRounding;
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
 #[serde(remote = "ConstraintOp")]
@@ -82,6 +90,18 @@ impl<'de> Deserialize<'de> for BlobWrapper {
                 // Assume Blob::from(&[u8]) is available for converting Vec<u8> to Blob
                 Ok(BlobWrapper(bytes))
             }
+
+            #[cfg(feature = "json")]
+            fn visit_str<E>(self, value: &str) -> Result<BlobWrapper, E>
+            where
+                E: de::Error,
+            {
+                use base64::Engine;
+                base64::engine::general_purpose::STANDARD
+                    .decode(value)
+                    .map(BlobWrapper)
+                    .map_err(de::Error::custom)
+            }
         }
 
         deserializer.deserialize_byte_buf(BlobVisitor)
@@ -92,7 +112,14 @@ impl Serialize for BlobWrapper {
     where
         S: Serializer,
     {
+        // JSON has no byte type, so blobs are written as base64 strings.
+        #[cfg(feature = "json")]
+        {
+            use base64::Engine;
+            serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(&self.0))
+        }
         // Convert Blob to Vec<u8> and serialize it
+        #[cfg(not(feature = "json"))]
         serializer.serialize_bytes(self.0.as_slice())
     }
 }
@@ -0,0 +1,170 @@
+use crate::error::TableError;
+use crate::shadow_tables::PartitionValue;
+use crate::{BucketRounding, ExpiredInsertPolicy, IndexFormat, PartitionNaming};
+
+/// The settings of a partitioned table given in the create statement besides the interval and
+/// the columns. They are stored in the root table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableOptions {
+    /// The lifetime of each partition in seconds, if partitions expire.
+    pub lifetime: Option<i64>,
+    /// What happens to rows inserted into partitions that have already expired.
+    pub on_expired_insert: ExpiredInsertPolicy,
+    /// The name of the integer column holding the partition value of each row, if rows carry it.
+    pub bucket_column: Option<String>,
+    /// Which interval boundary values of the partition column are bucketed to.
+    pub round: BucketRounding,
+    /// How query plans are serialized into the index string.
+    pub index_format: IndexFormat,
+    /// Whether rows without a usable partition column value are written to the
+    /// `<name>_default` table instead of failing the insert.
+    pub default_partition: bool,
+    /// Whether every written value has to match the declared type of its column, not only the
+    /// partition column value.
+    pub strict: bool,
+    /// How rows are assigned to partitions. It is declared in place of the interval, with hash
+    /// partitioning the interval holds the number of partitions.
+    pub strategy: PartitionValue,
+    /// The columns marked `partition_column` after the first, which make the partition key
+    /// composite: rows sharing a partition value are split further by their values.
+    pub key_columns: Vec<String>,
+    /// The values a list partitioned table was declared with, as the lookup table records them.
+    /// Only they get partitions, other values go to the default partition or are refused. Empty
+    /// if every value gets a partition.
+    pub list_values: Vec<String>,
+    /// How the tables holding the partitions are named.
+    pub partition_name: PartitionNaming,
+    /// The number of worker threads reading partitions ahead of a scan, each on a read-only
+    /// connection of its own, or `None` to read the partitions one after the other.
+    ///
+    /// The workers only run on databases in WAL mode and read one snapshot of the committed
+    /// data. Scans of other databases, inside a transaction or after writes of the same
+    /// statement are read sequentially.
+    pub parallel_scan: Option<usize>,
+    /// The most partition statements a connection keeps prepared for the table at once: cached
+    /// INSERT statements, and the statements of the `parallel_scan` workers, which read one
+    /// partition each at a time. `None` for the default, see
+    /// [`VirtualTable::max_statements`](crate::shadow_tables::interface::VirtualTable::max_statements).
+    pub max_statements: Option<usize>,
+}
+
+impl TableOptions {
+    /// The keyword introducing the bucket column in the create statement, as in
+    /// `add_bucket_column=bucket`.
+    pub const BUCKET_COLUMN_OPTION: &'static str = "add_bucket_column";
+
+    /// The keyword enabling the default partition in the create statement.
+    pub const DEFAULT_PARTITION_OPTION: &'static str = "default_partition";
+
+    /// The keyword enabling strict type checks in the create statement.
+    pub const STRICT_OPTION: &'static str = "strict";
+
+    /// The keyword introducing the number of scan workers in the create statement, as in
+    /// `parallel_scan=4`.
+    pub const PARALLEL_SCAN_OPTION: &'static str = "parallel_scan";
+
+    /// The keyword introducing the cap on prepared partition statements in the create
+    /// statement, as in `max_statements=16`.
+    pub const MAX_STATEMENTS_OPTION: &'static str = "max_statements";
+
+    /// Applies a create statement argument to the options if it declares one.
+    ///
+    /// Parameters:
+    /// - `arg`: An argument of the create statement, e.g. `on_expired_insert quarantine`,
+    ///   `add_bucket_column=bucket`, `round=ceil`, `index_format=binary`,
+    ///   `partition_name='p_{table}_{value}'`, `parallel_scan=4`, `max_statements=16`,
+    ///   `default_partition` or `strict`.
+    ///
+    /// Returns:
+    /// - `Ok(true)` if the argument declared an option, `Ok(false)` if it did not, e.g. because
+    ///   it is a column.
+    /// - An error if the argument declared an option with an invalid value.
+    pub fn apply_argument(&mut self, arg: &str) -> Result<bool, TableError> {
+        if let Some(policy) = ExpiredInsertPolicy::from_argument(arg) {
+            self.on_expired_insert = policy?;
+            return Ok(true);
+        }
+        if let Some(round) = BucketRounding::from_argument(arg) {
+            self.round = round?;
+            return Ok(true);
+        }
+        if let Some(format) = IndexFormat::from_argument(arg) {
+            self.index_format = format?;
+            return Ok(true);
+        }
+        if let Some(naming) = PartitionNaming::from_argument(arg) {
+            self.partition_name = naming?;
+            return Ok(true);
+        }
+        if arg
+            .trim()
+            .eq_ignore_ascii_case(Self::DEFAULT_PARTITION_OPTION)
+        {
+            self.default_partition = true;
+            return Ok(true);
+        }
+        if arg.trim().eq_ignore_ascii_case(Self::STRICT_OPTION) {
+            self.strict = true;
+            return Ok(true);
+        }
+        match arg.split_once('=') {
+            Some((option, name))
+                if option
+                    .trim()
+                    .eq_ignore_ascii_case(Self::BUCKET_COLUMN_OPTION) =>
+            {
+                let name = name.trim();
+                if name.is_empty()
+                    || !name
+                        .chars()
+                        .all(|char| char.is_ascii_alphanumeric() || char == '_')
+                {
+                    return Err(TableError::InvalidOption(format!(
+                        "Expected a column name after {}=, found '{}'",
+                        Self::BUCKET_COLUMN_OPTION,
+                        name
+                    )));
+                }
+                self.bucket_column = Some(name.to_owned());
+                Ok(true)
+            }
+            Some((option, workers))
+                if option
+                    .trim()
+                    .eq_ignore_ascii_case(Self::PARALLEL_SCAN_OPTION) =>
+            {
+                let workers = workers.trim();
+                match workers.parse::<usize>() {
+                    Ok(workers) if workers > 0 => {
+                        self.parallel_scan = Some(workers);
+                        Ok(true)
+                    }
+                    _ => Err(TableError::InvalidOption(format!(
+                        "Expected a positive number of workers after {}=, found '{}'",
+                        Self::PARALLEL_SCAN_OPTION,
+                        workers
+                    ))),
+                }
+            }
+            Some((option, statements))
+                if option
+                    .trim()
+                    .eq_ignore_ascii_case(Self::MAX_STATEMENTS_OPTION) =>
+            {
+                let statements = statements.trim();
+                match statements.parse::<usize>() {
+                    Ok(statements) if statements > 0 => {
+                        self.max_statements = Some(statements);
+                        Ok(true)
+                    }
+                    _ => Err(TableError::InvalidOption(format!(
+                        "Expected a positive number of statements after {}=, found '{}'",
+                        Self::MAX_STATEMENTS_OPTION,
+                        statements
+                    ))),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::TableError;
+use crate::utils::validation::unquote_identifier;
+
+/// How the tables holding the partitions are named.
+///
+/// Declared in the create statement as `partition_name='<template>'`, and stored in the root
+/// table. The template holds `{table}`, replaced by the name of the virtual table, and `{value}`,
+/// replaced by the partition value and, for composite keys, the key hash. SQLite refuses braces
+/// outside of quotes, so the template is given as a string. Tables created before the option
+/// existed use the default template `{table}_{value}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionNaming {
+    template: String,
+}
+
+impl Default for PartitionNaming {
+    fn default() -> Self {
+        Self {
+            template: Self::DEFAULT_TEMPLATE.to_owned(),
+        }
+    }
+}
+
+impl PartitionNaming {
+    /// The keyword introducing the template in the create statement, as in
+    /// `partition_name='p_{table}_{value}'`.
+    pub const OPTION: &'static str = "partition_name";
+
+    /// The placeholder for the name of the virtual table.
+    pub const TABLE: &'static str = "{table}";
+
+    /// The placeholder for the partition value.
+    pub const VALUE: &'static str = "{value}";
+
+    /// The template partitions are named with unless another one is declared, e.g. `test_3600`.
+    pub const DEFAULT_TEMPLATE: &'static str = "{table}_{value}";
+
+    /// Returns the template, as written in the create statement without quotes.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Parses a create statement argument declaring the template.
+    ///
+    /// Parameters:
+    /// - `arg`: An argument of the create statement, e.g. `partition_name='p_{table}_{value}'`.
+    ///
+    /// Returns:
+    /// - `None` if the argument does not declare the template, e.g. because it is a column.
+    /// - `Some(Err(..))` if it does but the template is invalid, see [`PartitionNaming::try_from`].
+    pub fn from_argument(arg: &str) -> Option<Result<Self, TableError>> {
+        let (option, template) = arg.split_once('=')?;
+        if !option.trim().eq_ignore_ascii_case(Self::OPTION) {
+            return None;
+        }
+        let template = template.trim();
+        let template = match template
+            .strip_prefix('\'')
+            .and_then(|template| template.strip_suffix('\''))
+        {
+            Some(template) => template.replace("''", "'"),
+            None => unquote_identifier(template),
+        };
+        Some(Self::try_from(template.as_str()))
+    }
+
+    /// Names the partition table of a partition.
+    ///
+    /// Parameters:
+    /// - `table`: The name of the virtual table.
+    /// - `value`: The partition value as written in names, e.g. `m3600` for -3600.
+    ///
+    /// Returns:
+    /// - The name of the partition table.
+    pub fn name(&self, table: &str, value: &str) -> String {
+        let (before, after) = self.split(table);
+        format!("{before}{value}{after}")
+    }
+
+    /// Recovers the partition value part of a partition table name, the inverse of
+    /// [`PartitionNaming::name`].
+    ///
+    /// Parameters:
+    /// - `table`: The name of the virtual table the partition was named after.
+    /// - `name`: The name of the partition table.
+    ///
+    /// Returns:
+    /// - The part `{value}` was replaced with, or `None` if the name does not follow the
+    ///   template, e.g. because the partition was attached under a name of its own.
+    pub fn value<'a>(&self, table: &str, name: &'a str) -> Option<&'a str> {
+        let (before, after) = self.split(table);
+        name.strip_prefix(&before)?
+            .strip_suffix(&after)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Renders the template for a table, returning the text before and after `{value}`.
+    fn split(&self, table: &str) -> (String, String) {
+        let (before, after) = self
+            .template
+            .split_once(Self::VALUE)
+            .unwrap_or((&self.template, ""));
+        (
+            before.replace(Self::TABLE, table),
+            after.replace(Self::TABLE, table),
+        )
+    }
+}
+
+impl TryFrom<&str> for PartitionNaming {
+    type Error = TableError;
+
+    /// Parses a template. It has to hold `{table}` and `{value}` once each, keeping partitions
+    /// of different tables and values apart, and otherwise only letters, digits and underscores.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = |reason: &str| {
+            Err(TableError::InvalidOption(format!(
+                "Invalid {} '{}', {}",
+                Self::OPTION,
+                value,
+                reason
+            )))
+        };
+        for placeholder in [Self::TABLE, Self::VALUE] {
+            if value.matches(placeholder).count() != 1 {
+                return invalid(&format!("expected {placeholder} exactly once"));
+            }
+        }
+        if !value
+            .replace(Self::TABLE, "")
+            .replace(Self::VALUE, "")
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+        {
+            return invalid("only letters, digits and underscores may surround the placeholders");
+        }
+        Ok(Self {
+            template: value.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_argument() {
+        let naming = PartitionNaming::from_argument(" PARTITION_NAME = 'p_{table}_{value}' ")
+            .unwrap()
+            .unwrap();
+        assert_eq!(naming.as_str(), "p_{table}_{value}");
+        assert_eq!(naming.name("test", "3600"), "p_test_3600");
+        assert_eq!(naming.value("test", "p_test_3600"), Some("3600"));
+        assert_eq!(naming.value("test", "test_3600"), None);
+        assert_eq!(naming.value("test", "p_test_"), None);
+
+        let naming = PartitionNaming::from_argument("partition_name=\"{value}_of_{table}\"")
+            .unwrap()
+            .unwrap();
+        assert_eq!(naming.name("test", "m60"), "m60_of_test");
+        assert_eq!(naming.value("test", "m60_of_test"), Some("m60"));
+
+        let naming = PartitionNaming::default();
+        assert_eq!(naming.name("test", "3600"), "test_3600");
+
+        assert!(PartitionNaming::from_argument("partition_name='p_{value}'")
+            .unwrap()
+            .is_err());
+        assert!(
+            PartitionNaming::from_argument("partition_name='{table}_{value}_{value}'")
+                .unwrap()
+                .is_err()
+        );
+        assert!(
+            PartitionNaming::from_argument("partition_name='{table} {value}'")
+                .unwrap()
+                .is_err()
+        );
+        assert!(PartitionNaming::from_argument("round=ceil").is_none());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::error::TableError;
+
+/// Decides which interval boundary a value of the partition column is bucketed to, i.e. which
+/// partition value the partition holding the row gets.
+///
+/// Declared in the create statement as `round=floor`, `round=ceil` or `round=nearest`, and stored
+/// in the root table. Whatever the rounding, every partition covers exactly one interval of
+/// values, it is only the label of the partition that moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BucketRounding {
+    /// A value belongs to the partition starting at the boundary at or before it.
+    #[default]
+    Floor,
+    /// A value belongs to the partition labelled with the boundary at or after it.
+    Ceil,
+    /// A value belongs to the partition labelled with the closest boundary, values halfway
+    /// between two boundaries go to the later one.
+    Nearest,
+}
+
+impl BucketRounding {
+    /// The keyword introducing the rounding in the create statement, as in `round=ceil`.
+    pub const OPTION: &'static str = "round";
+
+    /// Returns the name of the rounding, as written in the create statement.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BucketRounding::Floor => "floor",
+            BucketRounding::Ceil => "ceil",
+            BucketRounding::Nearest => "nearest",
+        }
+    }
+
+    /// Parses a create statement argument declaring the rounding.
+    ///
+    /// Parameters:
+    /// - `arg`: An argument of the create statement, e.g. `round=nearest`.
+    ///
+    /// Returns:
+    /// - `None` if the argument does not declare the rounding, e.g. because it is a column.
+    /// - `Some(Err(..))` if it does but names an unknown rounding.
+    pub fn from_argument(arg: &str) -> Option<Result<Self, TableError>> {
+        let (option, rounding) = arg.split_once('=')?;
+        if !option.trim().eq_ignore_ascii_case(Self::OPTION) {
+            return None;
+        }
+        Some(Self::try_from(rounding.trim()))
+    }
+
+    /// Returns how far a value is shifted before flooring it to the interval, which is also how
+    /// far the first value of a partition lies before its partition value.
+    ///
+    /// Parameters:
+    /// - `interval`: The partition interval in seconds.
+    pub fn offset(&self, interval: i64) -> i64 {
+        match self {
+            BucketRounding::Floor => 0,
+            BucketRounding::Ceil => interval - 1,
+            BucketRounding::Nearest => interval / 2,
+        }
+    }
+
+    /// Rounds a UNIX epoch to the partition value of the partition it belongs to.
+    ///
+    /// Parameters:
+    /// - `epoch`: The value of the partition column as a UNIX epoch.
+    /// - `interval`: The partition interval in seconds.
+    ///
+    /// Returns:
+    /// - The partition value, a multiple of the interval. Epochs before 1970 are floored too, so
+    ///   -1 belongs to the partition starting at `-interval` rather than to the one at 0. Epochs
+    ///   so close to the limits of `i64` that their partition value is out of range are clamped
+    ///   to the first or last representable multiple of the interval.
+    pub fn bucket(&self, epoch: i64, interval: i64) -> i64 {
+        let shifted = epoch.saturating_add(self.offset(interval));
+        shifted
+            .div_euclid(interval)
+            .checked_mul(interval)
+            .unwrap_or(i64::MIN / interval * interval)
+    }
+
+    /// Returns the first UNIX epoch that belongs to the partition with the given partition value.
+    /// The partition holds the epochs from there up to one interval later, exclusive.
+    ///
+    /// Parameters:
+    /// - `partition_value`: The partition value of a partition.
+    /// - `interval`: The partition interval in seconds.
+    pub fn first_epoch(&self, partition_value: i64, interval: i64) -> i64 {
+        partition_value.saturating_sub(self.offset(interval))
+    }
+}
+
+impl TryFrom<&str> for BucketRounding {
+    type Error = TableError;
+
+    /// Parses the name of a rounding, ignoring case.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "floor" => Ok(BucketRounding::Floor),
+            "ceil" => Ok(BucketRounding::Ceil),
+            "nearest" => Ok(BucketRounding::Nearest),
+            _ => Err(TableError::InvalidOption(format!(
+                "Unknown {} '{}', expected floor, ceil or nearest",
+                Self::OPTION,
+                value
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let hour = 3600;
+        let boundary = 1706745600;
+        let cases = [
+            (0, [boundary, boundary, boundary]),
+            (1, [boundary, boundary + hour, boundary]),
+            (1799, [boundary, boundary + hour, boundary]),
+            (1800, [boundary, boundary + hour, boundary + hour]),
+            (3599, [boundary, boundary + hour, boundary + hour]),
+        ];
+        for (offset, expected) in cases {
+            let rounded = [
+                BucketRounding::Floor,
+                BucketRounding::Ceil,
+                BucketRounding::Nearest,
+            ]
+            .map(|rounding| rounding.bucket(boundary + offset, hour));
+            assert_eq!(rounded, expected, "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn test_bucket_negative_epochs() {
+        let hour = 3600;
+        assert_eq!(BucketRounding::Floor.bucket(-1, hour), -3600);
+        assert_eq!(BucketRounding::Floor.bucket(-3600, hour), -3600);
+        assert_eq!(BucketRounding::Floor.bucket(-3601, hour), -7200);
+        assert_eq!(BucketRounding::Ceil.bucket(-3601, hour), -3600);
+        assert_eq!(BucketRounding::Ceil.bucket(-3600, hour), -3600);
+        assert_eq!(BucketRounding::Nearest.bucket(-1801, hour), -3600);
+        assert_eq!(BucketRounding::Nearest.bucket(-1800, hour), 0);
+        for rounding in [
+            BucketRounding::Floor,
+            BucketRounding::Ceil,
+            BucketRounding::Nearest,
+        ] {
+            let partition_value = rounding.bucket(-3601, hour);
+            let first = rounding.first_epoch(partition_value, hour);
+            assert_eq!(rounding.bucket(first, hour), partition_value);
+            assert_eq!(rounding.bucket(first + hour - 1, hour), partition_value);
+            assert_ne!(rounding.bucket(first - 1, hour), partition_value);
+        }
+    }
+
+    #[test]
+    fn test_bucket_extreme_epochs() {
+        let hour = 3600;
+        for rounding in [
+            BucketRounding::Floor,
+            BucketRounding::Ceil,
+            BucketRounding::Nearest,
+        ] {
+            let last = rounding.bucket(i64::MAX, hour);
+            assert_eq!(last, i64::MAX / hour * hour);
+            assert!(rounding.first_epoch(last, hour) <= last);
+            // The partition of the lowest epochs starts before i64::MIN, it is clamped to the
+            // first partition that can be represented.
+            let first = rounding.bucket(i64::MIN, hour);
+            assert_eq!(first, i64::MIN / hour * hour);
+            assert!(rounding.first_epoch(first, hour) <= first);
+            assert_eq!(rounding.first_epoch(i64::MIN, hour), i64::MIN);
+        }
+    }
+
+    #[test]
+    fn test_first_epoch() {
+        let hour = 3600;
+        for rounding in [
+            BucketRounding::Floor,
+            BucketRounding::Ceil,
+            BucketRounding::Nearest,
+        ] {
+            let partition_value = rounding.bucket(1706745600 + 1000, hour);
+            let first = rounding.first_epoch(partition_value, hour);
+            assert_eq!(rounding.bucket(first, hour), partition_value);
+            assert_eq!(rounding.bucket(first + hour - 1, hour), partition_value);
+            assert_ne!(rounding.bucket(first - 1, hour), partition_value);
+            assert_ne!(rounding.bucket(first + hour, hour), partition_value);
+        }
+    }
+
+    #[test]
+    fn test_from_argument() {
+        assert_eq!(
+            BucketRounding::from_argument("round=ceil")
+                .unwrap()
+                .unwrap(),
+            BucketRounding::Ceil
+        );
+        assert_eq!(
+            BucketRounding::from_argument(" ROUND = Nearest ")
+                .unwrap()
+                .unwrap(),
+            BucketRounding::Nearest
+        );
+        assert!(BucketRounding::from_argument("round=up").unwrap().is_err());
+        assert!(BucketRounding::from_argument("add_bucket_column=round").is_none());
+        assert!(BucketRounding::from_argument("round integer").is_none());
+    }
+}
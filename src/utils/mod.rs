@@ -1,4 +1,6 @@
 pub mod parsing;
+pub mod registry;
 pub mod validation;
 
 pub use parsing::*;
+pub use registry::{register_partition_value_parser, unregister_partition_value_parser};
@@ -1,26 +1,386 @@
 use std::{
     cmp::{max, min},
     collections::HashMap,
-    i64,
 };
 
 use chrono::{NaiveDate, NaiveDateTime};
 use regex::Regex;
 use sqlite3_ext::{ffi::SQLITE_FORMAT, vtab::ConstraintOp, FromValue, Value, ValueRef, ValueType};
 
-use crate::{constraints::Condition, error::TableError};
+use crate::{
+    constraints::Condition, error::TableError, shadow_tables::PartitionValue, BucketRounding,
+};
 
-/// Parses a `ValueRef` and adjusts it to the nearest lower interval boundary based on the provided interval.
+/// Parses a `ValueRef` and adjusts it to an interval boundary based on the provided interval.
 ///
 /// Parameters:
 /// - `value`: The value to be parsed and adjusted.
+/// - `declared_type`: The data type the partition column was declared with, see
+///   [`parse_partition_key`].
 /// - `interval`: The interval by which to adjust the value.
+/// - `round`: Which boundary the value is adjusted to.
+///
+/// Returns:
+/// - A result containing the adjusted UNIX epoch time or number, or an error if the value cannot
+///   be parsed
+pub fn parse_partition_value(
+    value: &ValueRef,
+    declared_type: &ValueType,
+    interval: i64,
+    round: BucketRounding,
+) -> sqlite3_ext::Result<i64> {
+    parse_partition_key(value, declared_type).map(|key| round.bucket(key, interval))
+}
+
+/// Reads a value of the partition column as the number partitions are cut from.
+///
+/// A column declared `integer` or `float` holds plain numbers, e.g. sensor readings, which are
+/// read as they are without datetime parsing. Any other column holds timestamps, see
+/// [`parse_to_unix_epoch`].
+///
+/// Parameters:
+/// - `value`: The value of the partition column.
+/// - `declared_type`: The data type the partition column was declared with.
+///
+/// Returns:
+/// - A result containing the number, or an error if the value cannot be read as one.
+pub fn parse_partition_key(
+    value: &ValueRef,
+    declared_type: &ValueType,
+) -> sqlite3_ext::Result<i64> {
+    match declared_type {
+        ValueType::Integer | ValueType::Float => parse_numeric_value(value),
+        _ => parse_to_unix_epoch(value),
+    }
+}
+
+/// Reads a value of a numeric partition column, rounding fractions down so that e.g. 999.5
+/// falls into the same range as 999 and -0.5 into the same range as -1.
+///
+/// Text holding a number is read like the number, since partition tables store the partition
+/// column with TEXT affinity and hand values back as text. Other text, including datetimes, is
+/// an error.
+///
+/// Parameters:
+/// - `value`: The value to be read.
+///
+/// Returns:
+/// - A result containing the number, or an error for NULL, blobs, non-finite floats and text
+///   that is not a number.
+pub fn parse_numeric_value(value: &ValueRef) -> sqlite3_ext::Result<i64> {
+    let number = match value.value_type() {
+        ValueType::Integer => Some(value.get_i64()),
+        ValueType::Float => Some(value.get_f64())
+            .filter(|number| number.is_finite())
+            .map(|number| number.floor() as i64),
+        ValueType::Text => {
+            let text = value.try_get_str()?.trim();
+            text.parse::<i64>().ok().or_else(|| {
+                text.parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .map(|number| number.floor() as i64)
+            })
+        }
+        ValueType::Blob | ValueType::Null => None,
+    };
+    number.ok_or_else(|| {
+        sqlite3_ext::Error::Sqlite(
+            SQLITE_FORMAT,
+            Some(format!(
+                "Could not parse {} value as a number",
+                value_type_to_string(&value.value_type())
+            )),
+        )
+    })
+}
+
+/// Reads a value of the partition column as the type the column was declared with, for hash
+/// and list partitioning.
+///
+/// The integer `5` and the text `'5'` read alike in an integer column, as they do in a text
+/// column, where an integer reads as its digits.
+///
+/// Parameters:
+/// - `value`: The value to be read.
+/// - `declared_type`: The data type the partition column was declared with.
+///
+/// Returns:
+/// - A result containing the value, or an error if the value cannot be read as the declared
+///   type, e.g. NULL.
+fn partition_key_value(
+    value: &ValueRef,
+    declared_type: &ValueType,
+) -> sqlite3_ext::Result<PartitionKeyValue> {
+    let key = match (declared_type, value.value_type()) {
+        (ValueType::Integer, ValueType::Integer) => {
+            Some(PartitionKeyValue::Integer(value.get_i64()))
+        }
+        (ValueType::Integer, ValueType::Text) => value
+            .try_get_str()?
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .map(PartitionKeyValue::Integer),
+        (_, ValueType::Integer) => Some(PartitionKeyValue::Text(value.get_i64().to_string())),
+        (_, ValueType::Text) => Some(PartitionKeyValue::Text(value.try_get_str()?.to_owned())),
+        _ => None,
+    };
+    key.ok_or_else(|| {
+        sqlite3_ext::Error::Sqlite(
+            SQLITE_FORMAT,
+            Some(format!(
+                "Could not hash {} value as {}",
+                value_type_to_string(&value.value_type()),
+                value_type_to_string(declared_type)
+            )),
+        )
+    })
+}
+
+/// A value of the partition column read as the type the column was declared with, see
+/// [`partition_key_value`].
+enum PartitionKeyValue {
+    Integer(i64),
+    Text(String),
+}
+
+/// Hashes a value of the partition column for hash and list partitioning.
+///
+/// The value is hashed with 64-bit FNV-1a, which unlike the hashers of the standard library is
+/// fixed, so rows keep their partition across builds and platforms. The value is read as the
+/// type the column was declared with first, see [`partition_key_value`].
+///
+/// Parameters:
+/// - `value`: The value to be hashed.
+/// - `declared_type`: The data type the partition column was declared with.
+///
+/// Returns:
+/// - A result containing the hash, or an error if the value cannot be read as the declared
+///   type, e.g. NULL.
+fn partition_key_hash(value: &ValueRef, declared_type: &ValueType) -> sqlite3_ext::Result<u64> {
+    let hash = match partition_key_value(value, declared_type)? {
+        PartitionKeyValue::Integer(integer) => fnv1a(FNV_OFFSET_BASIS, &integer.to_le_bytes()),
+        PartitionKeyValue::Text(text) => fnv1a(FNV_OFFSET_BASIS, text.as_bytes()),
+    };
+    Ok(hash)
+}
+
+/// The initial state of a 64-bit FNV-1a hash.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// Feeds bytes into a 64-bit FNV-1a hash.
+///
+/// Parameters:
+/// - `hash`: The state of the hash, [`FNV_OFFSET_BASIS`] for a new one.
+/// - `bytes`: The bytes to hash.
+///
+/// Returns:
+/// - The state of the hash after the bytes.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Computes the key telling apart the partitions of a composite partition key that share a
+/// partition value, from the values of the partition columns after the first.
+///
+/// Every value is hashed as in [`list_partition_value`] and the hashes are hashed together in
+/// column order, so `('a', 'b')` and `('b', 'a')` get different keys.
+///
+/// Parameters:
+/// - `values`: The values of the key columns, each with the data type its column was declared
+///   with.
+///
+/// Returns:
+/// - A result containing the key, never `0`, or an error if a value cannot be read as the
+///   declared type of its column, e.g. NULL.
+pub fn composite_partition_key(values: &[(&ValueRef, &ValueType)]) -> sqlite3_ext::Result<i64> {
+    values
+        .iter()
+        .try_fold(FNV_OFFSET_BASIS, |hash, (value, declared_type)| {
+            partition_key_hash(value, declared_type)
+                .map(|component| fnv1a(hash, &component.to_le_bytes()))
+        })
+        .map(composite_key_from_hash)
+}
+
+/// Turns the hash of the key columns into a partition key.
+///
+/// The key `0` marks a partition without a composite key in the lookup table, so a hash of `0`
+/// becomes `1`, the key of a hash of `1`.
+///
+/// Parameters:
+/// - `hash`: The hash of the key columns.
+///
+/// Returns:
+/// - The partition key, never `0`.
+fn composite_key_from_hash(hash: u64) -> i64 {
+    match hash as i64 {
+        0 => 1,
+        key => key,
+    }
+}
+
+/// Computes the partition a value of the partition column belongs to under hash partitioning,
+/// the hash of the value modulo the number of partitions.
+///
+/// Parameters:
+/// - `value`: The value to be hashed.
+/// - `declared_type`: The data type the partition column was declared with.
+/// - `partitions`: The number of partitions.
+///
+/// Returns:
+/// - A result containing the partition value, from `0` to `partitions - 1`, or an error if the
+///   value cannot be read as the declared type, e.g. NULL.
+pub fn hash_partition_value(
+    value: &ValueRef,
+    declared_type: &ValueType,
+    partitions: i64,
+) -> sqlite3_ext::Result<i64> {
+    partition_key_hash(value, declared_type).map(|hash| (hash % partitions as u64) as i64)
+}
+
+/// Computes the partition value a value of the partition column starts out with under list
+/// partitioning, where every distinct value has a partition of its own.
+///
+/// This is the whole 64-bit hash of the value. The lookup table records the value each list
+/// partition belongs to, see [`list_partition_label`], and a value whose hash is taken by the
+/// partition of another value gets the next free partition value instead, so values sharing a
+/// hash never share a partition. Only lookup tables created before the values were recorded
+/// key their partitions on the hash alone.
+///
+/// Parameters:
+/// - `value`: The value to be hashed.
+/// - `declared_type`: The data type the partition column was declared with.
 ///
 /// Returns:
-/// - A result containing the adjusted UNIX epoch time or an error if the value cannot be parsed
-pub fn parse_partition_value(value: &ValueRef, interval: i64) -> sqlite3_ext::Result<i64> {
-    parse_to_unix_epoch(value).map(|epoch| epoch - epoch % interval)
+/// - A result containing the partition value, or an error if the value cannot be read as the
+///   declared type, e.g. NULL.
+pub fn list_partition_value(
+    value: &ValueRef,
+    declared_type: &ValueType,
+) -> sqlite3_ext::Result<i64> {
+    partition_key_hash(value, declared_type).map(|hash| hash as i64)
 }
+
+/// Computes the text the lookup table records for the partition of a value under list
+/// partitioning, the value as read for hashing, so that values hashing alike get the same
+/// text.
+///
+/// Parameters:
+/// - `value`: A value of the partition column.
+/// - `declared_type`: The data type the partition column was declared with.
+///
+/// Returns:
+/// - A result containing the text, or an error if the value cannot be read as the declared
+///   type, e.g. NULL.
+pub fn list_partition_label(
+    value: &ValueRef,
+    declared_type: &ValueType,
+) -> sqlite3_ext::Result<String> {
+    Ok(match partition_key_value(value, declared_type)? {
+        PartitionKeyValue::Integer(integer) => integer.to_string(),
+        PartitionKeyValue::Text(text) => text,
+    })
+}
+
+/// Parses the values a list partitioned table is declared with, as in `list ('EU', 'US')`.
+///
+/// Parameters:
+/// - `values`: The values between the parentheses, separated by commas, each a text in single
+///   quotes, with a quote written twice within it, or an integer.
+/// - `declared_type`: The data type the partition column was declared with. Every value is
+///   read as that type, as values of the column are, see [`list_partition_label`].
+///
+/// Returns:
+/// - A result containing the values as the lookup table records them, in declaration order, or
+///   a `TableError::ParseInterval` if a value is neither a quoted text nor an integer, cannot be
+///   read as the declared type, or is declared twice.
+pub fn parse_list_values(
+    values: &str,
+    declared_type: &ValueType,
+) -> Result<Vec<String>, TableError> {
+    let invalid = |found: &str| {
+        TableError::ParseInterval(format!(
+            "Expected the values of list partitioning such as list ('EU', 'US'), found '{}'",
+            found.trim()
+        ))
+    };
+    let mut parsed: Vec<String> = Vec::new();
+    let mut rest = values.trim_start();
+    while !rest.is_empty() {
+        let (value, after) = match rest.strip_prefix('\'') {
+            Some(quoted) => {
+                let mut text = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next() {
+                        Some((index, '\'')) if quoted[index + 1..].starts_with('\'') => {
+                            text.push('\'');
+                            chars.next();
+                        }
+                        Some((index, '\'')) => break index + 1,
+                        Some((_, char)) => text.push(char),
+                        None => return Err(invalid(values)),
+                    }
+                };
+                let value = match declared_type {
+                    ValueType::Integer => text
+                        .trim()
+                        .parse::<i64>()
+                        .map(|integer| integer.to_string())
+                        .map_err(|_| invalid(&text))?,
+                    _ => text,
+                };
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let integer = rest[..end]
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| invalid(&rest[..end]))?;
+                (integer.to_string(), &rest[end..])
+            }
+        };
+        if parsed.contains(&value) {
+            return Err(TableError::ParseInterval(format!(
+                "The list partitioning value '{}' is declared twice",
+                value
+            )));
+        }
+        parsed.push(value);
+        let after = after.trim_start();
+        rest = match after.strip_prefix(',') {
+            Some(next) if !next.trim().is_empty() => next.trim_start(),
+            None if after.is_empty() => after,
+            _ => return Err(invalid(values)),
+        };
+    }
+    if parsed.is_empty() {
+        return Err(invalid(values));
+    }
+    Ok(parsed)
+}
+
+/// Writes the values a list partitioned table is declared with the way they are declared, as
+/// quoted texts separated by commas, which [`parse_list_values`] reads back.
+///
+/// Parameters:
+/// - `values`: The values as the lookup table records them.
+///
+/// Returns:
+/// - The values, e.g. `'EU', 'US'`.
+pub fn format_list_values(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| format!("'{}'", value.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Converts a [`ValueType`] enum to a string representation.
 ///
 /// Parameters:
@@ -99,6 +459,23 @@ pub fn parse_datetime_from_value(value: Value) -> sqlite3_ext::Result<i64> {
     }
 }
 
+/// Parses the text form of a number as a UNIX epoch time, e.g. "1706745600", "1706745600.5" or
+/// "1.7067456e9" as written by some JSON exporters.
+///
+/// Parameters:
+/// - `text`: The text to parse.
+///
+/// Returns:
+/// - The UNIX epoch time, with any fraction truncated, or `None` if the text is not a finite number.
+fn parse_numeric_epoch(text: &str) -> Option<i64> {
+    text.parse::<i64>().ok().or_else(|| {
+        text.parse::<f64>()
+            .ok()
+            .filter(|epoch| epoch.is_finite())
+            .map(|epoch| epoch as i64)
+    })
+}
+
 /// Parses a datetime string to a UNIX epoch time, trying multiple known formats.
 ///
 /// Parameters:
@@ -138,7 +515,10 @@ fn parse_datetime_to_epoch(datetime_str: &str) -> sqlite3_ext::Result<i64> {
 /// logic includes:
 /// - `Integer`: Directly returned as the UNIX epoch timestamp.
 /// - `Float`: Cast to `i64`, assuming rounding is acceptable for the use case.
-/// - `Text`: Attempted parsing as a datetime string to UNIX epoch. Supports multiple datetime formats.
+/// - `Text`: A number, including decimal and scientific notation, is read as the UNIX epoch
+///   timestamp like an `Integer` or `Float`, since partition tables store the partition column
+///   with TEXT affinity and hand epochs back as text, and some exporters write epochs as text.
+///   Other text is parsed as a datetime string. Supports multiple datetime formats.
 /// - `Blob` and `Null`: These types are considered incompatible with UNIX epoch timestamps, resulting in an error.
 ///
 /// Parameters:
@@ -155,61 +535,143 @@ pub fn parse_to_unix_epoch(value: &ValueRef) -> sqlite3_ext::Result<i64> {
     match value.value_type() {
         ValueType::Integer => Ok(value.get_i64()),
         ValueType::Float => Ok(value.get_f64() as i64), // Assuming rounding is the desired behavior
-        ValueType::Text => parse_datetime_to_epoch(value.try_get_str()?),
+        ValueType::Text => {
+            let text = value.try_get_str()?;
+            match parse_numeric_epoch(text) {
+                Some(epoch) => Ok(epoch),
+                None => parse_datetime_to_epoch(text),
+            }
+        }
         ValueType::Blob | ValueType::Null => Err(sqlite3_ext::Error::Sqlite(
             SQLITE_FORMAT,
             Some("Could not parse value to UNIX epoch".to_string()),
         )),
     }
 }
+/// The unit used when an interval is given as a bare number, e.g. "3600".
+pub const DEFAULT_INTERVAL_UNIT: &str = "second";
+
 /// Parses a textual representation of a datetime interval to its duration in seconds.
 ///
+/// A number without a unit is read in [`DEFAULT_INTERVAL_UNIT`]s. Besides the long forms, the
+/// units can be abbreviated to `s`, `m`, `h`, `d` and `w`, so "15m" is "15 minutes". Partitions are aligned to
+/// multiples of the interval since the UNIX epoch, which fell on a Thursday, so weekly
+/// partitions run from Thursday 00:00 UTC to the next Thursday.
+///
+/// Several number and unit pairs are added up, so "1 day 12 hours" and "1d12h" are 36 hours.
+/// Each unit may be given once, and every number needs a unit unless it stands alone.
+///
 /// Parameters:
-/// - `interval_str`: The interval string to parse, e.g., "1 hour".
+/// - `interval_str`: The interval string to parse, e.g., "1 hour", "1h", "1 day 12 hours" or
+///   "3600".
 ///
 /// Returns:
 /// - A result containing the interval in seconds or a `TableError` if parsing fails.
 pub fn parse_interval(interval_str: &str) -> Result<i64, TableError> {
-    // Initialize the Regex pattern
-    let re = Regex::new(r"(\d+)\s+(\w+)")
+    // The whole string has to consist of number and unit pairs, each pair is then read on its own.
+    let format = Regex::new(r"^(\d+\s*[[:alpha:]]*\s*)+$")
         .map_err(|_| TableError::ParseInterval("Failed to compile regex pattern.".to_string()))?;
-
-    println!("lifetime str {:#?}", interval_str);
-    // Attempt to find matches in the input string
-    let captures = re.captures(interval_str).ok_or(TableError::ParseInterval(
-        "Interval format is not valid.".to_string(),
-    ))?;
-
-    // Extract the numeric part and unit part from the captures
-    let numeric_part = captures
-        .get(1)
-        .ok_or(TableError::ParseInterval(
-            "Missing numeric value in interval.".to_string(),
-        ))?
-        .as_str();
-    let unit_part = captures
-        .get(2)
-        .ok_or(TableError::ParseInterval(
-            "Missing unit in interval.".to_string(),
-        ))?
-        .as_str();
-
-    // Parse the numeric part as a u32
-    let numeric_value = numeric_part.parse::<i64>().map_err(|_| {
-        TableError::ParseInterval(format!("Failed to parse '{}' as a number.", numeric_part))
-    })?;
+    let pair = Regex::new(r"(\d+)\s*([[:alpha:]]*)")
+        .map_err(|_| TableError::ParseInterval("Failed to compile regex pattern.".to_string()))?;
+    let interval_str = interval_str.trim();
+    if !format.is_match(interval_str) {
+        return Err(TableError::ParseInterval(
+            "Interval format is not valid.".to_string(),
+        ));
+    }
 
     // Define a map for interval units to their sizes in seconds
     let mut interval_unit_to_size = HashMap::new();
+    interval_unit_to_size.insert("s", 1);
+    interval_unit_to_size.insert("second", 1);
+    interval_unit_to_size.insert("seconds", 1);
+    interval_unit_to_size.insert("m", 60);
+    interval_unit_to_size.insert("minute", 60);
+    interval_unit_to_size.insert("minutes", 60);
+    interval_unit_to_size.insert("h", 60 * 60);
     interval_unit_to_size.insert("hour", 60 * 60);
+    interval_unit_to_size.insert("hours", 60 * 60);
+    interval_unit_to_size.insert("d", 24 * 60 * 60);
     interval_unit_to_size.insert("day", 24 * 60 * 60);
+    interval_unit_to_size.insert("days", 24 * 60 * 60);
+    interval_unit_to_size.insert("w", 7 * 24 * 60 * 60);
+    interval_unit_to_size.insert("week", 7 * 24 * 60 * 60);
+    interval_unit_to_size.insert("weeks", 7 * 24 * 60 * 60);
+
+    let pairs = pair.captures_iter(interval_str).collect::<Vec<_>>();
+    let mut seen_sizes = Vec::new();
+    let mut total: i64 = 0;
+    for captures in &pairs {
+        // Extract the numeric part and unit part from the captures
+        let numeric_part = &captures[1];
+        let unit_part = match &captures[2] {
+            // Only an interval consisting of a single number may leave out the unit.
+            "" if pairs.len() == 1 => DEFAULT_INTERVAL_UNIT.to_string(),
+            "" => {
+                return Err(TableError::ParseInterval(format!(
+                    "Missing unit after '{}' in interval '{}'.",
+                    numeric_part, interval_str
+                )))
+            }
+            unit => unit.to_lowercase(),
+        };
+
+        let numeric_value = numeric_part.parse::<i64>().map_err(|_| {
+            TableError::ParseInterval(format!("Failed to parse '{}' as a number.", numeric_part))
+        })?;
 
-    // Calculate and return the total interval size based on the unit
-    let size_in_seconds = interval_unit_to_size.get(unit_part).ok_or_else(|| {
-        TableError::ParseInterval(format!("Unsupported interval unit: '{}'.", unit_part))
-    })?;
-    println!("returns {:#?}", numeric_value * size_in_seconds);
-    Ok(numeric_value * size_in_seconds)
+        // Calculate the size of the pair based on the unit
+        let size_in_seconds = *interval_unit_to_size
+            .get(unit_part.as_str())
+            .ok_or_else(|| {
+                TableError::ParseInterval(format!("Unsupported interval unit: '{}'.", unit_part))
+            })?;
+        if seen_sizes.contains(&size_in_seconds) {
+            return Err(TableError::ParseInterval(format!(
+                "Unit '{}' is given more than once in interval '{}'.",
+                unit_part, interval_str
+            )));
+        }
+        seen_sizes.push(size_in_seconds);
+        total = numeric_value
+            .checked_mul(size_in_seconds)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(|| {
+                TableError::ParseInterval(format!("Interval '{}' is too large.", interval_str))
+            })?;
+    }
+    if total == 0 {
+        return Err(TableError::ParseInterval(
+            "Interval must be greater than zero.".to_string(),
+        ));
+    }
+    Ok(total)
+}
+
+/// Formats an interval in seconds in the largest unit [`parse_interval`] accepts that divides it
+/// exactly, e.g. 3600 as "1 hour" and 90000 as "25 hours". The result parses back to the same
+/// number of seconds.
+///
+/// Parameters:
+/// - `seconds`: The interval in seconds.
+///
+/// Returns:
+/// - The interval as a count and a unit, in the plural unless the count is one.
+pub fn format_interval(seconds: i64) -> String {
+    let (size, unit) = [
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ]
+    .into_iter()
+    .find(|(size, _)| seconds != 0 && seconds % size == 0)
+    .unwrap_or((1, DEFAULT_INTERVAL_UNIT));
+    let count = seconds / size;
+    match count {
+        1 => format!("{} {}", count, unit),
+        _ => format!("{} {}s", count, unit),
+    }
 }
 
 use std::ops::Bound::{self, *};
@@ -219,17 +681,29 @@ use std::ops::Bound::{self, *};
 /// Parameters:
 /// - `conditions`: A slice of conditions to aggregate.
 /// - `interval`: The interval by which the conditions should be adjusted.
+/// - `strategy`: How rows are assigned to partitions. Under hash and list partitioning the
+///   partition values are not ordered like the column values, so only equality narrows the
+///   range, to the one partition the value hashes to.
+/// - `partition_value`: Maps a condition value to the start of the partition it falls into.
+///   Conditions whose value cannot be mapped do not narrow the range. `IS NULL` carries no
+///   value, callers check for it with [`selects_no_partition`].
 ///
 /// Returns:
 /// - A `HashMap` where each key is a column name and its value is a tuple representing the column's value range.
 pub fn aggregate_conditions_to_ranges<'a>(
     conditions: &'a [Condition<'a>],
     interval: i64,
+    strategy: PartitionValue,
+    partition_value: impl Fn(&ValueRef) -> sqlite3_ext::Result<i64>,
 ) -> HashMap<&'a str, (Bound<i64>, Bound<i64>)> {
     let mut ranges: HashMap<&'a str, (Bound<i64>, Bound<i64>)> = HashMap::new();
     for condition in conditions {
-        let partition_start = parse_partition_value(condition.value, interval).unwrap(); //TODO handle
-                                                                                         //error
+        if strategy != PartitionValue::Interval && *condition.operator != ConstraintOp::Eq {
+            continue;
+        }
+        let Ok(partition_start) = partition_value(condition.value) else {
+            continue;
+        };
 
         ranges
             .entry(condition.column)
@@ -242,18 +716,56 @@ pub fn aggregate_conditions_to_ranges<'a>(
     ranges
 }
 
+/// Combines comparisons against the partition column into a single range of partition values.
+///
+/// This is the range [`aggregate_conditions_to_ranges`] computes for one column, for callers
+/// that have already mapped the comparison values to partition values.
+///
+/// Parameters:
+/// - `comparisons`: The operator of each comparison and the start of the partition its value
+///   falls into.
+/// - `interval`: The partition interval, used to bound `<` conditions.
+///
+/// Returns:
+/// - The lower and upper bound of the partition values all comparisons can hold for.
+pub fn partition_range(
+    comparisons: impl IntoIterator<Item = (ConstraintOp, i64)>,
+    interval: i64,
+) -> (Bound<i64>, Bound<i64>) {
+    let mut range = (Unbounded, Unbounded);
+    for (operator, partition_start) in comparisons {
+        update_bound(&mut range, &operator, partition_start, interval);
+    }
+    range
+}
+
+/// Decides whether a condition on the partition column rules out every partition of the lookup
+/// table.
+///
+/// Every row of a partition is stored there because of its partition column value, so the
+/// column is never NULL and `IS NULL` only matches rows of the default partition.
+///
+/// Parameters:
+/// - `operator`: The operator of a condition on the partition column.
+///
+/// Returns:
+/// - `true` if no partition has to be scanned.
+pub fn selects_no_partition(operator: &ConstraintOp) -> bool {
+    matches!(operator, ConstraintOp::IsNull)
+}
+
 /// Updates the range boundaries based on the provided operator and value.
 ///
-/// This function adjusts the lower or upper bounds of a range tuple to reflect the
-/// constraints imposed by a SQL condition. It uses `less_restrictive_bound` or
-/// `more_restrictive_bound` functions to ensure the updated range accurately
-/// represents the condition's intent.
+/// This function narrows the lower or upper bounds of a range tuple to reflect the
+/// constraints imposed by a SQL condition. The conditions of a scan all have to hold, so the
+/// more restrictive of the current and the new bound is kept, see
+/// `more_restrictive_lower_bound` and `more_restrictive_upper_bound`.
 ///
 /// Parameters:
 /// - `range`: A mutable reference to a tuple representing the current range (lower and upper bounds).
 /// - `operator`: The SQL comparison operator from the condition.
-/// - `value`: The comparison value from the condition.
-/// - `interval`: The interval for adjusting the range, used with certain operators to define the range more accurately.
+/// - `value`: The start of the partition the comparison value falls into.
+/// - `interval`: The partition interval, used to bound `<` conditions.
 ///
 /// No return value, but modifies the input range in place.
 fn update_bound(
@@ -262,74 +774,66 @@ fn update_bound(
     value: i64,
     interval: i64,
 ) {
-    match operator {
-        ConstraintOp::GT | ConstraintOp::GE => {
-            let lower_bound = Excluded(value);
-            range.0 = less_restrictive_bound(range.0, lower_bound);
-        }
-        ConstraintOp::LT => {
-            let upper_bound = Excluded(value + interval);
-            range.1 = more_restrictive_bound(range.1, upper_bound);
-        }
-        ConstraintOp::LE => {
-            let upper_bound = Included(value + interval);
-            range.1 = more_restrictive_bound(range.1, upper_bound);
-        }
-        ConstraintOp::Eq => {
-            let bound = Included(value);
-            range.0 = more_restrictive_bound(range.0, bound);
-            range.1 = more_restrictive_bound(range.1, bound);
-        }
-        _ => {}
-    }
+    let (lower_bound, upper_bound) = initial_bound(operator, value, interval);
+    range.0 = more_restrictive_lower_bound(range.0, lower_bound);
+    range.1 = more_restrictive_upper_bound(range.1, upper_bound);
 }
 
 /// Calculates the initial range boundaries based on a given operator, value, and interval.
 ///
 /// This function determines the starting lower and upper bounds for a range, based on the
 /// specified operator and value. It is particularly useful for initializing the bounds
-/// before refining them with further conditions.
+/// before refining them with further conditions. The bounds are partition values, so a value
+/// only ever selects the partition it falls into and those on the matching side of it, however
+/// large the interval is compared to the queried range.
 ///
 /// Parameters:
 /// - `operator`: The SQL comparison operator from the condition, dictating how the initial bounds are set.
-/// - `value`: The comparison value for the condition, used to establish the initial bounds.
-/// - `interval`: The interval for adjusting the range with certain operators, aiding in defining the initial range.
+/// - `value`: The start of the partition the comparison value falls into.
+/// - `interval`: The partition interval, used to bound `<` conditions.
 ///
 /// Returns:
 /// - A tuple representing the initial range (lower and upper bounds) based on the operator and value.
 fn initial_bound(operator: &ConstraintOp, value: i64, interval: i64) -> (Bound<i64>, Bound<i64>) {
     match operator {
-        ConstraintOp::GT | ConstraintOp::GE => (Excluded(value), Unbounded),
-        ConstraintOp::LT => (Unbounded, Excluded(value + interval)),
-        ConstraintOp::LE => (Unbounded, Included(value + interval)),
+        // Rows greater than the value may still live in the partition the value falls into.
+        ConstraintOp::GT | ConstraintOp::GE => (Included(value), Unbounded),
+        ConstraintOp::LT => (Unbounded, Excluded(value.saturating_add(interval))),
+        // Rows up to the value all live in its partition or earlier ones.
+        ConstraintOp::LE => (Unbounded, Included(value)),
         ConstraintOp::Eq => (Included(value), Included(value)),
+        // Rows other than the value may live in any partition, the partition queries filter
+        // them. The partition column is never NULL, so `IS NOT NULL` holds everywhere.
+        ConstraintOp::NE | ConstraintOp::IsNotNull => (Unbounded, Unbounded),
         _ => (Unbounded, Unbounded), // Default case
     }
 }
-/// Chooses the less restrictive (broader) of two bounds.
+
+/// Chooses the more restrictive (higher) of two lower bounds.
 ///
 /// Parameters:
 /// - `a`: The first bound to compare.
 /// - `b`: The second bound to compare.
 ///
 /// Returns:
-/// - The less restrictive bound.
-fn less_restrictive_bound(a: Bound<i64>, b: Bound<i64>) -> Bound<i64> {
+/// - The more restrictive bound.
+fn more_restrictive_lower_bound(a: Bound<i64>, b: Bound<i64>) -> Bound<i64> {
     match (a, b) {
-        (Unbounded, _) | (_, Unbounded) => Unbounded,
-        (Included(a_val), Included(b_val)) => Included(min(a_val, b_val)),
-        (Excluded(a_val), Excluded(b_val)) => Excluded(min(a_val, b_val)),
-        (Excluded(a_val), Included(b_val)) | (Included(a_val), Excluded(b_val)) => {
-            if a_val <= b_val {
-                Included(min(a_val, b_val))
+        (Unbounded, _) => b,
+        (_, Unbounded) => a,
+        (Included(a_val), Included(b_val)) => Included(max(a_val, b_val)),
+        (Excluded(a_val), Excluded(b_val)) => Excluded(max(a_val, b_val)),
+        (Excluded(excluded), Included(included)) | (Included(included), Excluded(excluded)) => {
+            if excluded >= included {
+                Excluded(excluded)
             } else {
-                Excluded(min(a_val, b_val))
+                Included(included)
             }
         }
     }
 }
 
-/// Chooses the more restrictive (narrower) of two bounds.
+/// Chooses the more restrictive (lower) of two upper bounds.
 ///
 /// Parameters:
 /// - `a`: The first bound to compare.
@@ -337,18 +841,280 @@ fn less_restrictive_bound(a: Bound<i64>, b: Bound<i64>) -> Bound<i64> {
 ///
 /// Returns:
 /// - The more restrictive bound.
-fn more_restrictive_bound(a: Bound<i64>, b: Bound<i64>) -> Bound<i64> {
+fn more_restrictive_upper_bound(a: Bound<i64>, b: Bound<i64>) -> Bound<i64> {
     match (a, b) {
         (Unbounded, _) => b,
         (_, Unbounded) => a,
-        (Included(a_val), Included(b_val)) => Included(max(a_val, b_val)),
-        (Excluded(a_val), Excluded(b_val)) => Excluded(max(a_val, b_val)),
-        (Excluded(a_val), Included(b_val)) | (Included(a_val), Excluded(b_val)) => {
-            if a_val >= b_val {
-                Excluded(a_val)
+        (Included(a_val), Included(b_val)) => Included(min(a_val, b_val)),
+        (Excluded(a_val), Excluded(b_val)) => Excluded(min(a_val, b_val)),
+        (Excluded(excluded), Included(included)) | (Included(included), Excluded(excluded)) => {
+            if excluded <= included {
+                Excluded(excluded)
             } else {
-                Included(b_val)
+                Included(included)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds_narrow_to_one_partition() {
+        let year = 365 * 24 * 60 * 60;
+        let partition = 54 * year;
+        // col1 >= x AND col1 < y with both values in the same partition.
+        let mut range = initial_bound(&ConstraintOp::GE, partition, year);
+        update_bound(&mut range, &ConstraintOp::LT, partition, year);
+        assert_eq!(range, (Included(partition), Excluded(partition + year)));
+        // col1 BETWEEN x AND y does not reach into the next partition.
+        let mut range = initial_bound(&ConstraintOp::GE, partition, year);
+        update_bound(&mut range, &ConstraintOp::LE, partition, year);
+        assert_eq!(range, (Included(partition), Included(partition)));
+        // Further conditions only ever narrow the range.
+        update_bound(&mut range, &ConstraintOp::GT, partition - year, year);
+        update_bound(&mut range, &ConstraintOp::LE, partition + year, year);
+        assert_eq!(range, (Included(partition), Included(partition)));
+        assert_eq!(
+            more_restrictive_lower_bound(Included(7), Excluded(3)),
+            Included(7)
+        );
+        assert_eq!(
+            more_restrictive_upper_bound(Included(7), Excluded(3)),
+            Excluded(3)
+        );
+    }
+
+    #[test]
+    fn test_bounds_near_i64_limits() {
+        let hour = 3600;
+        let last = i64::MAX / hour * hour;
+        assert_eq!(
+            initial_bound(&ConstraintOp::LT, last, hour),
+            (Unbounded, Excluded(i64::MAX))
+        );
+        assert_eq!(
+            partition_range(
+                [
+                    (ConstraintOp::GE, i64::MIN / hour * hour),
+                    (ConstraintOp::LT, last)
+                ],
+                hour
+            ),
+            (Included(i64::MIN / hour * hour), Excluded(i64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_hash_partition_value() -> sqlite3_ext::Result<()> {
+        use sqlite3_ext::FallibleIteratorMut;
+        let rusq_conn = rusqlite::Connection::open_in_memory().unwrap();
+        let db = sqlite3_ext::Connection::from_rusqlite(&rusq_conn);
+        let mut stmt = db.prepare("SELECT 5, '5', ' 5 ', 'a', NULL, 5.5")?;
+        stmt.query(())?;
+        let row = stmt.next()?.unwrap();
+        let hash = |index: usize, declared_type: ValueType| {
+            hash_partition_value(row[index].as_ref(), &declared_type, 8)
+        };
+        // An integer column hashes the integer, whether it arrives as integer or text.
+        assert_eq!(hash(0, ValueType::Integer)?, hash(1, ValueType::Integer)?);
+        assert_eq!(hash(0, ValueType::Integer)?, hash(2, ValueType::Integer)?);
+        assert!(hash(3, ValueType::Integer).is_err());
+        // A text column hashes the text, an integer as its digits.
+        assert_eq!(hash(0, ValueType::Text)?, hash(1, ValueType::Text)?);
+        assert_ne!(hash(1, ValueType::Text)?, hash(2, ValueType::Text)?);
+        // The FNV-1a hash of "a" is 0xaf63dc4c8601ec8c.
+        assert_eq!(hash(3, ValueType::Text)?, 4);
+        assert!(hash(4, ValueType::Text).is_err());
+        assert!(hash(5, ValueType::Text).is_err());
+        assert!((0..8).contains(&hash(0, ValueType::Integer)?));
+
+        // List partitioning keeps the whole hash.
+        assert_eq!(
+            list_partition_value(row[3].as_ref(), &ValueType::Text)?,
+            0xaf63dc4c8601ec8c_u64 as i64
+        );
+        assert_eq!(
+            list_partition_value(row[0].as_ref(), &ValueType::Integer)?,
+            list_partition_value(row[1].as_ref(), &ValueType::Integer)?
+        );
+        assert!(list_partition_value(row[4].as_ref(), &ValueType::Text).is_err());
+        // The recorded value is read like the hashed one.
+        assert_eq!(
+            list_partition_label(row[2].as_ref(), &ValueType::Integer)?,
+            "5"
+        );
+        assert_eq!(
+            list_partition_label(row[0].as_ref(), &ValueType::Text)?,
+            "5"
+        );
+        assert_eq!(
+            list_partition_label(row[2].as_ref(), &ValueType::Text)?,
+            " 5 "
+        );
+        assert!(list_partition_label(row[5].as_ref(), &ValueType::Text).is_err());
+
+        // A composite key depends on the order of its components.
+        let key = |indices: &[usize]| {
+            composite_partition_key(
+                &indices
+                    .iter()
+                    .map(|index| (row[*index].as_ref(), &ValueType::Text))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(key(&[0, 3])?, key(&[1, 3])?);
+        assert_ne!(key(&[0, 3])?, key(&[3, 0])?);
+        assert!(key(&[3, 4]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_list_values() {
+        assert_eq!(
+            parse_list_values(" 'EU', 'it''s' ,5", &ValueType::Text).unwrap(),
+            vec!["EU", "it's", "5"]
+        );
+        // An integer column reads its values as integers, quoted or not.
+        assert_eq!(
+            parse_list_values("1, ' 2 ', -3", &ValueType::Integer).unwrap(),
+            vec!["1", "2", "-3"]
+        );
+        let values = parse_list_values("'a, b', 'c'", &ValueType::Text).unwrap();
+        assert_eq!(format_list_values(&values), "'a, b', 'c'");
+        assert_eq!(
+            parse_list_values(&format_list_values(&values), &ValueType::Text).unwrap(),
+            values
+        );
+        for invalid in ["", "'EU", "EU", "'EU' 'US'", "'EU',", "1.5"] {
+            assert!(
+                parse_list_values(invalid, &ValueType::Text).is_err(),
+                "{invalid}"
+            );
+        }
+        assert!(parse_list_values("'EU'", &ValueType::Integer).is_err());
+        assert!(parse_list_values("1, '1'", &ValueType::Integer)
+            .unwrap_err()
+            .to_string()
+            .contains("declared twice"));
+    }
+
+    #[test]
+    fn test_composite_key_is_never_zero() {
+        assert_eq!(composite_key_from_hash(0), 1);
+        assert_eq!(composite_key_from_hash(1), 1);
+        assert_eq!(composite_key_from_hash(u64::MAX), -1);
+        assert_eq!(composite_key_from_hash(42), 42);
+    }
+    #[test]
+    fn test_format_interval() {
+        assert_eq!(format_interval(3600), "1 hour");
+        assert_eq!(format_interval(90000), "25 hours");
+        assert_eq!(format_interval(172800), "2 days");
+        assert_eq!(format_interval(1209600), "2 weeks");
+        assert_eq!(format_interval(5400), "90 minutes");
+        assert_eq!(format_interval(90), "90 seconds");
+        assert_eq!(format_interval(1), "1 second");
+        for seconds in [1, 59, 60, 90, 3600, 5400, 86400, 90000, 172800, 604800] {
+            assert_eq!(parse_interval(&format_interval(seconds)).unwrap(), seconds);
+        }
+    }
+
+    #[test]
+    fn test_parse_interval_is_case_insensitive() {
+        assert_eq!(parse_interval("1HOUR").unwrap(), 3600);
+        assert_eq!(parse_interval("1 Hour").unwrap(), 3600);
+        assert_eq!(parse_interval("1 day").unwrap(), 86400);
+    }
+
+    #[test]
+    fn test_parse_interval_tolerates_whitespace() {
+        assert_eq!(parse_interval("2  days").unwrap(), 2 * 86400);
+        assert_eq!(parse_interval(" 3 hours ").unwrap(), 3 * 3600);
+        assert!(parse_interval("1 fortnight").is_err());
+        assert!(parse_interval("hour").is_err());
+    }
+
+    #[test]
+    fn test_parse_interval_bare_number_is_seconds() {
+        assert_eq!(parse_interval("3600").unwrap(), 3600);
+        assert_eq!(parse_interval(" 90 ").unwrap(), 90);
+        assert_eq!(parse_interval("90 seconds").unwrap(), 90);
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("-5").is_err());
+    }
+
+    #[test]
+    fn test_parse_interval_minutes_and_seconds() {
+        assert_eq!(parse_interval("30 minute").unwrap(), 30 * 60);
+        assert_eq!(parse_interval("1 minute").unwrap(), 60);
+        assert_eq!(parse_interval("15 minutes").unwrap(), 15 * 60);
+        assert_eq!(parse_interval("1 second").unwrap(), 1);
+        assert_eq!(parse_interval("45 seconds").unwrap(), 45);
+        assert_eq!(parse_interval("2 hour").unwrap(), 2 * 3600);
+        assert!(parse_interval("1 min").is_err());
+    }
+
+    #[test]
+    fn test_parse_interval_weeks() {
+        assert_eq!(parse_interval("1 week").unwrap(), 604800);
+        assert_eq!(parse_interval("2 weeks").unwrap(), 2 * 604800);
+    }
+
+    #[test]
+    fn test_parse_interval_number_with_unit() {
+        assert_eq!(parse_interval("3600 hours").unwrap(), 3600 * 3600);
+        assert_eq!(parse_interval("2day").unwrap(), 2 * 86400);
+    }
+
+    #[test]
+    fn test_parse_interval_composite() {
+        assert_eq!(parse_interval("1 day 12 hours").unwrap(), 129600);
+        assert_eq!(parse_interval("1d12h").unwrap(), 129600);
+        assert_eq!(
+            parse_interval("1 week 1 day 1 hour 1 minute 1 second").unwrap(),
+            694861
+        );
+        assert_eq!(parse_interval("12 hours 1 day").unwrap(), 129600);
+        assert_eq!(parse_interval("1 hour 0 minutes").unwrap(), 3600);
+        assert_eq!(
+            parse_interval("1 day 12 hours").unwrap(),
+            parse_interval("36 hours").unwrap()
+        );
+
+        let error = |interval: &str| parse_interval(interval).unwrap_err().to_string();
+        assert!(error("1 hour 2 hours").contains("given more than once"));
+        assert!(error("1h 30 minutes 2 hour").contains("given more than once"));
+        assert!(error("1 day 12").contains("Missing unit after '12'"));
+        assert!(error("1 day 12 fortnights").contains("Unsupported interval unit"));
+        assert!(error("1 day and 12 hours").contains("Interval format is not valid"));
+        assert!(error("1 day 12 hours!").contains("Interval format is not valid"));
+        assert!(error("0 hours 0 minutes").contains("greater than zero"));
+    }
+
+    #[test]
+    fn test_parse_interval_short_units() {
+        let pairs = [
+            ("1h", "1 hour"),
+            ("30m", "30 minutes"),
+            ("15 m", "15 minutes"),
+            ("45s", "45 seconds"),
+            ("2d", "2 days"),
+            ("1w", "1 week"),
+            ("1H", "1 hour"),
+        ];
+        for (short, long) in pairs {
+            assert_eq!(
+                parse_interval(short).unwrap(),
+                parse_interval(long).unwrap(),
+                "{}",
+                short
+            );
+        }
+        assert_eq!(parse_interval("1h").unwrap(), 3600);
+        assert!(parse_interval("1y").is_err());
+        assert!(parse_interval("1hr").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use sqlite3_ext::{Result as ExtResult, ValueRef};
+
+/// A function converting a value of the partition column to a UNIX epoch timestamp.
+pub type PartitionValueParser = Arc<dyn Fn(&ValueRef) -> ExtResult<i64> + Send + Sync>;
+
+/// Identifies a virtual table across the databases of the process by the file of its database,
+/// empty for in-memory and temporary databases, and its name.
+type TableKey = (String, String);
+
+/// Custom partition value parsers, keyed by the database file and name of the virtual table they
+/// apply to.
+static PARTITION_VALUE_PARSERS: OnceLock<RwLock<HashMap<TableKey, PartitionValueParser>>> =
+    OnceLock::new();
+
+fn parsers() -> &'static RwLock<HashMap<TableKey, PartitionValueParser>> {
+    PARTITION_VALUE_PARSERS.get_or_init(RwLock::default)
+}
+
+fn read_parsers() -> ExtResult<RwLockReadGuard<'static, HashMap<TableKey, PartitionValueParser>>> {
+    parsers()
+        .read()
+        .map_err(|e| sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e))))
+}
+
+fn write_parsers() -> ExtResult<RwLockWriteGuard<'static, HashMap<TableKey, PartitionValueParser>>>
+{
+    parsers()
+        .write()
+        .map_err(|e| sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e))))
+}
+
+fn table_key(db_filename: &str, table_name: &str) -> TableKey {
+    (db_filename.to_owned(), table_name.to_owned())
+}
+
+/// Registers a custom parser for the partition column of a virtual table.
+///
+/// The parser replaces the built-in datetime parsing for the table named `table_name` in the
+/// database `db_filename`, both when routing inserted and updated rows to partitions and when
+/// pruning partitions for a query. Tables of the same name in other databases keep their own
+/// parsing. It must return a UNIX epoch timestamp, which is then aligned to the partition
+/// interval as usual. Since SQLite instantiates the module itself, the host registers parsers
+/// after loading the extension and before creating or querying the table. Registering a parser
+/// for a table that already has one replaces it, and renaming the table keeps it.
+///
+/// Parameters:
+/// - `db_filename`: The file of the main database the table is in, as reported by
+///   `PRAGMA database_list`, or an empty string for in-memory and temporary databases.
+/// - `table_name`: The name of the virtual table, as given to `CREATE VIRTUAL TABLE`.
+/// - `parser`: The function converting a partition column value to a UNIX epoch timestamp.
+///
+/// Returns:
+/// - `Ok(())` once the parser is registered, or an error if the registry lock is poisoned.
+pub fn register_partition_value_parser<F>(
+    db_filename: &str,
+    table_name: &str,
+    parser: F,
+) -> ExtResult<()>
+where
+    F: Fn(&ValueRef) -> ExtResult<i64> + Send + Sync + 'static,
+{
+    write_parsers()?.insert(table_key(db_filename, table_name), Arc::new(parser));
+    Ok(())
+}
+
+/// Removes the custom parser of a virtual table, restoring the built-in datetime parsing.
+///
+/// Parameters:
+/// - `db_filename`: The file of the main database the table is in, see
+///   [`register_partition_value_parser`].
+/// - `table_name`: The name of the virtual table.
+///
+/// Returns:
+/// - `true` if a parser was registered for the table.
+pub fn unregister_partition_value_parser(db_filename: &str, table_name: &str) -> ExtResult<bool> {
+    Ok(write_parsers()?
+        .remove(&table_key(db_filename, table_name))
+        .is_some())
+}
+
+/// Retrieves the custom parser registered for a virtual table, if any.
+pub(crate) fn partition_value_parser(
+    db_filename: &str,
+    table_name: &str,
+) -> ExtResult<Option<PartitionValueParser>> {
+    Ok(read_parsers()?
+        .get(&table_key(db_filename, table_name))
+        .cloned())
+}
+
+/// Moves the custom parser of a virtual table, if any, to its new name once it was renamed.
+pub(crate) fn rename_partition_value_parser(
+    db_filename: &str,
+    table_name: &str,
+    new_name: &str,
+) -> ExtResult<()> {
+    let mut parsers = write_parsers()?;
+    if let Some(parser) = parsers.remove(&table_key(db_filename, table_name)) {
+        parsers.insert(table_key(db_filename, new_name), parser);
+    }
+    Ok(())
+}
@@ -1,8 +1,8 @@
-use sqlite3_ext::{FromValue, ValueRef};
+use sqlite3_ext::{FromValue, ValueRef, ValueType};
 
 use crate::{error::TableError, ColumnDeclaration};
 
-use super::{parse_to_unix_epoch, parsing::value_type_to_string};
+use super::{parse_numeric_value, parse_to_unix_epoch, parsing::value_type_to_string};
 
 /// Validates and maps the columns from a slice of `ValueRef` against their declarations,
 /// specifically identifying the partition column within the input.
@@ -36,16 +36,13 @@ pub fn validate_and_map_columns<'a>(
     let mut partition_column: Option<&ValueRef> = None;
     info.iter().enumerate().try_for_each(|(i, &v)| {
         let reference_column = &column_declarations[i]; //info is always in the same order as the table was declared in.
-        if reference_column.get_name() == partition_column_name {
-            partition_column = Some(v);
+        if reference_column.get_name() != partition_column_name {
+            return Ok(()); // only confirm data type for partition column. Default sqlite behaviour
+                           // is to not enforce data type
         }
-        let at_partition_column = partition_column.is_some_and(|column| column == v);
-        if !at_partition_column
-            || &v.value_type() == reference_column.data_type()
-            || parse_to_unix_epoch(v).is_ok()
-        {
-            Ok(()) // only confirm data type for partition column. Default sqlite behaviour is to
-                   // not enforce data type
+        partition_column = Some(v);
+        if is_valid_partition_value(v, reference_column.data_type()) {
+            Ok(())
         } else {
             Err(sqlite3_ext::Error::Module(
                 TableError::ColumnTypeMismatch {
@@ -58,3 +55,269 @@ pub fn validate_and_map_columns<'a>(
     })?;
     Ok((info, partition_column))
 }
+
+/// Checks the values written to a table created `strict` against the declared types of their
+/// columns.
+///
+/// NULL is accepted in every column, an integer also in a `FLOAT` column and text also in a
+/// `BLOB` column, which `JSON` columns are declared as. Columns declared without a type accept
+/// any value, and values SQLite marks as unchanged by an UPDATE are not checked.
+///
+/// Parameters:
+/// - `info`: The values, in the order the columns were declared.
+/// - `column_declarations`: The declared columns.
+/// - `exempt`: Names of columns not to check, e.g. the partition column, which
+///   [`validate_and_map_columns`] checks on its own.
+///
+/// Returns:
+/// - `Ok(())` if every value matches its column, otherwise an `Error` naming the expected and
+///   found type of the first value that does not.
+pub fn check_strict_types(
+    info: &[&ValueRef],
+    column_declarations: &[ColumnDeclaration],
+    exempt: &[&str],
+) -> sqlite3_ext::Result<()> {
+    for (value, column) in info.iter().zip(column_declarations) {
+        if value.nochange() || exempt.contains(&column.get_name()) {
+            continue;
+        }
+        let matches = match (column.data_type(), value.value_type()) {
+            (_, ValueType::Null) | (ValueType::Null, _) => true,
+            (ValueType::Float, ValueType::Integer) | (ValueType::Blob, ValueType::Text) => true,
+            (declared, found) => *declared == found,
+        };
+        if !matches {
+            return Err(sqlite3_ext::Error::Module(
+                TableError::ColumnTypeMismatch {
+                    expected: value_type_to_string(column.data_type()),
+                    found: value_type_to_string(&value.value_type()),
+                }
+                .to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Decides whether a value is accepted for the partition column.
+///
+/// The rules are, by the type of the value:
+/// - `Null` and `Blob`: never accepted, there is no timestamp to partition on.
+/// - `Integer` and `Float`: always accepted, the value is taken as seconds since the UNIX epoch,
+///   or as a plain number in a column declared `integer` or `float`. SQLite's dynamic typing
+///   lets an application bind an epoch to a column declared `text`.
+/// - `Text`: accepted if the column is declared as text, if it holds a number in a column
+///   declared `integer` or `float`, or otherwise if the text parses as a datetime. The actual parsing happens when the partition is computed, so a text column can
+///   still be fed by a custom partition value parser.
+///
+/// Parameters:
+/// - `value`: The value bound to the partition column.
+/// - `declared_type`: The data type the partition column was declared with.
+///
+/// Returns:
+/// - `true` if the value can be used to compute a partition, `false` otherwise.
+pub fn is_valid_partition_value(value: &ValueRef, declared_type: &ValueType) -> bool {
+    match value.value_type() {
+        ValueType::Null | ValueType::Blob => false,
+        ValueType::Integer | ValueType::Float => true,
+        ValueType::Text => match declared_type {
+            ValueType::Text => true,
+            ValueType::Integer | ValueType::Float => parse_numeric_value(value).is_ok(),
+            _ => parse_to_unix_epoch(value).is_ok(),
+        },
+    }
+}
+
+/// Checks whether a column name can be placed into generated SQL as it is.
+///
+/// Accepted are bare identifiers made of ASCII letters, digits and underscores that do not start
+/// with a digit, and identifiers quoted with `"..."`, `` `...` `` or `[...]`. A quoted name may not
+/// contain whitespace or control characters, and a quote character inside it must be doubled.
+///
+/// Parameters:
+/// - `name`: The column name as written in the schema, including any quotes.
+///
+/// Returns:
+/// - `true` if the name is safe to use in generated SQL, `false` otherwise.
+pub fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let bare = chars
+        .next()
+        .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+    if bare {
+        return true;
+    }
+    [('"', '"'), ('`', '`'), ('[', ']')]
+        .into_iter()
+        .any(|(open, close)| {
+            let Some(inner) = name
+                .strip_prefix(open)
+                .and_then(|name| name.strip_suffix(close))
+            else {
+                return false;
+            };
+            let unescaped = match open == close {
+                true => inner.replace(&format!("{close}{close}"), ""),
+                false => inner.to_owned(),
+            };
+            !inner.is_empty()
+                && !unescaped.contains(close)
+                && !inner
+                    .chars()
+                    .any(|char| char.is_whitespace() || char.is_control())
+        })
+}
+
+/// Strips the quotes from an identifier quoted with `"..."`, `` `...` `` or `[...]` and undoes
+/// doubled quote characters inside it. Bare identifiers are returned as they are.
+///
+/// Parameters:
+/// - `name`: The identifier as written in SQL, including any quotes.
+///
+/// Returns:
+/// - The identifier as SQLite stores it in the schema.
+pub fn unquote_identifier(name: &str) -> String {
+    [('"', '"'), ('`', '`'), ('[', ']')]
+        .into_iter()
+        .find_map(|(open, close)| {
+            let inner = name
+                .strip_prefix(open)
+                .and_then(|name| name.strip_suffix(close))?;
+            Some(match open == close {
+                true => inner.replace(&format!("{close}{close}"), &close.to_string()),
+                false => inner.to_owned(),
+            })
+        })
+        .unwrap_or_else(|| name.to_owned())
+}
+
+/// Quotes a table or column name for use in generated SQL.
+///
+/// The name is wrapped in double quotes and any double quote inside it is doubled, so keywords
+/// such as `group` and names with spaces or quotes can neither break the statement nor inject
+/// SQL into it. A name that is already quoted is unquoted first with [`unquote_identifier`].
+///
+/// Parameters:
+/// - `name`: The table or column name, quoted or not.
+///
+/// Returns:
+/// - The name in double quotes.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", unquote_identifier(name).replace('"', "\"\""))
+}
+
+/// Checks a column name with [`is_valid_identifier`].
+///
+/// Parameters:
+/// - `name`: The column name as written in the schema, including any quotes.
+///
+/// Returns:
+/// - `Ok(())` if the name is valid, a `TableError::ColumnDeclaration` naming it otherwise.
+pub fn validate_identifier(name: &str) -> Result<(), TableError> {
+    if is_valid_identifier(name) {
+        return Ok(());
+    }
+    Err(TableError::ColumnDeclaration(format!(
+        "Invalid column name {:?}, expected letters, digits and underscores or a quoted name",
+        name
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Index;
+
+    use rusqlite::Connection as RusqConn;
+    use sqlite3_ext::{Connection, ValueType};
+
+    use super::{
+        is_valid_identifier, is_valid_partition_value, quote_identifier, unquote_identifier,
+    };
+
+    /// Checks an integer, a float, a timestamp, a non-timestamp text, a blob and a NULL value, in
+    /// that order, against the given declared type.
+    fn check_values(declared_type: ValueType, expected: [bool; 6]) -> sqlite3_ext::Result<()> {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        db.query_row(
+            "SELECT 1706745600, 1706745600.5, '2024-02-01 00:00', 'not a timestamp', x'00', NULL",
+            (),
+            |row| {
+                let accepted = (0..6)
+                    .map(|index| {
+                        is_valid_partition_value(row.index(index).as_ref(), &declared_type)
+                    })
+                    .collect::<Vec<bool>>();
+                assert_eq!(accepted, expected);
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn test_partition_value_declared_text() -> sqlite3_ext::Result<()> {
+        check_values(ValueType::Text, [true, true, true, true, false, false])
+    }
+
+    #[test]
+    fn test_partition_value_declared_integer() -> sqlite3_ext::Result<()> {
+        check_values(ValueType::Integer, [true, true, false, false, false, false])
+    }
+
+    #[test]
+    fn test_partition_value_declared_float() -> sqlite3_ext::Result<()> {
+        check_values(ValueType::Float, [true, true, false, false, false, false])
+    }
+
+    #[test]
+    fn test_partition_value_declared_blob() -> sqlite3_ext::Result<()> {
+        check_values(ValueType::Blob, [true, true, true, false, false, false])
+    }
+
+    #[test]
+    fn test_is_valid_identifier() {
+        for name in [
+            "col1",
+            "_col",
+            "Col_2",
+            "\"select\"",
+            "`col-1`",
+            "[col.1]",
+            "\"co\"\"l\"",
+        ] {
+            assert!(is_valid_identifier(name), "{}", name);
+        }
+        for name in [
+            "",
+            "1col",
+            "co;l",
+            "col-1",
+            "\"col",
+            "\"co\"l\"",
+            "\"\"",
+            "[col]]",
+            "\"co\tl\"",
+            "col\u{0}",
+        ] {
+            assert!(!is_valid_identifier(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("col1"), "\"col1\"");
+        assert_eq!(quote_identifier("group"), "\"group\"");
+        assert_eq!(quote_identifier("\"group\""), "\"group\"");
+        assert_eq!(quote_identifier("[order]"), "\"order\"");
+        assert_eq!(quote_identifier("`col-1`"), "\"col-1\"");
+        assert_eq!(quote_identifier("my table"), "\"my table\"");
+        assert_eq!(
+            quote_identifier("a\"; DROP TABLE t; --"),
+            "\"a\"\"; DROP TABLE t; --\""
+        );
+        assert_eq!(quote_identifier("\"co\"\"l\""), "\"co\"\"l\"");
+        assert_eq!(unquote_identifier("\"co\"\"l\""), "co\"l");
+        assert_eq!(unquote_identifier("col1"), "col1");
+    }
+}
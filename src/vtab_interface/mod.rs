@@ -1,25 +1,31 @@
 pub mod operations;
+mod partition_list;
 mod vtab_cursor;
 mod vtab_module;
 
+pub use partition_list::PartitionListTable;
+pub use vtab_module::{PartitionMetaTable, RowidMapper};
+
 use crate::constraints::{WhereClause, WhereClauses};
-use crate::{shadow_tables::interface::VirtualTable, vtab_interface::vtab_module::*};
+use crate::shadow_tables::interface::VirtualTable;
 use operations::create::*;
 use sqlite3_ext::{
     ffi::SQLITE_NOTFOUND,
+    function::FunctionOptions,
     sqlite3_ext_main,
-    vtab::{ChangeInfo, IndexInfoConstraint},
-    Connection, Result as ExtResult,
+    vtab::{ChangeInfo, ConstraintOp, IndexInfoConstraint},
+    Connection, Result as ExtResult, RiskLevel,
 };
 
 use std::{collections::HashMap, sync::RwLock};
 
-use crate::utils::parse_partition_value;
-
 /// Initializes the database with the Partitioner module.
 ///
 /// This function sets up the virtual table module "Partitioner" in the SQLite database
-/// to manage partitioned tables. It leverages a global lock for thread safety.
+/// to manage partitioned tables. It leverages a global lock for thread safety. It also
+/// registers the `partitioner_drop_before(table, cutoff)` SQL function and the
+/// `partitioner_row_count(partition_table)` function backing the partitions view, and the
+/// `partitioner_partitions(table)` table-valued function listing the partitions of a table.
 ///
 /// Parameters:
 /// - `db`: Reference to the active database connection.
@@ -27,19 +33,83 @@ use crate::utils::parse_partition_value;
 /// Returns:
 /// - `ExtResult<()>`: Ok if successful, or an error on failure.
 #[sqlite3_ext_main]
-fn init(db: &Connection) -> ExtResult<()> {
+pub(crate) fn init(db: &Connection) -> ExtResult<()> {
     db.create_module(
         "Partitioner",
         PartitionMetaTable::module(),
         RwLock::default(),
     )?;
+    db.create_module("partitioner_partitions", PartitionListTable::module(), ())?;
+    db.create_scalar_function(
+        "partitioner_drop_before",
+        &FunctionOptions::default()
+            .set_n_args(2)
+            .set_risk_level(RiskLevel::DirectOnly),
+        operations::retention::drop_before,
+    )?;
+    db.create_scalar_function(
+        crate::PartitionsView::ROW_COUNT_FUNCTION,
+        &FunctionOptions::default()
+            .set_n_args(1)
+            .set_risk_level(RiskLevel::Innocuous),
+        operations::metadata::row_count,
+    )?;
     Ok(())
 }
 
+/// Key in the serialized `WhereClauses` holding the LIMIT and OFFSET of a scan.
+const LIMIT_KEY: &str = "limit";
+
+/// Decides whether a constraint can be applied to the partition queries.
+///
+/// Constraints on the rowid, `MATCH` and overloaded functions have no equivalent on the
+/// partition tables and are left to SQLite. LIMIT and OFFSET are handled separately.
+///
+/// Parameters:
+/// - `constraint`: The constraint as reported to `best_index`.
+/// - `column_count`: The number of declared columns of the virtual table.
+///
+/// Returns:
+/// - `true` if the constraint can be pushed down into the partition queries.
+fn is_column_constraint_supported(constraint: &IndexInfoConstraint, column_count: usize) -> bool {
+    !matches!(
+        constraint.op(),
+        ConstraintOp::Limit
+            | ConstraintOp::Offset
+            | ConstraintOp::Match
+            | ConstraintOp::Function(_)
+    ) && (0..column_count as i32).contains(&constraint.column())
+}
+
+/// Decides whether an operator compares the partition column as a point in time.
+///
+/// Such comparisons prune partitions and are evaluated by the cursor on the parsed epoch of
+/// each value, so that text datetimes and integer epochs stored in the same column compare by
+/// the time they denote rather than by their storage class.
+///
+/// Parameters:
+/// - `op`: The operator of a constraint on the partition column.
+///
+/// Returns:
+/// - `true` for `=`, `>`, `>=`, `<` and `<=`.
+pub(crate) fn is_time_comparison(op: &ConstraintOp) -> bool {
+    matches!(
+        op,
+        ConstraintOp::Eq
+            | ConstraintOp::GT
+            | ConstraintOp::GE
+            | ConstraintOp::LT
+            | ConstraintOp::LE
+    )
+}
+
 /// Constructs `WhereClauses` from the provided index information and virtual table.
 ///
 /// This function parses the index information to generate SQL WHERE clauses that are
 /// applicable for querying the virtual table, based on its column constraints and indexes.
+/// Only constraints that were assigned an argv index in `best_index` are included, and each
+/// clause refers to the position of its value in the argv passed to `filter`. LIMIT and OFFSET
+/// are stored under their own key.
 ///
 /// Parameters:
 /// - `index_info`: Index information provided by the SQLite VTAB method bestIndex.
@@ -51,29 +121,38 @@ fn construct_where_clause(
     index_info: &sqlite3_ext::vtab::IndexInfo,
     virtual_table: &VirtualTable,
 ) -> ExtResult<WhereClauses> {
-    let mut column_name_map: HashMap<String, Vec<(IndexInfoConstraint, i32)>> = HashMap::new();
-    for (index, constraint) in index_info
-        .constraints()
-        .enumerate()
-        .filter(|(_index, c)| c.usable())
-    {
-        let column_name = virtual_table.columns().0[constraint.column() as usize]
-            .get_name()
-            .to_owned();
-        column_name_map
-            .entry(column_name)
-            .or_default()
-            .push((constraint, index as i32));
-    }
-
-    let where_clauses = column_name_map
+    let mut column_name_map: HashMap<String, Vec<(ConstraintOp, i32)>> = HashMap::new();
+    let mut limit_clauses = Vec::new();
+    for constraint in index_info.constraints() {
+        let Some(argv_index) = constraint.argv_index() else {
+            continue;
+        };
+        match constraint.op() {
+            op @ (ConstraintOp::Limit | ConstraintOp::Offset) => {
+                limit_clauses.push(WhereClause::new(
+                    LIMIT_KEY.to_owned(),
+                    op,
+                    argv_index as i32,
+                ));
+            }
+            op => {
+                let column_name = virtual_table.columns().0[constraint.column() as usize]
+                    .get_name()
+                    .to_owned();
+                column_name_map
+                    .entry(column_name)
+                    .or_default()
+                    .push((op, argv_index as i32));
+            }
+        }
+    }
+
+    let mut where_clauses: WhereClauses = column_name_map
         .iter()
         .map(|(column_name, constraints)| {
             let clauses = constraints
                 .iter()
-                .map(|(constraint, index)| {
-                    WhereClause::new(column_name.to_owned(), constraint.op(), *index)
-                })
+                .map(|(op, index)| WhereClause::new(column_name.to_owned(), *op, *index))
                 .collect::<Vec<WhereClause>>();
             (
                 virtual_table
@@ -85,23 +164,32 @@ fn construct_where_clause(
             )
         })
         .collect();
+    if !limit_clauses.is_empty() {
+        where_clauses.insert(LIMIT_KEY.to_owned(), limit_clauses);
+    }
     Ok(where_clauses)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::ops::{DerefMut, Index, IndexMut};
+    use std::ops::{Index, IndexMut};
+    use std::sync::RwLock;
 
     use rusqlite::Connection as RusqConn;
+    use sqlite3_ext::vtab::{
+        ConflictMode, ConstraintOp, TransactionVTab, VTab, VTabCursor, VTabTransaction,
+    };
     use sqlite3_ext::{
-        query::QueryResult, Connection, FallibleIterator, FallibleIteratorMut, FromValue,
+        Connection, FallibleIterator, FallibleIteratorMut, FromValue, Value, ValueType,
     };
 
-    use super::init;
+    use super::{init, PartitionMetaTable, VirtualTable, WhereClause, WhereClauses};
+    use crate::constraints::QueryPlan;
+    use crate::utils::list_partition_value;
+    use crate::IndexFormat;
     fn setup_db(rusq_conn: &RusqConn) -> &Connection {
-        let conn = Connection::from_rusqlite(rusq_conn);
-        conn
+        Connection::from_rusqlite(rusq_conn)
     }
     fn init_rusq_conn() -> RusqConn {
         RusqConn::open_in_memory().unwrap()
@@ -136,7 +224,42 @@ mod tests {
         let db = setup_db(&rusq_conn);
         assert!(init(db).is_ok());
         let sql = "CREATE VIRTUAL TABLE test USING partitioner(col1 timestamp partition_column, col2 text)";
-        assert!(db.execute(sql, ()).is_err())
+        let err = db.execute(sql, ()).unwrap_err();
+        assert!(
+            err.to_string().contains("Is the interval missing?"),
+            "unexpected error: {}",
+            err
+        );
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 fortnight, col1 timestamp partition_column, col2 text)";
+        let err = db.execute(sql, ()).unwrap_err();
+        assert!(!err.to_string().contains("Is the interval missing?"));
+    }
+    #[test]
+    fn test_create_virtual_table_name_conflict() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        db.execute("CREATE TABLE test_lookup (a)", ())?;
+        db.execute("CREATE VIEW TEST_CONFIG AS SELECT 1", ())?;
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text)";
+        let err = db.execute(sql, ()).unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "Cannot create test, names it derives are taken by: view TEST_CONFIG, table test_lookup"
+            ),
+            "unexpected error: {}",
+            err
+        );
+        // Nothing was created, and the existing table is left alone.
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        assert_eq!(
+            count("SELECT count(*) FROM sqlite_schema WHERE name LIKE 'test%'")?,
+            2
+        );
+        db.execute("DROP VIEW TEST_CONFIG", ())?;
+        db.execute("DROP TABLE test_lookup", ())?;
+        db.execute(sql, ())?;
+        Ok(())
     }
     #[test]
     fn test_created_root_table() -> sqlite3_ext::Result<()> {
@@ -152,7 +275,7 @@ mod tests {
                 let result_query = result.index_mut(0).get_str()?;
                 assert_eq!(
                     result_query,
-                    "CREATE TABLE test_root (partition_column TEXT, partition_value INTEGER, lifetime INTEGER)"
+                    "CREATE TABLE \"test_root\" (\"partition_column\" TEXT, \"partition_value\" INTEGER, \"lifetime\" INTEGER, \"on_expired_insert\" TEXT, \"bucket_column\" TEXT, \"sealed\" INTEGER, \"round\" TEXT, \"index_format\" TEXT, \"default_partition\" INTEGER, \"strict\" INTEGER, \"strategy\" TEXT, \"key_columns\" TEXT, \"partition_name\" TEXT, \"parallel_scan\" INTEGER, \"list_values\" TEXT, \"generation\" INTEGER, \"max_statements\" INTEGER)"
                 );
                 Ok(())
             },
@@ -174,7 +297,7 @@ mod tests {
                 let result_query = result.index_mut(0).get_str()?;
                 assert_eq!(
                     result_query,
-                    "CREATE TABLE test_lookup (partition_table TEXT UNIQUE, partition_value INTEGER UNIQUE, expires_at INTEGER)"
+                    "CREATE TABLE \"test_lookup\" (\"partition_table\" TEXT UNIQUE, \"partition_value\" INTEGER, \"expires_at\" INTEGER, \"row_count\" INTEGER DEFAULT 0, \"partition_key\" INTEGER DEFAULT 0, \"merged_through\" INTEGER, \"split_from\" INTEGER, \"split_interval\" INTEGER, \"list_value\" TEXT, UNIQUE (\"partition_value\", \"partition_key\"), UNIQUE (\"list_value\", \"partition_key\"))"
                 );
                 Ok(())
             },
@@ -195,7 +318,7 @@ mod tests {
                 let result_query = result.index_mut(0).get_str()?;
                 assert_eq!(
                     result_query,
-                    "CREATE TABLE test_template (col1 TEXT, col2 TEXT)"
+                    "CREATE TABLE \"test_template\" (\"col1\" TEXT, \"col2\" TEXT)"
                 );
                 Ok(())
             },
@@ -245,6 +368,90 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_integer_partition_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 integer partition_column, col2 text)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test (col1, col2) values (1706745600, 'a'),(1706747400, 'b'),(1706749200, 'c')",
+            (),
+        )?;
+        let mut stmt = db.prepare("SELECT partition_value FROM test_lookup ORDER BY 1")?;
+        let mut partitions = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            partitions.push(row[0].get_i64());
+        }
+        assert_eq!(partitions, vec![1706745600, 1706749200]);
+        db.query_row(
+            "SELECT group_concat(col2, ''), typeof(min(col1)) FROM test WHERE col1 >= 1706747400",
+            (),
+            |row| {
+                assert_eq!(row[0].get_str()?, "bc");
+                assert_eq!(row[1].get_str()?, "integer");
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_range_partitioning() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        for (table, declared_type) in [("ints", "integer"), ("floats", "float")] {
+            let sql = format!(
+                "CREATE VIRTUAL TABLE {} USING partitioner(1000, sensor_reading {} partition_column, sensor text)",
+                table, declared_type
+            );
+            db.execute(&sql, ())?;
+            db.insert(
+                &format!(
+                    "INSERT INTO {} VALUES (500, 'a'), (1500, 'b'), (999.5, 'c'), ('1999', 'd')",
+                    table
+                ),
+                (),
+            )?;
+            let mut stmt = db.prepare(&format!(
+                "SELECT partition_table FROM {}_lookup ORDER BY partition_value",
+                table
+            ))?;
+            let mut partitions = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                partitions.push(row[0].get_str()?.to_owned());
+            }
+            assert_eq!(
+                partitions,
+                vec![format!("{}_0", table), format!("{}_1000", table)]
+            );
+            db.query_row(
+                &format!(
+                    "SELECT group_concat(sensor, '') FROM (SELECT sensor FROM {} WHERE sensor_reading >= 1000 ORDER BY sensor)",
+                    table
+                ),
+                (),
+                |row| {
+                    assert_eq!(row[0].get_str()?, "bd");
+                    Ok(())
+                },
+            )?;
+            // A numeric partition column holds plain numbers, a datetime is not read as one.
+            let err = db
+                .insert(
+                    &format!("INSERT INTO {} VALUES ('2024-02-01', 'e')", table),
+                    (),
+                )
+                .unwrap_err();
+            assert!(err.to_string().contains("Expected column type"));
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_hourly_interval() -> sqlite3_ext::Result<()> {
         let rusq_conn = init_rusq_conn();
@@ -286,6 +493,160 @@ mod tests {
         })?;
         Ok(())
     }
+    #[test]
+    fn test_weekly_interval() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 week, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        // Weeks are aligned to the UNIX epoch and start on Thursdays, 2024-02-01 is one.
+        db.insert(
+            "INSERT INTO test (col1) values ('2024-01-25'),('2024-01-28 12:00'),('2024-01-31 23:59'),('2024-02-01'),('2024-02-07 23:00')",
+            (),
+        )?;
+        let mut stmt = db.prepare("SELECT partition_value FROM test_lookup ORDER BY 1")?;
+        let mut partitions = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            partitions.push(row[0].get_i64());
+        }
+        assert_eq!(partitions, vec![1706745600 - 604800, 1706745600]);
+        Ok(())
+    }
+    #[test]
+    fn test_round_option() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        // 2024-02-01 00:00 is 1706745600.
+        let cases = [
+            ("floor", vec![1706745600, 1706749200]),
+            ("ceil", vec![1706745600, 1706749200, 1706752800]),
+            ("nearest", vec![1706745600, 1706749200, 1706752800]),
+        ];
+        for (round, expected) in cases {
+            let sql = format!("CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, round={round})");
+            db.execute(&sql, ())?;
+            db.insert(
+                "INSERT INTO test (col1, col2) values ('2024-02-01 00:00', 1),('2024-02-01 00:10', 2),('2024-02-01 00:30', 3),('2024-02-01 00:50', 4),('2024-02-01 01:40', 5)",
+                (),
+            )?;
+            let mut stmt = db.prepare("SELECT partition_value FROM test_lookup ORDER BY 1")?;
+            let mut partitions = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                partitions.push(row[0].get_i64());
+            }
+            assert_eq!(partitions, expected, "round={}", round);
+
+            // Pruned range scans return the same rows whatever the rounding.
+            let ranges = [
+                (
+                    "col1 >= '2024-02-01 00:10' AND col1 < '2024-02-01 00:50'",
+                    5,
+                ),
+                ("col1 > '2024-02-01 00:30'", 9),
+                ("col1 <= '2024-02-01 00:30'", 6),
+                ("col1 = '2024-02-01 00:50'", 4),
+                ("col1 < '2024-02-01 00:00'", 0),
+            ];
+            for (condition, sum) in ranges {
+                let actual = db.query_row(
+                    &format!("SELECT coalesce(sum(col2), 0) FROM test WHERE {condition}"),
+                    (),
+                    |row| Ok(row[0].get_i64()),
+                )?;
+                assert_eq!(actual, sum, "round={}, {}", round, condition);
+            }
+            db.execute("DROP TABLE test", ())?;
+        }
+        assert!(db
+            .execute(
+                "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, round=up)",
+                ()
+            )
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_index_format_option() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, index_format=binary)";
+        if !cfg!(feature = "binary_index") {
+            assert!(db.execute(sql, ()).is_err());
+            return Ok(());
+        }
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test (col1, col2) values ('2024-02-01 00:10', 1),('2024-02-01 01:10', 2),('2024-02-01 02:10', 3)",
+            (),
+        )?;
+        let format = db.query_row(
+            "SELECT value FROM test_config WHERE setting = 'index_format'",
+            (),
+            |row| Ok(row[0].get_str()?.to_owned()),
+        )?;
+        assert_eq!(format, "binary");
+        let plan = db.query_row(
+            "EXPLAIN QUERY PLAN SELECT * FROM test WHERE col2 > 1",
+            (),
+            |row| Ok(row[3].get_str()?.to_owned()),
+        )?;
+        assert!(plan.contains(IndexFormat::BINARY_PREFIX), "{}", plan);
+        let sum = db.query_row(
+            "SELECT sum(col2) FROM test WHERE col1 >= '2024-02-01 01:00' AND col2 < 3",
+            (),
+            |row| Ok(row[0].get_i64()),
+        )?;
+        assert_eq!(sum, 2);
+        Ok(())
+    }
+    #[test]
+    fn test_primary_key_orders_partition_reads() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        db.execute("CREATE VIRTUAL TABLE plain USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)", ())?;
+        db.execute("CREATE VIRTUAL TABLE keyed USING partitioner(1 hour, col1 timestamp partition_column primary key, col2 integer)", ())?;
+        let read_order = |table: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            db.insert(
+                &format!("INSERT INTO {table} (col1, col2) values ('2024-02-01 01:40', 5),('2024-02-01 00:30', 1),('2024-02-01 00:10', 2),('2024-02-01 00:50', 3),('2024-02-01 00:20', 4)"),
+                (),
+            )?;
+            let mut stmt = db.prepare(&format!("SELECT col2 FROM {table}"))?;
+            let mut order = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                order.push(row[0].get_i64());
+            }
+            Ok(order)
+        };
+        // Partitions are read oldest first, within a partition rows come in rowid order unless
+        // the partition column is the primary key.
+        assert_eq!(read_order("plain")?, vec![1, 2, 3, 4, 5]);
+        assert_eq!(read_order("keyed")?, vec![2, 4, 1, 3, 5]);
+
+        let keyed = VirtualTable::connect(db, "keyed")?;
+        assert!(keyed.partition_column_is_primary_key());
+        assert!(!VirtualTable::connect(db, "plain")?.partition_column_is_primary_key());
+        // The key is enforced by the partitions only.
+        assert!(!keyed.create_table_query().contains("PRIMARY KEY"));
+        assert!(db
+            .insert(
+                "INSERT INTO keyed (col1, col2) values ('2024-02-01 00:10', 6)",
+                ()
+            )
+            .is_err());
+
+        assert!(db
+            .execute("CREATE VIRTUAL TABLE other USING partitioner(1 hour, col1 timestamp partition_column, col2 integer primary key)", ())
+            .is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_select() -> sqlite3_ext::Result<()> {
         let rusq_conn = init_rusq_conn();
@@ -407,4 +768,3350 @@ mod tests {
         )?;
         Ok(())
     }
+
+    #[test]
+    fn test_scan_keeps_one_partition_statement_open() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.execute("BEGIN", ())?;
+        for hour in 0..200 {
+            db.insert(
+                &format!(
+                    "INSERT INTO test values ({}, {})",
+                    1706745600 + hour * 3600,
+                    hour
+                ),
+                (),
+            )?;
+        }
+        db.execute("COMMIT", ())?;
+        let open_statements = || {
+            let handle = unsafe { rusq_conn.handle() };
+            let mut count = 0;
+            let mut stmt =
+                unsafe { rusqlite::ffi::sqlite3_next_stmt(handle, std::ptr::null_mut()) };
+            while !stmt.is_null() {
+                // Prepared INSERT statements are kept for reuse, only reads are counted.
+                if unsafe { rusqlite::ffi::sqlite3_stmt_readonly(stmt) } != 0 {
+                    count += 1;
+                }
+                stmt = unsafe { rusqlite::ffi::sqlite3_next_stmt(handle, stmt) };
+            }
+            count
+        };
+
+        let mut stmt = db.prepare("SELECT col2 FROM test")?;
+        stmt.query(())?;
+        let mut rows = 0;
+        while stmt.next()?.is_some() {
+            // The scan itself and the partition it is reading.
+            assert!(open_statements() <= 2);
+            rows += 1;
+        }
+        assert_eq!(rows, 200);
+        // The last partition is finalized once the scan is exhausted.
+        assert_eq!(open_statements(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_statements_caps_prepared_statements() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, parallel_scan=8, max_statements=3)";
+        assert!(db.execute(sql, ()).is_ok());
+        let open_statements = || {
+            let handle = unsafe { rusq_conn.handle() };
+            let mut count = 0;
+            let mut stmt =
+                unsafe { rusqlite::ffi::sqlite3_next_stmt(handle, std::ptr::null_mut()) };
+            while !stmt.is_null() {
+                count += 1;
+                stmt = unsafe { rusqlite::ffi::sqlite3_next_stmt(handle, stmt) };
+            }
+            count
+        };
+        assert_eq!(open_statements(), 0);
+
+        // Inserting into 200 partitions keeps no more than 3 INSERT statements prepared.
+        db.execute("BEGIN", ())?;
+        for hour in 0..200 {
+            db.insert(
+                &format!(
+                    "INSERT INTO test values ({}, {})",
+                    1706745600 + hour * 3600,
+                    hour
+                ),
+                (),
+            )?;
+            assert!(open_statements() <= 3);
+        }
+        db.execute("COMMIT", ())?;
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM test", (), |row| Ok(row[0].get_i64()))?,
+            200
+        );
+
+        // Scans start no more workers than that either.
+        assert_eq!(VirtualTable::connect(db, "test")?.scan_workers(), Some(3));
+        assert_eq!(
+            db.query_row(
+                "SELECT value FROM test_config WHERE setting = 'max_statements'",
+                (),
+                |row| Ok(row[0].get_i64())
+            )?,
+            3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_batch_insert_leaves_no_rows() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert("INSERT INTO test values ('2024-02-01 00:00', 0)", ())?;
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        let batch = "INSERT INTO test values ('2024-02-01 00:30', 1), ('2024-02-01 01:00', 2), \
+                     ('2024-02-01 02:00', 3), ('not a time', 4), ('2024-02-01 03:00', 5)";
+
+        assert!(db.execute(batch, ()).is_err());
+        assert_eq!(count("SELECT count(*) FROM test")?, 1);
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 1);
+
+        // Inside a transaction only the failed statement is undone.
+        db.execute("BEGIN", ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 05:00', 6)", ())?;
+        assert!(db.execute(batch, ()).is_err());
+        db.execute("COMMIT", ())?;
+        assert_eq!(count("SELECT count(*) FROM test")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+
+        // The partitions created by the failed statements can be created again.
+        db.insert("INSERT INTO test values ('2024-02-01 01:00', 2)", ())?;
+        assert_eq!(count("SELECT count(*) FROM test WHERE col2 = 2")?, 1);
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_with_limit() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for hour in 0..4 {
+            for minute in 0..3 {
+                db.insert(
+                    &format!(
+                        "INSERT INTO test values ('2024-02-01 {:02}:{:02}', {})",
+                        hour,
+                        minute,
+                        hour * 10 + minute
+                    ),
+                    (),
+                )?;
+            }
+        }
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        // SQLite runs the LIMIT as a separate scan of the rowids to delete, possibly over other
+        // partitions than the scan that deletes them.
+        let deleted = db.execute(
+            "DELETE FROM test WHERE col1 >= '2024-02-01 02:00' LIMIT 2",
+            (),
+        );
+        // DELETE ... LIMIT is only available if SQLite was compiled with it.
+        if deleted.is_err() {
+            db.execute(
+                "DELETE FROM test WHERE rowid IN (SELECT rowid FROM test WHERE col1 >= '2024-02-01 02:00' LIMIT 2)",
+                (),
+            )?;
+        }
+        assert_eq!(
+            collect("SELECT col2 FROM test")?,
+            vec![0, 1, 2, 10, 11, 12, 22, 30, 31, 32]
+        );
+        // Chunked retention deletes until nothing is left in range.
+        let mut chunks = 0;
+        loop {
+            let deleted = db.execute(
+                "DELETE FROM test WHERE rowid IN (SELECT rowid FROM test WHERE col1 < '2024-02-01 02:00' LIMIT 4)",
+                (),
+            )?;
+            if deleted == 0 {
+                break;
+            }
+            assert!(deleted <= 4);
+            chunks += 1;
+        }
+        assert_eq!(chunks, 2);
+        assert_eq!(collect("SELECT col2 FROM test")?, vec![22, 30, 31, 32]);
+        Ok(())
+    }
+    #[test]
+    fn test_update_after_vacuum() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for value in 0..10 {
+            db.insert(
+                &format!(
+                    "INSERT INTO test values ('2024-02-01 00:{:02}', {})",
+                    value, value
+                ),
+                (),
+            )?;
+        }
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        // Scan the table so the mapper holds the persisted rowids, then renumber them.
+        assert_eq!(count("SELECT count(rowid) FROM test")?, 10);
+        db.execute("DELETE FROM test_1706745600 WHERE col2 < 5", ())?;
+        // The rows were deleted past the virtual table, its row count is counted afresh.
+        db.execute("UPDATE test_lookup SET row_count = NULL", ())?;
+        db.execute("VACUUM", ())?;
+
+        db.execute("UPDATE test SET col2 = 70 WHERE col2 = 7", ())?;
+        db.execute("DELETE FROM test WHERE col2 = 8", ())?;
+
+        assert_eq!(count("SELECT count(*) FROM test")?, 4);
+        assert_eq!(count("SELECT count(*) FROM test WHERE col2 = 70")?, 1);
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 5 + 6 + 70 + 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_table_yields_no_rows() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.query_row("SELECT count(*) from test", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 0);
+            Ok(())
+        })?;
+        Ok(())
+    }
+    #[test]
+    fn test_estimated_cost() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        // Ten partitions, one per hour from 2024-02-01 00:00, which is 1706745600.
+        let first = 1706745600;
+        for hour in 0..10 {
+            db.insert(
+                "INSERT INTO test (col1, col2) VALUES (?, ?)",
+                sqlite3_ext::params![first + hour * 3600, hour],
+            )?;
+        }
+        let rowid_mapper = RwLock::default();
+        let meta_table =
+            PartitionMetaTable::new(VirtualTable::connect(db, "test")?, db, &rowid_mapper);
+        let cost = |comparisons: &[(ConstraintOp, Option<i64>)]| {
+            meta_table.estimated_cost(comparisons).unwrap()
+        };
+
+        let unbounded = cost(&[]);
+        let bounded = cost(&[(ConstraintOp::Eq, Some(first + 3600))]);
+        assert!(bounded < unbounded, "{} < {}", bounded, unbounded);
+        assert_eq!(unbounded, 10.0 * bounded);
+        assert_eq!(
+            cost(&[
+                (ConstraintOp::GE, Some(first + 2 * 3600)),
+                (ConstraintOp::LE, Some(first + 4 * 3600)),
+            ]),
+            3.0 * bounded
+        );
+        assert_eq!(
+            cost(&[(ConstraintOp::GE, Some(first + 5 * 3600))]),
+            5.0 * bounded
+        );
+        // No partition in range still costs one partition.
+        assert_eq!(
+            cost(&[(ConstraintOp::GT, Some(first + 20 * 3600))]),
+            bounded
+        );
+        assert_eq!(cost(&[(ConstraintOp::IsNull, None)]), bounded);
+        assert_eq!(cost(&[(ConstraintOp::IsNotNull, None)]), unbounded);
+        // Values only known at run time narrow the scan by a guess.
+        assert_eq!(cost(&[(ConstraintOp::Eq, None)]), bounded);
+        assert_eq!(cost(&[(ConstraintOp::GT, None)]), 5.0 * bounded);
+        assert_eq!(
+            cost(&[(ConstraintOp::GT, None), (ConstraintOp::LT, None)]),
+            3.0 * bounded
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_touched_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 'match'),('2024-02-02 10:00', 'other'),('2024-02-03 10:00', 'match')",
+            (),
+        )?;
+
+        let rowid_mapper = RwLock::default();
+        let meta_table =
+            PartitionMetaTable::new(VirtualTable::connect(db, "test")?, db, &rowid_mapper);
+        let where_clauses: WhereClauses = [(
+            "partition_table".to_string(),
+            vec![WhereClause::new("col2".to_string(), ConstraintOp::Eq, 0)],
+        )]
+        .into_iter()
+        .collect();
+        let idx_str = QueryPlan::new(String::new(), where_clauses, u64::MAX)
+            .to_index_str(IndexFormat::Json)?;
+        let mut args = db.query("SELECT 'match'", ())?;
+        let arg = args.next()?.unwrap().index_mut(0).as_mut();
+
+        let mut cursor = meta_table.open()?;
+        cursor.filter(0, Some(&idx_str), &mut [arg])?;
+        let mut rows = 0;
+        while !cursor.eof() {
+            rows += 1;
+            cursor.next()?;
+        }
+        assert_eq!(rows, 2);
+        assert_eq!(
+            meta_table.touched_partitions()?,
+            vec!["test_1706745600", "test_1706918400"]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_hash_partitioning() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(hash 4, id integer partition_column, col2 text)";
+        db.execute(sql, ())?;
+        for id in 0..100 {
+            db.insert(&format!("INSERT INTO test values ({id}, 'row {id}')"), ())?;
+        }
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 4);
+        assert_eq!(
+            count("SELECT count(*) FROM test_lookup WHERE partition_value NOT BETWEEN 0 AND 3")?,
+            0
+        );
+        for bucket in 0..4 {
+            assert!(count(&format!("SELECT count(*) FROM test_{bucket}"))? > 0);
+        }
+        assert_eq!(count("SELECT count(*) FROM test")?, 100);
+        assert_eq!(count("SELECT count(*) FROM test WHERE id = 42")?, 1);
+        assert_eq!(count("SELECT count(*) FROM test WHERE id = '42'")?, 1);
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE id IN (1, 2, 200)")?,
+            2
+        );
+        assert_eq!(count("SELECT count(*) FROM test WHERE id > 89")?, 10);
+        assert_eq!(
+            count(
+                "SELECT count(*) FROM test_config WHERE setting = 'strategy' AND value = 'hash'"
+            )?,
+            1
+        );
+
+        let plan = |sql: &str| -> sqlite3_ext::Result<String> {
+            let mut stmt = db.prepare(&format!("EXPLAIN QUERY PLAN {sql}"))?;
+            stmt.query(())?;
+            let detail = stmt.next()?.unwrap()[3].get_str()?.to_owned();
+            Ok(detail)
+        };
+        let detail = plan("SELECT * FROM test WHERE id = 42")?;
+        assert!(detail.contains("partitions where id = ?"), "{detail}");
+        // The buckets are not ordered like the ids, a range opens every partition.
+        let detail = plan("SELECT * FROM test WHERE id > 89")?;
+        assert!(detail.contains("all partitions"), "{detail}");
+
+        // An equality on the partition column opens only the partition the value hashes to.
+        let interface = VirtualTable::connect(db, "test")?;
+        assert_eq!(interface.interval_label(), "hash 4");
+        let mut stmt = db.prepare("SELECT 42")?;
+        stmt.query(())?;
+        let (bucket, partition) =
+            interface.partition_for_value(stmt.next()?.unwrap()[0].as_ref())?;
+        drop(stmt);
+        let rows_in_bucket = count(&format!("SELECT count(*) FROM {partition}"))?;
+        let rowid_mapper = RwLock::default();
+        let meta_table = PartitionMetaTable::new(interface, db, &rowid_mapper);
+        let where_clauses: WhereClauses = [(
+            "lookup_table".to_string(),
+            vec![WhereClause::new(
+                "partition_value".to_string(),
+                ConstraintOp::Eq,
+                0,
+            )],
+        )]
+        .into_iter()
+        .collect();
+        let idx_str = QueryPlan::new(String::new(), where_clauses, u64::MAX)
+            .to_index_str(IndexFormat::Json)?;
+        let mut args = db.query("SELECT 42", ())?;
+        let arg = args.next()?.unwrap().index_mut(0).as_mut();
+        let mut cursor = meta_table.open()?;
+        cursor.filter(0, Some(&idx_str), &mut [arg])?;
+        let mut rows = 0;
+        while !cursor.eof() {
+            rows += 1;
+            cursor.next()?;
+        }
+        assert_eq!(rows, rows_in_bucket);
+        assert_eq!(partition, format!("test_{bucket}"));
+        assert_eq!(meta_table.touched_partitions()?, vec![partition]);
+        Ok(())
+    }
+    #[test]
+    fn test_list_partitioning() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(list, region text partition_column, amount int)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('EU', 1), ('US', 2), ('US', 3)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test")?, 3);
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'US'")?,
+            5
+        );
+        assert_eq!(count("SELECT count(*) FROM test WHERE region = 'APAC'")?, 0);
+        assert_eq!(count("SELECT count(*) FROM test WHERE region > 'EU'")?, 2);
+
+        let interface = VirtualTable::connect(db, "test")?;
+        assert_eq!(interface.interval_label(), "list");
+        let err = interface.counts_by_bucket(0, 3600).unwrap_err();
+        assert!(
+            err.to_string().contains("test is list partitioned"),
+            "{err}"
+        );
+        let mut stmt = db.prepare("SELECT 'US'")?;
+        stmt.query(())?;
+        let (_, partition) = interface.partition_for_value(stmt.next()?.unwrap()[0].as_ref())?;
+        drop(stmt);
+        assert_eq!(count(&format!("SELECT count(*) FROM {partition}"))?, 2);
+
+        // An equality on the partition column opens only the partition of the value.
+        let mut stmt = db.prepare("EXPLAIN QUERY PLAN SELECT * FROM test WHERE region = 'US'")?;
+        stmt.query(())?;
+        let detail = stmt.next()?.unwrap()[3].get_str()?.to_owned();
+        drop(stmt);
+        assert!(detail.contains("partitions where region = ?"), "{detail}");
+        let rowid_mapper = RwLock::default();
+        let meta_table = PartitionMetaTable::new(interface, db, &rowid_mapper);
+        let where_clauses: WhereClauses = [(
+            "lookup_table".to_string(),
+            vec![WhereClause::new(
+                "partition_value".to_string(),
+                ConstraintOp::Eq,
+                0,
+            )],
+        )]
+        .into_iter()
+        .collect();
+        let idx_str = QueryPlan::new(String::new(), where_clauses, u64::MAX)
+            .to_index_str(IndexFormat::Json)?;
+        let mut args = db.query("SELECT 'US'", ())?;
+        let arg = args.next()?.unwrap().index_mut(0).as_mut();
+        let mut cursor = meta_table.open()?;
+        cursor.filter(0, Some(&idx_str), &mut [arg])?;
+        let mut rows = 0;
+        while !cursor.eof() {
+            rows += 1;
+            cursor.next()?;
+        }
+        assert_eq!(rows, 2);
+        assert_eq!(meta_table.touched_partitions()?, vec![partition]);
+        Ok(())
+    }
+    #[test]
+    fn test_list_partitions_sharing_a_hash() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(list, region text partition_column, amount int)";
+        db.execute(sql, ())?;
+        // Force a collision: the partition of 'APAC' takes the partition value 'EU' hashes to.
+        let interface = VirtualTable::connect(db, "test")?;
+        let mut stmt = db.prepare("SELECT 'EU'")?;
+        stmt.query(())?;
+        let hash = list_partition_value(stmt.next()?.unwrap()[0].as_ref(), &ValueType::Text)?;
+        drop(stmt);
+        interface.get_listed_partition(hash, 0, Some("APAC"))?;
+        drop(interface);
+
+        db.insert(
+            "INSERT INTO test values ('EU', 1), ('APAC', 2), ('EU', 4)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        let partition_of = |region: &str| {
+            db.query_row(
+                "SELECT partition_table FROM test_lookup WHERE list_value = ?",
+                [region],
+                |row| Ok(row[0].get_str()?.to_owned()),
+            )
+        };
+        assert_eq!(
+            count("SELECT count(DISTINCT partition_value) FROM test_lookup")?,
+            2
+        );
+        assert_eq!(
+            count("SELECT partition_value FROM test_lookup WHERE list_value = 'APAC'")?,
+            hash
+        );
+        assert_eq!(
+            count("SELECT partition_value FROM test_lookup WHERE list_value = 'EU'")?,
+            hash.wrapping_add(1)
+        );
+        assert_eq!(
+            count(&format!("SELECT count(*) FROM {}", partition_of("EU")?))?,
+            2
+        );
+        assert_eq!(
+            count(&format!("SELECT count(*) FROM {}", partition_of("APAC")?))?,
+            1
+        );
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'EU'")?,
+            5
+        );
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'APAC'")?,
+            2
+        );
+
+        // A row moved to the other value lands in the partition of that value.
+        db.execute("UPDATE test SET region = 'APAC' WHERE amount = 4", ())?;
+        assert_eq!(
+            count(&format!("SELECT count(*) FROM {}", partition_of("EU")?))?,
+            1
+        );
+        assert_eq!(
+            count(&format!("SELECT count(*) FROM {}", partition_of("APAC")?))?,
+            2
+        );
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'APAC'")?,
+            6
+        );
+
+        // Another connection finds the partition of 'EU' by the recorded value.
+        let interface = VirtualTable::connect(db, "test")?;
+        let mut stmt = db.prepare("SELECT 'EU'")?;
+        stmt.query(())?;
+        let (partition_value, partition) =
+            interface.partition_for_value(stmt.next()?.unwrap()[0].as_ref())?;
+        assert_eq!(partition_value, hash.wrapping_add(1));
+        assert_eq!(partition, partition_of("EU")?);
+        Ok(())
+    }
+    #[test]
+    fn test_list_values_outside_the_declared_ones() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+
+        // With a default partition, values outside the list go there.
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(list ('EU', 'US'), region text partition_column, amount int, default_partition)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('EU', 1), ('US', 2), ('APAC', 4), (NULL, 8)",
+            (),
+        )?;
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test_default")?, 2);
+        assert_eq!(count("SELECT sum(amount) FROM test")?, 15);
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'APAC'")?,
+            4
+        );
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'EU'")?,
+            1
+        );
+        let interface = VirtualTable::connect(db, "test")?;
+        assert_eq!(interface.interval_label(), "list ('EU', 'US')");
+        assert_eq!(interface.list_values(), ["EU", "US"]);
+        drop(interface);
+        assert_eq!(
+            db.query_row(
+                "SELECT value FROM test_config WHERE setting = 'list_values'",
+                (),
+                |row| Ok(row[0].get_str()?.to_owned())
+            )?,
+            "'EU', 'US'"
+        );
+        // A row stays in the default partition while its value is not listed, and moves to
+        // the partition of a listed one.
+        db.execute("UPDATE test SET region = 'LATAM' WHERE amount = 4", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_default")?, 2);
+        db.execute("UPDATE test SET region = 'US' WHERE amount = 4", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_default")?, 1);
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE region = 'US'")?,
+            6
+        );
+        // A row of a partition is not moved to a value without one.
+        assert!(db
+            .execute("UPDATE test SET region = 'APAC' WHERE amount = 1", ())
+            .is_err());
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+
+        // Without a default partition, values outside the list are refused.
+        let sql = "CREATE VIRTUAL TABLE strict_list USING partitioner(list (1, 2), id int partition_column, amount int)";
+        db.execute(sql, ())?;
+        db.insert("INSERT INTO strict_list values (1, 1), ('2', 2)", ())?;
+        let err = db
+            .insert("INSERT INTO strict_list values (3, 4)", ())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("'3' is not one of the values strict_list is list partitioned by"),
+            "{err}"
+        );
+        let interface = VirtualTable::connect(db, "strict_list")?;
+        assert_eq!(interface.existing_default_partition()?, None);
+        let mut stmt = db.prepare("SELECT 3")?;
+        stmt.query(())?;
+        let value = stmt.next()?.unwrap();
+        assert!(interface.check_listed(value[0].as_ref()).is_err());
+        // The partition is not created behind the check either.
+        let hash = interface.partition_value(value[0].as_ref())?;
+        assert!(interface
+            .get_value_partition(hash, 0, value[0].as_ref())
+            .is_err());
+        drop(stmt);
+        assert_eq!(count("SELECT count(*) FROM strict_list_lookup")?, 2);
+        assert_eq!(count("SELECT sum(amount) FROM strict_list")?, 3);
+        Ok(())
+    }
+    #[test]
+    fn test_hash_values_without_a_partition() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+
+        // A value that cannot be hashed as the declared type goes to the default partition.
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(hash 4, id integer partition_column, amount int, default_partition)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values (1, 1), (NULL, 2), ('not a number', 4), (1.5, 8)",
+            (),
+        )?;
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 1);
+        assert_eq!(count("SELECT count(*) FROM test_default")?, 3);
+        assert_eq!(count("SELECT sum(amount) FROM test")?, 15);
+        assert_eq!(count("SELECT sum(amount) FROM test WHERE id IS NULL")?, 2);
+
+        // Without one, it is refused.
+        let sql = "CREATE VIRTUAL TABLE strict_hash USING partitioner(hash 4, id integer partition_column, amount int)";
+        db.execute(sql, ())?;
+        db.insert("INSERT INTO strict_hash values (1, 1)", ())?;
+        for value in ["NULL", "'not a number'", "1.5"] {
+            assert!(
+                db.insert(&format!("INSERT INTO strict_hash values ({value}, 2)"), ())
+                    .is_err(),
+                "{value}"
+            );
+        }
+        assert_eq!(count("SELECT count(*) FROM strict_hash")?, 1);
+        assert_eq!(
+            VirtualTable::connect(db, "strict_hash")?.existing_default_partition()?,
+            None
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_composite_partition_key() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, day timestamp partition_column, tenant text partition_column, amount int)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 'acme', 1), ('2024-02-01 12:00', 'acme', 2), \
+             ('2024-02-01 11:00', 'globex', 4), ('2024-02-02 09:00', 'acme', 8)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        // One partition per day and tenant.
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 3);
+        assert_eq!(
+            count("SELECT count(DISTINCT partition_value) FROM test_lookup")?,
+            2
+        );
+        assert_eq!(count("SELECT count(*) FROM test")?, 4);
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE tenant = 'acme'")?,
+            11
+        );
+        assert_eq!(
+            count(
+                "SELECT sum(amount) FROM test WHERE tenant = 'acme' AND day < '2024-02-02 00:00'"
+            )?,
+            3
+        );
+        let mut stmt = db.prepare("SELECT value FROM test_config WHERE setting = 'key_columns'")?;
+        stmt.query(())?;
+        assert_eq!(stmt.next()?.unwrap()[0].get_str()?, "tenant");
+        drop(stmt);
+
+        // Changing the key column moves the row into the partition of its new key.
+        db.execute("UPDATE test SET tenant = 'globex' WHERE amount = 8", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 4);
+        assert_eq!(
+            count("SELECT sum(amount) FROM test WHERE tenant = 'globex'")?,
+            12
+        );
+        assert_eq!(count("SELECT count(*) FROM test")?, 4);
+        Ok(())
+    }
+    #[test]
+    fn test_count_and_max_pushdown() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, ts timestamp partition_column, amount int)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 1), ('2024-02-02 08:00', 2), \
+             ('2024-02-02 09:30', 3), ('2024-02-03 07:00', 4)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        let text = |sql: &str| -> sqlite3_ext::Result<String> {
+            let mut stmt = db.prepare(sql)?;
+            stmt.query(())?;
+            let value = stmt.next()?.unwrap()[0].get_str()?.to_owned();
+            Ok(value)
+        };
+        assert_eq!(count("SELECT count(*) FROM test")?, 4);
+        let mut stmt = db.prepare("EXPLAIN QUERY PLAN SELECT count(*) FROM test")?;
+        stmt.query(())?;
+        let detail = stmt.next()?.unwrap()[3].get_str()?.to_owned();
+        drop(stmt);
+        assert!(detail.contains("row counts of all partitions"), "{detail}");
+
+        // The count comes from the lookup table, a wrong row count shows up in it.
+        db.execute(
+            "UPDATE test_lookup SET row_count = 10 WHERE partition_table = 'test_1706745600'",
+            (),
+        )?;
+        assert_eq!(count("SELECT count(*) FROM test")?, 13);
+        // An unknown count is counted once.
+        db.execute(
+            "UPDATE test_lookup SET row_count = NULL WHERE partition_table = 'test_1706745600'",
+            (),
+        )?;
+        assert_eq!(count("SELECT count(*) FROM test")?, 4);
+        assert_eq!(count("SELECT count(*) FROM test WHERE amount > 1")?, 3);
+
+        // A text partition column is not read in order, so max() looks at every partition.
+        db.execute(
+            "INSERT INTO test_1706745600 (ts, amount) VALUES ('2024-12-31 00:00', 5)",
+            (),
+        )?;
+        assert_eq!(text("SELECT max(ts) FROM test")?, "2024-12-31 00:00");
+        db.execute("DELETE FROM test_1706745600 WHERE amount = 5", ())?;
+        let mut stmt = db.prepare("SELECT amount FROM test ORDER BY ts DESC")?;
+        stmt.query(())?;
+        let mut amounts = Vec::new();
+        while let Some(row) = stmt.next()? {
+            amounts.push(row[0].get_i64());
+        }
+        drop(stmt);
+        assert_eq!(amounts, vec![4, 3, 2, 1]);
+
+        // Rowids are read when asked for, e.g. to delete every row.
+        assert_eq!(
+            text("SELECT group_concat(rowid) FROM test")?,
+            text("SELECT group_concat(rowid) FROM (SELECT rowid, amount FROM test)")?
+        );
+        db.execute("DELETE FROM test", ())?;
+        assert_eq!(count("SELECT count(*) FROM test")?, 0);
+        assert_eq!(count("SELECT count(*) FROM test_1706832000")?, 0);
+        Ok(())
+    }
+    #[test]
+    fn test_rollback_removes_new_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 int)";
+        db.execute(sql, ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 1)", ())?;
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        let partitions = || -> sqlite3_ext::Result<(i64, i64)> {
+            Ok((
+                count("SELECT count(*) FROM sqlite_schema WHERE name GLOB 'test_[0-9]*'")?,
+                count("SELECT count(*) FROM test_lookup")?,
+            ))
+        };
+
+        // Aborting the transaction leaves neither the table nor the lookup row of the new
+        // partition behind.
+        db.execute("BEGIN", ())?;
+        db.insert("INSERT INTO test values ('2024-02-03 10:00', 2)", ())?;
+        assert_eq!(partitions()?, (2, 2));
+        db.execute("ROLLBACK", ())?;
+        assert_eq!(partitions()?, (1, 1));
+        assert_eq!(
+            count("SELECT count(*) FROM sqlite_schema WHERE name = 'test_1706918400'")?,
+            0
+        );
+
+        // The same goes for a savepoint, and for a statement failing inside a transaction.
+        db.execute("BEGIN", ())?;
+        db.execute("SAVEPOINT inner", ())?;
+        db.insert("INSERT INTO test values ('2024-02-03 11:00', 3)", ())?;
+        db.execute("ROLLBACK TO inner", ())?;
+        assert_eq!(partitions()?, (1, 1));
+        assert!(db
+            .insert(
+                "INSERT INTO test values ('2024-02-04 10:00', 4), ('not a date', 5)",
+                (),
+            )
+            .is_err());
+        assert_eq!(partitions()?, (1, 1));
+
+        // The partitions are created again once needed.
+        db.insert("INSERT INTO test values ('2024-02-03 12:00', 6)", ())?;
+        db.insert("INSERT INTO test values ('2024-02-04 12:00', 7)", ())?;
+        db.execute("COMMIT", ())?;
+        assert_eq!(partitions()?, (3, 3));
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 1 + 6 + 7);
+        assert_eq!(count("SELECT count(*) FROM test_1706918400")?, 1);
+        Ok(())
+    }
+    #[test]
+    fn test_rollback_to_savepoint_undoes_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 int)";
+        db.execute(sql, ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 1)", ())?;
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        let on_disk = |partition: &str| -> sqlite3_ext::Result<bool> {
+            Ok(count(&format!(
+                "SELECT (SELECT count(*) FROM sqlite_schema WHERE name = '{partition}') \
+                 + (SELECT count(*) FROM test_lookup WHERE partition_table = '{partition}')"
+            ))? == 2)
+        };
+
+        let rowid_mapper = RwLock::default();
+        let meta_table =
+            PartitionMetaTable::new(VirtualTable::connect(db, "test")?, db, &rowid_mapper);
+        let in_memory = |partition_value: i64| -> sqlite3_ext::Result<bool> {
+            Ok(meta_table
+                .interface
+                .partition_map_snapshot()?
+                .contains_key(&(partition_value, 0)))
+        };
+        db.execute("BEGIN", ())?;
+        let mut transaction = meta_table.begin()?;
+        db.execute("SAVEPOINT outer", ())?;
+        transaction.savepoint(0)?;
+        let first = meta_table.interface.get_partition(&1706832000)?;
+        db.execute("SAVEPOINT inner", ())?;
+        transaction.savepoint(1)?;
+        let second = meta_table.interface.get_partition(&1706918400)?;
+        assert!(on_disk(&first)? && on_disk(&second)?);
+        assert!(in_memory(1706832000)? && in_memory(1706918400)?);
+
+        // Rolling back to the inner savepoint only undoes the partition created after it.
+        db.execute("ROLLBACK TO inner", ())?;
+        transaction.rollback_to(1)?;
+        assert!(on_disk(&first)? && !on_disk(&second)?);
+        assert!(in_memory(1706832000)? && !in_memory(1706918400)?);
+
+        db.execute("ROLLBACK TO outer", ())?;
+        transaction.rollback_to(0)?;
+        assert!(!on_disk(&first)?);
+        assert!(!in_memory(1706832000)?);
+        assert!(in_memory(1706745600)?);
+        db.execute("RELEASE outer", ())?;
+        transaction.release(0)?;
+        db.execute("COMMIT", ())?;
+        transaction.commit()?;
+
+        // The partitions are created again once needed.
+        assert_eq!(meta_table.interface.get_partition(&1706918400)?, second);
+        assert!(on_disk(&second)?);
+        db.insert("INSERT INTO test values ('2024-02-02 10:00', 2)", ())?;
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 3);
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 3);
+        Ok(())
+    }
+    #[test]
+    fn test_filter_rejects_corrupt_index_str() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        db.execute(sql, ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 'a')", ())?;
+
+        let rowid_mapper = RwLock::default();
+        let meta_table =
+            PartitionMetaTable::new(VirtualTable::connect(db, "test")?, db, &rowid_mapper);
+        let mut cursor = meta_table.open()?;
+        for idx_str in [
+            r#"{"scan":"all partitions","#,
+            "(scan:\"all partitions\")",
+            "bin:",
+        ] {
+            match cursor.filter(0, Some(idx_str), &mut []) {
+                Err(sqlite3_ext::Error::Module(message)) => {
+                    assert!(message.contains("Invalid index string"), "{}", message)
+                }
+                other => panic!("{idx_str} was accepted: {other:?}"),
+            }
+        }
+        // A well-formed plan still scans.
+        let idx_str = QueryPlan::default().to_index_str(IndexFormat::Json)?;
+        cursor.filter(0, Some(&idx_str), &mut [])?;
+        assert!(!cursor.eof());
+        Ok(())
+    }
+    #[test]
+    fn test_delete_whole_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column primary key, col2 integer)";
+        db.execute(sql, ())?;
+        // 1000 rows a minute apart on each of three days, from 2024-02-01.
+        for day in 0..3 {
+            db.execute(
+                "WITH RECURSIVE minutes(minute) AS (SELECT 0 UNION ALL SELECT minute + 1 FROM minutes WHERE minute < 999) \
+                 INSERT INTO test SELECT datetime(1706745600 + ?1 * 86400 + minute * 60, 'unixepoch'), minute FROM minutes",
+                [day],
+            )?;
+        }
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        // The first day is deleted as a whole, the second one partly.
+        assert_eq!(
+            db.execute("DELETE FROM test WHERE col1 < '2024-02-02 10:00'", ())?,
+            1600
+        );
+        assert_eq!(count("SELECT count(*) FROM test_1706745600")?, 0);
+        assert_eq!(count("SELECT count(*) FROM test_1706832000")?, 400);
+        assert_eq!(count("SELECT count(*) FROM test")?, 1400);
+        // SQLite refuses to drop a table while the DELETE runs, the emptied partition is left
+        // registered until retention drops it.
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 3);
+        assert_eq!(
+            count("SELECT partitioner_drop_before('test', '2024-02-02')")?,
+            1
+        );
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+
+        // Inside a transaction the rows are gone from the partition as soon as the DELETE is
+        // done, and make room for new rows with the same key.
+        db.execute("BEGIN", ())?;
+        db.execute("DELETE FROM test WHERE col1 >= '2024-02-03'", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_1706918400")?, 0);
+        db.insert("INSERT INTO test VALUES ('2024-02-03 00:00:00', -1)", ())?;
+        db.execute("COMMIT", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_1706918400")?, 1);
+        Ok(())
+    }
+    #[test]
+    fn test_reinterval_hourly_to_daily() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 'a'),('2024-02-01 11:30', 'b'),('2024-02-01 23:59', 'c'),('2024-02-02 00:00', 'd'),('2024-02-02 05:00', 'e')",
+            (),
+        )?;
+        let count_partitions = || {
+            db.query_row("SELECT count(*) FROM test_lookup", (), |res| {
+                Ok(res.index(0).get_i64())
+            })
+        };
+        assert_eq!(count_partitions()?, 5);
+
+        VirtualTable::connect(db, "test")?.reinterval(86400)?;
+
+        assert_eq!(count_partitions()?, 2);
+        assert_eq!(
+            VirtualTable::connect(db, "test")?.partition_interval(),
+            86400
+        );
+        db.query_row("SELECT count(*) FROM test", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 5);
+            Ok(())
+        })?;
+        let mut stmt =
+            db.prepare("SELECT col2 FROM test WHERE col1 < '2024-02-02 00:00' ORDER BY col2")?;
+        let mut values = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            values.push(row[0].get_str()?.to_owned());
+        }
+        assert_eq!(values, vec!["a", "b", "c"]);
+        // New rows end up in daily partitions as well.
+        db.insert("INSERT INTO test values ('2024-02-02 18:00', 'f')", ())?;
+        assert_eq!(count_partitions()?, 2);
+        Ok(())
+    }
+    #[test]
+    fn test_reinterval_keeps_rounding() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text, round=ceil)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 00:30', 'a'),('2024-02-01 02:00', 'b')",
+            (),
+        )?;
+        VirtualTable::connect(db, "test")?.reinterval(7200)?;
+        db.query_row(
+            "SELECT count(*), min(partition_value) FROM test_lookup",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 1);
+                assert_eq!(res.index(1).get_i64(), 1706745600 + 7200);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_sealed_table_refuses_schema_changes() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 'a')", ())?;
+        let table = VirtualTable::connect(db, "test")?;
+        assert!(!table.is_sealed());
+        table.seal()?;
+
+        // The flag is persisted, other connections to the table see it as well.
+        let table = VirtualTable::connect(db, "test")?;
+        assert!(table.is_sealed());
+        let err = table.reinterval(86400).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Table test is sealed, its schema can no longer be changed"));
+        assert_eq!(table.partition_interval(), 3600);
+        assert!(table.add_column("col3 integer").is_err());
+        assert!(table.rename("renamed").is_err());
+        let err = db
+            .execute("ALTER TABLE test RENAME TO renamed", ())
+            .unwrap_err();
+        assert!(err.to_string().contains("Table test is sealed"));
+        assert!(VirtualTable::connect(db, "test").is_ok());
+
+        // Rows can still be read and written.
+        db.insert("INSERT INTO test values ('2024-02-01 11:00', 'b')", ())?;
+        db.execute("UPDATE test SET col2 = 'c' WHERE col2 = 'a'", ())?;
+        db.execute("DELETE FROM test WHERE col2 = 'b'", ())?;
+        db.query_row("SELECT group_concat(col2) FROM test", (), |res| {
+            assert_eq!(res.index_mut(0).get_str()?, "c");
+            Ok(())
+        })?;
+        db.query_row(
+            "SELECT value FROM test_config WHERE setting = 'sealed'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 1);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_partition_column_next_to_bucket_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        // The partition column sits between user columns, the bucket column is appended.
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col0 text, col1 timestamp partition_column, col2 integer, add_bucket_column=_bucket, round=ceil)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test (col0, col1, col2) values ('a', '2024-02-01 10:15:30', 1), ('b', 1706786130, 2)",
+            (),
+        )?;
+        let rows = |sql: &str| -> sqlite3_ext::Result<Vec<Vec<Value>>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut rows = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                rows.push(
+                    (0..row.len())
+                        .map(|index| row[index].to_owned())
+                        .collect::<sqlite3_ext::Result<Vec<Value>>>()?,
+                );
+            }
+            Ok(rows)
+        };
+        let text = |value: &str| Value::Text(value.to_owned());
+        let integer = Value::Integer;
+
+        // The value is returned as written, not as the bucket it was rounded to. A timestamp
+        // column has text affinity, so the epoch is stored as text, as in a plain table.
+        assert_eq!(
+            rows("SELECT _bucket, col1, col2, col1 FROM test ORDER BY col2")?,
+            vec![
+                vec![
+                    integer(1706785200),
+                    text("2024-02-01 10:15:30"),
+                    integer(1),
+                    text("2024-02-01 10:15:30")
+                ],
+                vec![
+                    integer(1706788800),
+                    text("1706786130"),
+                    integer(2),
+                    text("1706786130")
+                ],
+            ]
+        );
+        assert_eq!(
+            rows("SELECT * FROM test WHERE col1 < '2024-02-01 10:30' ORDER BY col2")?,
+            vec![vec![
+                text("a"),
+                text("2024-02-01 10:15:30"),
+                integer(1),
+                integer(1706785200)
+            ]]
+        );
+        assert_eq!(
+            rows("SELECT col1 FROM test WHERE _bucket = 1706788800")?,
+            vec![vec![text("1706786130")]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_name_template() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, partition_name='p_{table}_{value}')";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('1970-01-01 01:30', 1), ('1969-12-31 23:30', 2)",
+            (),
+        )?;
+        let partitions = || -> sqlite3_ext::Result<String> {
+            db.query_row(
+                "SELECT group_concat(partition_table, ',') FROM \
+                 (SELECT partition_table FROM test_lookup ORDER BY partition_value)",
+                (),
+                |res| Ok(res.index_mut(0).get_str()?.to_owned()),
+            )
+        };
+        assert_eq!(partitions()?, "p_test_m3600,p_test_3600");
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE name IN ('p_test_m3600', 'p_test_3600')",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 2);
+                Ok(())
+            },
+        )?;
+        db.query_row(
+            "SELECT value FROM test_config WHERE setting = 'partition_name'",
+            (),
+            |res| {
+                assert_eq!(res.index_mut(0).get_str()?, "p_{table}_{value}");
+                Ok(())
+            },
+        )?;
+
+        // Renaming follows the template, dropping removes the partitions it named.
+        db.execute("ALTER TABLE test RENAME TO other", ())?;
+        db.query_row(
+            "SELECT group_concat(partition_table, ',') FROM \
+             (SELECT partition_table FROM other_lookup ORDER BY partition_value)",
+            (),
+            |res| {
+                assert_eq!(res.index_mut(0).get_str()?, "p_other_m3600,p_other_3600");
+                Ok(())
+            },
+        )?;
+        db.query_row("SELECT sum(col2) FROM other", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 3);
+            Ok(())
+        })?;
+        db.execute("DROP TABLE other", ())?;
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE name LIKE 'p\\_%' ESCAPE '\\'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 0);
+                Ok(())
+            },
+        )?;
+
+        // A root table from before the template was stored names partitions as before.
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        db.execute("DROP VIEW test_config", ())?;
+        db.execute("ALTER TABLE test_root DROP COLUMN partition_name", ())?;
+        let table = VirtualTable::connect(db, "test")?;
+        assert_eq!(table.partition_table_name(3600, 0), "test_3600");
+        db.insert("INSERT INTO test values ('1970-01-01 01:30', 1)", ())?;
+        assert_eq!(partitions()?, "test_3600");
+
+        let sql = "CREATE VIRTUAL TABLE bad USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, partition_name='p_{value}')";
+        let err = db.execute(sql, ()).unwrap_err();
+        assert!(
+            err.to_string().contains("expected {table} exactly once"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_rename() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text, lifetime 1 day, on_expired_insert quarantine)";
+        db.execute(sql, ())?;
+        db.execute("CREATE INDEX test_col2 ON test_template(col2)", ())?;
+        db.insert(
+            "INSERT INTO test values ('2999-02-01 10:00', 'a'), ('2999-02-01 11:00', 'b'), ('2024-02-01 10:00', 'old')",
+            (),
+        )?;
+        let tables = || -> sqlite3_ext::Result<Vec<String>> {
+            let mut stmt = db.prepare(
+                "SELECT name FROM sqlite_schema WHERE type IN ('table', 'view') ORDER BY name",
+            )?;
+            let mut tables = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                tables.push(row[0].get_str()?.to_owned());
+            }
+            Ok(tables)
+        };
+
+        db.execute("ALTER TABLE test RENAME TO \"new name\"", ())?;
+        assert_eq!(
+            tables()?,
+            vec![
+                "new name",
+                "new name_32474858400",
+                "new name_32474862000",
+                "new name_config",
+                "new name_lookup",
+                "new name_partitions",
+                "new name_quarantine",
+                "new name_root",
+                "new name_template",
+            ]
+        );
+        db.insert(
+            "INSERT INTO \"new name\" values ('2999-02-01 10:30', 'c'), ('2999-02-01 12:00', 'd')",
+            (),
+        )?;
+        db.query_row(
+            "SELECT group_concat(col2, '') FROM (SELECT col2 FROM \"new name\" ORDER BY col1)",
+            (),
+            |res| {
+                assert_eq!(res.index_mut(0).get_str()?, "acbd");
+                Ok(())
+            },
+        )?;
+        db.query_row(
+            "SELECT group_concat(partition_table || ':' || row_count, ',') FROM \"new name_partitions\"",
+            (),
+            |res| {
+                assert_eq!(
+                    res.index_mut(0).get_str()?,
+                    "new name_32474858400:2,new name_32474862000:1,new name_32474865600:1"
+                );
+                Ok(())
+            },
+        )?;
+        db.query_row("SELECT col2 FROM \"new name_quarantine\"", (), |res| {
+            assert_eq!(res.index_mut(0).get_str()?, "old");
+            Ok(())
+        })?;
+        // The template index still reaches new partitions.
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE type = 'index' AND tbl_name = 'new name_32474865600'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 1);
+                Ok(())
+            },
+        )?;
+        assert!(db.prepare("SELECT * FROM test").is_err());
+
+        // A rename clashing with an existing table is rolled back as a whole.
+        let before = tables()?;
+        db.execute("CREATE TABLE taken_lookup (a)", ())?;
+        assert!(db
+            .execute("ALTER TABLE \"new name\" RENAME TO taken", ())
+            .is_err());
+        db.execute("DROP TABLE taken_lookup", ())?;
+        assert_eq!(tables()?, before);
+        db.query_row("SELECT count(*) FROM \"new name\"", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 4);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_column_reads_null_in_old_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text)";
+        db.execute(sql, ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 'a')", ())?;
+        let table = VirtualTable::connect(db, "test")?;
+        table.add_column("col3 integer")?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:30', 'b', 2), ('2024-02-01 11:00', 'c', 3)",
+            (),
+        )?;
+        let mut stmt = db.prepare("SELECT col2, col3 FROM test ORDER BY col1")?;
+        let mut rows = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            rows.push((
+                row[0].get_str()?.to_owned(),
+                (!row[1].is_null()).then(|| row[1].get_i64()),
+            ));
+        }
+        assert_eq!(
+            rows,
+            vec![
+                ("a".to_owned(), None),
+                ("b".to_owned(), Some(2)),
+                ("c".to_owned(), Some(3))
+            ]
+        );
+
+        let table = VirtualTable::connect(db, "test")?;
+        let err = table.add_column("COL2 integer").unwrap_err();
+        assert!(err.to_string().contains("Column COL2 already exists"));
+        Ok(())
+    }
+    #[test]
+    fn test_add_column_is_rerunnable() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 'a'),('2024-02-01 11:00', 'b')",
+            (),
+        )?;
+        let table = VirtualTable::connect(db, "test")?;
+        let columns_named = |name: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            ["test_template", "test_1706781600", "test_1706785200"]
+                .iter()
+                .map(|table| {
+                    db.query_row(
+                        "SELECT count(*) FROM pragma_table_info(?) WHERE name = ?",
+                        [*table, name],
+                        |res| Ok(res.index(0).get_i64()),
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            table.add_column("col3 integer")?,
+            vec!["test_1706781600", "test_1706785200"]
+        );
+        assert_eq!(table.add_column("col3 integer")?, Vec::<String>::new());
+        assert_eq!(columns_named("col3")?, vec![1, 1, 1]);
+
+        // A run that stopped part way is completed by running it again.
+        db.execute("ALTER TABLE test_template ADD COLUMN col4 text", ())?;
+        db.execute("ALTER TABLE test_1706781600 ADD COLUMN col4 text", ())?;
+        assert_eq!(table.add_column("col4 text")?, vec!["test_1706785200"]);
+        assert_eq!(columns_named("col4")?, vec![1, 1, 1]);
+
+        // SQLite reconnects the virtual table after the schema change, while the `VirtualTable`
+        // connected before keeps inserting into the altered partitions.
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 12:00', 'c', 3, 'd')",
+            (),
+        )?;
+        db.query_row("SELECT '2024-02-01 11:30', 'e'", (), |row| {
+            table.insert(
+                1706785200,
+                &[row[0].as_ref(), row[1].as_ref()],
+                ConflictMode::Abort,
+            )
+        })?;
+        db.query_row(
+            "SELECT group_concat(col2, '') FROM test WHERE col3 IS NULL",
+            (),
+            |res| {
+                assert_eq!(res.index_mut(0).get_str()?, "abe");
+                Ok(())
+            },
+        )?;
+        let columns = VirtualTable::connect(db, "test")?.columns().to_string();
+        assert_eq!(
+            columns,
+            "\"col1\" TEXT, \"col2\" TEXT, \"col3\" INTEGER, \"col4\" TEXT"
+        );
+        assert_eq!(columns_named("col3")?, vec![1, 1, 1]);
+        db.query_row(
+            "SELECT count(*) FROM pragma_table_info('test_1706788800') WHERE name IN ('col3', 'col4')",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 2);
+                Ok(())
+            },
+        )?;
+
+        assert!(table.add_column("col5 timestamp partition_column").is_err());
+        assert!(table.add_column("col5").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_date_function_on_partition_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 'a'),('2024-02-02 00:00', 'b'),('2024-02-02 23:00', 'c'),('2024-02-03 00:00', 'd')",
+            (),
+        )?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<String>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_str()?.to_owned());
+            }
+            Ok(values)
+        };
+        // Not pruned, but SQLite still filters the rows correctly.
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE date(col1) = '2024-02-02' ORDER BY col2")?,
+            vec!["b", "c"]
+        );
+        // The recommended range rewrite returns the same rows.
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= '2024-02-02' AND col1 < '2024-02-03' ORDER BY col2")?,
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 > '2024-02-02 10:00' ORDER BY col2")?,
+            vec!["c", "d"]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_update_rows_within_partition() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer, col3 text)";
+        db.execute(sql, ())?;
+        // More rows in one partition than a single batched statement updates.
+        db.execute("BEGIN", ())?;
+        for minute in 0..1200 {
+            db.insert(
+                "INSERT INTO test (col1, col2, col3) VALUES (?, ?, 'row')",
+                sqlite3_ext::params![1706745600 + minute * 60, minute],
+            )?;
+        }
+        db.insert(
+            "INSERT INTO test (col1, col2, col3) VALUES ('2024-02-02 12:00', -1, 'row')",
+            (),
+        )?;
+        db.execute("COMMIT", ())?;
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+
+        let changed = db.execute(
+            "UPDATE test SET col3 = 'updated' WHERE col1 < '2024-02-02' AND col2 % 2 = 0",
+            (),
+        )?;
+        assert_eq!(changed, 600);
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col3 = 'updated'")?,
+            600
+        );
+        assert_eq!(
+            count("SELECT count(*) FROM test_1706745600 WHERE col3 = 'updated' AND col2 % 2 = 0")?,
+            600
+        );
+        assert_eq!(
+            count("SELECT count(*) FROM test_1706832000 WHERE col3 = 'row'")?,
+            1
+        );
+
+        // Values differing from row to row are assigned to the right rows.
+        db.execute("UPDATE test SET col2 = col2 * 10 WHERE col2 >= 1190", ())?;
+        assert_eq!(
+            count("SELECT sum(col2) FROM test WHERE col2 >= 1190")?,
+            (1190..1200).map(|value| value * 10).sum::<i64>()
+        );
+        assert_eq!(count("SELECT count(*) FROM test")?, 1201);
+
+        // Updates not yet written when the transaction rolls back are dropped.
+        db.execute("BEGIN", ())?;
+        db.execute("UPDATE test SET col3 = 'discarded'", ())?;
+        db.execute("ROLLBACK", ())?;
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col3 = 'discarded'")?,
+            0
+        );
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col3 = 'updated'")?,
+            600
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_update_moves_rows_to_new_partition() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        for hour in 0..24 {
+            for minute in [0, 15, 30] {
+                db.insert(
+                    &format!(
+                        "INSERT INTO test values ('2024-02-01 {:02}:{:02}', 'row')",
+                        hour, minute
+                    ),
+                    (),
+                )?;
+            }
+        }
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 24);
+
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-05 12:00' WHERE col1 < '2024-02-01 20:00'",
+            (),
+        )?;
+
+        assert_eq!(count("SELECT count(*) FROM test")?, 72);
+        assert_eq!(count("SELECT count(*) FROM test_1707134400")?, 60);
+        // The moved rows are gone from the partitions they used to live in.
+        for hour in 0..20 {
+            let partition = 1706745600 + hour * 3600;
+            assert_eq!(
+                count(&format!("SELECT count(*) FROM test_{}", partition))?,
+                0
+            );
+        }
+        assert_eq!(count("SELECT count(*) FROM test_1706817600")?, 3);
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col1 = '2024-02-05 12:00'")?,
+            60
+        );
+
+        // Inside a transaction the moved rows are visible to the next query, and a rollback
+        // leaves them where they were.
+        db.execute("BEGIN", ())?;
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-06 00:00' WHERE col1 = '2024-02-05 12:00'",
+            (),
+        )?;
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col1 = '2024-02-06 00:00'")?,
+            60
+        );
+        db.execute("ROLLBACK", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_1707134400")?, 60);
+        assert_eq!(count("SELECT count(*) FROM test")?, 72);
+        Ok(())
+    }
+    #[test]
+    fn test_update_moves_row_across_day_boundary() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 'b')", ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 23:30', 'a')", ())?;
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-02 00:30' WHERE col2 = 'a'",
+            (),
+        )?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<String>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_str()?.to_owned());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= '2024-02-02' AND col1 < '2024-02-03'")?,
+            vec!["a"]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 < '2024-02-02'")?,
+            vec!["b"]
+        );
+        assert_eq!(collect("SELECT col2 FROM test_1706745600")?, vec!["b"]);
+        assert_eq!(collect("SELECT col2 FROM test_1706832000")?, vec!["a"]);
+        Ok(())
+    }
+    #[test]
+    fn test_custom_partition_value_parser() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        // In-memory databases have no file.
+        crate::utils::register_partition_value_parser("", "custom", |value| {
+            value
+                .try_get_str()?
+                .strip_prefix("epoch+")
+                .and_then(|seconds| seconds.parse::<i64>().ok())
+                .ok_or_else(|| sqlite3_ext::Error::Module("not a custom timestamp".to_owned()))
+        })?;
+        // A table of the same name in another database file is left alone.
+        crate::utils::register_partition_value_parser("other.db", "custom", |_| {
+            Err(sqlite3_ext::Error::Module(
+                "parser of another database".to_owned(),
+            ))
+        })?;
+        let sql = "CREATE VIRTUAL TABLE custom USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert(
+            "INSERT INTO custom values ('epoch+1706781600', 'a'),('epoch+1706868000', 'b')",
+            (),
+        )?;
+        let mut stmt = db.prepare("SELECT partition_table FROM custom_lookup ORDER BY 1")?;
+        let mut partitions = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            partitions.push(row[0].get_str()?.to_owned());
+        }
+        assert_eq!(partitions, vec!["custom_1706745600", "custom_1706832000"]);
+        db.query_row(
+            "SELECT col2 FROM custom WHERE col1 = 'epoch+1706868000'",
+            (),
+            |res| {
+                assert_eq!(res.index_mut(0).get_str()?, "b");
+                Ok(())
+            },
+        )?;
+
+        // The parser follows the table when it is renamed.
+        db.execute("ALTER TABLE custom RENAME TO renamed", ())?;
+        db.insert("INSERT INTO renamed values ('epoch+1706954400', 'c')", ())?;
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM renamed_lookup", (), |row| Ok(
+                row[0].get_i64()
+            ))?,
+            3
+        );
+        assert!(!crate::utils::unregister_partition_value_parser(
+            "", "custom"
+        )?);
+        assert!(crate::utils::unregister_partition_value_parser(
+            "", "renamed"
+        )?);
+        assert!(crate::utils::unregister_partition_value_parser(
+            "other.db", "custom"
+        )?);
+        Ok(())
+    }
+    #[test]
+    fn test_limit_stops_before_later_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for hour in 0..6 {
+            for minute in 0..10 {
+                db.insert(
+                    &format!(
+                        "INSERT INTO test values ('2024-02-01 {:02}:{:02}', {})",
+                        hour,
+                        minute,
+                        hour * 10 + minute
+                    ),
+                    (),
+                )?;
+            }
+        }
+        // Partitions are only opened once the cursor reaches them, so a query satisfied by the
+        // oldest partitions never notices that the last one is gone.
+        db.execute("DROP TABLE test_1706763600", ())?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 < '2024-02-02' LIMIT 10")?,
+            (0..10).collect::<Vec<i64>>()
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test LIMIT 5 OFFSET 12")?,
+            (12..17).collect::<Vec<i64>>()
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col2 >= 20 LIMIT 3")?,
+            vec![20, 21, 22]
+        );
+        // Scanning past the dropped partition still reports the error.
+        assert!(collect("SELECT col2 FROM test").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_in_and_between_prune_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        for day in 1..=6 {
+            for hour in [0, 12] {
+                db.insert(
+                    &format!("INSERT INTO test values ('2024-02-0{day} {hour:02}:00', {day})"),
+                    (),
+                )?;
+            }
+        }
+        // Partitions that are opened although no value selects them fail the query.
+        db.execute("DROP TABLE test_1706832000", ())?;
+        db.execute("DROP TABLE test_1706918400", ())?;
+        db.execute("DROP TABLE test_1707004800", ())?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 IN ('2024-02-01', '2024-02-05') ORDER BY 1")?,
+            vec![1, 5]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 IN ('2024-02-01 12:00', 1707220800, 'not a time') ORDER BY 1")?,
+            vec![1, 6]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 IN ('2024-02-01', '2024-02-05') AND col1 >= '2024-02-02'")?,
+            vec![5]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 BETWEEN '2024-02-05' AND '2024-02-06 06:00' ORDER BY 1")?,
+            vec![5, 5, 6]
+        );
+        assert_eq!(
+            collect("SELECT count(*) FROM test WHERE col1 IN (SELECT '2024-02-05' UNION SELECT '2024-02-06 12:00')")?,
+            vec![2]
+        );
+        assert!(
+            collect("SELECT col2 FROM test WHERE col1 IN ('2024-02-01', '2024-02-03')").is_err()
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_null_and_not_equal_on_partition_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        for day in 1..=3 {
+            db.insert(
+                &format!("INSERT INTO test values ('2024-02-0{day} 12:00', {day})"),
+                (),
+            )?;
+        }
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col1 IS NOT NULL")?,
+            3
+        );
+        assert_eq!(
+            count("SELECT count(*) FROM test WHERE col1 != '2024-02-02 12:00'")?,
+            2
+        );
+
+        // The partition column is declared NOT NULL, so SQLite answers IS NULL without opening
+        // any partition, while opened partitions fail the query.
+        for partition in ["test_1706745600", "test_1706832000", "test_1706918400"] {
+            db.execute(&format!("DROP TABLE {partition}"), ())?;
+        }
+        assert_eq!(count("SELECT count(*) FROM test WHERE col1 IS NULL")?, 0);
+        assert!(count("SELECT count(*) FROM test WHERE col1 IS NOT NULL").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_order_by_partition_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 integer partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        for (day, hour) in [(3, 12), (1, 12), (2, 0), (1, 0), (3, 0), (2, 12)] {
+            let epoch = 1706745600 + (day - 1) * 86400 + hour * 3600;
+            db.insert(
+                &format!("INSERT INTO test values ({epoch}, {day}{hour:02})"),
+                (),
+            )?;
+        }
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        let sorts = |sql: &str| -> sqlite3_ext::Result<bool> {
+            let mut stmt = db.prepare(&format!("EXPLAIN QUERY PLAN {sql}"))?;
+            let mut sorts = false;
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                sorts |= row[3].get_str()?.contains("ORDER BY");
+            }
+            Ok(sorts)
+        };
+        let ascending = "SELECT col2 FROM test ORDER BY col1";
+        assert_eq!(collect(ascending)?, vec![100, 112, 200, 212, 300, 312]);
+        assert!(!sorts(ascending)?);
+        let descending = "SELECT col2 FROM test ORDER BY col1 DESC";
+        assert_eq!(collect(descending)?, vec![312, 300, 212, 200, 112, 100]);
+        assert!(!sorts(descending)?);
+        assert!(sorts("SELECT col2 FROM test ORDER BY col1, col2")?);
+        assert!(sorts("SELECT col2 FROM test ORDER BY col2")?);
+
+        // max() only reads the newest partition, a later value stored in an older one is not seen.
+        db.execute(
+            "INSERT INTO test_1706745600 (col1, col2) VALUES (1735603200, 5)",
+            (),
+        )?;
+        assert_eq!(
+            collect("SELECT max(col1) FROM test")?,
+            vec![1706745600 + 2 * 86400 + 12 * 3600]
+        );
+        db.execute("DELETE FROM test_1706745600 WHERE col2 = 5", ())?;
+        // min() likewise only reads the oldest partition.
+        db.execute("INSERT INTO test_1706918400 (col1, col2) VALUES (0, 5)", ())?;
+        assert_eq!(collect("SELECT min(col1) FROM test")?, vec![1706745600]);
+        db.execute("DELETE FROM test_1706918400 WHERE col2 = 5", ())?;
+
+        // The limit is reached before the last partition has to be opened.
+        db.execute("DROP TABLE test_1706918400", ())?;
+        assert_eq!(
+            collect("SELECT col2 FROM test ORDER BY col1 LIMIT 2 OFFSET 1")?,
+            vec![112, 200]
+        );
+        assert!(collect(ascending).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_order_by_mixed_format_partition_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        // Datetime text and epoch integers in the same partitions, SQL orders every integer
+        // before any text, whatever the time.
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 00:00', 1), (1706778000, 2), \
+             ('2024-02-02 00:00', 3), (1706896800, 4), ('2024-02-03 12:00', 5)",
+            (),
+        )?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        let sorted = collect("SELECT col2 FROM (SELECT col1, col2 FROM test) ORDER BY col1")?;
+        assert_eq!(sorted, vec![2, 4, 1, 3, 5]);
+        assert_eq!(collect("SELECT col2 FROM test ORDER BY col1")?, sorted);
+        let descending: Vec<i64> = sorted.iter().rev().copied().collect();
+        assert_eq!(
+            collect("SELECT col2 FROM test ORDER BY col1 DESC")?,
+            descending
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test ORDER BY col1 LIMIT 2")?,
+            vec![2, 4]
+        );
+        let max = db.query_row("SELECT max(col1) FROM test", (), |row| {
+            Ok(row[0].get_str()?.to_owned())
+        })?;
+        assert_eq!(max, "2024-02-03 12:00");
+        Ok(())
+    }
+    #[test]
+    fn test_epochs_near_i64_limits() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let hour = 3600;
+        let last_partition = i64::MAX / hour * hour;
+        db.execute("CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, lifetime 1 day)", ())?;
+        db.insert(
+            "INSERT INTO test (col1, col2) VALUES (?, 1)",
+            sqlite3_ext::params![i64::MAX - 10],
+        )?;
+        // The expiration is clamped rather than overflowing.
+        let (partition_value, expires_at) = db.query_row(
+            "SELECT partition_value, expires_at FROM test_lookup",
+            (),
+            |row| Ok((row[0].get_i64(), row[1].to_owned()?)),
+        )?;
+        assert_eq!(partition_value, last_partition);
+        assert_eq!(expires_at, Value::Integer(i64::MAX));
+        VirtualTable::connect(db, "test")?.recompute_expirations()?;
+        let expires_at = db.query_row("SELECT expires_at FROM test_lookup", (), |row| {
+            row[0].to_owned()
+        })?;
+        assert_eq!(expires_at, Value::Integer(i64::MAX));
+        let count = |sql: &str| db.query_row(sql, (), |row| Ok(row[0].get_i64()));
+        assert_eq!(
+            count(&format!(
+                "SELECT count(*) FROM test WHERE col1 < {}",
+                i64::MAX
+            ))?,
+            1
+        );
+        assert_eq!(
+            count(&format!(
+                "SELECT count(*) FROM test WHERE col1 > {}",
+                last_partition
+            ))?,
+            1
+        );
+
+        db.execute("CREATE VIRTUAL TABLE low USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, round=ceil)", ())?;
+        db.insert(
+            "INSERT INTO low (col1, col2) VALUES (?, 1), (?, 2)",
+            sqlite3_ext::params![i64::MIN, i64::MAX],
+        )?;
+        assert_eq!(
+            count(&format!(
+                "SELECT sum(col2) FROM low WHERE col1 <= {}",
+                i64::MIN / hour * hour
+            ))?,
+            1
+        );
+        assert_eq!(
+            count(&format!(
+                "SELECT sum(col2) FROM low WHERE col1 = {}",
+                i64::MAX
+            ))?,
+            2
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_interval_larger_than_query_range() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(365 days, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for (timestamp, value) in [
+            ("2023-06-01 00:00", 1),
+            ("2024-03-01 10:00", 2),
+            ("2024-03-01 12:00", 3),
+            ("2024-03-02 00:00", 4),
+            ("2025-06-01 00:00", 5),
+        ] {
+            db.insert(
+                &format!("INSERT INTO test values ('{timestamp}', {value})"),
+                (),
+            )?;
+        }
+        // Only the partition holding March 2024 may be scanned, reading either of the
+        // neighbouring partitions fails.
+        db.execute("DROP TABLE test_1671408000", ())?;
+        db.execute("DROP TABLE test_1734480000", ())?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= '2024-03-01' AND col1 < '2024-03-02'")?,
+            vec![2, 3]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 BETWEEN '2024-03-01' AND '2024-03-02'")?,
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 > '2023-06-01' AND col1 > '2024-03-01 11:00' AND col1 <= '2025-06-01' AND col1 <= '2024-03-01 23:00'")?,
+            vec![3]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 = '2024-03-02 00:00'")?,
+            vec![4]
+        );
+        assert!(collect("SELECT col2 FROM test WHERE col1 <= '2025-06-01'").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_expired_insert_rejected() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, lifetime 1 day)";
+        assert!(db.execute(sql, ()).is_ok());
+        let err = db
+            .insert("INSERT INTO test values ('2000-01-01 10:00', 1)", ())
+            .unwrap_err();
+        assert!(err.to_string().contains("expired"), "{}", err);
+        // Nothing is created for the rejected row.
+        db.query_row("SELECT count(*) FROM test_lookup", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 0);
+            Ok(())
+        })?;
+        db.insert("INSERT INTO test values ('2100-01-01 10:00', 2)", ())?;
+        db.query_row("SELECT count(*) FROM test", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 1);
+            Ok(())
+        })?;
+        Ok(())
+    }
+    #[test]
+    fn test_expired_partitions_are_invisible() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, lifetime 1 day)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert("INSERT INTO test values ('2100-01-01 10:00', 2)", ())?;
+        // A partition that expired without being dropped, e.g. because no retention job ran.
+        let expired = VirtualTable::connect(db, "test")?.get_partition(&946720800)?;
+        db.insert(
+            &format!("INSERT INTO {expired} values ('2000-01-01 10:00', 1)"),
+            (),
+        )?;
+        db.query_row("SELECT count(*), min(col2) FROM test", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 1);
+            assert_eq!(res.index(1).get_i64(), 2);
+            Ok(())
+        })?;
+        db.query_row(
+            "SELECT count(*) FROM test WHERE col1 < '2001-01-01'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 0);
+                Ok(())
+            },
+        )?;
+        db.query_row(&format!("SELECT count(*) FROM {expired}"), (), |res| {
+            assert_eq!(res.index(0).get_i64(), 1);
+            Ok(())
+        })?;
+        Ok(())
+    }
+    #[test]
+    fn test_expired_insert_quarantined() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, lifetime 1 day, on_expired_insert quarantine)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert("INSERT INTO test values ('2000-01-01 10:00', 1)", ())?;
+        db.insert("INSERT INTO test values ('2100-01-01 10:00', 2)", ())?;
+        db.query_row("SELECT col2 FROM test_quarantine", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 1);
+            Ok(())
+        })?;
+        // The quarantined row is not part of the partitioned table.
+        db.query_row("SELECT count(*), min(col2) FROM test", (), |res| {
+            assert_eq!(res.index(0).get_i64(), 1);
+            assert_eq!(res.index(1).get_i64(), 2);
+            Ok(())
+        })?;
+        db.execute("DROP TABLE test", ())?;
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE name = 'test_quarantine'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 0);
+                Ok(())
+            },
+        )?;
+
+        let sql = "CREATE VIRTUAL TABLE other USING partitioner(1 hour, col1 timestamp partition_column, on_expired_insert discard)";
+        assert!(db.execute(sql, ()).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_default_partition_rows_are_scanned() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, default_partition)";
+        db.execute(sql, ())?;
+        let sum = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        // Without rows in the default partition the table has none yet.
+        db.insert("INSERT INTO test values ('2024-02-01 00:10', 1)", ())?;
+        assert_eq!(sum("SELECT sum(col2) FROM test")?, 1);
+        db.insert(
+            "INSERT INTO test values (NULL, 2), ('not a time', 4), ('2024-02-01 01:10', 8)",
+            (),
+        )?;
+
+        assert_eq!(sum("SELECT count(*) FROM test")?, 4);
+        assert_eq!(sum("SELECT sum(col2) FROM test")?, 15);
+        assert_eq!(sum("SELECT sum(col2) FROM test WHERE col1 IS NULL")?, 2);
+        assert_eq!(
+            sum("SELECT sum(col2) FROM test WHERE col1 IS NOT NULL")?,
+            13
+        );
+        assert_eq!(sum("SELECT sum(col2) FROM test WHERE col2 > 1")?, 14);
+        // Rows without a time never match a time range.
+        assert_eq!(
+            sum("SELECT sum(col2) FROM test WHERE col1 >= '2024-02-01 01:00'")?,
+            8
+        );
+        let ordered = db.query_row(
+            "SELECT group_concat(col2) FROM (SELECT col2 FROM test ORDER BY col1)",
+            (),
+            |res| Ok(res.index_mut(0).get_str()?.to_owned()),
+        )?;
+        assert_eq!(ordered, "2,1,8,4");
+
+        db.execute("DELETE FROM test WHERE col1 IS NULL", ())?;
+        db.execute("UPDATE test SET col2 = 16 WHERE col1 = 'not a time'", ())?;
+        assert_eq!(sum("SELECT count(*) FROM test_default")?, 1);
+        assert_eq!(sum("SELECT col2 FROM test_default")?, 16);
+        assert_eq!(sum("SELECT sum(col2) FROM test")?, 25);
+        // A row given a time moves to the partition of its interval.
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-01 00:20' WHERE col2 = 16",
+            (),
+        )?;
+        assert_eq!(sum("SELECT count(*) FROM test_default")?, 0);
+        assert_eq!(sum("SELECT sum(col2) FROM test_1706745600")?, 17);
+        db.execute("INSERT INTO test values (NULL, 32)", ())?;
+        db.execute("DELETE FROM test", ())?;
+        assert_eq!(sum("SELECT count(*) FROM test_default")?, 0);
+        assert_eq!(sum("SELECT count(*) FROM test")?, 0);
+        Ok(())
+    }
+    #[test]
+    fn test_default_partition() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, add_bucket_column=bucket, default_partition)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.execute(
+            "INSERT INTO test values (NULL, 1, NULL), ('not a time', 2, NULL), \
+             ('2024-02-01 00:10', 3, NULL)",
+            (),
+        )?;
+        let mut stmt = db.prepare("SELECT col1, col2, bucket FROM test_default ORDER BY col2")?;
+        stmt.query(())?;
+        let mut rows = Vec::new();
+        while let Some(row) = stmt.next()? {
+            rows.push((
+                row[0].to_owned()?,
+                row[1].get_i64(),
+                row[2].value_type() == ValueType::Null,
+            ));
+        }
+        drop(stmt);
+        assert_eq!(
+            rows,
+            vec![
+                (Value::Null, 1, true),
+                (Value::Text("not a time".to_owned()), 2, true)
+            ]
+        );
+        // Unlike the quarantine table, the default partition is part of the partitioned table.
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM test", (), |res| Ok(res
+                .index(0)
+                .get_i64()))?,
+            3
+        );
+        assert_eq!(
+            db.query_row(
+                "SELECT value FROM test_config WHERE setting = 'default_partition'",
+                (),
+                |res| Ok(res.index(0).get_i64())
+            )?,
+            1
+        );
+
+        db.execute("ALTER TABLE test RENAME TO renamed", ())?;
+        db.insert("INSERT INTO renamed values (NULL, 4, NULL)", ())?;
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM renamed_default", (), |res| Ok(res
+                .index(0)
+                .get_i64()))?,
+            3
+        );
+        db.execute("DROP TABLE renamed", ())?;
+        assert_eq!(
+            db.query_row(
+                "SELECT count(*) FROM sqlite_schema WHERE name LIKE '%default'",
+                (),
+                |res| Ok(res.index(0).get_i64())
+            )?,
+            0
+        );
+
+        // Without the option such rows still fail the insert.
+        let sql = "CREATE VIRTUAL TABLE other USING partitioner(1 hour, col1 timestamp partition_column, col2 int)";
+        assert!(db.execute(sql, ()).is_ok());
+        assert!(db.insert("INSERT INTO other values (NULL, 1)", ()).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_strict_types() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        // By default only the partition column is checked, like SQLite does not check types.
+        db.execute(
+            "CREATE VIRTUAL TABLE lax USING partitioner(1 hour, col1 timestamp partition_column, col2 int)",
+            (),
+        )?;
+        db.insert("INSERT INTO lax values ('2024-02-01 00:10', 'abc')", ())?;
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM lax WHERE col2 = 'abc'", (), |res| Ok(
+                res.index(0).get_i64()
+            ))?,
+            1
+        );
+
+        db.execute(
+            "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, col3 float, strict)",
+            (),
+        )?;
+        let err = db
+            .insert(
+                "INSERT INTO test values ('2024-02-01 00:10', 'abc', 1.5)",
+                (),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("Expected column type"), "{err}");
+        // NULL fits every column and an integer fits a float column.
+        db.insert("INSERT INTO test values ('2024-02-01 00:10', 1, NULL)", ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 00:20', NULL, 2)", ())?;
+        assert!(db
+            .execute("UPDATE test SET col2 = 'abc' WHERE col2 = 1", ())
+            .is_err());
+        db.execute("UPDATE test SET col3 = 2.5 WHERE col2 = 1", ())?;
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM test WHERE col3 = 2.5", (), |res| Ok(
+                res.index(0).get_i64()
+            ))?,
+            1
+        );
+        assert_eq!(
+            db.query_row(
+                "SELECT value FROM test_config WHERE setting = 'strict'",
+                (),
+                |res| Ok(res.index(0).get_i64())
+            )?,
+            1
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_bucket_column() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, add_bucket_column=bucket)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert(
+            "INSERT INTO test (col1, col2) values ('2024-02-01 00:30', 1)",
+            (),
+        )?;
+        // A value given for the bucket column is replaced by the partition value.
+        db.insert(
+            "INSERT INTO test (col1, col2, bucket) values ('2024-02-01 01:15', 2, 42)",
+            (),
+        )?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<(i64, i64)>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push((row[0].get_i64(), row[1].get_i64()));
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT col2, bucket FROM test ORDER BY col2")?,
+            vec![(1, 1706745600), (2, 1706749200)]
+        );
+        assert_eq!(
+            collect("SELECT col2, bucket FROM test_1706749200")?,
+            vec![(2, 1706749200)]
+        );
+        assert_eq!(
+            collect("SELECT col2, bucket FROM test WHERE bucket = 1706745600")?,
+            vec![(1, 1706745600)]
+        );
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE type = 'index' AND name IN ('test_1706745600_bucket', 'test_1706749200_bucket')",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 2);
+                Ok(())
+            },
+        )?;
+
+        // Moving a row to another partition moves its bucket along, writes to it are ignored.
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-01 02:05' WHERE col2 = 1",
+            (),
+        )?;
+        db.execute("UPDATE test SET bucket = 7 WHERE col2 = 2", ())?;
+        assert_eq!(
+            collect("SELECT col2, bucket FROM test ORDER BY col2")?,
+            vec![(1, 1706752800), (2, 1706749200)]
+        );
+
+        let sql = "CREATE VIRTUAL TABLE other USING partitioner(1 hour, col1 timestamp partition_column, col2 integer, add_bucket_column=col2)";
+        assert!(db.execute(sql, ()).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_config_view() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(2 hours, col1 timestamp partition_column, col2 int, col3 varchar, lifetime 3 days)";
+        assert!(db.execute(sql, ()).is_ok());
+        let mut stmt = db.prepare("SELECT setting, quote(value) FROM test_config")?;
+        let mut config = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            config.push((row[0].get_str()?.to_owned(), row[1].get_str()?.to_owned()));
+        }
+        let expected = [
+            ("interval", "7200"),
+            ("lifetime", "259200"),
+            ("partition_column", "'col1'"),
+            ("on_expired_insert", "'reject'"),
+            ("bucket_column", "NULL"),
+            ("sealed", "0"),
+            ("round", "'floor'"),
+            ("index_format", "'json'"),
+            ("default_partition", "0"),
+            ("strict", "0"),
+            ("strategy", "'interval'"),
+            ("key_columns", "NULL"),
+            ("partition_name", "'{table}_{value}'"),
+            ("parallel_scan", "NULL"),
+            ("list_values", "NULL"),
+            ("max_statements", "NULL"),
+            ("column.col1", "'TEXT'"),
+            ("column.col2", "'INTEGER'"),
+            ("column.col3", "'TEXT'"),
+        ];
+        assert_eq!(
+            config,
+            expected
+                .iter()
+                .map(|(setting, value)| (setting.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        // The view follows changes to the stored configuration and goes away with the table.
+        VirtualTable::connect(db, "test")?.reinterval(86400)?;
+        db.query_row(
+            "SELECT value FROM test_config WHERE setting = 'interval'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 86400);
+                Ok(())
+            },
+        )?;
+        db.execute("DROP TABLE test", ())?;
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE name = 'test_config'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 0);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_negative_epochs() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int)";
+        assert!(db.execute(sql, ()).is_ok());
+        for (timestamp, value) in [
+            ("1969-12-31 22:59:59", 1),
+            ("1969-12-31 23:00:00", 2),
+            ("1969-12-31 23:59:59", 3),
+            ("1970-01-01 00:00:00", 4),
+        ] {
+            db.insert(
+                &format!("INSERT INTO test values ('{timestamp}', {value})"),
+                (),
+            )?;
+        }
+        let mut stmt = db.prepare(
+            "SELECT partition_value, partition_table, row_count FROM test_partitions ORDER BY partition_value",
+        )?;
+        let mut partitions = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            partitions.push((
+                row[0].get_i64(),
+                row[1].get_str()?.to_owned(),
+                row[2].get_i64(),
+            ));
+        }
+        assert_eq!(
+            partitions,
+            [
+                (-7200, "test_m7200".to_owned(), 1),
+                (-3600, "test_m3600".to_owned(), 2),
+                (0, "test_0".to_owned(), 1),
+            ]
+        );
+        db.query_row(
+            "SELECT count(*), sum(col2) FROM test WHERE col1 >= '1969-12-31 23:00:00' AND col1 < '1970-01-01 00:00:00'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 2);
+                assert_eq!(res.index(1).get_i64(), 5);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_partitioner_partitions_function() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, lifetime 1 day)";
+        assert!(db.execute(sql, ()).is_ok());
+        for timestamp in ["2100-01-01 11:15", "2100-01-01 10:00", "2100-01-01 10:30"] {
+            db.insert(&format!("INSERT INTO test values ('{timestamp}', 1)"), ())?;
+        }
+        let mut stmt = db.prepare("SELECT * FROM partitioner_partitions('test')")?;
+        let mut partitions = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            partitions.push((
+                row[0].get_i64(),
+                row[1].get_str()?.to_owned(),
+                row[2].get_i64(),
+            ));
+        }
+        assert_eq!(
+            partitions,
+            [
+                (4102480800, "test_4102480800".to_owned(), 4102480800 + 86400),
+                (4102484400, "test_4102484400".to_owned(), 4102484400 + 86400),
+            ]
+        );
+
+        // Without a lifetime nothing expires.
+        let sql =
+            "CREATE VIRTUAL TABLE other USING partitioner(1 hour, col1 timestamp partition_column)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert("INSERT INTO other values ('2024-02-01 00:30')", ())?;
+        db.query_row(
+            "SELECT partition_table = 'other_1706745600', expires_at IS NULL FROM partitioner_partitions WHERE table_name = 'other'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 1);
+                assert_eq!(res.index(1).get_i64(), 1);
+                Ok(())
+            },
+        )?;
+        assert!(db
+            .query_row(
+                "SELECT count(*) FROM partitioner_partitions('missing')",
+                (),
+                |_| Ok(())
+            )
+            .is_err());
+        assert!(db.prepare("SELECT * FROM partitioner_partitions").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_partitions_view() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 int, lifetime 1 day)";
+        assert!(db.execute(sql, ()).is_ok());
+        for (timestamp, value) in [
+            ("2100-01-01 10:00", 1),
+            ("2100-01-01 10:30", 2),
+            ("2100-01-01 11:15", 3),
+        ] {
+            db.insert(
+                &format!("INSERT INTO test values ('{timestamp}', {value})"),
+                (),
+            )?;
+        }
+        let mut stmt = db.prepare(
+            "SELECT partition_value, partition_table, row_count FROM test_partitions ORDER BY partition_value",
+        )?;
+        let mut partitions = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            partitions.push((
+                row[0].get_i64(),
+                row[1].get_str()?.to_owned(),
+                row[2].get_i64(),
+            ));
+        }
+        assert_eq!(
+            partitions,
+            [
+                (4102480800, "test_4102480800".to_owned(), 2),
+                (4102484400, "test_4102484400".to_owned(), 1),
+            ]
+        );
+
+        // The view can be filtered on expiry and joined against the partitioned table.
+        db.query_row(
+            "SELECT p.partition_value, sum(t.col2) FROM test_partitions p \
+             JOIN test t ON CAST(strftime('%s', t.col1) AS INTEGER) - p.partition_value BETWEEN 0 AND 3599 \
+             WHERE p.expires_at < (SELECT max(expires_at) FROM test_partitions) \
+             GROUP BY p.partition_value",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 4102480800);
+                assert_eq!(res.index(1).get_i64(), 3);
+                Ok(())
+            },
+        )?;
+        db.execute("DROP TABLE test", ())?;
+        db.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE name = 'test_partitions'",
+            (),
+            |res| {
+                assert_eq!(res.index(0).get_i64(), 0);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_drop_partitions_before() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for hour in 0..6 {
+            db.insert(
+                &format!(
+                    "INSERT INTO test values ('2024-02-01 {:02}:00', {}),('2024-02-01 {:02}:45', {})",
+                    hour,
+                    hour * 2,
+                    hour,
+                    hour * 2 + 1
+                ),
+                (),
+            )?;
+        }
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        assert_eq!(count("SELECT count(*) FROM test")?, 12);
+
+        // The partition holding the cutoff is kept.
+        assert_eq!(
+            count("SELECT partitioner_drop_before('test', '2024-02-01 03:30')")?,
+            3
+        );
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 3);
+        assert_eq!(count("SELECT count(*) FROM test")?, 6);
+        assert_eq!(count("SELECT min(col2) FROM test")?, 6);
+        assert_eq!(
+            count("SELECT count(*) FROM sqlite_schema WHERE name = 'test_1706745600'")?,
+            0
+        );
+        assert_eq!(
+            count("SELECT partitioner_drop_before('test', '2024-02-01 03:30')")?,
+            0
+        );
+
+        // Late rows for a dropped interval get a fresh partition.
+        db.insert("INSERT INTO test values ('2024-02-01 00:10', 100)", ())?;
+        assert_eq!(count("SELECT count(*) FROM test")?, 7);
+        assert_eq!(count("SELECT count(*) FROM test_1706745600")?, 1);
+
+        assert!(db
+            .query_row(
+                "SELECT partitioner_drop_before('missing', '2024-02-01')",
+                (),
+                |_| Ok(())
+            )
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_drop_partition() -> sqlite3_ext::Result<()> {
+        use std::ops::Bound;
+
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 00:10', 1), ('2024-02-01 01:10', 2), ('2024-02-01 01:20', 3)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        let table = VirtualTable::connect(db, "test")?;
+        table.drop_partition(1706749200)?;
+
+        let partitions =
+            table
+                .lookup()
+                .get_partitions_by_range(db, &Bound::Unbounded, &Bound::Unbounded)?;
+        assert_eq!(partitions, vec![(1706745600, "test_1706745600".to_owned())]);
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 1);
+        assert_eq!(
+            count("SELECT count(*) FROM sqlite_schema WHERE name = 'test_1706749200'")?,
+            0
+        );
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 1);
+
+        let err = table.drop_partition(1706749200).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("No partition exists for partition value 1706749200"),
+            "{}",
+            err
+        );
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 1);
+
+        // A later row for the interval gets a fresh partition.
+        db.insert("INSERT INTO test values ('2024-02-01 01:30', 4)", ())?;
+        assert_eq!(count("SELECT count(*) FROM test_1706749200")?, 1);
+        Ok(())
+    }
+    #[test]
+    fn test_merge_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 00:10', 1), ('2024-02-01 01:10', 2), \
+             ('2024-02-01 01:50', 3), ('2024-02-01 02:10', 4), ('2024-02-01 03:10', 5)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        let table = VirtualTable::connect(db, "test")?;
+        assert_eq!(table.merge_partitions(1706745600, 1706752800)?, 2);
+
+        assert_eq!(
+            count("SELECT count(*) FROM sqlite_schema WHERE name IN ('test_1706749200', 'test_1706752800')")?,
+            0
+        );
+        assert_eq!(
+            count("SELECT merged_through FROM test_lookup WHERE partition_value = 1706745600")?,
+            1706752800
+        );
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test_1706745600")?, 4);
+        assert_eq!(count("SELECT count(*) FROM test")?, 5);
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 15);
+        // Ranges reaching into a merged interval read the merged partition, row by row.
+        assert_eq!(
+            count("SELECT sum(col2) FROM test WHERE col1 >= '2024-02-01 01:30' AND col1 < '2024-02-01 03:00'")?,
+            7
+        );
+        assert_eq!(
+            count(
+                "SELECT sum(col2) FROM test WHERE col1 IN ('2024-02-01 02:10', '2024-02-01 03:10')"
+            )?,
+            9
+        );
+        assert_eq!(
+            table.counts_by_bucket(1706745600, 1706760000)?,
+            vec![
+                (1706745600, 1),
+                (1706749200, 2),
+                (1706752800, 1),
+                (1706756400, 1)
+            ]
+        );
+
+        // Rows for a merged interval are written to the merged partition.
+        db.insert("INSERT INTO test values ('2024-02-01 01:30', 6)", ())?;
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-01 02:30' WHERE col2 = 5",
+            (),
+        )?;
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test_1706745600")?, 6);
+        assert_eq!(count("SELECT count(*) FROM test_1706756400")?, 0);
+        assert!(table.drop_partition(1706749200).is_err());
+        assert_eq!(table.merge_partitions(1706749200, 1706752800)?, 0);
+
+        // The merged partition is only dropped once its last interval is before the cutoff.
+        assert_eq!(table.drop_partitions_before(1706754600)?, 0);
+        assert_eq!(table.drop_partitions_before(1706756400)?, 1);
+        assert_eq!(count("SELECT count(*) FROM test")?, 0);
+        Ok(())
+    }
+    #[test]
+    fn test_sealed_table_keeps_its_partitions() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 00:10', 1), ('2024-02-01 01:10', 2)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        VirtualTable::connect(db, "test")?.seal()?;
+        let table = VirtualTable::connect(db, "test")?;
+
+        let err = table.merge_partitions(1706745600, 1706749200).unwrap_err();
+        assert!(err.to_string().contains("Table test is sealed"), "{}", err);
+        let err = table.drop_partition(1706749200).unwrap_err();
+        assert!(err.to_string().contains("Table test is sealed"), "{}", err);
+        let err = table.split_partition(1706749200, 600).unwrap_err();
+        assert!(err.to_string().contains("Table test is sealed"), "{}", err);
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test_1706749200")?, 1);
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 3);
+        Ok(())
+    }
+    #[test]
+    fn test_split_partition() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer)";
+        db.execute(sql, ())?;
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 00:10', 1), ('2024-02-01 01:10', 2), \
+             ('2024-02-01 01:50', 3), ('2024-02-01 05:10', 4), ('2024-02-02 00:10', 5)",
+            (),
+        )?;
+        let count = |sql: &str| db.query_row(sql, (), |res| Ok(res.index(0).get_i64()));
+        let table = VirtualTable::connect(db, "test")?;
+        for finer_interval in [7000, 86400, 0] {
+            assert!(table.split_partition(1706745600, finer_interval).is_err());
+        }
+        assert!(table.split_partition(1706659200, 3600).is_err());
+        assert_eq!(table.split_partition(1706745600, 3600)?, 3);
+
+        assert_eq!(
+            count("SELECT count(*) FROM sqlite_schema WHERE name IN ('test_1706745600', 'test_1706749200', 'test_1706763600', 'test_1706745600_split')")?,
+            3
+        );
+        assert_eq!(count("SELECT count(*) FROM test_lookup")?, 4);
+        assert_eq!(
+            count("SELECT count(*) FROM test_lookup WHERE split_from = 1706745600 AND split_interval = 3600")?,
+            3
+        );
+        assert_eq!(count("SELECT count(*) FROM test_1706749200")?, 2);
+        assert_eq!(count("SELECT count(*) FROM test")?, 5);
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 15);
+        // Queries on the day read the partitions split from it, row by row.
+        assert_eq!(
+            count("SELECT sum(col2) FROM test WHERE col1 >= '2024-02-01 01:30' AND col1 < '2024-02-02'")?,
+            7
+        );
+        assert_eq!(
+            count("SELECT sum(col2) FROM test WHERE col1 < '2024-02-01 06:00'")?,
+            10
+        );
+        assert_eq!(
+            count(
+                "SELECT sum(col2) FROM test WHERE col1 IN ('2024-02-01 05:10', '2024-02-02 00:10')"
+            )?,
+            9
+        );
+        let ordered = db.query_row(
+            "SELECT group_concat(col2) FROM (SELECT col2 FROM test ORDER BY col1 DESC)",
+            (),
+            |res| Ok(res.index_mut(0).get_str()?.to_owned()),
+        )?;
+        assert_eq!(ordered, "5,4,3,2,1");
+
+        // Rows for the day are written to the partition of their hour.
+        db.insert("INSERT INTO test values ('2024-02-01 03:30', 6)", ())?;
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-01 07:15' WHERE col2 = 5",
+            (),
+        )?;
+        assert_eq!(count("SELECT count(*) FROM test_1706756400")?, 1);
+        assert_eq!(count("SELECT count(*) FROM test_1706770800")?, 1);
+        assert_eq!(count("SELECT count(*) FROM test_1706832000")?, 0);
+        assert_eq!(
+            count("SELECT count(*) FROM test_lookup WHERE split_from = 1706745600")?,
+            5
+        );
+        assert_eq!(
+            table.counts_by_bucket(1706745600, 1706918400)?,
+            vec![(1706745600, 6), (1706832000, 0)]
+        );
+        assert!(table.merge_partitions(1706745600, 1706832000).is_err());
+        assert!(table.split_partition(1706749200, 600).is_err());
+
+        // The partitions of the finer interval are dropped as soon as their hour is before the
+        // cutoff.
+        assert_eq!(table.drop_partitions_before(1706756400)?, 2);
+        assert_eq!(count("SELECT count(*) FROM test")?, 3);
+        assert_eq!(count("SELECT sum(col2) FROM test")?, 15);
+        Ok(())
+    }
+    #[test]
+    fn test_projection_reads_only_used_columns() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer, col3 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.insert(
+            "INSERT INTO test values ('2024-02-01 10:00', 1, 'a'),('2024-02-01 11:00', 2, 'b')",
+            (),
+        )?;
+        db.execute(
+            "CREATE INDEX test_1706745600_col2 ON test_1706745600(col2)",
+            (),
+        )?;
+
+        let rowid_mapper = RwLock::default();
+        let meta_table =
+            PartitionMetaTable::new(VirtualTable::connect(db, "test")?, db, &rowid_mapper);
+        let where_clauses: WhereClauses = [(
+            "partition_table".to_string(),
+            vec![WhereClause::new("col2".to_string(), ConstraintOp::GT, 0)],
+        )]
+        .into_iter()
+        .collect();
+        // Only col2 is used by the statement.
+        let idx_str =
+            QueryPlan::new(String::new(), where_clauses, 0b010).to_index_str(IndexFormat::Json)?;
+        let mut args = db.query("SELECT 0", ())?;
+        let arg = args.next()?.unwrap().index_mut(0).as_mut();
+
+        let mut cursor = meta_table.open()?;
+        cursor.filter(0, Some(&idx_str), &mut [arg])?;
+        let mut values_read = 0;
+        while !cursor.eof() {
+            let row = cursor
+                .current_partition
+                .as_ref()
+                .unwrap()
+                .get_current_row()
+                .unwrap();
+            values_read += (1..row.len())
+                .filter(|index| !row.index(*index).as_ref().is_null())
+                .count();
+            cursor.next()?;
+        }
+        assert_eq!(values_read, 2);
+
+        // The partition query can be answered from the index alone.
+        let query = cursor.partition_filter.query("test_1706745600");
+        let mut stmt = db.prepare(&format!("EXPLAIN QUERY PLAN {}", query))?;
+        let mut plan = String::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            plan.push_str(row[3].get_str()?);
+        }
+        assert!(plan.contains("COVERING INDEX"), "{}", plan);
+
+        // Through SQL the projected columns still come back in the right positions.
+        let mut stmt = db.prepare("SELECT col3, col2 FROM test ORDER BY col2")?;
+        let mut values = Vec::new();
+        stmt.query(())?;
+        while let Some(row) = stmt.next()? {
+            values.push((row[0].get_str()?.to_owned(), row[1].get_i64()));
+        }
+        assert_eq!(values, vec![("a".to_owned(), 1), ("b".to_owned(), 2)]);
+        Ok(())
+    }
+    #[test]
+    fn test_query_plan_shows_partition_pruning() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        let query_plan = |sql: &str| -> sqlite3_ext::Result<String> {
+            let mut stmt = db.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+            let mut plan = String::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                plan.push_str(row[3].get_str()?);
+            }
+            Ok(plan)
+        };
+        let plan = query_plan("SELECT * FROM test WHERE col1 > '2024-02-01' AND col2 = 'a'")?;
+        assert!(plan.contains("INDEX 1:"), "{}", plan);
+        assert!(
+            plan.contains(r#""scan":"partitions where col1 > ?""#),
+            "{}",
+            plan
+        );
+
+        let plan = query_plan("SELECT * FROM test WHERE col2 = 'a'")?;
+        assert!(plan.contains("INDEX 0:"), "{}", plan);
+        assert!(plan.contains(r#""scan":"all partitions""#), "{}", plan);
+        Ok(())
+    }
+    #[test]
+    fn test_numeric_text_partition_values() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for (col1, col2) in [
+            ("'1.7067456e9'", 1),      // 2024-02-01 00:00
+            ("'1706749200.75'", 2),    // 2024-02-01 01:00
+            ("'1.70675208E+9'", 3),    // 2024-02-01 01:48
+            ("'2024-02-01 01:59'", 4), // the same partition, written as a datetime
+        ] {
+            db.insert(&format!("INSERT INTO test values ({}, {})", col1, col2), ())?;
+        }
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT partition_value FROM test_lookup ORDER BY partition_value")?,
+            vec![1706745600, 1706749200]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test_1706749200 ORDER BY col2")?,
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= '2024-02-01 01:30' ORDER BY col2")?,
+            vec![3, 4]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_partition_value_from_expression() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for (col1, col2) in [
+            ("strftime('%s', '2024-02-01 00:30')", 1), // text epoch
+            ("CAST(strftime('%s', '2024-02-01 01:15') AS INTEGER)", 2),
+            ("1706745600 + 2 * 3600 + 59", 3),
+            ("datetime('2024-02-01', '+3 hours', '+10 minutes')", 4),
+            ("unixepoch('2024-02-01 03:59:59')", 5),
+        ] {
+            db.insert(&format!("INSERT INTO test values ({}, {})", col1, col2), ())?;
+        }
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT partition_value FROM test_lookup ORDER BY partition_value")?,
+            vec![1706745600, 1706749200, 1706752800, 1706756400]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test_1706756400 ORDER BY col2")?,
+            vec![4, 5]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= '2024-02-01 01:00' AND col1 < '2024-02-01 03:00' ORDER BY col2")?,
+            vec![2, 3]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_mixed_text_and_integer_partition_values() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        for (col1, col2) in [
+            ("'2024-02-01 00:30'", 1),
+            ("1706749200", 2), // 2024-02-01 01:00
+            ("'2024-02-01 01:45'", 3),
+            ("1706754600", 4), // 2024-02-01 02:30
+            ("'2024-02-01 02:15'", 5),
+        ] {
+            db.insert(&format!("INSERT INTO test values ({}, {})", col1, col2), ())?;
+        }
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT partition_value FROM test_lookup ORDER BY partition_value")?,
+            vec![1706745600, 1706749200, 1706752800]
+        );
+        assert_eq!(collect("SELECT count(*) FROM test_1706749200")?, vec![2]);
+        assert_eq!(collect("SELECT count(*) FROM test_1706752800")?, vec![2]);
+
+        // Bounds of either kind compare by time against values of either kind.
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= '2024-02-01 01:00' AND col1 < '2024-02-01 02:20' ORDER BY col2")?,
+            vec![2, 3, 5]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 >= 1706749200 AND col1 < 1706754000 ORDER BY col2")?,
+            vec![2, 3, 5]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 > 1706749200 ORDER BY col2")?,
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 = '2024-02-01 01:00:00'")?,
+            vec![2]
+        );
+        assert_eq!(
+            collect("SELECT col2 FROM test WHERE col1 = 1706747400")?,
+            vec![1]
+        );
+        assert_eq!(
+            collect(
+                "SELECT col2 FROM test WHERE col1 <= '2024-02-01 02:15' ORDER BY col2 LIMIT 4"
+            )?,
+            vec![1, 2, 3, 5]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyword_and_spaced_names() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        db.execute(
+            "CREATE VIRTUAL TABLE \"my data\" USING partitioner(1 hour, \"group\" timestamp partition_column, order integer, add_bucket_column=bucket)",
+            (),
+        )?;
+        db.execute(
+            "INSERT INTO \"my data\" (\"group\", \"order\") VALUES ('2024-02-01 00:30', 1), ('2024-02-01 01:30', 2)",
+            (),
+        )?;
+        let collect = |sql: &str| -> sqlite3_ext::Result<Vec<i64>> {
+            let mut stmt = db.prepare(sql)?;
+            let mut values = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                values.push(row[0].get_i64());
+            }
+            Ok(values)
+        };
+        assert_eq!(
+            collect("SELECT \"order\" FROM \"my data\" WHERE \"group\" >= '2024-02-01 01:00'")?,
+            vec![2]
+        );
+        db.execute(
+            "UPDATE \"my data\" SET \"group\" = '2024-02-01 01:45' WHERE \"order\" = 1",
+            (),
+        )?;
+        assert_eq!(
+            collect("SELECT \"order\" FROM \"my data_1706749200\" ORDER BY \"order\"")?,
+            vec![1, 2]
+        );
+        assert_eq!(
+            collect("SELECT row_count FROM \"my data_partitions\" ORDER BY partition_value")?,
+            vec![0, 2]
+        );
+        db.execute("DELETE FROM \"my data\" WHERE \"order\" = 2", ())?;
+        assert_eq!(collect("SELECT bucket FROM \"my data\"")?, vec![1706749200]);
+        db.execute("DROP TABLE \"my data\"", ())?;
+        assert_eq!(
+            collect("SELECT count(*) FROM sqlite_schema WHERE name LIKE 'my data%'")?,
+            vec![0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_on_conflict() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 day, col1 timestamp partition_column, col2 integer, col3 text)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.execute("CREATE UNIQUE INDEX test_col2 ON test_template(col2)", ())?;
+        db.insert("INSERT INTO test values ('2024-02-01 10:00', 1, 'a')", ())?;
+        assert_eq!(
+            db.query_row(
+                "SELECT count(*) FROM sqlite_schema WHERE type = 'index' AND tbl_name = 'test_1706745600'",
+                (),
+                |row| Ok(row[0].get_i64()),
+            )?,
+            1
+        );
+        let rows = || -> sqlite3_ext::Result<Vec<(i64, String)>> {
+            let mut stmt = db.prepare("SELECT col2, col3 FROM test ORDER BY col1")?;
+            let mut rows = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                rows.push((row[0].get_i64(), row[1].get_str()?.to_owned()));
+            }
+            Ok(rows)
+        };
+
+        assert!(db
+            .insert("INSERT INTO test values ('2024-02-01 11:00', 1, 'b')", ())
+            .is_err());
+        db.insert(
+            "INSERT OR IGNORE INTO test values ('2024-02-01 11:00', 1, 'b'), ('2024-02-01 12:00', 2, 'c')",
+            (),
+        )?;
+        assert_eq!(rows()?, vec![(1, "a".to_owned()), (2, "c".to_owned())]);
+
+        db.insert(
+            "INSERT OR REPLACE INTO test values ('2024-02-01 13:00', 1, 'd')",
+            (),
+        )?;
+        assert_eq!(rows()?, vec![(2, "c".to_owned()), (1, "d".to_owned())]);
+
+        // The index is per partition, another day may hold the same value.
+        db.insert("INSERT INTO test values ('2024-02-02 10:00', 1, 'e')", ())?;
+        assert_eq!(rows()?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_stats() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        let sql = "CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, col2 integer)";
+        assert!(db.execute(sql, ()).is_ok());
+        db.execute("CREATE UNIQUE INDEX test_col2 ON test_template(col2)", ())?;
+        let stats = || VirtualTable::connect(db, "test")?.partition_stats();
+        // The counts kept in the lookup table, without scanning the partitions.
+        let kept = || -> sqlite3_ext::Result<Vec<Option<i64>>> {
+            let mut stmt =
+                db.prepare("SELECT row_count FROM test_lookup ORDER BY partition_value")?;
+            let mut counts = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                counts.push(match row[0].value_type() {
+                    ValueType::Null => None,
+                    _ => Some(row[0].get_i64()),
+                });
+            }
+            Ok(counts)
+        };
+
+        db.execute(
+            "INSERT INTO test values ('2024-02-01 00:00', 1), ('2024-02-01 00:10', 2), \
+             ('2024-02-01 00:20', 3), ('2024-02-01 01:00', 4), ('2024-02-01 01:10', 5)",
+            (),
+        )?;
+        assert_eq!(
+            stats()?,
+            vec![
+                (1706745600, "test_1706745600".to_owned(), 3),
+                (1706749200, "test_1706749200".to_owned(), 2)
+            ]
+        );
+        assert_eq!(kept()?, vec![Some(3), Some(2)]);
+
+        // Deletes, and moves into a partition created by the same statement.
+        db.execute("DELETE FROM test WHERE col2 = 1", ())?;
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-01 02:00' WHERE col2 = 5",
+            (),
+        )?;
+        assert_eq!(kept()?, vec![Some(2), Some(1), Some(1)]);
+
+        // A partition created inside a transaction is counted once it commits.
+        db.execute("BEGIN", ())?;
+        db.execute(
+            "INSERT INTO test values ('2024-02-01 05:00', 6), ('2024-02-01 05:30', 7)",
+            (),
+        )?;
+        db.execute("SAVEPOINT inner", ())?;
+        db.execute("INSERT INTO test values ('2024-02-01 05:40', 8)", ())?;
+        db.execute("ROLLBACK TO inner", ())?;
+        db.execute("COMMIT", ())?;
+        assert_eq!(kept()?, vec![Some(2), Some(1), Some(1), Some(2)]);
+
+        // Rolled back rows are not counted, nor is a partition that was rolled back.
+        db.execute("BEGIN", ())?;
+        db.execute("INSERT INTO test values ('2024-02-01 00:30', 9)", ())?;
+        db.execute("INSERT INTO test values ('2024-02-01 07:00', 10)", ())?;
+        db.execute("ROLLBACK", ())?;
+        assert_eq!(kept()?, vec![Some(2), Some(1), Some(1), Some(2)]);
+
+        // Ignored rows are not counted, replaced ones leave the count to be counted again.
+        db.execute(
+            "INSERT OR IGNORE INTO test values ('2024-02-01 00:40', 2), ('2024-02-01 00:50', 11)",
+            (),
+        )?;
+        db.execute(
+            "INSERT OR REPLACE INTO test values ('2024-02-01 01:20', 4)",
+            (),
+        )?;
+        assert_eq!(kept()?, vec![Some(3), None, Some(1), Some(2)]);
+        assert_eq!(
+            stats()?
+                .into_iter()
+                .map(|(_, _, rows)| rows)
+                .collect::<Vec<i64>>(),
+            vec![3, 1, 1, 2]
+        );
+        assert_eq!(kept()?, vec![Some(3), Some(1), Some(1), Some(2)]);
+        assert_eq!(
+            db.query_row("SELECT count(*) FROM test", (), |row| Ok(row[0].get_i64()))?,
+            7
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rowid_named_columns() -> sqlite3_ext::Result<()> {
+        let rusq_conn = init_rusq_conn();
+        let db = setup_db(&rusq_conn);
+        assert!(init(db).is_ok());
+        db.execute("CREATE VIRTUAL TABLE test USING partitioner(1 hour, col1 timestamp partition_column, rowid integer, col2 text)", ())?;
+        db.execute(
+            "INSERT INTO test values ('2024-02-01 00:30', 10, 'a'), ('2024-02-01 00:40', 10, 'b'), ('2024-02-01 00:50', 10, 'c')",
+            (),
+        )?;
+        let rows = || -> sqlite3_ext::Result<Vec<(String, i64, String)>> {
+            let mut stmt = db.prepare("SELECT col1, rowid, col2 FROM test ORDER BY col1")?;
+            let mut rows = Vec::new();
+            stmt.query(())?;
+            while let Some(row) = stmt.next()? {
+                rows.push((
+                    row[0].get_str()?.to_owned(),
+                    row[1].get_i64(),
+                    row[2].get_str()?.to_owned(),
+                ));
+            }
+            Ok(rows)
+        };
+
+        // The column shares its value between rows, the rows are still told apart.
+        db.execute("UPDATE test SET col2 = 'x' WHERE col2 = 'b'", ())?;
+        db.execute("DELETE FROM test WHERE col2 = 'a'", ())?;
+        db.execute(
+            "UPDATE test SET col1 = '2024-02-01 01:50' WHERE col2 = 'c'",
+            (),
+        )?;
+        assert_eq!(
+            rows()?,
+            vec![
+                ("2024-02-01 00:40".to_owned(), 10, "x".to_owned()),
+                ("2024-02-01 01:50".to_owned(), 10, "c".to_owned()),
+            ]
+        );
+
+        db.execute("CREATE VIRTUAL TABLE other USING partitioner(1 hour, col1 timestamp partition_column, row_id integer)", ())?;
+        db.execute(
+            "INSERT INTO other values ('2024-02-01 00:30', 7), ('2024-02-01 00:40', 7)",
+            (),
+        )?;
+        db.execute("DELETE FROM other WHERE col1 = '2024-02-01 00:30'", ())?;
+        assert_eq!(
+            db.query_row("SELECT count(*), sum(row_id) FROM other", (), |row| {
+                Ok((row[0].get_i64(), row[1].get_i64()))
+            })?,
+            (1, 7)
+        );
+
+        let err = db
+            .execute("CREATE VIRTUAL TABLE hidden USING partitioner(1 hour, col1 timestamp partition_column, rowid integer, _rowid_ integer, oid integer)", ())
+            .unwrap_err();
+        assert!(err.to_string().contains("hide the rowid"));
+        Ok(())
+    }
 }
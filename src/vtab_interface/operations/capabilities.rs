@@ -0,0 +1,135 @@
+use sqlite3_ext::{Connection, FromValue, SQLITE_VERSION};
+
+use crate::error::TableError;
+
+/// The optional features of the SQLite library the module runs on, as far as the module
+/// depends on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The version of the library, encoded as `major * 1000000 + minor * 1000 + patch`.
+    pub version: i32,
+    /// Whether the `dbstat` virtual table is available, which sizes are computed with.
+    pub dbstat: bool,
+    /// Whether SQLite was compiled with `SQLITE_ENABLE_UPDATE_DELETE_LIMIT`, which
+    /// `DELETE ... LIMIT` needs.
+    pub update_delete_limit: bool,
+    /// Whether the connection runs in defensive mode, which refuses writes to the schema and
+    /// to shadow tables.
+    pub defensive: bool,
+}
+
+impl Capabilities {
+    /// The first version calling `xShadowName`, which marks shadow tables read-only in
+    /// defensive mode.
+    pub const SHADOW_NAME_VERSION: i32 = 3_026_000;
+
+    /// Returns whether SQLite recognizes the shadow tables of the module.
+    pub fn shadow_names(&self) -> bool {
+        self.version >= Self::SHADOW_NAME_VERSION
+    }
+
+    /// Fails with a `TableError::Unsupported` naming `feature` unless `supported` holds.
+    ///
+    /// Parameters:
+    /// - `supported`: One of the capabilities.
+    /// - `feature`: What the capability is needed for, for the error message.
+    ///
+    /// Returns:
+    /// - `Ok(())` if the capability is present.
+    pub fn require(supported: bool, feature: &str) -> Result<(), TableError> {
+        if supported {
+            Ok(())
+        } else {
+            Err(TableError::Unsupported(feature.to_owned()))
+        }
+    }
+}
+
+/// Probes the SQLite library and connection for the features listed in [`Capabilities`].
+///
+/// Nothing is changed by the probes. Defensive mode is detected by preparing a write to
+/// `sqlite_schema` with `writable_schema` turned on, which only defensive mode refuses.
+///
+/// Parameters:
+/// - `db`: The connection to probe.
+///
+/// Returns:
+/// - The detected capabilities, or an error if a probe fails unexpectedly.
+pub fn detect_capabilities(db: &Connection) -> sqlite3_ext::Result<Capabilities> {
+    let dbstat = db.prepare("SELECT 1 FROM dbstat LIMIT 0").is_ok();
+    // Builds without compile option diagnostics cannot tell, the option is assumed missing.
+    let update_delete_limit = db
+        .query_row(
+            "SELECT count(*) FROM pragma_compile_options \
+             WHERE compile_options = 'ENABLE_UPDATE_DELETE_LIMIT'",
+            (),
+            |row| Ok(row[0].get_i64() > 0),
+        )
+        .unwrap_or(false);
+    Ok(Capabilities {
+        version: SQLITE_VERSION.as_i32(),
+        dbstat,
+        update_delete_limit,
+        defensive: is_defensive(db)?,
+    })
+}
+
+/// Returns whether the connection refuses writes to the schema even with `writable_schema`
+/// turned on, i.e. runs in defensive mode. The write is only prepared, never run, and
+/// `writable_schema` is restored afterwards.
+fn is_defensive(db: &Connection) -> sqlite3_ext::Result<bool> {
+    let writable_schema =
+        db.query_row(
+            "PRAGMA writable_schema",
+            (),
+            |row| Ok(row[0].get_i64() != 0),
+        )?;
+    if !writable_schema {
+        db.execute("PRAGMA writable_schema = ON", ())?;
+    }
+    let defensive = db
+        .prepare("UPDATE sqlite_schema SET sql = sql WHERE 0")
+        .is_err();
+    if !writable_schema {
+        db.execute("PRAGMA writable_schema = OFF", ())?;
+    }
+    Ok(defensive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection as RusqConn;
+
+    #[test]
+    fn test_detect_capabilities() -> sqlite3_ext::Result<()> {
+        let rusq_conn = RusqConn::open_in_memory().unwrap();
+        let db = Connection::from_rusqlite(&rusq_conn);
+        let capabilities = detect_capabilities(db)?;
+        assert_eq!(capabilities.version, SQLITE_VERSION.as_i32());
+        assert!(capabilities.shadow_names());
+        // The bundled SQLite is built with dbstat.
+        assert!(capabilities.dbstat);
+        db.execute("CREATE TABLE t (a)", ())?;
+        assert_eq!(
+            capabilities.update_delete_limit,
+            db.prepare("DELETE FROM t LIMIT 1").is_ok()
+        );
+        assert!(!capabilities.defensive);
+        // Probing leaves the schema read-only.
+        assert_eq!(
+            db.query_row("PRAGMA writable_schema", (), |row| Ok(row[0].get_i64()))?,
+            0
+        );
+
+        db.db_config_defensive(true)?;
+        assert!(detect_capabilities(db)?.defensive);
+
+        let err = Capabilities::require(false, "DELETE ... LIMIT").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "DELETE ... LIMIT is not supported on this SQLite build"
+        );
+        Ok(())
+    }
+}
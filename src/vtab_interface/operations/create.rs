@@ -1,14 +1,14 @@
-use std::borrow::Borrow;
-
 use crate::error::TableError;
 use crate::expiration::LifetimeColumn;
 use crate::shadow_tables::interface::VirtualTable;
 use crate::shadow_tables::PartitionValue;
-use crate::utils::parse_interval;
+use crate::utils::{parse_interval, parse_list_values};
+use crate::BucketRounding;
 use crate::ColumnDeclaration;
 use crate::ColumnDeclarations;
 use crate::PartitionColumn;
-use sqlite3_ext::Connection;
+use crate::TableOptions;
+use sqlite3_ext::{Connection, FallibleIteratorMut, FromValue, ValueType};
 extern crate sqlite3_ext;
 
 /// Connects to an existing virtual table by name.
@@ -30,46 +30,95 @@ pub fn connect_to_virtual_table<'a>(
     VirtualTable::connect(db, table_name)
 }
 
-/// Creates a new virtual table within the database, based on the provided arguments.
-///
-/// This function processes the arguments to define the structure and behavior of the virtual table,
-/// including its name, interval for partitioning, and column definitions. It also ensures that a
-/// partition column is specified and matches the expected data type.
+/// The configuration of a partitioned table parsed from the arguments of a CREATE VIRTUAL TABLE
+/// statement, ready to be passed on to [`VirtualTable::create`].
+#[derive(Debug, Clone)]
+pub struct CreateTableArgs {
+    /// The name of the virtual table.
+    pub table_name: String,
+    /// The declared columns, followed by the bucket column if one was requested.
+    pub columns: ColumnDeclarations,
+    /// The name of the partition column.
+    pub partition_column: String,
+    /// The partition interval in seconds, the number of partitions if `options.strategy` is hash
+    /// partitioning, or `0` for list partitioning.
+    pub interval: i64,
+    /// The remaining settings, such as the lifetime of each partition.
+    pub options: TableOptions,
+}
+
+/// Parses and validates the arguments of a CREATE VIRTUAL TABLE statement without touching the
+/// database, e.g. to lint a statement before running it.
 ///
 /// Parameters:
-/// - `db`: A reference to the active database connection.
-/// - `args`: A slice of string slices representing the arguments required for creating the virtual table.
-///   Expected order: [module, database_name, table_name, interval_col, column_args...].
+/// - `args`: The arguments as passed to the module, see [`create_virtual_table`].
 ///
 /// Returns:
-/// - On success, a `VirtualTable` instance representing the newly created virtual table.
-/// - On failure, a `TableError` indicating issues such as parsing errors or missing partition column.
-pub fn create_virtual_table<'a>(
-    db: &'a Connection,
-    args: &[&str],
-) -> Result<VirtualTable<'a>, TableError> {
+/// - On success, the parsed configuration.
+/// - On failure, the first `TableError` found, such as an invalid interval, column declaration,
+///   lifetime or option, or a missing partition column.
+pub fn validate_create_args(args: &[&str]) -> Result<CreateTableArgs, TableError> {
+    if args.len() < 4 {
+        return Err(TableError::ParseInterval(
+            "Expected a partition interval such as '1 hour' followed by the columns".to_owned(),
+        ));
+    }
     let _module = args[0];
     let _database_name = args[1];
     let table_name = args[2];
     let interval_col = args[3];
-    let column_args = &args[4..];
-    let mut columns: ColumnDeclarations = ColumnDeclarations::from_iter(column_args);
+    let mut options = TableOptions::default();
+    let mut column_args = Vec::new();
+    for arg in &args[4..] {
+        if !options.apply_argument(arg)? {
+            column_args.push(*arg);
+        }
+    }
+    // Unlike `ColumnDeclarations::from_iter`, a declaration that cannot be parsed is an error
+    // rather than a column that silently goes missing.
+    let mut columns = ColumnDeclarations(
+        column_args
+            .iter()
+            .map(|arg| ColumnDeclaration::try_from(*arg))
+            .collect::<Result<Vec<_>, _>>()?,
+    );
     let mut lifetime_column_index: Option<usize> = None;
     for (index, column) in columns.0.iter().enumerate() {
         if column.is_lifetime_column() {
             lifetime_column_index = Some(index);
-            println!("lifetime column: {:#?}", index);
-
             break;
         }
     }
-    let lifetime_column: Option<LifetimeColumn> = match lifetime_column_index {
-        Some(index) => Some(columns.0.remove(index)),
-        None => None,
-    };
+    let lifetime_column: Option<LifetimeColumn> =
+        lifetime_column_index.map(|index| columns.0.remove(index));
     // columns.0.remove(index)
-    let interval = parse_interval(interval_col)?;
-    let lifetime: Option<i64> = lifetime_column.and_then(|column| column.default_value());
+    let interval = match PartitionValue::from_argument(interval_col) {
+        Some(strategy) => {
+            // Hash and list partitions do not cover a time range, so they cannot expire or be
+            // rounded.
+            if lifetime_column.is_some() || options.round != BucketRounding::default() {
+                return Err(TableError::InvalidOption(format!(
+                    "A lifetime or round=<rounding> needs a partition interval, not '{}'",
+                    interval_col.trim()
+                )));
+            }
+            let (strategy, interval) = strategy?;
+            options.strategy = strategy;
+            interval
+        }
+        None => parse_interval(interval_col).map_err(|err| {
+            // A valid column declaration in the interval position means the interval was left
+            // out.
+            match ColumnDeclaration::try_from(interval_col) {
+                Ok(_) => TableError::ParseInterval(format!(
+                    "Expected a partition interval such as '1 hour' as the first argument, found the column declaration '{}'. Is the interval missing?",
+                    interval_col
+                )),
+                Err(_) => err,
+            }
+        })?,
+    };
+    options.lifetime = lifetime_column.and_then(|column| column.default_value());
     let partition_column: ColumnDeclaration =
         match PartitionColumn::from_iter(columns.clone()).column_def() {
             Some(col) => Ok(col),
@@ -79,13 +128,371 @@ pub fn create_virtual_table<'a>(
         }?
         .clone();
     PartitionValue::try_from(partition_column.data_type())?;
+    if let Some(values) = PartitionValue::list_values_argument(interval_col) {
+        options.list_values = parse_list_values(values, partition_column.data_type())?;
+    }
+    if options.strategy != PartitionValue::Interval
+        && partition_column.data_type() == &ValueType::Float
+    {
+        return Err(TableError::PartitionColumn(format!(
+            "{} partitioning needs an integer or text partition column, '{}' is declared float",
+            options.strategy.as_str(),
+            partition_column.get_name()
+        )));
+    }
+    // Further partition columns make the key composite, each of their values gets partitions of
+    // its own, as under list partitioning.
+    options.key_columns = columns
+        .0
+        .iter()
+        .filter(|column| column.is_partition_column())
+        .skip(1)
+        .map(|column| match column.data_type() {
+            ValueType::Text | ValueType::Integer => Ok(column.get_name().to_owned()),
+            _ => Err(TableError::PartitionColumn(format!(
+                "A partition column after the first needs to be declared integer or text, '{}' is declared {}",
+                column.get_name(),
+                column.get_type().to_lowercase()
+            ))),
+        })
+        .collect::<Result<Vec<String>, TableError>>()?;
+    if let Some(column) = columns
+        .0
+        .iter()
+        .find(|column| column.is_primary_key() && column.get_name() != partition_column.get_name())
+    {
+        return Err(TableError::ColumnDeclaration(format!(
+            "Only the partition column can be declared as primary key, found '{}'",
+            column.get_name()
+        )));
+    }
+    if let Some(bucket_column) = &options.bucket_column {
+        if columns
+            .0
+            .iter()
+            .any(|column| column.get_name().eq_ignore_ascii_case(bucket_column))
+        {
+            return Err(TableError::InvalidOption(format!(
+                "The bucket column '{}' is already declared as a column",
+                bucket_column
+            )));
+        }
+        columns.0.push(ColumnDeclaration::new(
+            bucket_column.clone().into(),
+            ValueType::Integer,
+        ));
+    }
+    columns.require_rowid_name()?;
 
-    Ok(VirtualTable::create(
-        db,
-        table_name,
+    Ok(CreateTableArgs {
+        table_name: table_name.to_owned(),
         columns,
-        partition_column.get_name().to_string(),
+        partition_column: partition_column.get_name().to_string(),
         interval,
-        lifetime,
+        options,
+    })
+}
+
+/// Creates a new virtual table within the database, based on the provided arguments.
+///
+/// This function processes the arguments to define the structure and behavior of the virtual table,
+/// including its name, interval for partitioning, and column definitions. It also ensures that a
+/// partition column is specified and matches the expected data type. The arguments are checked
+/// by [`validate_create_args`] before anything is written, and so are the names of the tables
+/// and views derived from the table name, see [`check_name_conflicts`].
+///
+/// Parameters:
+/// - `db`: A reference to the active database connection.
+/// - `args`: A slice of string slices representing the arguments required for creating the virtual table.
+///   Expected order: [module, database_name, table_name, interval_col, column_args...]. In place
+///   of the interval, `hash <n>` spreads the rows over `n` partitions by a hash of the partition
+///   column, and `list` gives every value of the partition column a partition of its own.
+///   Options such as `on_expired_insert reject|quarantine`, `add_bucket_column=<name>`,
+///   `round=floor|ceil|nearest`, `index_format=json|binary`, `partition_name='<template>'`,
+///   `parallel_scan=<workers>`, `max_statements=<statements>`, `default_partition` or `strict`
+///   may appear among the column arguments.
+///
+/// Returns:
+/// - On success, a `VirtualTable` instance representing the newly created virtual table.
+/// - On failure, a `TableError` indicating issues such as parsing errors or missing partition column.
+pub fn create_virtual_table<'a>(
+    db: &'a Connection,
+    args: &[&str],
+) -> Result<VirtualTable<'a>, TableError> {
+    let args = validate_create_args(args)?;
+    check_name_conflicts(db, &args.table_name)?;
+    Ok(VirtualTable::create(
+        db,
+        &args.table_name,
+        args.columns,
+        args.partition_column,
+        args.interval,
+        args.options,
     )?)
 }
+
+/// Checks that none of the names the virtual table derives from its own, see
+/// [`VirtualTable::derived_names`], is taken by a table, view, index or trigger of the database.
+/// SQLite compares these names without regard to case.
+///
+/// Parameters:
+/// - `db`: A reference to the active database connection.
+/// - `table_name`: The name of the virtual table to create.
+///
+/// Returns:
+/// - `Ok(())` if every derived name is free.
+/// - `TableError::NameConflict` naming the objects in the way otherwise.
+pub fn check_name_conflicts(db: &Connection, table_name: &str) -> Result<(), TableError> {
+    let names = VirtualTable::derived_names(table_name);
+    let placeholders = vec!["lower(?)"; names.len()].join(", ");
+    let mut stmt = db.prepare(&format!(
+        "SELECT type, name FROM sqlite_schema WHERE lower(name) IN ({}) ORDER BY name",
+        placeholders
+    ))?;
+    stmt.query(names.iter().map(String::as_str).collect::<Vec<&str>>())?;
+    let mut existing = Vec::new();
+    while let Some(row) = stmt.next()? {
+        let kind = row[0].get_str()?.to_owned();
+        existing.push(format!("{} {}", kind, row[1].get_str()?));
+    }
+    if existing.is_empty() {
+        Ok(())
+    } else {
+        Err(TableError::NameConflict {
+            table: table_name.to_owned(),
+            existing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args<'a>(arguments: &[&'a str]) -> Vec<&'a str> {
+        ["partitioner", "main", "test"]
+            .into_iter()
+            .chain(arguments.iter().copied())
+            .collect()
+    }
+
+    fn validation_error(arguments: &[&str]) -> String {
+        validate_create_args(&args(arguments))
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_validate_create_args() {
+        let parsed = validate_create_args(&args(&[
+            "2 hours",
+            "col1 timestamp partition_column",
+            "col2 int",
+            "lifetime 3 days",
+            "on_expired_insert quarantine",
+            "add_bucket_column=bucket",
+            "default_partition",
+            "STRICT",
+            "parallel_scan = 4",
+            "max_statements=16",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.table_name, "test");
+        assert_eq!(parsed.partition_column, "col1");
+        assert_eq!(parsed.interval, 7200);
+        assert_eq!(
+            parsed.options,
+            TableOptions {
+                lifetime: Some(259200),
+                on_expired_insert: crate::ExpiredInsertPolicy::Quarantine,
+                bucket_column: Some("bucket".to_owned()),
+                round: crate::BucketRounding::Floor,
+                index_format: crate::IndexFormat::Json,
+                default_partition: true,
+                strict: true,
+                strategy: PartitionValue::Interval,
+                key_columns: Vec::new(),
+                partition_name: crate::PartitionNaming::default(),
+                parallel_scan: Some(4),
+                list_values: Vec::new(),
+                max_statements: Some(16),
+            }
+        );
+        assert_eq!(
+            parsed.columns.to_string(),
+            "\"col1\" TEXT, \"col2\" INTEGER, \"bucket\" INTEGER"
+        );
+        for workers in ["0", "-1", "four", ""] {
+            assert!(
+                validation_error(&[
+                    "2 hours",
+                    "col1 timestamp partition_column",
+                    &format!("parallel_scan={workers}"),
+                ])
+                .contains("Expected a positive number of workers"),
+                "{}",
+                workers
+            );
+            assert!(validation_error(&[
+                "2 hours",
+                "col1 timestamp partition_column",
+                &format!("max_statements={workers}"),
+            ])
+            .contains("Expected a positive number of statements"));
+        }
+    }
+
+    #[test]
+    fn test_validate_create_args_compact_interval() {
+        for interval in ["1h", "1 hour", "60m"] {
+            let parsed =
+                validate_create_args(&args(&[interval, "col1 timestamp partition_column"]))
+                    .unwrap();
+            assert_eq!(parsed.interval, 3600, "{}", interval);
+        }
+    }
+
+    #[test]
+    fn test_validate_create_args_hash() {
+        let parsed = validate_create_args(&args(&[
+            "HASH 8",
+            "id integer partition_column",
+            "col2 text",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.interval, 8);
+        assert_eq!(parsed.options.strategy, PartitionValue::Hash);
+        let parsed =
+            validate_create_args(&args(&[" List ", "region text partition_column"])).unwrap();
+        assert_eq!(parsed.interval, 0);
+        assert_eq!(parsed.options.strategy, PartitionValue::List);
+        assert!(parsed.options.list_values.is_empty());
+        // The declared values are read as the type of the partition column.
+        let parsed =
+            validate_create_args(&args(&["list(1, '2')", "region int partition_column"])).unwrap();
+        assert_eq!(parsed.options.strategy, PartitionValue::List);
+        assert_eq!(parsed.options.list_values, vec!["1", "2"]);
+        let parsed = validate_create_args(&args(&[
+            "LIST ('EU', 'US')",
+            "region text partition_column",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.options.list_values, vec!["EU", "US"]);
+        for list in ["list ('EU'", "list ()", "list ('EU') 'US'", "list EU"] {
+            assert!(
+                validation_error(&[list, "region text partition_column"])
+                    .contains("Expected the values of list partitioning"),
+                "{}",
+                list
+            );
+        }
+        assert!(
+            validation_error(&["list ('EU')", "region int partition_column"])
+                .contains("Expected the values of list partitioning")
+        );
+
+        for partitions in ["hash", "hash 0", "hash -2", "hash eight", "hash 8 16"] {
+            assert!(
+                validation_error(&[partitions, "id integer partition_column"])
+                    .contains("Expected a positive number of partitions"),
+                "{}",
+                partitions
+            );
+        }
+        assert!(
+            validation_error(&["hash 8", "id integer partition_column", "lifetime 3 days"])
+                .contains("needs a partition interval")
+        );
+        assert!(
+            validation_error(&["hash 8", "id integer partition_column", "round=ceil"])
+                .contains("needs a partition interval")
+        );
+        assert!(
+            validation_error(&["list", "region text partition_column", "lifetime 3 days"])
+                .contains("needs a partition interval")
+        );
+        assert!(
+            validation_error(&["list 2", "region text partition_column"])
+                .contains("Parse interval error")
+        );
+    }
+
+    #[test]
+    fn test_validate_create_args_composite_key() {
+        let parsed = validate_create_args(&args(&[
+            "1 day",
+            "day timestamp partition_column",
+            "tenant text partition_column",
+            "region int partition_column",
+            "col2 text",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.partition_column, "day");
+        assert_eq!(parsed.options.key_columns, vec!["tenant", "region"]);
+        assert!(
+            validate_create_args(&args(&["1 day", "day timestamp partition_column"]))
+                .unwrap()
+                .options
+                .key_columns
+                .is_empty()
+        );
+        assert!(validation_error(&[
+            "1 day",
+            "day timestamp partition_column",
+            "score float partition_column"
+        ])
+        .contains("needs to be declared integer or text"));
+        assert!(validation_error(&[
+            "1 day",
+            "day timestamp partition_column",
+            "tenant text partition_column primary key"
+        ])
+        .contains("Only the partition column can be declared as primary key"));
+    }
+
+    #[test]
+    fn test_validate_create_args_errors() {
+        assert!(validation_error(&[]).contains("Expected a partition interval"));
+        assert!(
+            validation_error(&["col1 timestamp partition_column", "col2 int"])
+                .contains("Is the interval missing?")
+        );
+        assert!(
+            validation_error(&["1 fortnight", "col1 timestamp partition_column"])
+                .contains("Parse interval error")
+        );
+        assert!(validation_error(&["1 hour", "col1 timestamp", "col2 int"])
+            .contains("Could not find column with identifier partition_column"));
+        assert!(validation_error(&["1 hour", "col1 blob partition_column"])
+            .contains("Supported types for partition column"));
+        assert!(validation_error(&["hash 4", "col1 float partition_column"])
+            .contains("needs an integer or text partition column"));
+        assert!(
+            validation_error(&["1 hour", "col1 timestamp partition_column", "col2 widget"])
+                .contains("Cannot parse input type")
+        );
+        assert!(validation_error(&[
+            "1 hour",
+            "col1 timestamp partition_column",
+            "lifetime 3 fortnights"
+        ])
+        .contains("Parse interval error"));
+        assert!(validation_error(&[
+            "1 hour",
+            "col1 timestamp partition_column",
+            "on_expired_insert ignore"
+        ])
+        .contains("Invalid option"));
+        assert!(validation_error(&[
+            "1 hour",
+            "col1 timestamp partition_column",
+            "col2 int",
+            "add_bucket_column=col2"
+        ])
+        .contains("already declared as a column"));
+        assert!(
+            validation_error(&["1 hour", "col1 timestamp partition_column", "co;l2 int"])
+                .contains("Invalid column name \"co;l2\"")
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::utils::validation::quote_identifier;
+
 /// Prepares a SQL DELETE statement for a specified partition and number of columns.
 ///
 /// This function constructs a DELETE statement to remove rows from a given partition
@@ -8,18 +10,24 @@
 /// - `partition_name`: The name of the partition (table) from which rows are to be deleted.
 /// - `num_columns`: The number of columns in the partition, determining the number of placeholders
 ///   in the DELETE statement's WHERE clause.
+/// - `rowid_name`: The name the rowid of the partition is addressed by.
 ///
 /// Returns:
 /// - A string containing the SQL DELETE statement ready for execution with the appropriate
 ///   number of placeholders for binding ROWID values.
-pub fn prepare_delete_statement(partition_name: &str, num_columns: usize) -> String {
-    let placeholders = std::iter::repeat("?")
-        .take(num_columns)
+pub fn prepare_delete_statement(
+    partition_name: &str,
+    num_columns: usize,
+    rowid_name: &str,
+) -> String {
+    let placeholders = std::iter::repeat_n("?", num_columns)
         .collect::<Vec<&str>>()
         .join(",");
     format!(
-        "DELETE FROM {} WHERE ROWID IN ({})",
-        partition_name, placeholders
+        "DELETE FROM {} WHERE {} IN ({})",
+        quote_identifier(partition_name),
+        rowid_name,
+        placeholders
     )
 }
 
@@ -32,10 +40,15 @@ pub fn prepare_delete_statement(partition_name: &str, num_columns: usize) -> Str
 ///
 /// Parameters:
 /// - `partition_name`: The name of the partition (table) from which rows are to be deleted.
+/// - `rowid_name`: The name the rowid of the partition is addressed by.
 ///
 /// Returns:
 /// - A string containing the SQL DELETE statement with a single placeholder for the ROWID value.
-pub fn delete(partition_name: &String) -> String {
-    let sql = format!("DELETE FROM {} WHERE ROWID IN (?)", partition_name);
+pub fn delete(partition_name: &str, rowid_name: &str) -> String {
+    let sql = format!(
+        "DELETE FROM {} WHERE {} IN (?)",
+        quote_identifier(partition_name),
+        rowid_name
+    );
     sql
 }
@@ -26,21 +26,55 @@ use crate::{
 ///
 /// This function is critical for ensuring that data is correctly inserted into the appropriate
 /// partition of a partitioned virtual table, adhering to the table's partitioning scheme.
+///
+/// SQLite evaluates the inserted expressions before the module sees them, so a partition column
+/// given as e.g. `strftime('%s', 'now')` or `1706745600 + 3600` arrives as the resulting text or
+/// integer and is bucketed like the same value written out literally.
+///
+/// The ON CONFLICT mode of the statement, e.g. `INSERT OR IGNORE`, is passed on to the insert into
+/// the partition, see [`VirtualTable::insert`].
+///
+/// If the table was created with `default_partition`, a row whose partition value cannot be
+/// computed, e.g. because the partition column is NULL or not a time, is written to the default
+/// partition instead of failing, see [`VirtualTable::insert_default`]. The same goes for a row
+/// whose partition key cannot be computed, e.g. because a further partition column is NULL,
+/// and for a row of a list partitioned table declared with values whose value is not among
+/// them, see [`VirtualTable::check_listed`]. Without a default partition such rows are refused.
+///
+/// In a table created `strict` the other columns have to match their declared types as well,
+/// see [`VirtualTable::check_value_types`].
 pub fn insert(interface: &VirtualTable, info: &mut ChangeInfo) -> sqlite3_ext::Result<i64> {
-    let (columns, partition_column) = validate_and_map_columns(
-        &info.args()[1..],
+    let values = &info.args()[1..];
+    interface.check_value_types(values)?;
+    let routed = validate_and_map_columns(
+        values,
         interface.columns().into(),
         interface.partition_column_name(),
-    )?;
-    let partition_column = match partition_column {
-        Some(value) => value,
-        None => {
-            return Err(sqlite3_ext::Error::Sqlite(
+    )
+    .and_then(|(columns, partition_column)| {
+        let partition_column = partition_column.ok_or_else(|| {
+            sqlite3_ext::Error::Sqlite(
                 SQLITE_NOTFOUND,
                 Some("Partition column not found".to_string()),
-            ))
+            )
+        })?;
+        interface.check_listed(partition_column)?;
+        Ok((
+            columns,
+            interface.partition_value(partition_column)?,
+            interface.partition_key(columns)?,
+        ))
+    });
+    match routed {
+        Ok((columns, partition_value, partition_key)) => interface.insert_with_key(
+            partition_value,
+            partition_key,
+            columns,
+            info.conflict_mode(),
+        ),
+        Err(_) if interface.has_default_partition() => {
+            interface.insert_default(values, info.conflict_mode())
         }
-    };
-    let partition_value = parse_partition_value(partition_column, interface.partition_interval())?;
-    interface.insert(partition_value, columns)
+        Err(err) => Err(err),
+    }
 }
@@ -0,0 +1,23 @@
+use sqlite3_ext::{function::Context, FromValue, ValueRef};
+
+use crate::utils::validation::quote_identifier;
+
+/// Implements the `partitioner_row_count(partition_table)` SQL function used by the partitions
+/// view.
+///
+/// Parameters:
+/// - `ctx`: The function context, used to reach the connection and to set the result.
+/// - `args`: The name of the partition table.
+///
+/// Returns:
+/// - Sets the number of rows in the partition as the result of the function.
+/// - An error if the table does not exist.
+pub fn row_count(ctx: &Context, args: &mut [&mut ValueRef]) -> sqlite3_ext::Result<()> {
+    let table_name = args[0].try_get_str()?.to_owned();
+    let count = ctx.db().query_row(
+        &format!("SELECT count(*) FROM {}", quote_identifier(&table_name)),
+        (),
+        |row| Ok(row[0].get_i64()),
+    )?;
+    ctx.set_result(count)
+}
@@ -1,6 +1,10 @@
+pub mod capabilities;
 pub mod create;
 pub mod delete;
 pub mod insert;
+pub mod metadata;
+pub mod retention;
 pub mod update;
 
+pub use capabilities::{detect_capabilities, Capabilities};
 pub use create::*;
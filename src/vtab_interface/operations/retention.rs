@@ -0,0 +1,24 @@
+use sqlite3_ext::{function::Context, FromValue, ValueRef};
+
+use crate::shadow_tables::interface::VirtualTable;
+
+/// Implements the `partitioner_drop_before(table, cutoff)` SQL function.
+///
+/// Resolves `cutoff` to a UNIX epoch the same way values of the partition column are parsed,
+/// including any custom parser registered for the table, and drops every partition of `table`
+/// that lies entirely below it. The partition containing the cutoff is kept.
+///
+/// Parameters:
+/// - `ctx`: The function context, used to reach the connection and to set the result.
+/// - `args`: The name of the partitioned table and the cutoff.
+///
+/// Returns:
+/// - Sets the number of dropped partitions as the result of the function.
+/// - An error if the table is not a partitioned table or the cutoff cannot be parsed.
+pub fn drop_before(ctx: &Context, args: &mut [&mut ValueRef]) -> sqlite3_ext::Result<()> {
+    let table_name = args[0].try_get_str()?.to_owned();
+    let interface = VirtualTable::connect(ctx.db(), &table_name)?;
+    let cutoff = interface.parse_partition_epoch(args[1])?;
+    let dropped = interface.drop_partitions_before(cutoff)?;
+    ctx.set_result(dropped as i64)
+}
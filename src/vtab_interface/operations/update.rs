@@ -1,13 +1,52 @@
 use sqlite3_ext::ValueRef;
 
 use crate::shadow_tables::interface::VirtualTable;
+use crate::utils::validation::quote_identifier;
+
+/// Constructs an SQL statement that copies rows from one partition into another.
+///
+/// Used together with a `DELETE` on the source partition to move rows whose partition column
+/// was updated to a value belonging to a different partition.
+///
+/// Parameters:
+/// - `from_partition`: The partition the rows currently live in.
+/// - `to_partition`: The partition the rows belong to after the update.
+/// - `columns`: The columns of the partitions, copied in this order.
+/// - `num_rows`: The number of ROWID placeholders to generate.
+/// - `rowid_name`: The name the rowid of the source partition is addressed by.
+///
+/// Returns:
+/// - The `INSERT INTO ... SELECT` statement with one placeholder per ROWID.
+pub fn move_rows_query(
+    from_partition: &str,
+    to_partition: &str,
+    columns: &[&str],
+    num_rows: usize,
+    rowid_name: &str,
+) -> String {
+    let columns = columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Vec<String>>()
+        .join(", ");
+    let to_partition = quote_identifier(to_partition);
+    let from_partition = quote_identifier(from_partition);
+    let placeholders = std::iter::repeat_n("?", num_rows)
+        .collect::<Vec<&str>>()
+        .join(",");
+    format!(
+        "INSERT INTO {to_partition} ({columns}) SELECT {columns} FROM {from_partition} WHERE {rowid_name} IN ({placeholders})"
+    )
+}
 
 /// Constructs an SQL UPDATE statement and identifies the changed values for a specific partition.
 ///
 /// This function iterates over the provided arguments, which represent the new values for the row,
 /// and constructs an UPDATE statement by determining which columns have changed. It skips columns
 /// where the value has not changed (using the `nochange()` method to check) and prepares a list of
-/// changed values to be used in the query execution.
+/// changed values to be used in the query execution. The bucket column is always skipped, it
+/// follows the partition column instead. When no values remain the returned list is empty and
+/// the statement must not be run.
 ///
 /// Parameters:
 /// - `partition_name`: The name of the partition (table) where the update will occur.
@@ -29,8 +68,31 @@ pub fn update<'vtab>(
     partition_name: &str,
     partition: &VirtualTable,
     args: &'vtab mut [&'vtab mut ValueRef],
+) -> (String, Vec<&'vtab mut &'vtab mut ValueRef>) {
+    let (update_clause, return_values) = update_clause(partition, args);
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {} = ?",
+        quote_identifier(partition_name),
+        update_clause,
+        partition.rowid_name()
+    );
+    (sql, return_values)
+}
+
+/// Builds the SET clause of an update from the new values of a row, see [`update`].
+///
+/// Parameters:
+/// - `partition`: A reference to the `VirtualTable` representing the partition.
+/// - `args`: The new rowid followed by the new values for the row.
+///
+/// Returns:
+/// - The assignments, e.g. `"col2" = ?, "col3" = ?`, and the values to bind to them in order.
+pub fn update_clause<'vtab>(
+    partition: &VirtualTable,
+    args: &'vtab mut [&'vtab mut ValueRef],
 ) -> (String, Vec<&'vtab mut &'vtab mut ValueRef>) {
     let columns = partition.columns();
+    let bucket_column = partition.bucket_column();
     let mut return_values = Vec::new();
 
     let (mut _new_rowid, cols) = args.split_first_mut().unwrap();
@@ -38,21 +100,41 @@ pub fn update<'vtab>(
         .iter_mut()
         .enumerate()
         .filter_map(|(index, value)| {
-            if value.nochange() {
+            let column_name = columns.0.get(index).unwrap().get_name();
+            if value.nochange() || Some(column_name) == bucket_column {
                 None
             } else {
                 return_values.push(value);
 
-                let column_name = columns.0.get(index).unwrap().get_name();
-                Some(format!("{} = ?", column_name))
+                Some(format!("{} = ?", quote_identifier(column_name)))
             }
         })
         .collect::<Vec<String>>()
         .join(", ");
+    (update_clause, return_values)
+}
 
-    let sql = format!(
-        "UPDATE {} SET {} WHERE ROWID = ?",
-        partition_name, update_clause
-    );
-    (sql, return_values)
+/// Constructs an SQL statement applying the same assignments to several rows of a partition.
+///
+/// Parameters:
+/// - `partition_name`: The partition the rows live in.
+/// - `update_clause`: The assignments, as built by [`update_clause`].
+/// - `num_rows`: The number of ROWID placeholders to generate, after those of the assignments.
+/// - `rowid_name`: The name the rowid of the partition is addressed by.
+///
+/// Returns:
+/// - The `UPDATE ... WHERE rowid IN (...)` statement.
+pub fn update_rows_query(
+    partition_name: &str,
+    update_clause: &str,
+    num_rows: usize,
+    rowid_name: &str,
+) -> String {
+    let placeholders = std::iter::repeat_n("?", num_rows)
+        .collect::<Vec<&str>>()
+        .join(",");
+    format!(
+        "UPDATE {} SET {update_clause} WHERE {rowid_name} IN ({placeholders})",
+        quote_identifier(partition_name)
+    )
 }
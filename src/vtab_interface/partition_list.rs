@@ -0,0 +1,146 @@
+use std::ops::Bound;
+
+use crate::shadow_tables::interface::VirtualTable;
+use sqlite3_ext::ffi::SQLITE_CONSTRAINT;
+use sqlite3_ext::vtab::{ColumnContext, ConstraintOp, IndexInfo, VTab, VTabConnection, VTabCursor};
+use sqlite3_ext::{
+    sqlite3_ext_vtab, Connection, FromValue, Result as ExtResult, RiskLevel, ValueRef,
+};
+
+/// Index of the hidden column holding the name of the partitioned table to list.
+const TABLE_NAME_COLUMN: i32 = 3;
+
+/// The read-only table-valued function `partitioner_partitions(table)`, listing the partitions
+/// of a partitioned table.
+///
+/// Each row holds the `partition_value`, the `partition_table` and the `expires_at` of one
+/// partition, oldest first. `expires_at` is NULL when the table has no lifetime. The name of
+/// the partitioned table is the hidden `table_name` column and has to be given, e.g.
+/// `SELECT * FROM partitioner_partitions('test')`.
+#[derive(Debug)]
+#[sqlite3_ext_vtab(EponymousModule)]
+pub struct PartitionListTable<'vtab> {
+    /// Reference to the SQLite connection, used to connect to the listed table.
+    connection: &'vtab Connection,
+}
+
+impl<'vtab> VTab<'vtab> for PartitionListTable<'vtab> {
+    type Aux = ();
+    type Cursor = PartitionListCursor<'vtab>;
+
+    /// Declares the columns of the function. It only reads from the database it lives in.
+    fn connect(
+        db: &'vtab VTabConnection,
+        _aux: &'vtab Self::Aux,
+        _args: &[&str],
+    ) -> ExtResult<(String, Self)>
+    where
+        Self: Sized,
+    {
+        db.set_risk_level(RiskLevel::Innocuous);
+        Ok((
+            "CREATE TABLE x (partition_value INTEGER, partition_table TEXT, expires_at INTEGER, table_name TEXT HIDDEN)".to_owned(),
+            PartitionListTable { connection: db },
+        ))
+    }
+
+    /// Requires an equality constraint on the hidden `table_name` column, which is handed to
+    /// the cursor as its only argument. Without it there is nothing to list and the plan is
+    /// refused.
+    fn best_index(&'vtab self, index_info: &mut IndexInfo) -> ExtResult<()> {
+        let mut has_table_name = false;
+        for mut constraint in index_info.constraints() {
+            if constraint.column() == TABLE_NAME_COLUMN && constraint.op() == ConstraintOp::Eq {
+                if !constraint.usable() {
+                    return Err(sqlite3_ext::Error::Sqlite(SQLITE_CONSTRAINT, None));
+                }
+                constraint.set_argv_index(Some(0));
+                constraint.set_omit(true);
+                has_table_name = true;
+            }
+        }
+        if !has_table_name {
+            return Err(sqlite3_ext::Error::Module(
+                "partitioner_partitions requires the name of a partitioned table, e.g. partitioner_partitions('test')".to_owned(),
+            ));
+        }
+        index_info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&'vtab self) -> ExtResult<Self::Cursor> {
+        Ok(PartitionListCursor {
+            table: self,
+            table_name: String::new(),
+            partitions: Vec::new(),
+            position: 0,
+        })
+    }
+}
+
+/// Iterates the partitions of the table given to [`PartitionListTable`].
+#[derive(Debug)]
+pub struct PartitionListCursor<'vtab> {
+    /// The function the cursor belongs to.
+    table: &'vtab PartitionListTable<'vtab>,
+    /// The name of the listed table, returned as the hidden column.
+    table_name: String,
+    /// The value, name and expiry of each partition, oldest first.
+    partitions: Vec<(i64, String, Option<i64>)>,
+    /// The index of the current partition in `partitions`.
+    position: usize,
+}
+
+impl<'vtab> VTabCursor<'vtab> for PartitionListCursor<'vtab> {
+    /// Reads the partitions of the table named by the only argument from its lookup table.
+    ///
+    /// # Returns
+    /// An error if the argument does not name a partitioned table.
+    fn filter(
+        &mut self,
+        _index_num: i32,
+        _index_str: Option<&str>,
+        args: &mut [&mut ValueRef],
+    ) -> ExtResult<()> {
+        let table_name = args[0].try_get_str()?.to_owned();
+        let interface = VirtualTable::connect(self.table.connection, &table_name)?;
+        self.partitions = interface
+            .lookup()
+            .get_partitions_by_range(self.table.connection, &Bound::Unbounded, &Bound::Unbounded)?
+            .into_iter()
+            .map(|(partition_value, partition_table)| {
+                (
+                    partition_value,
+                    partition_table,
+                    interface.expires_at(partition_value),
+                )
+            })
+            .collect();
+        self.table_name = table_name;
+        self.position = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> ExtResult<()> {
+        self.position += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.position >= self.partitions.len()
+    }
+
+    fn column(&self, idx: usize, c: &ColumnContext) -> ExtResult<()> {
+        let (partition_value, partition_table, expires_at) = &self.partitions[self.position];
+        match idx {
+            0 => c.set_result(*partition_value),
+            1 => c.set_result(partition_table.clone()),
+            2 => c.set_result(*expires_at),
+            _ => c.set_result(self.table_name.clone()),
+        }
+    }
+
+    fn rowid(&self) -> ExtResult<i64> {
+        Ok(self.position as i64)
+    }
+}
@@ -1,16 +1,21 @@
 use std::borrow::BorrowMut;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::{Bound, Deref, Index};
-use std::usize;
 
-use super::PartitionMetaTable;
-use crate::constraints::{Conditions, WhereClauses};
-use crate::shadow_tables::Partition;
-use crate::utils::aggregate_conditions_to_ranges;
+use super::{is_time_comparison, PartitionMetaTable, LIMIT_KEY};
+use crate::constraints::{Conditions, QueryPlan};
+use crate::shadow_tables::{Partition, PartitionFilter, PartitionPrefetch, PrefetchedRow};
+use crate::utils::validation::quote_identifier;
+use crate::utils::{aggregate_conditions_to_ranges, selects_no_partition};
 use sqlite3_ext::query::QueryResult;
-use sqlite3_ext::vtab::ColumnContext;
-use sqlite3_ext::{vtab::VTabCursor, ValueRef};
-use sqlite3_ext::{FromValue, Result as ExtResult};
+use sqlite3_ext::vtab::{ColumnContext, ConstraintOp};
+use sqlite3_ext::{vtab::VTabCursor, FallibleIteratorMut, ValueList, ValueRef};
+use sqlite3_ext::{Connection, FromValue, Result as ExtResult};
+
+/// Stands in for the partition value of the default partition among the partitions of a scan,
+/// which has none.
+const DEFAULT_PARTITION_VALUE: i64 = i64::MIN;
 
 /// Represents a cursor for iterating over partitioned data in a virtual table.
 ///
@@ -18,16 +23,122 @@ use sqlite3_ext::{FromValue, Result as ExtResult};
 /// for seamless iteration and data retrieval across multiple partitions based on query conditions.
 #[derive(Debug)]
 pub struct RangePartitionCursor<'vtab> {
-    /// Tracks the internal ROWID counter for the cursor's current position.
-    pub internal_rowid_counter: i64,
     /// Reference to the metadata table associated with the partitioned data.
     pub meta_table: &'vtab PartitionMetaTable<'vtab>,
-    /// Iterator over partitions prepared for querying based on the current query conditions.
-    pub prepared_partitions: std::vec::IntoIter<Partition>,
-    /// The current partition under iteration by the cursor.
+    /// Values and names of the partitions in range that have not been opened yet, oldest first,
+    /// followed by the default partition, if any, with [`DEFAULT_PARTITION_VALUE`].
+    pub pending_partitions: std::vec::IntoIter<(i64, String)>,
+    /// The WHERE clause applied to each partition when it is opened.
+    pub partition_filter: PartitionFilter,
+    /// The index of the partition column among the declared columns.
+    pub partition_column_index: usize,
+    /// Comparisons on the partition column, as operators and the epochs they compare against.
+    /// They are evaluated by the cursor rather than by the partition queries, since a text
+    /// datetime and an integer epoch only compare correctly once both are parsed.
+    pub time_conditions: Vec<(ConstraintOp, i64)>,
+    /// The epochs of an `IN` list on the partition column, sorted, if the query has one. Rows
+    /// have to match one of them besides the `time_conditions`.
+    pub time_values: Option<Vec<i64>>,
+    /// Whether the rows of the current partition have to be checked against `time_conditions`.
+    /// Only partitions that are partly in range need this, all rows of the others match.
+    pub check_rows: bool,
+    /// The number of rows the scan may still produce, if a LIMIT was pushed down.
+    pub remaining_rows: Option<i64>,
+    /// The current partition under iteration by the cursor. It holds the only prepared
+    /// statement of the cursor: partitions are prepared when the cursor reaches them and
+    /// finalized once exhausted, so a scan keeps one partition statement open however many
+    /// partitions are in range.
     pub current_partition: Option<Partition>,
+    /// The workers reading the partitions ahead of the cursor, for a table created with
+    /// `parallel_scan`. While they run the rows are taken from them instead of
+    /// `current_partition`, see [`RangePartitionCursor::start_prefetch`].
+    pub prefetch: Option<PartitionPrefetch>,
     /// Indicates whether the cursor has reached the end of available data.
     pub eof: bool,
+    /// Names of the partitions that yielded at least one row during the current scan.
+    /// Published to the `PartitionMetaTable` once the cursor reaches the end of its data.
+    pub touched_partitions: BTreeSet<String>,
+    /// The rows of a scan answered from the row counts of the partitions, see
+    /// [`QueryPlan::counted`], or `None` if the partitions are read.
+    pub counted_rows: Option<CountedRows>,
+}
+
+/// The rows of a scan answered from the row counts the lookup table keeps, for statements
+/// reading no column such as `SELECT count(*)`.
+///
+/// A row is only a position within its partition. Rowids are read if SQLite asks for one, e.g.
+/// to delete every row, by stepping a rowid query of the partition along with the position.
+#[derive(Debug, Default)]
+pub struct CountedRows {
+    /// The partitions not reached yet, with their numbers of rows.
+    partitions: std::vec::IntoIter<(String, i64)>,
+    /// The current partition, the position of the current row within it and its number of rows.
+    current: Option<(String, i64, i64)>,
+    /// The rowid query of the current partition and the number of rows it has read, once a rowid
+    /// was asked for.
+    rowids: RefCell<Option<(Partition, i64)>>,
+}
+
+impl CountedRows {
+    /// Moves on to the next row, skipping partitions without rows.
+    ///
+    /// # Returns
+    ///
+    /// `true` if positioned on a row, `false` once every partition is counted.
+    fn advance(&mut self) -> bool {
+        if let Some((_, position, rows)) = self.current.as_mut() {
+            *position += 1;
+            if *position < *rows {
+                return true;
+            }
+        }
+        self.rowids.get_mut().take();
+        self.current = self
+            .partitions
+            .find(|(_, rows)| *rows > 0)
+            .map(|(partition, rows)| (partition, 0, rows));
+        self.current.is_some()
+    }
+
+    /// Reads the rowid of the current row, preparing the rowid query of the partition the first
+    /// time.
+    ///
+    /// # Parameters
+    ///
+    /// * `filter` - The filter of the scan, selecting no column besides the rowid.
+    /// * `db` - The connection the partitions live on.
+    ///
+    /// # Returns
+    ///
+    /// The rowid and the name of the partition, or an error if the partition holds fewer rows
+    /// than its row count.
+    fn rowid(&self, filter: &PartitionFilter, db: &Connection) -> ExtResult<(i64, &str)> {
+        let Some((partition_name, position, _)) = &self.current else {
+            return Err(sqlite3_ext::Error::Sqlite(
+                1,
+                Some("Could not access current row".to_owned()),
+            ));
+        };
+        let mut rowids = self.rowids.borrow_mut();
+        let (partition, read) = match &mut *rowids {
+            Some(rowids) => rowids,
+            rowids @ None => rowids.insert((filter.prepare(db, partition_name)?, 0)),
+        };
+        while *read <= *position {
+            if partition.next_row()?.is_none() {
+                return Err(sqlite3_ext::Error::Module(format!(
+                    "The row count of {} is out of date",
+                    partition_name
+                )));
+            }
+            *read += 1;
+        }
+        let rowid = partition
+            .get_current_row()
+            .map(|row| row.index(0).get_i64())
+            .unwrap_or_default();
+        Ok((rowid, partition_name))
+    }
 }
 impl<'vtab> RangePartitionCursor<'vtab> {
     /// Constructs a new `RangePartitionCursor` for interacting with partitioned data.
@@ -42,10 +153,18 @@ impl<'vtab> RangePartitionCursor<'vtab> {
     pub fn new(meta_table: &'vtab PartitionMetaTable) -> Self {
         Self {
             meta_table,
-            internal_rowid_counter: i64::default(),
-            prepared_partitions: std::vec::IntoIter::default(),
+            pending_partitions: std::vec::IntoIter::default(),
+            partition_filter: PartitionFilter::default(),
+            partition_column_index: usize::default(),
+            time_conditions: Vec::new(),
+            time_values: None,
+            check_rows: false,
+            remaining_rows: None,
             current_partition: None,
+            prefetch: None,
             eof: false,
+            touched_partitions: BTreeSet::new(),
+            counted_rows: None,
         }
     }
 
@@ -57,39 +176,151 @@ impl<'vtab> RangePartitionCursor<'vtab> {
         self.current_partition.as_ref()
     }
     fn get_current_row(&self) -> Option<&QueryResult> {
-        let current_partition = self.get_current_partition();
-        let row = match current_partition {
-            Some(partition) => partition.get_current_row(),
-            None => None,
-        };
-        row
+        self.get_current_partition()
+            .and_then(|partition| partition.get_current_row())
+    }
+    fn get_prefetched_row(&self) -> Option<&PrefetchedRow> {
+        self.prefetch
+            .as_ref()
+            .and_then(|prefetch| prefetch.current_row())
+    }
+    /// Retrieves the name of the partition the cursor is reading, from the workers if they run.
+    fn current_partition_name(&self) -> Option<&str> {
+        match &self.prefetch {
+            Some(prefetch) => prefetch.current_name(),
+            None => self.get_current_partition().map(Partition::get_name),
+        }
     }
-    /// Advances the cursor to the next partition.
+    /// Advances the cursor to the next partition, preparing its query unless the workers read
+    /// it. The statement of the exhausted partition is finalized first.
     ///
     /// # Returns
     ///
-    /// An `Option<&mut Partition>` which is:
-    /// - `Some(&mut Partition)` if the next partition exists within the current result set.
-    /// - `None` if there are no more partitions in the current result set.
-    fn advance_to_next_partition(&mut self) -> Option<&Partition> {
-        self.current_partition = self.prepared_partitions.borrow_mut().next();
-        self.get_current_partition()
+    /// An `ExtResult<bool>` which is:
+    /// - `Ok(true)` if the next partition exists within the current result set.
+    /// - `Ok(false)` if there are no more partitions in the current result set.
+    /// - `Err(e)` if the query for the partition could not be prepared.
+    fn advance_to_next_partition(&mut self) -> ExtResult<bool> {
+        self.current_partition = None;
+        let next_partition = self.pending_partitions.next();
+        if let Some(prefetch) = self.prefetch.as_mut() {
+            prefetch.next_partition();
+        }
+        let Some((value, name)) = next_partition else {
+            return Ok(false);
+        };
+        // The rows of the default partition are not bound to an interval, each is checked.
+        let in_range = if value == DEFAULT_PARTITION_VALUE
+            && name == self.meta_table.interface.default_partition_name()
+        {
+            self.time_conditions.is_empty()
+        } else {
+            let (first_epoch, last_epoch) = self.meta_table.interface.partition_epochs(value);
+            self.time_conditions.iter().all(|(operator, epoch)| {
+                time_range_satisfies(operator, first_epoch, last_epoch, *epoch)
+            })
+        };
+        self.check_rows = self.time_values.is_some() || !in_range;
+        if self.prefetch.is_none() {
+            self.current_partition = Some(
+                self.partition_filter
+                    .prepare(self.meta_table.connection, &name)?,
+            );
+        }
+        Ok(true)
     }
     /// Advances the cursor to the next row within the current partition.
     ///
     /// # Returns
     ///
-    /// An `Option<&mut QueryResult>` which is:
-    /// - `Some(&mut QueryResult)` if the next row exists within the current partition.
-    /// - `None` if there are no more rows in the current partition.
-    fn advance_to_next_row(&mut self) -> ExtResult<Option<&mut QueryResult>> {
+    /// An `ExtResult<bool>` which is:
+    /// - `Ok(true)` if the next row exists within the current partition.
+    /// - `Ok(false)` if there are no more rows in the current partition.
+    fn advance_to_next_row(&mut self) -> ExtResult<bool> {
+        if let Some(prefetch) = self.prefetch.as_mut() {
+            return Ok(prefetch.next_row()?.is_some());
+        }
         let current_partition = self.get_mut_current_partition();
         match current_partition {
-            Some(v) => v.next_row(),
-            None => Ok(None),
+            Some(v) => Ok(v.next_row()?.is_some()),
+            None => Ok(false),
         }
     }
 
+    /// Checks the partition column of the current row against the time conditions, when the
+    /// current partition is only partly in range. Values that cannot be parsed never match.
+    fn current_row_in_range(&self) -> bool {
+        if !self.check_rows {
+            return true;
+        }
+        let epoch = match &self.prefetch {
+            Some(_) => self.get_prefetched_row().and_then(PrefetchedRow::epoch),
+            None => self.get_current_row().and_then(|row| {
+                self.meta_table
+                    .interface
+                    .parse_partition_epoch(row.index(self.partition_column_index + 1).as_ref())
+                    .ok()
+            }),
+        };
+        epoch.is_some_and(|epoch| {
+            self.time_conditions
+                .iter()
+                .all(|(operator, value)| time_range_satisfies(operator, epoch, epoch, *value))
+                && self
+                    .time_values
+                    .as_ref()
+                    .is_none_or(|values| values.binary_search(&epoch).is_ok())
+        })
+    }
+
+    /// Positions the cursor on the next available row, moving on to the following partitions
+    /// when the current one is exhausted. Partitions without any matching rows are skipped and
+    /// never recorded as touched, as are rows outside the time conditions. Once a pushed down
+    /// LIMIT is reached no further rows are read and no further partitions are opened.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the cursor is positioned on a row, `false` if all partitions are exhausted.
+    fn advance(&mut self) -> ExtResult<bool> {
+        if let Some(counted_rows) = self.counted_rows.as_mut() {
+            return Ok(counted_rows.advance());
+        }
+        if self.remaining_rows == Some(0) {
+            return Ok(false);
+        }
+        loop {
+            if self.advance_to_next_row()? {
+                if !self.current_row_in_range() {
+                    continue;
+                }
+                if let Some(remaining_rows) = self.remaining_rows.as_mut() {
+                    *remaining_rows -= 1;
+                }
+                if let Some(name) = self.current_partition_name() {
+                    if !self.touched_partitions.contains(name) {
+                        let name = name.to_owned();
+                        self.touched_partitions.insert(name);
+                    }
+                }
+                return Ok(true);
+            }
+            if !self.advance_to_next_partition()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Marks the cursor as exhausted, finalizing the statement of the last partition and
+    /// stopping the workers, and hands the set of touched partitions over to the metadata table.
+    fn finish(&mut self) -> ExtResult<()> {
+        self.eof = true;
+        self.current_partition = None;
+        self.prefetch = None;
+        self.counted_rows = None;
+        self.meta_table
+            .record_touched_partitions(std::mem::take(&mut self.touched_partitions))
+    }
+
     /// Retrieves a list of partition identifiers and names that fall within the specified bounds.
     ///
     /// This function queries the partition lookup to find partitions whose values are within
@@ -123,50 +354,277 @@ impl<'vtab> RangePartitionCursor<'vtab> {
     /// # Parameters
     /// * `partition_conditions` - Optional conditions specific to the partition table.
     /// * `lookup_conditions` - Optional conditions for looking up partitions.
+    /// * `ordered` - Whether the rows of each partition have to be read ordered by the
+    ///   partition column, see [`QueryPlan::ordered`].
+    /// * `descending` - Whether that order is descending, see [`QueryPlan::descending`].
     ///
     /// # Returns
-    /// An iterator over partitions that match the given conditions.
+    /// The values and names of the partitions in range that have not expired, oldest first, or
+    /// newest first for a descending order. A merged partition is in range if any of its
+    /// intervals is, and expires with the last of them, a partition split from another one is in
+    /// range if that one is. With an `IN` list on the partition column only the partitions its
+    /// values fall into remain, and with `IS NULL` on the partition column none of them. The
+    /// default partition comes last whenever it exists, its rows are checked against the time
+    /// conditions one by one. The partitions are opened by `advance_to_next_partition` once the
+    /// cursor reaches them, and the cursor is not yet positioned on a row.
     fn initialize_partitions<'b>(
         &mut self,
         partition_conditions: Option<&'b Conditions<'b>>,
         lookup_conditions: Option<&'b Conditions<'b>>,
-    ) -> ExtResult<std::vec::IntoIter<Partition>> {
+        ordered: bool,
+        descending: bool,
+    ) -> ExtResult<std::vec::IntoIter<(i64, String)>> {
         let ranges = lookup_conditions
             .zip(Some(self.meta_table.interface.partition_interval()))
             .map(|(conditions, interval)| {
-                aggregate_conditions_to_ranges(conditions.as_slice(), interval)
+                aggregate_conditions_to_ranges(
+                    conditions.as_slice(),
+                    interval,
+                    self.meta_table.interface.strategy(),
+                    |value| self.meta_table.interface.partition_value(value),
+                )
             })
             .unwrap_or_default();
 
         let (lower_bound, upper_bound) = ranges
             .get("partition_value")
             .unwrap_or(&(Bound::Unbounded, Bound::Unbounded));
+        let selects_none = lookup_conditions.is_some_and(|conditions| {
+            conditions
+                .as_slice()
+                .iter()
+                .any(|condition| selects_no_partition(condition.operator))
+        });
+
+        let interface = &self.meta_table.interface;
+        // Rows of expired partitions are not visible anymore, even before the partitions are
+        // dropped.
+        let partitions = if selects_none {
+            Vec::new()
+        } else {
+            self.get_partitions_to_query(lower_bound, upper_bound)?
+        };
+        let mut partitions = partitions
+            .into_iter()
+            .filter(|(partition_value, _)| {
+                let (first_epoch, last_epoch) = interface.partition_epochs(*partition_value);
+                let through = interface.lookup().covered_through(*partition_value);
+                self.time_values.as_ref().is_none_or(|values| {
+                    values
+                        .get(values.partition_point(|epoch| *epoch < first_epoch))
+                        .is_some_and(|epoch| *epoch <= last_epoch)
+                }) && interface.expired_at(through).is_none()
+            })
+            .collect::<Vec<_>>();
+        self.partition_filter = PartitionFilter::try_from(partition_conditions)?
+            .with_rowid_name(self.meta_table.interface.rowid_name());
+        if ordered || self.meta_table.interface.partition_column_is_primary_key() {
+            self.partition_filter = std::mem::take(&mut self.partition_filter)
+                .with_order_by(self.meta_table.interface.partition_column_name());
+        }
+        if ordered && descending {
+            partitions.reverse();
+            self.partition_filter =
+                std::mem::take(&mut self.partition_filter).with_descending_order();
+        }
+        // Rows without a partition value may match `IS NULL` or conditions on other columns.
+        if let Some(default_partition) = interface.existing_default_partition()? {
+            partitions.push((DEFAULT_PARTITION_VALUE, default_partition));
+        }
+        self.current_partition = None;
+
+        Ok(partitions.into_iter())
+    }
+
+    /// Reads the `IN` lists on the partition column that SQLite hands over at once, and takes
+    /// their clauses out of the plan, since a list cannot be bound to a partition query.
+    ///
+    /// Values that do not parse as a time are left out. Every stored value of the partition
+    /// column parses, so they would not match any row.
+    ///
+    /// # Parameters
+    /// * `query_plan` - The plan of the scan, listing the argv indexes of the lists.
+    /// * `args` - The arguments passed to `filter`.
+    ///
+    /// # Returns
+    /// The sorted epochs the partition column has to equal one of, the values common to all
+    /// lists if there are several, or `None` if the scan has no lists.
+    fn read_value_lists(
+        &self,
+        query_plan: &mut QueryPlan,
+        args: &mut [&mut ValueRef],
+    ) -> ExtResult<Option<Vec<i64>>> {
+        let mut time_values: Option<BTreeSet<i64>> = None;
+        for index in &query_plan.value_lists {
+            let mut list = ValueList::from_value_ref(&mut *args[*index as usize])?;
+            let mut epochs = BTreeSet::new();
+            while let Some(value) = list.next()? {
+                if let Ok(epoch) = self.meta_table.interface.parse_partition_epoch(value) {
+                    epochs.insert(epoch);
+                }
+            }
+            time_values = Some(match time_values {
+                Some(values) => values.intersection(&epochs).copied().collect(),
+                None => epochs,
+            });
+        }
+        let value_lists = &query_plan.value_lists;
+        for clauses in query_plan.where_clauses.values_mut() {
+            clauses.retain(|clause| !value_lists.contains(&clause.get_constraint_index()));
+        }
+        Ok(time_values.map(|values| values.into_iter().collect()))
+    }
 
-        let prepared_partitions: ExtResult<Vec<Partition>> = self
-            .borrow_mut()
-            .get_partitions_to_query(lower_bound, upper_bound)?
+    /// Takes the partitions of the scan over from `pending_partitions` together with their row
+    /// counts, for a plan answered from the row counts. The lookup table keeps no count for the
+    /// default partition, its rows are counted.
+    ///
+    /// # Returns
+    /// The partitions to count, with their numbers of rows.
+    fn count_rows(&mut self) -> ExtResult<CountedRows> {
+        let mut counts = self
+            .meta_table
+            .interface
+            .partition_stats()?
+            .into_iter()
+            .map(|(_, partition, rows)| (partition, rows))
+            .collect::<BTreeMap<String, i64>>();
+        if let Some((_, default_partition)) = self
+            .pending_partitions
+            .as_slice()
+            .last()
+            .filter(|(value, _)| *value == DEFAULT_PARTITION_VALUE)
+        {
+            let rows = self.meta_table.connection.query_row(
+                &format!(
+                    "SELECT count(*) FROM {}",
+                    quote_identifier(default_partition)
+                ),
+                (),
+                |row| Ok(row[0].get_i64()),
+            )?;
+            counts.insert(default_partition.clone(), rows);
+        }
+        let partitions = std::mem::take(&mut self.pending_partitions)
+            .map(|(_, partition)| {
+                let rows = counts.get(&partition).copied().unwrap_or_default();
+                (partition, rows)
+            })
+            .collect::<Vec<_>>();
+        Ok(CountedRows {
+            partitions: partitions.into_iter(),
+            ..CountedRows::default()
+        })
+    }
+
+    /// Starts workers reading the partitions of the scan ahead of the cursor, if the table was
+    /// created with `parallel_scan` and the scan can use them, no more than `max_statements`
+    /// allows.
+    ///
+    /// The workers read the database file on connections of their own, so they are only started
+    /// for file databases in WAL mode, see [`PartitionPrefetch`], outside of transactions and
+    /// when the scan did not just write pending changes, neither of which they would see. Scans answered from the row counts, scans with
+    /// a pushed down LIMIT, which may stop early, and scans of a single partition read the
+    /// partitions on the table's connection as well.
+    ///
+    /// # Parameters
+    /// * `reads_partition_column` - Whether rows are checked against the time of their
+    ///   partition column, which the workers parse then.
+    /// * `flushed` - Whether pending writes were written before the scan, within the statement
+    ///   running it.
+    ///
+    /// # Returns
+    /// The started workers, or `None` if the cursor reads the partitions itself.
+    fn start_prefetch(
+        &self,
+        reads_partition_column: bool,
+        flushed: bool,
+    ) -> ExtResult<Option<PartitionPrefetch>> {
+        let interface = &self.meta_table.interface;
+        let Some(workers) = interface.scan_workers() else {
+            return Ok(None);
+        };
+        if flushed
+            || self.counted_rows.is_some()
+            || self.remaining_rows.is_some()
+            || self.pending_partitions.len() < 2
+            || interface.in_transaction()
+        {
+            return Ok(None);
+        }
+        let Ok(filename) = interface.database_filename() else {
+            return Ok(None);
+        };
+        let epoch_parser = match reads_partition_column {
+            true => Some((
+                self.partition_column_index,
+                interface.partition_epoch_parser()?,
+            )),
+            false => None,
+        };
+        let partitions = self
+            .pending_partitions
+            .as_slice()
             .iter()
-            .try_fold(
-                Vec::new(),
-                |mut accumulator, (_partition_value, partition_name)| {
-                    let partition: Partition = Partition::try_from((
-                        self.meta_table.connection,
-                        partition_name.as_str(),
-                        partition_conditions,
-                    ))?;
-                    accumulator.push(partition);
-                    Ok(accumulator)
-                },
-            );
-        let prepared_partitions = prepared_partitions?;
+            .map(|(_, partition)| partition.clone())
+            .collect();
+        PartitionPrefetch::start(
+            filename,
+            partitions,
+            &self.partition_filter,
+            epoch_parser,
+            workers,
+        )
+    }
 
-        let mut partition_iter = prepared_partitions.into_iter();
-        self.current_partition = partition_iter.next();
-        self.current_partition
-            .as_mut()
-            .and_then(|partition| partition.next_row().transpose());
+    /// Computes how many rows the scan may produce from the pushed down LIMIT and OFFSET.
+    ///
+    /// SQLite skips the OFFSET rows itself, so they have to be produced as well. A negative
+    /// LIMIT means there is no limit.
+    ///
+    /// # Parameters
+    /// * `limit_conditions` - The LIMIT and OFFSET conditions, if any were pushed down.
+    ///
+    /// # Returns
+    /// The maximum number of rows to produce, or `None` if the scan is unlimited.
+    fn row_limit(limit_conditions: Option<&Conditions>) -> Option<i64> {
+        let conditions = limit_conditions?;
+        let mut limit = None;
+        let mut offset = 0;
+        for condition in conditions.as_slice() {
+            match condition.operator {
+                ConstraintOp::Limit => limit = Some(condition.value.get_i64()),
+                ConstraintOp::Offset => offset = condition.value.get_i64().max(0),
+                _ => {}
+            }
+        }
+        limit
+            .filter(|limit| *limit >= 0)
+            .map(|limit| limit.saturating_add(offset))
+    }
+}
 
-        Ok(partition_iter)
+/// Decides whether every epoch in an inclusive range satisfies a comparison.
+///
+/// A single value is checked with a range of one epoch, a whole partition with the range of
+/// epochs it covers.
+///
+/// # Parameters
+/// * `operator` - The comparison, one of `=`, `>`, `>=`, `<` and `<=`.
+/// * `first` - The first epoch of the range.
+/// * `last` - The last epoch of the range.
+/// * `value` - The epoch compared against.
+///
+/// # Returns
+/// `true` if the comparison holds for all epochs from `first` to `last`.
+fn time_range_satisfies(operator: &ConstraintOp, first: i64, last: i64, value: i64) -> bool {
+    match operator {
+        ConstraintOp::Eq => first == value && last == value,
+        ConstraintOp::GT => first > value,
+        ConstraintOp::GE => first >= value,
+        ConstraintOp::LT => last < value,
+        ConstraintOp::LE => last <= value,
+        _ => false,
     }
 }
 
@@ -184,16 +642,25 @@ impl<'vtab> VTabCursor<'vtab> for RangePartitionCursor<'vtab> {
     ///
     /// # Returns
     ///
-    /// A `Result<(), Error>` indicating the success or failure of the filter operation.
+    /// A `Result<(), Error>` indicating the success or failure of the filter operation. An
+    /// `idx_str` that is not a plan written by `best_index` fails with `Error::Module` rather
+    /// than scanning every partition unfiltered.
     fn filter(
         &mut self,
         _idx_num: i32,
         idx_str: Option<&str>,
         args: &mut [&mut ValueRef],
     ) -> ExtResult<()> {
-        let where_clauses_serialized = idx_str.unwrap_or("");
-        let where_clauses: WhereClauses =
-            ron::from_str(where_clauses_serialized).unwrap_or(WhereClauses(HashMap::default()));
+        // The workers of an earlier scan are stopped first.
+        self.prefetch = None;
+        // Rows updated or moved by earlier statements have to be written before scanning.
+        let flushed = self.meta_table.flush_pending_writes()?;
+        let mut query_plan = idx_str
+            .map(QueryPlan::from_index_str)
+            .transpose()?
+            .unwrap_or_default();
+        self.time_values = self.read_value_lists(&mut query_plan, args)?;
+        let where_clauses = &query_plan.where_clauses;
         let lookup_conditions: Option<Conditions> = where_clauses
             .get("lookup_table")
             .map(|where_clauses| Conditions::try_from((where_clauses, args.deref())))
@@ -206,9 +673,77 @@ impl<'vtab> VTabCursor<'vtab> for RangePartitionCursor<'vtab> {
             .transpose()
             .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?;
 
-        self.prepared_partitions =
-            self.initialize_partitions(partition_conditions.as_ref(), lookup_conditions.as_ref())?;
+        // Time comparisons on the partition column are taken out of the partition queries. A
+        // value that does not parse as a time is left to the partition queries as it is, as are
+        // all comparisons of a hash or list partitioned table.
+        let interface = &self.meta_table.interface;
+        let time_partitioned = interface.is_time_partitioned();
+        let mut time_conditions = Vec::new();
+        let partition_conditions = partition_conditions.map(|conditions| {
+            conditions
+                .as_slice()
+                .iter()
+                .filter(|condition| {
+                    if !time_partitioned
+                        || condition.column != interface.partition_column_name()
+                        || !is_time_comparison(condition.operator)
+                    {
+                        return true;
+                    }
+                    match interface.parse_partition_epoch(condition.value) {
+                        Ok(epoch) => {
+                            time_conditions.push((*condition.operator, epoch));
+                            false
+                        }
+                        Err(_) => true,
+                    }
+                })
+                .copied()
+                .collect::<Conditions>()
+        });
 
+        let limit_conditions: Option<Conditions> = where_clauses
+            .get(LIMIT_KEY)
+            .map(|where_clauses| Conditions::try_from((where_clauses, args.deref())))
+            .transpose()
+            .map_err(|err| sqlite3_ext::Error::Module(err.to_string()))?;
+
+        self.eof = false;
+        self.touched_partitions.clear();
+        self.remaining_rows = Self::row_limit(limit_conditions.as_ref());
+        self.partition_column_index = interface
+            .columns()
+            .0
+            .iter()
+            .position(|column| column.get_name() == interface.partition_column_name())
+            .unwrap_or_default();
+        self.time_conditions = time_conditions;
+        self.check_rows = false;
+        self.pending_partitions = self.initialize_partitions(
+            partition_conditions.as_ref(),
+            lookup_conditions.as_ref(),
+            query_plan.ordered,
+            query_plan.descending,
+        )?;
+        let columns = self.meta_table.interface.columns().0.iter().enumerate();
+        let reads_partition_column = !self.time_conditions.is_empty() || self.time_values.is_some();
+        let partition_column_index = self.partition_column_index;
+        self.partition_filter = std::mem::take(&mut self.partition_filter).with_projection(
+            columns.map(|(index, column)| {
+                let used = query_plan.is_column_used(index)
+                    || (reads_partition_column && index == partition_column_index);
+                (column.get_name(), used)
+            }),
+        );
+        self.counted_rows = match query_plan.counted {
+            true => Some(self.count_rows()?),
+            false => None,
+        };
+        self.prefetch = self.start_prefetch(reads_partition_column, flushed)?;
+
+        if !self.advance()? {
+            self.finish()?;
+        }
         Ok(())
     }
     /// Advances the cursor to the next row within the current or next partition.
@@ -221,18 +756,9 @@ impl<'vtab> VTabCursor<'vtab> for RangePartitionCursor<'vtab> {
     /// A `Result<(), Error>` indicating the success or failure of advancing the cursor.
     fn next(&mut self) -> ExtResult<()> {
         // Attempt to advance to the next row within the current partition.
-        // If there's no next row (None is returned), attempt to move to the next partition.
-        let did_advance = match self.advance_to_next_row()? {
-            Some(_) => true,
-            None => match self.advance_to_next_partition() {
-                Some(_) => self.advance_to_next_row()?.is_some(),
-                None => false,
-            },
-        };
-        if did_advance {
-            self.internal_rowid_counter += 1;
-        } else {
-            self.eof = true;
+        // If there's no next row, move on to the first row of the following partitions.
+        if !self.advance()? {
+            self.finish()?;
         }
         Ok(())
     }
@@ -256,6 +782,12 @@ impl<'vtab> VTabCursor<'vtab> for RangePartitionCursor<'vtab> {
     ///
     /// A `Result<(), Error>` indicating the success or failure of the column retrieval operation.
     fn column(&self, idx: usize, c: &ColumnContext) -> ExtResult<()> {
+        if self.prefetch.is_some() {
+            if let Some(value) = self.get_prefetched_row().and_then(|row| row.column(idx)) {
+                value.set_result(c)?
+            }
+            return Ok(());
+        }
         if let Some(current_row) = self.get_current_row() {
             c.set_result(current_row.index(idx + 1).as_ref())?
         };
@@ -263,14 +795,26 @@ impl<'vtab> VTabCursor<'vtab> for RangePartitionCursor<'vtab> {
         Ok(())
     }
 
-    /// Returns the row ID of the current row.
+    /// Returns the row ID of the current row. A row is reported with the same row ID in every
+    /// scan, see `RowidMapper`.
     ///
     /// # Returns
     /// The row ID or an error if it cannot be retrieved.
     fn rowid(&self) -> ExtResult<i64> {
-        let rowid_column = self.get_current_row().map(|row| row.index(0));
-        let partition_name = match self.get_current_partition() {
-            Some(partition) => partition.get_name(),
+        if let Some(counted_rows) = &self.counted_rows {
+            let (rowid, partition_name) =
+                counted_rows.rowid(&self.partition_filter, self.meta_table.connection)?;
+            let mut rowid_mapper = self.meta_table.rowid_mapper.write().map_err(|e| {
+                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+            })?;
+            return Ok(rowid_mapper.id_for(rowid, partition_name));
+        }
+        let rowid = match &self.prefetch {
+            Some(_) => self.get_prefetched_row().map(PrefetchedRow::rowid),
+            None => self.get_current_row().map(|row| row.index(0).get_i64()),
+        };
+        let partition_name = match self.current_partition_name() {
+            Some(partition_name) => partition_name,
             None => {
                 return Err(sqlite3_ext::Error::Sqlite(
                     1,
@@ -278,17 +822,15 @@ impl<'vtab> VTabCursor<'vtab> for RangePartitionCursor<'vtab> {
                 ))
             }
         };
-        if let Some(column) = rowid_column {
-            let mut rowid_mapper = self.meta_table.rowid_mapper.write().map_err(|e| {
-                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
-            })?;
-
-            rowid_mapper.insert(
-                self.internal_rowid_counter,
-                (column.get_i64(), partition_name.to_string()),
-            );
-        }
-
-        Ok(self.internal_rowid_counter)
+        let Some(rowid) = rowid else {
+            return Err(sqlite3_ext::Error::Sqlite(
+                1,
+                Some("Could not access current row".to_owned()),
+            ));
+        };
+        let mut rowid_mapper = self.meta_table.rowid_mapper.write().map_err(|e| {
+            sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+        })?;
+        Ok(rowid_mapper.id_for(rowid, partition_name))
     }
 }
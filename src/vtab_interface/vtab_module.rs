@@ -1,26 +1,112 @@
 use std::borrow::BorrowMut;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::RwLock;
 
-use crate::constraints::WhereClause;
-use crate::operations::{delete::delete, insert::insert, update::update};
+use crate::constraints::{QueryPlan, WhereClause};
+use crate::operations::delete::{delete, prepare_delete_statement};
+use crate::operations::{
+    insert::insert,
+    update::{move_rows_query, update, update_clause, update_rows_query},
+};
 use crate::shadow_tables::interface::VirtualTable;
+use crate::utils::validation::quote_identifier;
+use crate::utils::{partition_range, selects_no_partition};
 use crate::vtab_interface::vtab_cursor::*;
+use sqlite3_ext::ffi::SQLITE_ABORT;
 use sqlite3_ext::query::ToParam;
-use sqlite3_ext::FromValue;
+use sqlite3_ext::{params, FromValue, Value};
 use sqlite3_ext::{sqlite3_ext_vtab, vtab::VTab};
 use sqlite3_ext::{
-    vtab::{ChangeInfo, ChangeType, CreateVTab, UpdateVTab, VTabConnection},
+    vtab::{
+        ChangeInfo, ChangeType, ConstraintOp, CreateVTab, RenameVTab, TransactionVTab, UpdateVTab,
+        VTabConnection, VTabTransaction,
+    },
     Connection, Result as ExtResult,
 };
 
-use super::{connect_to_virtual_table, construct_where_clause, create_virtual_table};
+use super::{
+    connect_to_virtual_table, construct_where_clause, create_virtual_table,
+    is_column_constraint_supported, is_time_comparison,
+};
+
+/// The maximum number of rows moved or updated by a single statement when flushing pending
+/// writes.
+const BATCH_SIZE: usize = 500;
+
+/// Index number of a scan over every partition.
+const FULL_SCAN: i32 = 0;
+/// Index number of a scan limited to the partitions matching the partition column constraints.
+const PRUNED_SCAN: i32 = 1;
+/// Index number of a statement answered from the row counts of the partitions.
+const COUNTED_SCAN: i32 = 2;
+/// Estimated cost of scanning a single partition, see [`PartitionMetaTable::estimated_cost`].
+const PARTITION_SCAN_COST: f64 = 100.0;
+
+/// Rows waiting to be moved between partitions, grouped by (source, destination) partition.
+type PendingMoves = BTreeMap<(String, String), Vec<i64>>;
+
+/// Rows of one partition that an update assigns the same values to, leaving their partition
+/// column alone.
+#[derive(Debug)]
+struct PendingUpdate {
+    /// The partition the rows live in.
+    partition: String,
+    /// The assignments, e.g. `"col2" = ?`.
+    update_clause: String,
+    /// The values bound to the assignments, in order.
+    values: Vec<Value>,
+    /// The persisted rowids of the rows.
+    rowids: Vec<i64>,
+}
+
+/// Maps the rowids reported by cursors to the persisted rowid and partition of each row.
+///
+/// Persisted rowids are only unique within one partition, so cursors report rowids of their
+/// own. A row gets the same rowid in every scan, which statements made of several scans rely
+/// on: SQLite runs `DELETE ... LIMIT n` as `DELETE ... WHERE rowid IN (SELECT rowid ... LIMIT n)`
+/// and deletes the rows whose rowids the inner scan reported as the outer scan reaches them.
+#[derive(Debug, Default)]
+pub struct RowidMapper {
+    /// The persisted rowid and partition of each reported rowid.
+    rows: HashMap<i64, (i64, String)>,
+    /// The reported rowid of each partition and persisted rowid.
+    ids: HashMap<(String, i64), i64>,
+}
+impl RowidMapper {
+    /// Returns the rowid to report for a row, assigning a new one to a row not seen before.
+    ///
+    /// Parameters:
+    /// - `rowid`: The persisted rowid of the row.
+    /// - `partition_name`: The partition the row lives in.
+    pub fn id_for(&mut self, rowid: i64, partition_name: &str) -> i64 {
+        let key = (partition_name.to_owned(), rowid);
+        if let Some(id) = self.ids.get(&key) {
+            return *id;
+        }
+        let id = self.rows.len() as i64;
+        self.rows.insert(id, (rowid, partition_name.to_owned()));
+        self.ids.insert(key, id);
+        id
+    }
+
+    /// Looks up the persisted rowid and partition of a reported rowid.
+    pub fn get(&self, id: i64) -> Option<&(i64, String)> {
+        self.rows.get(&id)
+    }
+
+    /// Forgets all rows, e.g. once the statements that scanned them are done.
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.ids.clear();
+    }
+}
+
 /// Represents a metadata table for managing partitions in a SQLite database.
 ///
 /// This structure implements the `VTab` trait to provide custom virtual table functionality,
 /// allowing operations such as insert, update, and delete to be performed on partitioned data.
 #[derive(Debug)]
-#[sqlite3_ext_vtab(StandardModule, UpdateVTab)]
+#[sqlite3_ext_vtab(StandardModule, UpdateVTab, TransactionVTab, RenameVTab)]
 pub struct PartitionMetaTable<'vtab> {
     /// An interface to the partition logic, encapsulating partition management.
     pub interface: VirtualTable<'vtab>,
@@ -28,8 +114,327 @@ pub struct PartitionMetaTable<'vtab> {
     pub connection: &'vtab Connection,
     /// A map for tracking row IDs provided by the VTab-cursor to their corresponding persisted rowid and what partition it is stored in.
     /// Needed because persisted rowid are only unique within one table, not across multiple
-    /// partitions
-    pub rowid_mapper: &'vtab RwLock<HashMap<i64, (i64, String)>>,
+    /// partitions. An entry is only valid for the statement whose scan produced it: maintenance
+    /// such as VACUUM may renumber the persisted rowids between statements, and every UPDATE or
+    /// DELETE scans the table again before it writes. The map is cleared when a write
+    /// transaction ends.
+    pub rowid_mapper: &'vtab RwLock<RowidMapper>,
+    /// The distinct partitions that yielded rows during the most recently completed scan.
+    pub touched_partitions: RwLock<BTreeSet<String>>,
+    /// Rows whose partition column was updated to a value of another partition. They are moved
+    /// in batches before the next scan and when the transaction commits.
+    pending_moves: RwLock<PendingMoves>,
+    /// Rows updated without changing their partition, grouped by partition and assignments. They
+    /// are written in batches before the next scan and when the transaction commits.
+    pending_updates: RwLock<Vec<PendingUpdate>>,
+}
+impl<'vtab> PartitionMetaTable<'vtab> {
+    /// Constructs a new `PartitionMetaTable` around an already created or connected `VirtualTable`.
+    ///
+    /// Parameters:
+    /// - `interface`: The partitioning interface of the table.
+    /// - `connection`: The connection the virtual table lives on.
+    /// - `rowid_mapper`: The module wide map from cursor rowids to persisted rowids.
+    ///
+    /// Returns a new `PartitionMetaTable`.
+    pub fn new(
+        interface: VirtualTable<'vtab>,
+        connection: &'vtab Connection,
+        rowid_mapper: &'vtab RwLock<RowidMapper>,
+    ) -> Self {
+        Self {
+            interface,
+            connection,
+            rowid_mapper,
+            touched_partitions: RwLock::default(),
+            pending_moves: RwLock::default(),
+            pending_updates: RwLock::default(),
+        }
+    }
+
+    /// Returns the names of the partitions that yielded at least one row during the most recently
+    /// completed scan, in ascending order. Partitions that were selected by the range pruning but
+    /// did not contain any matching rows are not part of the set.
+    ///
+    /// Useful for invalidating caches after a query, since it only reports partitions that were
+    /// actually read.
+    pub fn touched_partitions(&self) -> ExtResult<Vec<String>> {
+        let touched_partitions = self.touched_partitions.read().map_err(|e| {
+            sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+        })?;
+        Ok(touched_partitions.iter().cloned().collect())
+    }
+
+    /// Replaces the recorded set of touched partitions. Called by the cursor once it has
+    /// reached the end of its data.
+    pub(crate) fn record_touched_partitions(&self, partitions: BTreeSet<String>) -> ExtResult<()> {
+        let mut touched_partitions = self.touched_partitions.write().map_err(|e| {
+            sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+        })?;
+        *touched_partitions = partitions;
+        Ok(())
+    }
+
+    /// Looks up the partition and persisted rowid of a row produced by the current scan.
+    ///
+    /// Parameters:
+    /// - `id`: The rowid the cursor reported for the row.
+    ///
+    /// Returns:
+    /// - The persisted rowid and the name of the partition, or an error if the cursor never
+    ///   reported the row.
+    fn mapped_row(&self, id: i64) -> ExtResult<(i64, String)> {
+        let rowid_mapper = self.rowid_mapper.read().map_err(|e| {
+            sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+        })?;
+        rowid_mapper
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Self::stale_row(id, "any partition"))
+    }
+
+    /// Reports a row that is not where the scan found it, instead of writing to whichever row
+    /// holds its old rowid now.
+    fn stale_row(id: i64, partition_name: &str) -> sqlite3_ext::Error {
+        sqlite3_ext::Error::Sqlite(
+            SQLITE_ABORT,
+            Some(format!(
+                "Row {} is no longer in {}, the table changed since it was scanned",
+                id, partition_name
+            )),
+        )
+    }
+
+    /// Schedules a row to be moved to another partition.
+    ///
+    /// Parameters:
+    /// - `from_partition`: The partition the row currently lives in.
+    /// - `to_partition`: The partition the row belongs to after the update.
+    /// - `rowid`: The persisted rowid of the row in `from_partition`.
+    fn queue_move(&self, from_partition: &str, to_partition: &str, rowid: i64) -> ExtResult<()> {
+        let mut pending_moves = self.pending_moves.write().map_err(|e| {
+            sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+        })?;
+        pending_moves
+            .entry((from_partition.to_owned(), to_partition.to_owned()))
+            .or_default()
+            .push(rowid);
+        Ok(())
+    }
+
+    /// Schedules an update of a row that stays in its partition.
+    ///
+    /// A statement updates the rows of a partition one after another, so the row joins the
+    /// previously scheduled rows if the assignments and values are the same.
+    ///
+    /// Parameters:
+    /// - `partition`: The partition the row lives in.
+    /// - `update_clause`: The assignments, as built by `update_clause`.
+    /// - `values`: The values bound to the assignments, in order.
+    /// - `rowid`: The persisted rowid of the row in `partition`.
+    fn queue_update(
+        &self,
+        partition: &str,
+        update_clause: String,
+        values: Vec<Value>,
+        rowid: i64,
+    ) -> ExtResult<()> {
+        let mut pending_updates = self.pending_updates.write().map_err(|e| {
+            sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+        })?;
+        match pending_updates.last_mut() {
+            Some(last)
+                if last.partition == partition
+                    && last.update_clause == update_clause
+                    && last.values == values =>
+            {
+                last.rowids.push(rowid)
+            }
+            _ => pending_updates.push(PendingUpdate {
+                partition: partition.to_owned(),
+                update_clause,
+                values,
+                rowids: vec![rowid],
+            }),
+        }
+        Ok(())
+    }
+
+    /// Writes all scheduled updates and moves, see [`PartitionMetaTable::flush_pending_updates`]
+    /// and [`PartitionMetaTable::flush_pending_moves`], followed by the row counts of the
+    /// partitions they and earlier inserts and deletes changed.
+    ///
+    /// Returns whether anything was written.
+    pub fn flush_pending_writes(&self) -> ExtResult<bool> {
+        let counted = self.interface.has_row_count_changes();
+        let updated = self.flush_pending_updates()?;
+        let moved = self.flush_pending_moves()?;
+        self.interface.flush_row_counts()?;
+        Ok(counted || updated || moved)
+    }
+
+    /// Writes all scheduled updates of rows that stay in their partition.
+    ///
+    /// Rows sharing the same assignments cost one `UPDATE ... WHERE rowid IN (...)` per batch
+    /// rather than one statement per row. A row that is gone by now fails the flush, as it would
+    /// have failed the update.
+    ///
+    /// Returns whether any update was scheduled.
+    fn flush_pending_updates(&self) -> ExtResult<bool> {
+        let pending_updates = {
+            let mut pending_updates = self.pending_updates.write().map_err(|e| {
+                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+            })?;
+            std::mem::take(&mut *pending_updates)
+        };
+        let updated = !pending_updates.is_empty();
+        for update in pending_updates {
+            for batch in update.rowids.chunks(BATCH_SIZE) {
+                let sql = update_rows_query(
+                    &update.partition,
+                    &update.update_clause,
+                    batch.len(),
+                    self.interface.rowid_name(),
+                );
+                let params = update
+                    .values
+                    .iter()
+                    .cloned()
+                    .chain(batch.iter().map(|rowid| Value::Integer(*rowid)))
+                    .collect::<Vec<Value>>();
+                let changed = self.connection.execute(&sql, params)?;
+                if changed < batch.len() as i64 {
+                    return Err(sqlite3_ext::Error::Sqlite(
+                        SQLITE_ABORT,
+                        Some(format!(
+                            "Rows are no longer in {}, the table changed since it was scanned",
+                            update.partition
+                        )),
+                    ));
+                }
+            }
+        }
+        Ok(updated)
+    }
+
+    /// Moves all rows scheduled by updates of the partition column into their new partitions.
+    ///
+    /// Moves are grouped per pair of source and destination partition, so a bulk update that
+    /// shifts many rows into the same bucket costs one `INSERT ... SELECT` and one `DELETE` per
+    /// batch of rows rather than two statements per row.
+    ///
+    /// Returns whether any move was scheduled.
+    fn flush_pending_moves(&self) -> ExtResult<bool> {
+        let pending_moves = {
+            let mut pending_moves = self.pending_moves.write().map_err(|e| {
+                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+            })?;
+            std::mem::take(&mut *pending_moves)
+        };
+        if pending_moves.is_empty() {
+            return Ok(false);
+        }
+        let columns = self
+            .interface
+            .columns()
+            .0
+            .iter()
+            .map(|column| column.get_name())
+            .collect::<Vec<&str>>();
+        for ((from_partition, to_partition), rowids) in pending_moves {
+            for batch in rowids.chunks(BATCH_SIZE) {
+                let sql = move_rows_query(
+                    &from_partition,
+                    &to_partition,
+                    &columns,
+                    batch.len(),
+                    self.interface.rowid_name(),
+                );
+                let moved = self.connection.execute(&sql, batch.to_vec())?;
+                let sql = prepare_delete_statement(
+                    &from_partition,
+                    batch.len(),
+                    self.interface.rowid_name(),
+                );
+                self.connection.execute(&sql, batch.to_vec())?;
+                self.interface
+                    .record_row_count_change(&from_partition, Some(-moved));
+                self.interface
+                    .record_row_count_change(&to_partition, Some(moved));
+            }
+        }
+        Ok(true)
+    }
+
+    /// Forgets the rowids reported by earlier scans. Used when a write transaction ends, no
+    /// statement of it refers to them anymore.
+    fn forget_rowids(&self) -> ExtResult<()> {
+        self.rowid_mapper
+            .write()
+            .map_err(|e| {
+                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+            })?
+            .clear();
+        Ok(())
+    }
+
+    /// Drops all scheduled updates and moves along with the row count changes not yet written.
+    /// Used when the changes that scheduled them are rolled back, which may also roll back
+    /// partitions the lookup table has synced.
+    fn discard_pending_writes(&self) -> ExtResult<()> {
+        self.interface.lookup().forget_synced();
+        self.interface.discard_row_counts();
+        self.pending_updates
+            .write()
+            .map_err(|e| {
+                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+            })?
+            .clear();
+        self.pending_moves
+            .write()
+            .map_err(|e| {
+                sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
+            })?
+            .clear();
+        Ok(())
+    }
+
+    /// Estimates the cost of a scan from the number of partitions it visits.
+    ///
+    /// Comparisons whose value is known while planning narrow the partitions the same way the
+    /// cursor does, by counting the partitions in the lookup table. The values of the others are
+    /// only known when the scan starts, e.g. host parameters or columns of other tables in a
+    /// join. Each of these is assumed to narrow the scan to one partition for `=`, and to halve
+    /// it otherwise. `IS NULL` selects no partition and `IS NOT NULL` every one.
+    ///
+    /// Parameters:
+    /// - `comparisons`: The operator of each usable comparison against the partition column and
+    ///   the partition value it compares to, if already known.
+    ///
+    /// Returns:
+    /// - `PARTITION_SCAN_COST` for each partition visited, counting at least one partition.
+    pub fn estimated_cost(&self, comparisons: &[(ConstraintOp, Option<i64>)]) -> ExtResult<f64> {
+        let range = partition_range(
+            comparisons
+                .iter()
+                .filter_map(|(operator, value)| value.map(|value| (*operator, value))),
+            self.interface.partition_interval(),
+        );
+        let mut partitions = self
+            .interface
+            .lookup()
+            .get_partitions_by_range(self.connection, &range.0, &range.1)?
+            .len();
+        for (operator, _) in comparisons.iter().filter(|(_, value)| value.is_none()) {
+            partitions = match operator {
+                ConstraintOp::IsNull => 0,
+                ConstraintOp::IsNotNull => partitions,
+                ConstraintOp::Eq => partitions.min(1),
+                _ => partitions.div_ceil(2),
+            };
+        }
+        Ok(partitions.max(1) as f64 * PARTITION_SCAN_COST)
+    }
 }
 impl<'vtab> CreateVTab<'vtab> for PartitionMetaTable<'vtab> {
     /// Creates a new instance of the partition metadata table.
@@ -44,20 +449,15 @@ impl<'vtab> CreateVTab<'vtab> for PartitionMetaTable<'vtab> {
     where
         Self: Sized,
     {
+        // Lets `update` see the ON CONFLICT mode of inserts, e.g. `INSERT OR IGNORE`.
+        db.enable_constraints();
         // Creation logic for the partition, including SQL table creation
-        let virtual_table = match create_virtual_table(db, args) {
-            Ok(partition) => partition,
-            Err(err) => return Err(err.into()),
-        };
+        let virtual_table = create_virtual_table(db, args)?;
         // The schema that serves as a interface to the user.
         let sql = virtual_table.create_table_query();
         Ok((
             sql,
-            PartitionMetaTable {
-                interface: virtual_table,
-                connection: db,
-                rowid_mapper,
-            },
+            PartitionMetaTable::new(virtual_table, db, rowid_mapper),
         ))
     }
     /// Destroys the virtual table, cleaning up all associated resources.
@@ -68,6 +468,16 @@ impl<'vtab> CreateVTab<'vtab> for PartitionMetaTable<'vtab> {
         self.interface.destroy()
     }
 }
+impl<'vtab> RenameVTab<'vtab> for PartitionMetaTable<'vtab> {
+    /// Renames the partitions and shadow tables along with the virtual table, see
+    /// [`VirtualTable::rename`]. Scheduled updates and moves still name the old partitions, so
+    /// they are carried out first. A sealed table keeps its name.
+    fn rename(&'vtab self, name: &str) -> ExtResult<()> {
+        self.interface.check_unsealed()?;
+        self.flush_pending_writes()?;
+        self.interface.rename(name)
+    }
+}
 impl<'vtab> UpdateVTab<'vtab> for PartitionMetaTable<'vtab> {
     /// Handles updates to the virtual table, including inserts, updates, and deletes.
     ///
@@ -77,45 +487,208 @@ impl<'vtab> UpdateVTab<'vtab> for PartitionMetaTable<'vtab> {
         match info.change_type() {
             ChangeType::Insert => insert(&self.interface, info),
             ChangeType::Update => {
-                let rowid_mapper = self.rowid_mapper.read().map_err(|e| {
-                    sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
-                })?;
                 let id = info.rowid_mut().get_i64();
-                if let Some((db_rowid, partition_name)) = rowid_mapper.get(&id) {
-                    let (sql, mut values) =
-                        update(partition_name, &self.interface, info.args_mut());
+                let (db_rowid, partition_name) = self.mapped_row(id)?;
+                // args()[0] is the new rowid, followed by the column values.
+                self.interface.check_value_types(&info.args()[1..])?;
+                let partition_column_index =
+                    self.interface.columns().0.iter().position(|column| {
+                        column.get_name() == self.interface.partition_column_name()
+                    });
+                let new_partition_value = match partition_column_index
+                    .and_then(|index| info.args().get(index + 1))
+                {
+                    Some(value) if !value.nochange() => {
+                        match self
+                            .interface
+                            .check_listed(value)
+                            .and_then(|_| self.interface.partition_value(value))
+                        {
+                            Ok(partition_value) => Some((
+                                partition_value,
+                                self.interface
+                                    .split_partition_value(partition_value, value)?,
+                                self.interface.list_value(value)?,
+                            )),
+                            // A row of the default partition stays there as long as its
+                            // partition value cannot be computed or has no partition.
+                            Err(_) if partition_name == self.interface.default_partition_name() => {
+                                None
+                            }
+                            Err(err) => return Err(err),
+                        }
+                    }
+                    _ => None,
+                };
+                // A changed key column of a composite partition key moves the row as well.
+                let key_changed =
+                    self.interface
+                        .columns()
+                        .0
+                        .iter()
+                        .enumerate()
+                        .any(|(index, column)| {
+                            self.interface
+                                .key_columns()
+                                .iter()
+                                .any(|key_column| key_column.as_str() == column.get_name())
+                                && info
+                                    .args()
+                                    .get(index + 1)
+                                    .is_some_and(|value| !value.nochange())
+                        });
+                // Rows staying in their partition are written in batches, see `queue_update`.
+                if new_partition_value.is_none() && !key_changed {
+                    let (update_clause, values) = update_clause(&self.interface, info.args_mut());
+                    if !values.is_empty() {
+                        let values = values
+                            .iter()
+                            .map(|value| (**value).to_owned())
+                            .collect::<ExtResult<Vec<Value>>>()?;
+                        self.queue_update(&partition_name, update_clause, values, db_rowid)?;
+                    }
+                    return Ok(id);
+                }
+                let (sql, mut values) = update(&partition_name, &self.interface, info.args_mut());
+                if !values.is_empty() {
                     let mut stmt = self.connection.prepare(&sql)?;
                     values.iter_mut().enumerate().for_each(|(index, value)| {
                         value.bind_param(&mut stmt, (index + 1) as i32).unwrap();
                     });
 
                     db_rowid.bind_param(stmt.borrow_mut(), (values.len() + 1) as i32)?;
-                    stmt.execute(())?;
+                    if stmt.execute(())? == 0 {
+                        return Err(Self::stale_row(id, &partition_name));
+                    }
+                }
+
+                // Without key columns the partition follows from the new partition value alone,
+                // otherwise it is read back from the updated row.
+                let moved = match (new_partition_value, self.interface.key_columns().is_empty()) {
+                    // The bucket column keeps the partition value of the table's interval when the
+                    // row moves to a split partition.
+                    (Some((partition_value, split_value, list_value)), true) => Some((
+                        partition_value,
+                        self.interface.get_listed_partition(
+                            split_value,
+                            0,
+                            list_value.as_deref(),
+                        )?,
+                    )),
+                    (None, true) => None,
+                    (_, false) => Some(self.interface.partition_of_row(&partition_name, db_rowid)?),
+                };
+                if let Some((partition_value, new_partition)) = moved {
+                    if let Some(bucket_column) = self.interface.bucket_column() {
+                        self.connection.execute(
+                            &format!(
+                                "UPDATE {} SET {} = ? WHERE {} = ?",
+                                quote_identifier(&partition_name),
+                                quote_identifier(bucket_column),
+                                self.interface.rowid_name()
+                            ),
+                            params![partition_value, db_rowid],
+                        )?;
+                    }
+                    if new_partition != partition_name {
+                        self.queue_move(&partition_name, &new_partition, db_rowid)?;
+                    }
                 }
 
                 Ok(id)
             }
             ChangeType::Delete => {
-                let rowid_mapper = self.rowid_mapper.write().map_err(|e| {
-                    sqlite3_ext::Error::Sqlite(1, Some(format!("Lock acquisition failed: {}", e)))
-                })?;
                 let id = info.rowid().get_i64();
-                if let Some((db_rowid, partition_name)) = rowid_mapper.get(&id) {
-                    let sql = delete(partition_name);
-                    let mut stmt = self.connection.prepare(&sql)?;
-                    db_rowid.bind_param(stmt.borrow_mut(), 1)?;
-                    stmt.execute(())?;
+                let (db_rowid, partition_name) = self.mapped_row(id)?;
+                // Deleted right away, so the partition reflects the delete as soon as the
+                // statement is done, also within a transaction.
+                let sql = delete(&partition_name, self.interface.rowid_name());
+                let mut stmt = self.connection.prepare(&sql)?;
+                db_rowid.bind_param(stmt.borrow_mut(), 1)?;
+                if stmt.execute(())? == 0 {
+                    return Err(Self::stale_row(id, &partition_name));
                 }
-
+                self.interface
+                    .record_row_count_change(&partition_name, Some(-1));
                 Ok(id)
             }
         }
     }
 }
+impl<'vtab> TransactionVTab<'vtab> for PartitionMetaTable<'vtab> {
+    type Transaction = PartitionTransaction<'vtab>;
+
+    /// Starts tracking a write transaction on the virtual table.
+    fn begin(&'vtab self) -> ExtResult<Self::Transaction> {
+        self.interface.lookup().track_changes();
+        Ok(PartitionTransaction {
+            table: self,
+            savepoints: BTreeMap::new(),
+        })
+    }
+}
+
+/// A write transaction on a `PartitionMetaTable`.
+///
+/// It keeps the rows scheduled for updating or moving between partitions in step with the
+/// transaction: they are flushed before the commit and whenever a savepoint is taken, and
+/// discarded when the changes that scheduled them are rolled back.
+///
+/// Rows and partitions are written through the same connection, so a statement that fails
+/// midway, e.g. on the fifth row of a multi-row INSERT, is undone as a whole by SQLite itself,
+/// including the partition tables and lookup rows it created. Outside a transaction the implicit
+/// transaction is rolled back, inside one the statement is rolled back to the savepoint SQLite
+/// takes before it. The changes made to the in-memory partitions map since are undone as well,
+/// so it never names a table that was rolled back nor misses one whose removal was.
+///
+/// Savepoints nest: each remembers how many changes to the map came before it, and rolling back
+/// to one undoes the changes made after it, newest first, keeping the savepoint open.
+pub struct PartitionTransaction<'vtab> {
+    table: &'vtab PartitionMetaTable<'vtab>,
+    /// The number of changes to the partitions map made before each open savepoint, by
+    /// savepoint number, see
+    /// [`LookupTable::change_count`](crate::shadow_tables::LookupTable::change_count).
+    savepoints: BTreeMap<i32, usize>,
+}
+impl<'vtab> VTabTransaction<'vtab> for PartitionTransaction<'vtab> {
+    fn sync(&mut self) -> ExtResult<()> {
+        self.table.flush_pending_writes().map(|_| ())
+    }
+    fn commit(self) -> ExtResult<()> {
+        self.table.interface.lookup().untrack_changes();
+        self.table.forget_rowids()
+    }
+    fn rollback(self) -> ExtResult<()> {
+        self.table.discard_pending_writes()?;
+        self.table.interface.lookup().undo_changes(0);
+        self.table.interface.lookup().untrack_changes();
+        self.table.forget_rowids()
+    }
+    fn savepoint(&mut self, n: i32) -> ExtResult<()> {
+        self.savepoints.split_off(&n);
+        self.savepoints
+            .insert(n, self.table.interface.lookup().change_count());
+        self.table.flush_pending_writes().map(|_| ())
+    }
+    fn release(&mut self, n: i32) -> ExtResult<()> {
+        self.savepoints.split_off(&n);
+        Ok(())
+    }
+    fn rollback_to(&mut self, n: i32) -> ExtResult<()> {
+        // Everything scheduled before the savepoint was flushed when it was taken.
+        self.table.discard_pending_writes()?;
+        // A savepoint taken before the virtual table joined the transaction predates every
+        // change it made.
+        let mark = self.savepoints.get(&n).copied().unwrap_or_default();
+        self.savepoints.split_off(&n.saturating_add(1));
+        self.table.interface.lookup().undo_changes(mark);
+        Ok(())
+    }
+}
 impl<'vtab> VTab<'vtab> for PartitionMetaTable<'vtab> {
     /// Auxiliary type used by this virtual table, specifically for row ID mapping. This type will
     /// be initialized by the sqlite3 engine.
-    type Aux = RwLock<HashMap<i64, (i64, String)>>; //internal rowid. rowid from table, table name
+    type Aux = RwLock<RowidMapper>;
     /// The cursor type used for iterating over partition data.
     type Cursor = RangePartitionCursor<'vtab>;
     /// Connects to the virtual table, initializing it with necessary arguments.
@@ -131,26 +704,19 @@ impl<'vtab> VTab<'vtab> for PartitionMetaTable<'vtab> {
         Self: Sized,
     {
         // Connection logic, similar to `create` but for establishing connections without creating tables.
-        let p = match connect_to_virtual_table(db, args[2]) {
-            Ok(partition) => partition,
-            Err(err) => return Err(err),
-        };
+        db.enable_constraints();
+        let p = connect_to_virtual_table(db, args[2])?;
         let connection = db;
 
         Ok((
             p.create_table_query().to_string(),
-            PartitionMetaTable {
-                interface: p,
-                connection,
-                rowid_mapper, // rows: None,
-            },
+            PartitionMetaTable::new(p, connection, rowid_mapper),
         ))
     }
     /// Opens a cursor for accessing the virtual table's data.
     ///
     /// This method initializes and returns a cursor that can be used to query
     /// and manipulate the data within the virtual table.
-
     fn open(&'vtab self) -> ExtResult<Self::Cursor> {
         Ok(RangePartitionCursor::new(self))
     }
@@ -158,16 +724,142 @@ impl<'vtab> VTab<'vtab> for PartitionMetaTable<'vtab> {
     ///
     /// Basically builds WHERE clauses to constrain the range of which partition tables to scan, as well
     /// as where clauses to apply to the actual partition tables.
-
+    ///
+    /// An `ORDER BY` on a numeric partition column alone is satisfied by the scan itself:
+    /// partitions are visited oldest first, or newest first for a descending order, and the rows
+    /// of each partition are read ordered by the partition column, so SQLite skips its own sort.
+    /// Within a partition the rows are ordered as SQL compares the stored values, which is only
+    /// sure to be time order for numbers, see [`VirtualTable::partition_column_orders_by_time`].
+    /// Text partition columns, which may mix datetime text and epoch integers, and other
+    /// orderings are left to SQLite. SQLite hands `min()` and `max()` of the partition column
+    /// over as an ascending and a descending order and stops after the first row, so they only
+    /// read the oldest or the newest partition. `min()` is only handed over because the column is
+    /// declared `NOT NULL`, see [`VirtualTable::create_table_query`].
+    ///
+    /// A statement without constraints that reads no column, such as `SELECT count(*)`, is
+    /// answered from the row counts the lookup table keeps, see [`QueryPlan::counted`].
+    ///
+    /// A LIMIT (and OFFSET) is only used when the scan produces exactly the rows SQLite keeps:
+    /// every constraint is applied to the partition queries and there is no ORDER BY, or one the
+    /// scan satisfies. The cursor then stops opening partitions once enough rows have been
+    /// produced.
+    ///
+    /// The plan is visible in `EXPLAIN QUERY PLAN` as `INDEX <idxNum>:<idxStr>`. `idxNum` is
+    /// `PRUNED_SCAN` (1) when constraints on the partition column limit the partitions scanned,
+    /// `COUNTED_SCAN` (2) when the row counts answer the statement and `FULL_SCAN` (0) otherwise,
+    /// and `idxStr` starts with a description such as `{"scan":"partitions where col1 > ?"`.
+    ///
+    /// The columns the statement uses are passed on as well, unused columns are not read from the
+    /// partitions so that an index on a partition can cover the query.
+    ///
+    /// The estimated cost grows with the number of partitions the scan visits, so that SQLite
+    /// prefers plans scanning few partitions, see [`PartitionMetaTable::estimated_cost`].
+    ///
+    /// Only plain comparisons against the partition column can prune partitions. SQLite does not
+    /// hand constraints on expressions such as `date(col1) = '2024-02-02'` to the virtual table at
+    /// all, so such queries scan every partition and SQLite filters the rows itself. Results stay
+    /// correct, but to benefit from pruning the predicate should be rewritten as a range on the
+    /// column, e.g. `col1 >= '2024-02-02' AND col1 < '2024-02-03'`.
     fn best_index(&self, index_info: &mut sqlite3_ext::vtab::IndexInfo) -> ExtResult<()> {
+        let column_count = self.interface.columns().0.len();
+        let partition_column_index = self
+            .interface
+            .columns()
+            .0
+            .iter()
+            .position(|column| column.get_name() == self.interface.partition_column_name());
+        // Partitions are scanned oldest first, so ordering the rows of each partition orders the
+        // whole scan by the partition column. Hash and list partitions are not ordered that way,
+        // nor are the partitions of a composite key sharing a partition value or the rows of the
+        // default partition read after them.
+        let time_partitioned = self.interface.is_time_partitioned();
+        let mut order_by = index_info.order_by();
+        let (ordered, descending) = match (order_by.next(), order_by.next()) {
+            (Some(term), None)
+                if time_partitioned
+                    && self.interface.partition_column_orders_by_time()
+                    && self.interface.key_columns().is_empty()
+                    && !self.interface.has_default_partition()
+                    && Some(term.column() as usize) == partition_column_index =>
+            {
+                (true, term.desc())
+            }
+            _ => (false, false),
+        };
+        if ordered {
+            index_info.set_order_by_consumed(true);
+        }
+        // A statement reading no column, without constraints, only needs the number of rows,
+        // which the lookup table keeps.
+        let counted = index_info.constraints().next().is_none()
+            && index_info.order_by().next().is_none()
+            && index_info
+                .columns_used()
+                .is_ok_and(|columns_used| columns_used == 0)
+            && self.interface.lookup().has_row_counts();
+        let limit_supported = (ordered || index_info.order_by().next().is_none())
+            && index_info.constraints().all(|constraint| {
+                matches!(constraint.op(), ConstraintOp::Limit | ConstraintOp::Offset)
+                    || (constraint.usable()
+                        && is_column_constraint_supported(&constraint, column_count))
+            });
         let mut argv_index = 0;
+        let mut comparisons = Vec::new();
+        let mut value_lists = Vec::new();
         for mut constraint in index_info.constraints() {
-            if constraint.usable() {
+            let supported = match constraint.op() {
+                ConstraintOp::Limit | ConstraintOp::Offset => limit_supported,
+                _ => is_column_constraint_supported(&constraint, column_count),
+            };
+            if constraint.usable() && supported {
                 constraint.set_argv_index(Some(argv_index));
+                // The cursor compares the partition column as points in time, SQLite would compare
+                // text datetimes and integer epochs by storage class instead.
+                if Some(constraint.column() as usize) == partition_column_index
+                    && !time_partitioned
+                    && is_time_comparison(&constraint.op())
+                {
+                    // The partition queries compare the values of a hash or list partitioned
+                    // table, only an equality picks the partition to open.
+                    if constraint.op() == ConstraintOp::Eq {
+                        let value = constraint
+                            .rhs()
+                            .ok()
+                            .and_then(|value| self.interface.partition_value(value).ok());
+                        comparisons.push((constraint.op(), value));
+                    }
+                } else if Some(constraint.column() as usize) == partition_column_index
+                    && is_time_comparison(&constraint.op())
+                {
+                    constraint.set_omit(true);
+                    let value = constraint
+                        .rhs()
+                        .ok()
+                        .and_then(|value| self.interface.partition_value(value).ok());
+                    comparisons.push((constraint.op(), value));
+                    // The values of an IN list are handed over at once, so that the cursor
+                    // only opens the partitions they fall into.
+                    if constraint.op() == ConstraintOp::Eq
+                        && constraint.value_list_available()
+                        && constraint.set_value_list_wanted(true)
+                    {
+                        value_lists.push(argv_index as i32);
+                    }
+                } else if Some(constraint.column() as usize) == partition_column_index
+                    && matches!(
+                        constraint.op(),
+                        ConstraintOp::IsNull | ConstraintOp::IsNotNull
+                    )
+                {
+                    // The partition column is only NULL in the default partition, the cursor
+                    // answers both checks by the partitions it opens and their queries.
+                    constraint.set_omit(true);
+                    comparisons.push((constraint.op(), None));
+                }
                 argv_index += 1;
             }
         }
-        index_info.set_estimated_cost(1.0); // Set a default cost, could be refined.
+        index_info.set_estimated_cost(self.estimated_cost(&comparisons)?);
         let mut where_clauses = construct_where_clause(index_info, &self.interface)?;
         let partitions_where_clauses =
             where_clauses.get(self.interface.lookup().partition_table_column().get_name());
@@ -178,6 +870,27 @@ impl<'vtab> VTab<'vtab> for PartitionMetaTable<'vtab> {
                 .filter(|clause| clause.get_name() == self.interface.partition_column_name())
                 .collect::<Vec<&WhereClause>>()
         });
+        let pruning_constraints = partition_column_constraints
+            .iter()
+            .flatten()
+            .filter(|clause| {
+                let operator = clause.get_operator();
+                (is_time_comparison(operator)
+                    && (time_partitioned || *operator == ConstraintOp::Eq))
+                    || selects_no_partition(operator)
+            })
+            .map(|clause| clause.to_string())
+            .collect::<Vec<String>>();
+        let scan = if counted {
+            index_info.set_index_num(COUNTED_SCAN);
+            "row counts of all partitions".to_owned()
+        } else if pruning_constraints.is_empty() {
+            index_info.set_index_num(FULL_SCAN);
+            "all partitions".to_owned()
+        } else {
+            index_info.set_index_num(PRUNED_SCAN);
+            format!("partitions where {}", pruning_constraints.join(" AND "))
+        };
 
         let lookup_where_clause = match partition_column_constraints {
             Some(constraints) => constraints
@@ -200,7 +913,15 @@ impl<'vtab> VTab<'vtab> for PartitionMetaTable<'vtab> {
         lookup_where_clause
             .and_then(|clause| where_clauses.insert("lookup_table".to_string(), clause));
 
-        index_info.set_index_str(Some(&ron::to_string(&where_clauses).unwrap()))?;
+        let columns_used = index_info.columns_used().unwrap_or(u64::MAX);
+        let query_plan = QueryPlan::new(scan, where_clauses, columns_used)
+            .with_value_lists(value_lists)
+            .with_ordered(ordered)
+            .with_descending(descending)
+            .with_counted(counted);
+        index_info.set_index_str(Some(
+            &query_plan.to_index_str(self.interface.index_format())?,
+        ))?;
 
         Ok(())
     }